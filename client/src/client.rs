@@ -0,0 +1,301 @@
+use reqwest::{RequestBuilder, Response, StatusCode, header};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::credentials::{Credentials, sign};
+use crate::error::ClientError;
+use crate::types::{
+    CheckBackupRequest, CheckBackupResponse, DeleteUserRequest, DeleteUserResponse, ErrorResponse,
+    RegisterRequest, RetrieveBackupResponse, StoreBackupRequest, StoreBackupResponse,
+};
+
+/// Default timeout for a single HTTP attempt
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How transient failures (connection errors, 5xx) are retried
+///
+/// Rate-limit responses are never retried: the server's windows are an hour
+/// or a day long, so the caller gets [`ClientError::RateLimited`] instead.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled for each further retry
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// HTTP client for the backup API
+#[derive(Debug, Clone)]
+pub struct BackupClient {
+    http: reqwest::Client,
+    base_url: String,
+    app_secret: String,
+    register_secret: Option<String>,
+    delete_secret: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl BackupClient {
+    /// Create a client for the server at `base_url`, signing with `app_secret`
+    pub fn new(base_url: impl Into<String>, app_secret: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            app_secret: app_secret.into(),
+            register_secret: None,
+            delete_secret: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Sign registrations with `secret` (servers with `REGISTER_SECRET_KEY`)
+    pub fn with_register_secret(mut self, secret: impl Into<String>) -> Self {
+        self.register_secret = Some(secret.into());
+        self
+    }
+
+    /// Sign account deletion with `secret` instead of the app secret
+    /// (servers with `DELETE_SECRET_KEY`)
+    pub fn with_delete_secret(mut self, secret: impl Into<String>) -> Self {
+        self.delete_secret = Some(secret.into());
+        self
+    }
+
+    /// Replace the retry policy
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Register the user (`POST /api/register`)
+    ///
+    /// `captcha_token` is only needed when the server has captcha enabled.
+    pub async fn register(
+        &self,
+        creds: &Credentials,
+        captcha_token: Option<&str>,
+    ) -> Result<(), ClientError> {
+        self.send(|| {
+            let body = RegisterRequest {
+                user_id: &creds.user_id,
+                captcha_token,
+                signature: self
+                    .register_secret
+                    .as_ref()
+                    .map(|secret| sign(&creds.user_id, secret)),
+                timestamp: self.register_secret.as_ref().map(|_| unix_now()),
+            };
+            self.http.post(self.url("/api/register")).json(&body)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Store or replace the user's backup (`POST /api/backup`)
+    ///
+    /// `data` must already be encrypted; it is signed and sent as-is.
+    /// Returns the server's `updatedAt` timestamp.
+    pub async fn store_backup(
+        &self,
+        creds: &Credentials,
+        data: &str,
+    ) -> Result<String, ClientError> {
+        let response = self
+            .send(|| {
+                // Re-signed per attempt so retries carry a fresh timestamp
+                let body = StoreBackupRequest {
+                    user_id: &creds.user_id,
+                    storage_key: &creds.storage_key,
+                    data,
+                    signature: sign(data, &self.app_secret),
+                    timestamp: unix_now(),
+                };
+                self.http.post(self.url("/api/backup")).json(&body)
+            })
+            .await?;
+
+        let body: StoreBackupResponse = response.json().await?;
+        Ok(body.updated_at)
+    }
+
+    /// Whether the server already holds exactly `data` (`POST /api/backup/check`)
+    ///
+    /// Only a checksum is sent. Call this before [`store_backup`](Self::store_backup)
+    /// to skip uploads that would change nothing and save a rate-limit slot.
+    pub async fn backup_unchanged(
+        &self,
+        creds: &Credentials,
+        data: &str,
+    ) -> Result<bool, ClientError> {
+        let checksum = dailyreps_signing::checksum(data.as_bytes());
+        let response = self
+            .send(|| {
+                let body = CheckBackupRequest {
+                    user_id: &creds.user_id,
+                    storage_key: &creds.storage_key,
+                    checksum: &checksum,
+                };
+                self.http.post(self.url("/api/backup/check")).json(&body)
+            })
+            .await?;
+
+        let body: CheckBackupResponse = response.json().await?;
+        Ok(body.unchanged)
+    }
+
+    /// Fetch the user's backup (`GET /api/backup`)
+    ///
+    /// Fails with [`ClientError::ChecksumMismatch`] if the data doesn't match
+    /// the checksum the server sent with it.
+    pub async fn retrieve_backup(
+        &self,
+        creds: &Credentials,
+    ) -> Result<RetrieveBackupResponse, ClientError> {
+        let response = self
+            .send(|| {
+                self.http.get(self.url("/api/backup")).query(&[
+                    ("userId", creds.user_id.as_str()),
+                    ("storageKey", creds.storage_key.as_str()),
+                ])
+            })
+            .await?;
+
+        let backup: RetrieveBackupResponse = response.json().await?;
+        if let Some(checksum) = &backup.checksum
+            && *checksum != dailyreps_signing::checksum(backup.data.as_bytes())
+        {
+            return Err(ClientError::ChecksumMismatch);
+        }
+
+        Ok(backup)
+    }
+
+    /// Delete the user and all their backups (`DELETE /api/user`)
+    ///
+    /// Returns the deadline until which [`restore_user`](Self::restore_user)
+    /// can undo it, or `None` if the server deleted the data immediately.
+    pub async fn delete_user(&self, creds: &Credentials) -> Result<Option<String>, ClientError> {
+        let response = self
+            .send(|| {
+                self.http
+                    .delete(self.url("/api/user"))
+                    .json(&self.delete_request(creds))
+            })
+            .await?;
+
+        let body: DeleteUserResponse = response.json().await?;
+        Ok(body.restorable_until)
+    }
+
+    /// Undo [`delete_user`](Self::delete_user) within the server's grace
+    /// period (`POST /api/user/restore`)
+    pub async fn restore_user(&self, creds: &Credentials) -> Result<(), ClientError> {
+        let response = self
+            .send(|| {
+                self.http
+                    .post(self.url("/api/user/restore"))
+                    .json(&self.delete_request(creds))
+            })
+            .await?;
+
+        let _: DeleteUserResponse = response.json().await?;
+        Ok(())
+    }
+
+    /// Body for the delete and restore endpoints, signed with the delete secret
+    fn delete_request<'a>(&self, creds: &'a Credentials) -> DeleteUserRequest<'a> {
+        DeleteUserRequest {
+            user_id: &creds.user_id,
+            storage_key: &creds.storage_key,
+            signature: sign(
+                &creds.storage_key,
+                self.delete_secret.as_ref().unwrap_or(&self.app_secret),
+            ),
+            timestamp: unix_now(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Send a request built by `build`, retrying transient failures
+    async fn send<F>(&self, build: F) -> Result<Response, ClientError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut backoff = self.retry.initial_backoff;
+        let mut attempt = 0;
+
+        loop {
+            let result = match build().send().await {
+                Ok(response) => check_status(response).await,
+                Err(e) => Err(ClientError::Http(e)),
+            };
+
+            match result {
+                Err(e) if e.is_retryable() && attempt < self.retry.max_retries => {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Map non-success responses to typed errors
+async fn check_status(response: Response) -> Result<Response, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = response
+        .headers()
+        .get(header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs);
+
+    let (message, code) = response
+        .json::<ErrorResponse>()
+        .await
+        .map(|e| (e.error, e.code))
+        .unwrap_or_else(|_| (status.to_string(), None));
+
+    Err(match status {
+        // Sync conflicts and replays are 409s too
+        StatusCode::CONFLICT if code.as_deref().is_none_or(|c| c == "USER_EXISTS") => {
+            ClientError::UserAlreadyExists
+        }
+        StatusCode::NOT_FOUND => ClientError::BackupNotFound,
+        StatusCode::UNAUTHORIZED => ClientError::Unauthorized(message),
+        StatusCode::TOO_MANY_REQUESTS => ClientError::RateLimited { retry_after },
+        _ => ClientError::Api {
+            status: status.as_u16(),
+            message,
+        },
+    })
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,49 @@
+/// Server-facing identity derived from a username and password
+///
+/// - `user_id` = sha256(lowercase username)
+/// - `storage_key` = sha256(user_id + password)
+///
+/// Neither value reveals the username or password to the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub user_id: String,
+    pub storage_key: String,
+}
+
+impl Credentials {
+    /// Derive credentials the same way the mobile app does
+    pub fn derive(username: &str, password: &str) -> Self {
+        let user_id = dailyreps_signing::derive_user_id(username);
+        let storage_key = dailyreps_signing::derive_storage_key(&user_id, password);
+
+        Self {
+            user_id,
+            storage_key,
+        }
+    }
+}
+
+/// HMAC-SHA256 signature (hex) of `data` with the app secret
+pub fn sign(data: impl AsRef<[u8]>, secret: &str) -> String {
+    dailyreps_signing::sign(data.as_ref(), secret.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_is_case_insensitive_on_username() {
+        let a = Credentials::derive("Alice", "pw");
+        let b = Credentials::derive("alice", "pw");
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_derive_storage_key_depends_on_password() {
+        let a = Credentials::derive("alice", "pw1");
+        let b = Credentials::derive("alice", "pw2");
+        assert_eq!(a.user_id, b.user_id);
+        assert_ne!(a.storage_key, b.storage_key);
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors returned by [`BackupClient`](crate::BackupClient)
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("User already exists")]
+    UserAlreadyExists,
+
+    #[error("Backup not found")]
+    BackupNotFound,
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Rate limit exceeded")]
+    RateLimited {
+        /// Server-provided `Retry-After`, when present
+        retry_after: Option<Duration>,
+    },
+
+    #[error("Server returned {status}: {message}")]
+    Api { status: u16, message: String },
+
+    #[error("Backup does not match its checksum")]
+    ChecksumMismatch,
+}
+
+impl ClientError {
+    /// Whether the request may succeed if sent again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Http(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            ClientError::Api { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Client SDK for the DailyReps backup server
+//!
+//! Implements the client half of the protocol: identity derivation,
+//! request signing, and the register/store/retrieve/delete calls with
+//! retries. Backup data is treated as an opaque, already-encrypted string;
+//! encryption keys never pass through this crate.
+//!
+//! ```no_run
+//! use dailyreps_backup_client::{BackupClient, Credentials};
+//!
+//! # async fn run() -> Result<(), dailyreps_backup_client::ClientError> {
+//! let client = BackupClient::new("https://backup.example.com", "app-secret");
+//! let creds = Credentials::derive("alice", "correct horse battery staple");
+//!
+//! client.register(&creds, None).await?;
+//! client.store_backup(&creds, "base64-ciphertext").await?;
+//! let backup = client.retrieve_backup(&creds).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod client;
+mod credentials;
+mod error;
+pub mod types;
+
+pub use client::{BackupClient, RetryPolicy};
+pub use credentials::{Credentials, sign};
+pub use error::ClientError;
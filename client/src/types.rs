@@ -0,0 +1,93 @@
+//! Wire types for the JSON API
+//!
+//! Field names follow the server's JSON shape (camelCase).
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisterRequest<'a> {
+    #[serde(rename = "userId")]
+    pub user_id: &'a str,
+    #[serde(rename = "captchaToken", skip_serializing_if = "Option::is_none")]
+    pub captcha_token: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoreBackupRequest<'a> {
+    #[serde(rename = "userId")]
+    pub user_id: &'a str,
+    #[serde(rename = "storageKey")]
+    pub storage_key: &'a str,
+    pub data: &'a str,
+    pub signature: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoreBackupResponse {
+    pub success: bool,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+    /// Hex SHA-256 of the stored data (absent from older servers)
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckBackupRequest<'a> {
+    #[serde(rename = "userId")]
+    pub user_id: &'a str,
+    #[serde(rename = "storageKey")]
+    pub storage_key: &'a str,
+    pub checksum: &'a str,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckBackupResponse {
+    pub unchanged: bool,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetrieveBackupResponse {
+    pub data: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+    /// Hex SHA-256 of `data` (absent from older servers)
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeleteUserRequest<'a> {
+    #[serde(rename = "userId")]
+    pub user_id: &'a str,
+    #[serde(rename = "storageKey")]
+    pub storage_key: &'a str,
+    pub signature: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeleteUserResponse {
+    pub success: bool,
+    pub message: String,
+    /// RFC 3339 deadline for [`crate::BackupClient::restore_user`]; absent
+    /// when the server deleted the data immediately
+    #[serde(rename = "restorableUntil", default)]
+    pub restorable_until: Option<String>,
+}
+
+/// Error body returned by the server
+#[derive(Debug, Clone, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Machine-readable code; absent from servers that predate it
+    #[serde(default)]
+    pub code: Option<String>,
+}
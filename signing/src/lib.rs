@@ -0,0 +1,278 @@
+//! Request signing and identity derivation for the DailyReps backup protocol
+//!
+//! This is the single source of truth for how clients derive `userId` and
+//! `storageKey` and how signed requests are canonicalized. The server verifies
+//! with the same code, and the web client uses it compiled to WASM (see the
+//! `wasm` feature) instead of a hand-ported copy.
+//!
+//! Signed payloads by endpoint:
+//! - `POST /api/backup`: the `data` string as sent, plus any opt-in `stats`
+//!   (see [`backup_payload`])
+//! - `POST /api/v2/backup`: the raw request body bytes
+//! - `DELETE /api/user`, `POST /api/backup/archive`: the `storageKey` string
+//! - `POST /api/recovery/rekey`: the `newStorageKey` string
+//! - `POST /api/register`: the `userId` string, only when the server sets
+//!   `REGISTER_SECRET_KEY`
+//!
+//! Any signed request may also carry a `nonce` (16-64 characters of
+//! `[A-Za-z0-9_-]`), which the server accepts only once. The signature then
+//! covers [`nonce_payload`] of the endpoint's payload, so the nonce can't be
+//! swapped or stripped by whoever replays the request.
+//!
+//! The server may use a separate secret per class (`REGISTER_SECRET_KEY`,
+//! `STORE_SECRET_KEY`, `DELETE_SECRET_KEY`), each defaulting to
+//! `APP_SECRET_KEY`.
+//!
+//! `POST /api/backup` also accepts `sigVersion: 2`, signing
+//! [`canonical_payload`] of every field so identity and timestamp are covered
+//! too; without `sigVersion` the v1 payload above is used.
+//!
+//! `POST /api/backup/check` is unsigned; it carries [`checksum`] of the data.
+//!
+//! `no_std` (needs `alloc`) so it builds for `wasm32-unknown-unknown` without
+//! pulling in the server's dependencies. To produce the JS package:
+//!
+//! ```text
+//! cargo rustc -p dailyreps-signing --release --features wasm \
+//!     --target wasm32-unknown-unknown --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg \
+//!     target/wasm32-unknown-unknown/release/dailyreps_signing.wasm
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "wasm")]
+mod wasm;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length of an HMAC-SHA256 tag in bytes
+const SIGNATURE_LEN: usize = 32;
+
+/// Server user ID: hex sha256 of the lowercased username
+pub fn derive_user_id(username: &str) -> String {
+    hex::encode(Sha256::digest(username.to_lowercase().as_bytes()))
+}
+
+/// Storage key: hex sha256 of `user_id` followed by the password
+pub fn derive_storage_key(user_id: &str, password: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(user_id.as_bytes());
+    hasher.update(password.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Recovery contact hash: hex sha256 of `user_id`, a newline, and the
+/// contact lowercased with whitespace removed
+///
+/// Salted with the user ID so the same email gives unrelated hashes for
+/// different accounts. Phone numbers should be in E.164 form (`+31612345678`)
+/// so the app and support compute the same hash.
+pub fn recovery_hash(user_id: &str, contact: &str) -> String {
+    let normalized: String = contact
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    let mut hasher = Sha256::new();
+    hasher.update(user_id.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(normalized.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Signed payload for `POST /api/backup`
+///
+/// Without stats this is just `data`, so existing clients are unaffected.
+/// With stats it is `data`, a newline, then `key=value` pairs sorted by key
+/// and joined with `&`.
+pub fn backup_payload<'a>(
+    data: &str,
+    stats: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Vec<u8> {
+    let mut stats: Vec<_> = stats.into_iter().collect();
+    let mut payload = Vec::from(data.as_bytes());
+    if stats.is_empty() {
+        return payload;
+    }
+
+    stats.sort_unstable();
+    payload.push(b'\n');
+    for (i, (key, value)) in stats.into_iter().enumerate() {
+        if i > 0 {
+            payload.push(b'&');
+        }
+        payload.extend_from_slice(key.as_bytes());
+        payload.push(b'=');
+        payload.extend_from_slice(value.as_bytes());
+    }
+    payload
+}
+
+/// Signed payload for `sigVersion: 2`: every request field except the signature
+///
+/// `v2` and a newline, then one `key=len:value` line per field sorted by key,
+/// where `len` is the value's length in bytes. The length prefix stops a value
+/// from spilling into the next field, so none of them (identity, timestamp,
+/// sync token) can be changed without breaking the signature.
+pub fn canonical_payload<K: AsRef<str>, V: AsRef<str>>(
+    fields: impl IntoIterator<Item = (K, V)>,
+) -> Vec<u8> {
+    let mut fields: Vec<_> = fields.into_iter().collect();
+    fields.sort_unstable_by(|a, b| a.0.as_ref().cmp(b.0.as_ref()));
+
+    let mut payload = Vec::from(&b"v2\n"[..]);
+    for (key, value) in fields {
+        let value = value.as_ref();
+        payload.extend_from_slice(key.as_ref().as_bytes());
+        payload.extend_from_slice(format!("={}:", value.len()).as_bytes());
+        payload.extend_from_slice(value.as_bytes());
+        payload.push(b'\n');
+    }
+    payload
+}
+
+/// Signed payload for `POST /api/backup/delete`: the storage keys in request
+/// order, joined with newlines
+pub fn delete_backups_payload<'a>(storage_keys: impl IntoIterator<Item = &'a str>) -> Vec<u8> {
+    storage_keys
+        .into_iter()
+        .collect::<Vec<_>>()
+        .join("\n")
+        .into_bytes()
+}
+
+/// Signed payload for a request carrying a `nonce`: the endpoint's payload,
+/// a newline, then the nonce
+pub fn nonce_payload(payload: &[u8], nonce: &str) -> Vec<u8> {
+    let mut signed = Vec::with_capacity(payload.len() + 1 + nonce.len());
+    signed.extend_from_slice(payload);
+    signed.push(b'\n');
+    signed.extend_from_slice(nonce.as_bytes());
+    signed
+}
+
+/// Backup checksum for `POST /api/backup/check`: hex sha256 of the `data`
+/// string exactly as it would be uploaded
+pub fn checksum(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Hex-encoded HMAC-SHA256 of `data` keyed with the app secret
+pub fn sign(data: &[u8], secret: &[u8]) -> String {
+    hex::encode(mac(secret).chain_update(data).finalize().into_bytes())
+}
+
+/// Check a hex-encoded HMAC-SHA256 signature in constant time
+pub fn verify(data: &[u8], signature: &str, secret: &[u8]) -> bool {
+    let mut tag = [0u8; SIGNATURE_LEN];
+    if hex::decode_to_slice(signature, &mut tag).is_err() {
+        return false;
+    }
+
+    mac(secret).chain_update(data).verify_slice(&tag).is_ok()
+}
+
+fn mac(secret: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret).expect("HMAC can take key of any size")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_known_vector() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign(b"what do ya want for nothing?", b"Jefe"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_verify_round_trip() {
+        let signature = sign(b"payload", b"secret");
+        assert!(verify(b"payload", &signature, b"secret"));
+        assert!(!verify(b"payload", &signature, b"other-secret"));
+        assert!(!verify(b"tampered", &signature, b"secret"));
+    }
+
+    #[test]
+    fn test_verify_rejects_malformed_signature() {
+        assert!(!verify(b"payload", "not-hex", b"secret"));
+        assert!(!verify(b"payload", "abcd", b"secret"));
+    }
+
+    #[test]
+    fn test_derive_identity() {
+        let user_id = derive_user_id("Alice");
+        assert_eq!(user_id, derive_user_id("alice"));
+        assert_eq!(user_id.len(), 64);
+
+        let storage_key = derive_storage_key(&user_id, "pw");
+        assert_eq!(storage_key.len(), 64);
+        assert_ne!(storage_key, derive_storage_key(&user_id, "pw2"));
+    }
+
+    #[test]
+    fn test_recovery_hash_normalizes_contact() {
+        let user_id = derive_user_id("alice");
+        let hash = recovery_hash(&user_id, "Alice@Example.com ");
+        assert_eq!(hash, recovery_hash(&user_id, "alice@example.com"));
+        assert_eq!(hash.len(), 64);
+        assert_ne!(
+            hash,
+            recovery_hash(&derive_user_id("bob"), "alice@example.com")
+        );
+    }
+
+    #[test]
+    fn test_backup_payload() {
+        assert_eq!(backup_payload("blob", []), b"blob");
+        assert_eq!(
+            backup_payload("blob", [("workouts", "10-49"), ("app", "2.1")]),
+            b"blob\napp=2.1&workouts=10-49"
+        );
+    }
+
+    #[test]
+    fn test_delete_backups_payload() {
+        assert_eq!(delete_backups_payload(["a"]), b"a");
+        assert_eq!(delete_backups_payload(["b", "a"]), b"b\na");
+    }
+
+    #[test]
+    fn test_canonical_payload() {
+        assert_eq!(
+            canonical_payload([("userId", "u"), ("data", "a\nb")]),
+            b"v2\ndata=3:a\nb\nuserId=1:u\n"
+        );
+        assert_ne!(
+            canonical_payload([("data", "x"), ("timestamp", "1")]),
+            canonical_payload([("data", "x\ntimestamp=1:1"), ("timestamp", "2")])
+        );
+    }
+
+    #[test]
+    fn test_nonce_payload() {
+        assert_eq!(nonce_payload(b"blob", "n0nce"), b"blob\nn0nce");
+    }
+
+    #[test]
+    fn test_checksum_known_vector() {
+        assert_eq!(
+            checksum(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[wasm_bindgen(js_name = deriveUserId)]
+pub fn derive_user_id(username: &str) -> String {
+    crate::derive_user_id(username)
+}
+
+#[wasm_bindgen(js_name = deriveStorageKey)]
+pub fn derive_storage_key(user_id: &str, password: &str) -> String {
+    crate::derive_storage_key(user_id, password)
+}
+
+/// Salted hash of a recovery email/phone (for `recoveryHash` at registration)
+#[wasm_bindgen(js_name = recoveryHash)]
+pub fn recovery_hash(user_id: &str, contact: &str) -> String {
+    crate::recovery_hash(user_id, contact)
+}
+
+/// Checksum of a backup `data` string (for `POST /api/backup/check`)
+#[wasm_bindgen(js_name = checksum)]
+pub fn checksum(data: &str) -> String {
+    crate::checksum(data.as_bytes())
+}
+
+/// Sign a UTF-8 payload (backup `data` or `storageKey`)
+#[wasm_bindgen(js_name = sign)]
+pub fn sign(data: &str, secret: &str) -> String {
+    crate::sign(data.as_bytes(), secret.as_bytes())
+}
+
+/// Sign raw bytes (the `/api/v2/backup` body)
+#[wasm_bindgen(js_name = signBytes)]
+pub fn sign_bytes(data: &[u8], secret: &str) -> String {
+    crate::sign(data, secret.as_bytes())
+}
+
+/// Sign a backup upload with opt-in stats (`keys[i]` = `values[i]`)
+#[wasm_bindgen(js_name = signBackup)]
+pub fn sign_backup(data: &str, keys: Vec<String>, values: Vec<String>, secret: &str) -> String {
+    let stats = keys
+        .iter()
+        .map(String::as_str)
+        .zip(values.iter().map(String::as_str));
+    crate::sign(&crate::backup_payload(data, stats), secret.as_bytes())
+}
+
+/// Payload to sign instead of `payload` when the request carries a `nonce`
+#[wasm_bindgen(js_name = noncePayload)]
+pub fn nonce_payload(payload: &[u8], nonce: &str) -> Vec<u8> {
+    crate::nonce_payload(payload, nonce)
+}
+
+/// `sigVersion: 2` payload of a request's fields (`keys[i]` = `values[i]`)
+#[wasm_bindgen(js_name = canonicalPayload)]
+pub fn canonical_payload(keys: Vec<String>, values: Vec<String>) -> Vec<u8> {
+    crate::canonical_payload(keys.iter().zip(values.iter()))
+}
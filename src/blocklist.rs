@@ -0,0 +1,214 @@
+//! Network blocklist for registration
+//!
+//! Account farms tend to register through Tor and cloud VMs. When
+//! `REGISTRATION_BLOCKLIST_URLS` is set, a background task downloads each list
+//! (one IP or CIDR range per line, `#` comments allowed; e.g. the Tor bulk exit
+//! list or a datacenter range list) every `BLOCKLIST_REFRESH_SECS`, and
+//! `/api/register` refuses clients inside any listed range. Backup retrieval
+//! and uploads are never checked, so existing users are unaffected.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Timeout for downloading one list
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// IP ranges that may not register
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    /// Sorted, non-overlapping inclusive ranges of IPv6 addresses, with
+    /// IPv4 stored as IPv4-mapped (`::ffff:a.b.c.d`)
+    ranges: RwLock<Vec<(u128, u128)>>,
+}
+
+impl Blocklist {
+    /// Whether `ip` falls inside a blocked range
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.ranges
+            .read()
+            .map(|ranges| {
+                let value = to_u128(ip);
+                let idx = ranges.partition_point(|(start, _)| *start <= value);
+                idx > 0 && value <= ranges[idx - 1].1
+            })
+            .unwrap_or(false)
+    }
+
+    /// Number of (merged) ranges currently blocked
+    pub fn len(&self) -> usize {
+        self.ranges.read().map(|ranges| ranges.len()).unwrap_or(0)
+    }
+
+    /// Whether nothing is blocked
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replace the blocked ranges with those listed in `lists`
+    ///
+    /// Each list is newline-separated IPs or CIDR ranges; lines that don't
+    /// parse are skipped. Returns the number of skipped lines.
+    pub fn replace<'a>(&self, lists: impl IntoIterator<Item = &'a str>) -> usize {
+        let mut ranges = Vec::new();
+        let mut skipped = 0;
+
+        for line in lists.into_iter().flat_map(str::lines) {
+            let entry = line.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            match parse_range(entry) {
+                Some(range) => ranges.push(range),
+                None => skipped += 1,
+            }
+        }
+
+        let ranges = merge(ranges);
+        if let Ok(mut current) = self.ranges.write() {
+            *current = ranges;
+        }
+
+        skipped
+    }
+
+    /// Download `urls` now and then every `interval`, replacing the blocklist
+    ///
+    /// A list that fails to download keeps its last good copy, so a flaky
+    /// source doesn't silently unblock everything. Must be called from within
+    /// a Tokio runtime.
+    pub fn spawn_refresher(
+        self: Arc<Self>,
+        urls: Vec<String>,
+        interval: Duration,
+    ) -> JoinHandle<()> {
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        tokio::spawn(async move {
+            let mut last_good: HashMap<String, String> = HashMap::new();
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                ticker.tick().await;
+
+                for url in &urls {
+                    match fetch(&client, url).await {
+                        Ok(body) => {
+                            last_good.insert(url.clone(), body);
+                        }
+                        Err(e) => tracing::warn!("Failed to refresh blocklist {}: {}", url, e),
+                    }
+                }
+
+                let skipped = self.replace(last_good.values().map(String::as_str));
+                tracing::info!(
+                    "Registration blocklist refreshed: {} ranges ({} lines skipped)",
+                    self.len(),
+                    skipped
+                );
+            }
+        })
+    }
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> reqwest::Result<String> {
+    client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+}
+
+/// An address as a number, IPv4 mapped into IPv6
+pub(crate) fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(ip.to_ipv6_mapped()),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+/// Parse `1.2.3.4`, `1.2.3.0/24`, `2001:db8::/32`, ... into an inclusive range
+pub(crate) fn parse_range(entry: &str) -> Option<(u128, u128)> {
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix.parse::<u32>().ok()?)),
+        None => (entry, None),
+    };
+
+    let ip: IpAddr = addr.parse().ok()?;
+    // IPv4 prefixes count from the start of the mapped address's last 32 bits
+    let prefix = match (ip, prefix) {
+        (_, None) => 128,
+        (IpAddr::V4(_), Some(p)) if p <= 32 => 96 + p,
+        (IpAddr::V6(_), Some(p)) if p <= 128 => p,
+        _ => return None,
+    };
+
+    let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+    let start = to_u128(ip) & mask;
+    Some((start, start | !mask))
+}
+
+/// Sort and coalesce overlapping or adjacent ranges
+fn merge(mut ranges: Vec<(u128, u128)>) -> Vec<(u128, u128)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_contains_ips_and_cidrs() {
+        let blocklist = Blocklist::default();
+        let skipped = blocklist.replace([
+            "# Tor exit nodes\n185.220.101.4\n",
+            "10.0.0.0/8  # datacenter\n2001:db8::/32\nnot-an-ip\n1.2.3.4/33\n",
+        ]);
+        assert_eq!(skipped, 2);
+
+        assert!(blocklist.contains(ip("185.220.101.4")));
+        assert!(!blocklist.contains(ip("185.220.101.5")));
+        assert!(blocklist.contains(ip("10.255.255.255")));
+        assert!(!blocklist.contains(ip("11.0.0.0")));
+        assert!(blocklist.contains(ip("2001:db8:1::1")));
+        assert!(!blocklist.contains(ip("2001:db9::1")));
+        // IPv4-mapped IPv6 (dual-stack sockets) matches the IPv4 ranges
+        assert!(blocklist.contains(ip("::ffff:10.1.2.3")));
+    }
+
+    #[test]
+    fn test_overlapping_ranges_are_merged() {
+        let blocklist = Blocklist::default();
+        blocklist.replace(["10.0.0.0/24\n10.0.0.5\n10.0.1.0/24\n0.0.0.0/0\n"]);
+        assert_eq!(blocklist.len(), 1);
+        assert!(blocklist.contains(ip("255.255.255.255")));
+    }
+
+    #[test]
+    fn test_replace_clears_previous_entries() {
+        let blocklist = Blocklist::default();
+        blocklist.replace(["192.0.2.1"]);
+        blocklist.replace([""]);
+        assert!(blocklist.is_empty());
+        assert!(!blocklist.contains(ip("192.0.2.1")));
+    }
+}
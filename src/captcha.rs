@@ -0,0 +1,76 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Cloudflare Turnstile verification endpoint (default provider)
+pub const TURNSTILE_VERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+/// Timeout for a single verification call to the provider
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Provider response (shared shape between Turnstile and hCaptcha)
+#[derive(Debug, Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+/// Server-side verifier for Turnstile/hCaptcha tokens
+///
+/// Both providers accept the same `siteverify` form (`secret`, `response`),
+/// so switching providers is a matter of changing the verify URL.
+#[derive(Debug, Clone)]
+pub struct CaptchaVerifier {
+    client: reqwest::Client,
+    secret: String,
+    verify_url: String,
+}
+
+impl CaptchaVerifier {
+    /// Create a verifier for the given provider secret and endpoint
+    pub fn new(secret: String, verify_url: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(VERIFY_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            secret,
+            verify_url,
+        }
+    }
+
+    /// Verify a client token with the provider
+    ///
+    /// Fails closed: provider errors and timeouts count as a failed check.
+    pub async fn verify(&self, token: &str) -> bool {
+        let response = self
+            .client
+            .post(&self.verify_url)
+            .form(&[("secret", self.secret.as_str()), ("response", token)])
+            .send()
+            .await;
+
+        let body = match response {
+            Ok(resp) => resp.json::<SiteVerifyResponse>().await,
+            Err(e) => {
+                tracing::error!("Captcha provider request failed: {}", e);
+                return false;
+            }
+        };
+
+        match body {
+            Ok(result) => {
+                if !result.success {
+                    tracing::warn!("Captcha rejected: {:?}", result.error_codes);
+                }
+                result.success
+            }
+            Err(e) => {
+                tracing::error!("Invalid captcha provider response: {}", e);
+                false
+            }
+        }
+    }
+}
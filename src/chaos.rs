@@ -0,0 +1,157 @@
+//! Fault injection for development (`chaos` feature)
+//!
+//! Never enable this feature in production builds. Faults are configured via
+//! environment variables, read once at first use:
+//!
+//! - `CHAOS_ERROR_PERCENT` - share of requests answered with a 500 before
+//!   reaching the handler (0-100)
+//! - `CHAOS_DB_DELAY_MS` - extra latency added to every write transaction
+//! - `CHAOS_COMMIT_FAILURE_PERCENT` - share of write transactions aborted
+//!   with a storage error instead of committing (0-100)
+
+use axum::{extract::Request, middleware::Next, response::IntoResponse, response::Response};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::{AppError, Result};
+
+/// Active fault settings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChaosConfig {
+    pub error_percent: f64,
+    pub db_delay: Duration,
+    pub commit_failure_percent: f64,
+}
+
+impl ChaosConfig {
+    /// Read settings with `lookup` (the environment in production use)
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let percent = |name: &str| {
+            lookup(name)
+                .and_then(|v| v.parse::<f64>().ok())
+                .unwrap_or(0.0)
+                .clamp(0.0, 100.0)
+        };
+
+        Self {
+            error_percent: percent("CHAOS_ERROR_PERCENT"),
+            db_delay: Duration::from_millis(
+                lookup("CHAOS_DB_DELAY_MS")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+            ),
+            commit_failure_percent: percent("CHAOS_COMMIT_FAILURE_PERCENT"),
+        }
+    }
+}
+
+/// Process-wide settings, loaded from the environment on first use
+pub fn config() -> &'static ChaosConfig {
+    static CONFIG: OnceLock<ChaosConfig> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let config = ChaosConfig::from_lookup(|name| std::env::var(name).ok());
+        tracing::warn!("Chaos mode enabled: {:?}", config);
+        config
+    })
+}
+
+/// Middleware answering a share of requests with a 500
+pub async fn inject_errors(request: Request, next: Next) -> Response {
+    if roll(config().error_percent) {
+        tracing::warn!("Chaos: injected 500 for {}", request.uri().path());
+        return injected_error().into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Hook run inside write transactions just before commit
+///
+/// Blocks for the configured delay (callers are on the blocking pool) and
+/// may fail, which drops the transaction uncommitted.
+pub fn before_commit() -> Result<()> {
+    let config = config();
+
+    if !config.db_delay.is_zero() {
+        std::thread::sleep(config.db_delay);
+    }
+
+    if roll(config.commit_failure_percent) {
+        tracing::warn!("Chaos: injected commit failure");
+        return Err(injected_error());
+    }
+
+    Ok(())
+}
+
+fn injected_error() -> AppError {
+    AppError::Storage(redb::StorageError::Io(std::io::Error::other(
+        "chaos: injected fault",
+    )))
+}
+
+/// True with probability `percent`/100
+fn roll(percent: f64) -> bool {
+    if percent <= 0.0 {
+        return false;
+    }
+    if percent >= 100.0 {
+        return true;
+    }
+
+    ((next_random() % 10_000) as f64) < percent * 100.0
+}
+
+/// xorshift64* - plenty for fault injection, no extra dependency
+fn next_random() -> u64 {
+    static STATE: AtomicU64 = AtomicU64::new(0);
+
+    let mut x = STATE.load(Ordering::Relaxed);
+    if x == 0 {
+        x = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0x9E37_79B9_7F4A_7C15)
+            | 1;
+    }
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    STATE.store(x, Ordering::Relaxed);
+
+    x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_lookup() {
+        let config = ChaosConfig::from_lookup(|name| match name {
+            "CHAOS_ERROR_PERCENT" => Some("12.5".to_string()),
+            "CHAOS_DB_DELAY_MS" => Some("250".to_string()),
+            "CHAOS_COMMIT_FAILURE_PERCENT" => Some("400".to_string()),
+            _ => None,
+        });
+
+        assert_eq!(config.error_percent, 12.5);
+        assert_eq!(config.db_delay, Duration::from_millis(250));
+        assert_eq!(config.commit_failure_percent, 100.0);
+    }
+
+    #[test]
+    fn test_config_defaults_to_no_faults() {
+        assert_eq!(ChaosConfig::from_lookup(|_| None), ChaosConfig::default());
+    }
+
+    #[test]
+    fn test_roll_bounds() {
+        assert!((0..1000).all(|_| !roll(0.0)));
+        assert!((0..1000).all(|_| roll(100.0)));
+
+        let hits = (0..10_000).filter(|_| roll(50.0)).count();
+        assert!((4_000..6_000).contains(&hits), "got {}", hits);
+    }
+}
@@ -1,92 +1,779 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
 use std::env;
 
-/// Application configuration loaded from environment variables
+use crate::constants::MIN_INACTIVE_PURGE_DAYS;
+use crate::notifier::WebhookKind;
+use crate::proxy::TrustedProxies;
+
+/// Class of signed operation, each of which may use its own HMAC secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningScope {
+    /// Uploads and reads that need the storage key (store, archive)
+    Store,
+    /// Account-destroying or -rebinding requests (delete, recovery rekey)
+    Delete,
+}
+
+/// Application configuration loaded from environment variables and `CONFIG_FILE`
 #[derive(Debug, Clone)]
 pub struct Config {
     pub server_host: String,
     pub server_port: u16,
+    /// PEM certificate chain and private key for native TLS (see `tls`)
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    /// Idle seconds before a keep-alive connection closes (0 = no keep-alive)
+    pub http_keep_alive_secs: u64,
+    /// Open connections at once (0 = unlimited)
+    pub max_connections: usize,
+    /// Speak HTTP/2 as well as HTTP/1.1
+    pub http2: bool,
     pub database_path: String,
+    pub db_restore_snapshot_dir: Option<String>,
+    /// Where `POST /admin/snapshot` writes copies; defaults to
+    /// `db_restore_snapshot_dir` so a restore finds them
+    pub snapshot_dir: Option<String>,
     pub allowed_origins: Vec<String>,
+    pub cors_max_age_secs: u64,
+    pub cors_allow_credentials: bool,
+    pub cors_expose_headers: Vec<String>,
+    /// gzip/brotli-compress backup downloads for clients that accept it
+    pub response_compression: bool,
     pub rate_limit_requests: u64,
     pub rate_limit_window_secs: u64,
     pub register_rate_limit_requests: u64,
     pub register_rate_limit_window_secs: u64,
+    pub max_in_flight_per_user: usize,
+    pub min_backup_interval_secs: u64,
+    pub backup_versions_kept: u64,
+    pub deletion_grace_days: u64,
+    /// Purge accounts inactive for this many days, daily; `None` disables it
+    pub inactive_purge_days: Option<u64>,
+    /// Compact the database file every this many hours; `None` disables it
+    pub compaction_interval_hours: Option<u64>,
     pub environment: String,
-    pub app_secret_key: String,
+    /// Accepted HMAC secrets, newest first; never empty
+    pub app_secret_keys: Vec<String>,
+    pub register_secret_key: Option<String>,
+    pub store_secret_key: Option<String>,
+    pub delete_secret_key: Option<String>,
     pub admin_secret_key: Option<String>,
     pub log_requests: bool,
+    /// Start with writes refused (see `POST /admin/maintenance`)
+    pub maintenance_mode: bool,
+    pub statsd_addr: Option<String>,
+    pub statsd_prefix: String,
+    pub statsd_tags: Vec<String>,
+    pub captcha_secret_key: Option<String>,
+    pub captcha_verify_url: String,
+    pub oidc_issuer: Option<String>,
+    pub oidc_audience: Option<String>,
+    pub oidc_allowed_subjects: Vec<String>,
+    pub alert_webhook_url: Option<String>,
+    pub alert_webhook_kind: WebhookKind,
+    pub geoip_db_path: Option<String>,
+    pub client_ip_header: Option<String>,
+    /// Peers whose forwarding headers are believed (see `proxy`)
+    pub trusted_proxies: TrustedProxies,
+    pub allowed_countries: Vec<String>,
+    pub denied_countries: Vec<String>,
+    /// Apply the country policy to `/api/register` only
+    pub country_policy_registration_only: bool,
+    pub registration_blocklist_urls: Vec<String>,
+    pub blocklist_refresh_secs: u64,
+    /// Standby to forward committed writes to (see `replication`)
+    pub replica_url: Option<String>,
+    /// Shared key signing replication requests, on both primary and standby
+    pub replication_secret: Option<String>,
 }
 
 impl Config {
-    /// Load configuration from environment variables
-    pub fn from_env() -> Result<Self, String> {
+    /// Load configuration from the environment, `CONFIG_FILE`, and defaults
+    ///
+    /// Each setting comes from the first of: its environment variable
+    /// (including `.env`), the TOML file named by `CONFIG_FILE`, the built-in
+    /// default. File settings that nothing read are logged, to catch typos.
+    pub fn load() -> Result<Self, String> {
         // Load .env file if it exists (development)
         dotenvy::dotenv().ok();
 
-        let server_host = env::var("SERVER_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-        let server_port = env::var("SERVER_PORT")
+        let source = match env::var("CONFIG_FILE") {
+            Ok(path) if !path.is_empty() => Source::from_file(&path)?,
+            _ => Source::default(),
+        };
+        let config = Self::from_source(&source)?;
+        for key in source.unused() {
+            tracing::warn!(
+                "Setting '{}' in CONFIG_FILE was not used (unknown, or set in the environment)",
+                key
+            );
+        }
+
+        Ok(config)
+    }
+
+    /// Load configuration from environment variables and defaults only
+    pub fn from_env() -> Result<Self, String> {
+        dotenvy::dotenv().ok();
+        Self::from_source(&Source::default())
+    }
+
+    fn from_source(source: &Source) -> Result<Self, String> {
+        let server_host = source
+            .var("SERVER_HOST")
+            .unwrap_or_else(|_| "0.0.0.0".to_string());
+        let server_port = source
+            .var("SERVER_PORT")
             .unwrap_or_else(|_| "8080".to_string())
             .parse()
             .map_err(|_| "Invalid SERVER_PORT")?;
 
-        let database_path =
-            env::var("DATABASE_PATH").unwrap_or_else(|_| "./data/dailyreps.db".to_string());
+        let database_path = source
+            .var("DATABASE_PATH")
+            .unwrap_or_else(|_| "./data/dailyreps.db".to_string());
+
+        let db_restore_snapshot_dir = source
+            .var("DB_RESTORE_SNAPSHOT_DIR")
+            .ok()
+            .filter(|s| !s.is_empty());
 
-        let allowed_origins = env::var("ALLOWED_ORIGINS")
+        let snapshot_dir = source
+            .var("SNAPSHOT_DIR")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .or_else(|| db_restore_snapshot_dir.clone());
+
+        let allowed_origins = source
+            .var("ALLOWED_ORIGINS")
             .unwrap_or_else(|_| "http://localhost:5173".to_string())
             .split(',')
             .map(|s| s.trim().to_string())
             .collect();
 
-        let rate_limit_requests = env::var("RATE_LIMIT_REQUESTS")
+        let cors_max_age_secs = source
+            .var("CORS_MAX_AGE_SECS")
+            .unwrap_or_else(|_| "7200".to_string())
+            .parse()
+            .map_err(|_| "Invalid CORS_MAX_AGE_SECS")?;
+
+        let cors_allow_credentials = source
+            .var("CORS_ALLOW_CREDENTIALS")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+
+        let cors_expose_headers = source
+            .var("CORS_EXPOSE_HEADERS")
+            .unwrap_or_else(|_| "retry-after,x-request-id".to_string())
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        let response_compression = source
+            .var("RESPONSE_COMPRESSION")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(true);
+
+        let rate_limit_requests = source
+            .var("RATE_LIMIT_REQUESTS")
             .unwrap_or_else(|_| "100".to_string())
             .parse()
             .map_err(|_| "Invalid RATE_LIMIT_REQUESTS")?;
 
-        let rate_limit_window_secs = env::var("RATE_LIMIT_WINDOW_SECS")
+        let rate_limit_window_secs = source
+            .var("RATE_LIMIT_WINDOW_SECS")
             .unwrap_or_else(|_| "60".to_string())
             .parse()
             .map_err(|_| "Invalid RATE_LIMIT_WINDOW_SECS")?;
 
-        let register_rate_limit_requests = env::var("REGISTER_RATE_LIMIT_REQUESTS")
+        let register_rate_limit_requests = source
+            .var("REGISTER_RATE_LIMIT_REQUESTS")
             .unwrap_or_else(|_| "5".to_string())
             .parse()
             .map_err(|_| "Invalid REGISTER_RATE_LIMIT_REQUESTS")?;
 
-        let register_rate_limit_window_secs = env::var("REGISTER_RATE_LIMIT_WINDOW_SECS")
+        let register_rate_limit_window_secs = source
+            .var("REGISTER_RATE_LIMIT_WINDOW_SECS")
             .unwrap_or_else(|_| "300".to_string())
             .parse()
             .map_err(|_| "Invalid REGISTER_RATE_LIMIT_WINDOW_SECS")?;
 
-        let environment = env::var("ENVIRONMENT").unwrap_or_else(|_| "development".to_string());
+        let max_in_flight_per_user = source
+            .var("MAX_IN_FLIGHT_PER_USER")
+            .unwrap_or_else(|_| "2".to_string())
+            .parse()
+            .map_err(|_| "Invalid MAX_IN_FLIGHT_PER_USER")?;
+
+        let min_backup_interval_secs = source
+            .var("MIN_BACKUP_INTERVAL_SECS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .map_err(|_| "Invalid MIN_BACKUP_INTERVAL_SECS")?;
 
-        let app_secret_key = env::var("APP_SECRET_KEY")
-            .map_err(|_| "APP_SECRET_KEY must be set for HMAC verification")?;
+        let backup_versions_kept = source
+            .var("BACKUP_VERSIONS_KEPT")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse()
+            .map_err(|_| "Invalid BACKUP_VERSIONS_KEPT")?;
+
+        let deletion_grace_days = source
+            .var("DELETION_GRACE_DAYS")
+            .unwrap_or_else(|_| "7".to_string())
+            .parse()
+            .map_err(|_| "Invalid DELETION_GRACE_DAYS")?;
 
-        let admin_secret_key = env::var("ADMIN_SECRET_KEY").ok();
+        let inactive_purge_days = match source.var("INACTIVE_PURGE_DAYS") {
+            Ok(v) if !v.is_empty() && v != "0" => {
+                let days: u64 = v.parse().map_err(|_| "Invalid INACTIVE_PURGE_DAYS")?;
+                if days < MIN_INACTIVE_PURGE_DAYS {
+                    return Err(format!(
+                        "INACTIVE_PURGE_DAYS must be at least {}",
+                        MIN_INACTIVE_PURGE_DAYS
+                    ));
+                }
+                Some(days)
+            }
+            _ => None,
+        };
 
-        let log_requests = env::var("LOG_REQUESTS")
+        let compaction_interval_hours = match source.var("COMPACTION_INTERVAL_HOURS") {
+            Ok(v) if !v.is_empty() && v != "0" => {
+                Some(v.parse().map_err(|_| "Invalid COMPACTION_INTERVAL_HOURS")?)
+            }
+            _ => None,
+        };
+
+        let environment = source
+            .var("ENVIRONMENT")
+            .unwrap_or_else(|_| "development".to_string());
+
+        // APP_SECRET_KEYS lists the current secret first, then ones still
+        // accepted while clients move over; APP_SECRET_KEY alone still works
+        let app_secret_keys: Vec<String> = source
+            .var("APP_SECRET_KEYS")
+            .or_else(|_| source.var("APP_SECRET_KEY"))
+            .map_err(|_| "APP_SECRET_KEYS or APP_SECRET_KEY must be set for HMAC verification")?
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if app_secret_keys.is_empty() {
+            return Err("APP_SECRET_KEYS must contain at least one key".to_string());
+        }
+
+        let register_secret_key = source
+            .var("REGISTER_SECRET_KEY")
+            .ok()
+            .filter(|s| !s.is_empty());
+        let store_secret_key = source
+            .var("STORE_SECRET_KEY")
+            .ok()
+            .filter(|s| !s.is_empty());
+        let delete_secret_key = source
+            .var("DELETE_SECRET_KEY")
+            .ok()
+            .filter(|s| !s.is_empty());
+
+        // An empty key would let `Authorization: Bearer ` through, so treat
+        // it as unset rather than as a valid credential
+        let admin_secret_key = source
+            .var("ADMIN_SECRET_KEY")
+            .ok()
+            .filter(|k| !k.trim().is_empty());
+
+        let log_requests = source
+            .var("LOG_REQUESTS")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+
+        let maintenance_mode = source
+            .var("MAINTENANCE_MODE")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+
+        let statsd_addr = source.var("STATSD_ADDR").ok().filter(|s| !s.is_empty());
+
+        let statsd_prefix = source
+            .var("STATSD_PREFIX")
+            .unwrap_or_else(|_| "dailyreps".to_string());
+
+        let statsd_tags = source
+            .var("STATSD_TAGS")
+            .map(|v| {
+                v.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let http_keep_alive_secs = source
+            .var("HTTP_KEEP_ALIVE_SECS")
+            .unwrap_or_else(|_| "75".to_string())
+            .parse()
+            .map_err(|_| "Invalid HTTP_KEEP_ALIVE_SECS")?;
+
+        let max_connections = source
+            .var("MAX_CONNECTIONS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .map_err(|_| "Invalid MAX_CONNECTIONS")?;
+
+        let http2 = source
+            .var("HTTP2")
             .map(|v| v == "true" || v == "1")
             .unwrap_or(false);
 
+        let tls_cert_path = source.var("TLS_CERT_PATH").ok().filter(|s| !s.is_empty());
+        let tls_key_path = source.var("TLS_KEY_PATH").ok().filter(|s| !s.is_empty());
+        if tls_cert_path.is_some() != tls_key_path.is_some() {
+            return Err("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string());
+        }
+
+        let captcha_secret_key = source
+            .var("CAPTCHA_SECRET_KEY")
+            .ok()
+            .filter(|s| !s.is_empty());
+
+        let captcha_verify_url = source
+            .var("CAPTCHA_VERIFY_URL")
+            .unwrap_or_else(|_| crate::captcha::TURNSTILE_VERIFY_URL.to_string());
+
+        let oidc_issuer = source.var("OIDC_ISSUER").ok().filter(|s| !s.is_empty());
+        let oidc_audience = source.var("OIDC_AUDIENCE").ok().filter(|s| !s.is_empty());
+        if oidc_issuer.is_some() != oidc_audience.is_some() {
+            return Err("OIDC_ISSUER and OIDC_AUDIENCE must be set together".to_string());
+        }
+
+        let oidc_allowed_subjects = source
+            .var("OIDC_ALLOWED_SUBJECTS")
+            .map(|v| {
+                v.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        // Every subject the issuer signs for the audience would be an admin,
+        // so that has to be asked for with `*`
+        if oidc_issuer.is_some() && oidc_allowed_subjects.is_empty() {
+            return Err(
+                "OIDC_ALLOWED_SUBJECTS must be set with OIDC_ISSUER (* allows any subject)"
+                    .to_string(),
+            );
+        }
+
+        let alert_webhook_url = source
+            .var("ALERT_WEBHOOK_URL")
+            .ok()
+            .filter(|s| !s.is_empty());
+
+        let alert_webhook_kind = source
+            .var("ALERT_WEBHOOK_KIND")
+            .unwrap_or_else(|_| "slack".to_string())
+            .parse()?;
+
+        let geoip_db_path = source.var("GEOIP_DB_PATH").ok().filter(|s| !s.is_empty());
+
+        let client_ip_header = source
+            .var("CLIENT_IP_HEADER")
+            .ok()
+            .map(|s| s.trim().to_ascii_lowercase())
+            .filter(|s| !s.is_empty());
+
+        let trusted_proxies =
+            TrustedProxies::parse(&source.var("TRUSTED_PROXIES").unwrap_or_default())?;
+
+        let allowed_countries = parse_country_list(source, "ALLOWED_COUNTRIES")?;
+        let denied_countries = parse_country_list(source, "DENIED_COUNTRIES")?;
+        if (!allowed_countries.is_empty() || !denied_countries.is_empty())
+            && geoip_db_path.is_none()
+        {
+            return Err("ALLOWED_COUNTRIES/DENIED_COUNTRIES require GEOIP_DB_PATH".to_string());
+        }
+
+        let country_policy_registration_only = source
+            .var("COUNTRY_POLICY_REGISTRATION_ONLY")
+            .map(|v| v != "false" && v != "0")
+            .unwrap_or(true);
+
+        let registration_blocklist_urls = source
+            .var("REGISTRATION_BLOCKLIST_URLS")
+            .map(|v| {
+                v.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let blocklist_refresh_secs = source
+            .var("BLOCKLIST_REFRESH_SECS")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or("Invalid BLOCKLIST_REFRESH_SECS")?;
+
+        let replica_url = source
+            .var("REPLICA_URL")
+            .ok()
+            .map(|s| s.trim().trim_end_matches('/').to_string())
+            .filter(|s| !s.is_empty());
+        let replication_secret = source
+            .var("REPLICATION_SECRET")
+            .ok()
+            .filter(|s| !s.is_empty());
+        if replica_url.is_some() && replication_secret.is_none() {
+            return Err("REPLICA_URL requires REPLICATION_SECRET".to_string());
+        }
+
         Ok(Config {
             server_host,
             server_port,
+            http_keep_alive_secs,
+            max_connections,
+            http2,
+            tls_cert_path,
+            tls_key_path,
             database_path,
+            db_restore_snapshot_dir,
+            snapshot_dir,
             allowed_origins,
+            cors_max_age_secs,
+            cors_allow_credentials,
+            cors_expose_headers,
+            response_compression,
             rate_limit_requests,
             rate_limit_window_secs,
             register_rate_limit_requests,
             register_rate_limit_window_secs,
+            max_in_flight_per_user,
+            min_backup_interval_secs,
+            backup_versions_kept,
+            deletion_grace_days,
+            inactive_purge_days,
+            compaction_interval_hours,
             environment,
-            app_secret_key,
+            app_secret_keys,
+            register_secret_key,
+            store_secret_key,
+            delete_secret_key,
             admin_secret_key,
             log_requests,
+            maintenance_mode,
+            statsd_addr,
+            statsd_prefix,
+            statsd_tags,
+            captcha_secret_key,
+            captcha_verify_url,
+            oidc_issuer,
+            oidc_audience,
+            oidc_allowed_subjects,
+            alert_webhook_url,
+            alert_webhook_kind,
+            geoip_db_path,
+            client_ip_header,
+            trusted_proxies,
+            allowed_countries,
+            denied_countries,
+            country_policy_registration_only,
+            registration_blocklist_urls,
+            blocklist_refresh_secs,
+            replica_url,
+            replication_secret,
         })
     }
 
+    /// Current app secret, used for anything the server signs itself
+    pub fn app_secret_key(&self) -> &str {
+        &self.app_secret_keys[0]
+    }
+
+    /// HMAC secrets accepted for `scope`, falling back to every app secret
+    ///
+    /// Registration is only signed when `REGISTER_SECRET_KEY` is set, so it
+    /// has no fallback and is read from `register_secret_key` directly.
+    pub fn signing_secrets(&self, scope: SigningScope) -> Vec<&str> {
+        let specific = match scope {
+            SigningScope::Store => &self.store_secret_key,
+            SigningScope::Delete => &self.delete_secret_key,
+        };
+        match specific {
+            Some(secret) => vec![secret.as_str()],
+            None => self.app_secret_keys.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Whether any admin credential (static key or OIDC) is configured
+    pub fn admin_enabled(&self) -> bool {
+        self.admin_secret_key.is_some() || self.oidc_issuer.is_some()
+    }
+
+    /// This configuration with the settings that can change at runtime
+    /// taken from `new`, and the names of those that changed
+    ///
+    /// Reloadable: CORS origins, the per-network, registration and
+    /// backup-interval rate limits, and the admin key. Everything else
+    /// (listeners, database, secrets clients sign with, ...) needs a restart.
+    pub fn with_reloaded(&self, new: &Config) -> (Config, Vec<&'static str>) {
+        let mut merged = self.clone();
+        let mut changed = Vec::new();
+
+        macro_rules! reload {
+            ($($field:ident => $name:literal,)*) => {$(
+                if merged.$field != new.$field {
+                    merged.$field = new.$field.clone();
+                    changed.push($name);
+                }
+            )*};
+        }
+        reload! {
+            allowed_origins => "ALLOWED_ORIGINS",
+            rate_limit_requests => "RATE_LIMIT_REQUESTS",
+            rate_limit_window_secs => "RATE_LIMIT_WINDOW_SECS",
+            register_rate_limit_requests => "REGISTER_RATE_LIMIT_REQUESTS",
+            register_rate_limit_window_secs => "REGISTER_RATE_LIMIT_WINDOW_SECS",
+            min_backup_interval_secs => "MIN_BACKUP_INTERVAL_SECS",
+            admin_secret_key => "ADMIN_SECRET_KEY",
+        }
+
+        (merged, changed)
+    }
+
     /// Get server address as string
     pub fn server_address(&self) -> String {
         format!("{}:{}", self.server_host, self.server_port)
     }
 }
+
+/// Comma-separated ISO 3166-1 alpha-2 codes, normalized to uppercase
+fn parse_country_list(source: &Source, var: &str) -> Result<Vec<String>, String> {
+    let Ok(value) = source.var(var) else {
+        return Ok(Vec::new());
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|code| {
+            if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+                Ok(code.to_ascii_uppercase())
+            } else {
+                Err(format!("Invalid country code '{}' in {}", code, var))
+            }
+        })
+        .collect()
+}
+
+/// Where settings are read from: the environment, then the config file
+#[derive(Debug, Default)]
+struct Source {
+    /// Config file settings by environment variable name
+    file: HashMap<String, String>,
+    /// File settings that were looked up
+    used: RefCell<BTreeSet<String>>,
+}
+
+impl Source {
+    fn from_file(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read CONFIG_FILE {}: {}", path, e))?;
+        Self::from_toml(&contents).map_err(|e| format!("Invalid CONFIG_FILE {}: {}", path, e))
+    }
+
+    /// Flatten a TOML document into environment variable names
+    ///
+    /// Keys are the variable names in any case (`server_port = 8080`), tables
+    /// prefix their keys (`[rate_limit] requests = 100` is
+    /// `RATE_LIMIT_REQUESTS`), and arrays become comma-separated lists.
+    fn from_toml(contents: &str) -> Result<Self, String> {
+        let document: toml_edit::DocumentMut = contents.parse().map_err(|e| format!("{}", e))?;
+        let mut file = HashMap::new();
+        flatten_table(document.as_table(), "", &mut file)?;
+
+        Ok(Self {
+            file,
+            used: RefCell::default(),
+        })
+    }
+
+    /// A setting by environment variable name, the environment taking
+    /// precedence over the file
+    fn var(&self, name: &str) -> Result<String, env::VarError> {
+        env::var(name).or_else(|e| {
+            let value = self.file.get(name).cloned().ok_or(e)?;
+            self.used.borrow_mut().insert(name.to_string());
+            Ok(value)
+        })
+    }
+
+    /// File settings that were never looked up, in name order
+    fn unused(&self) -> Vec<String> {
+        let used = self.used.borrow();
+        let mut unused: Vec<String> = self
+            .file
+            .keys()
+            .filter(|key| !used.contains(*key))
+            .cloned()
+            .collect();
+        unused.sort();
+        unused
+    }
+}
+
+fn flatten_table(
+    table: &toml_edit::Table,
+    prefix: &str,
+    out: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    for (key, item) in table.iter() {
+        let name = format!("{}{}", prefix, key.to_ascii_uppercase());
+        match item {
+            toml_edit::Item::Table(table) => flatten_table(table, &format!("{}_", name), out)?,
+            toml_edit::Item::Value(toml_edit::Value::InlineTable(table)) => {
+                flatten_table(&table.clone().into_table(), &format!("{}_", name), out)?
+            }
+            toml_edit::Item::Value(toml_edit::Value::Array(array)) => {
+                let values = array
+                    .iter()
+                    .map(|value| {
+                        scalar(value)
+                            .ok_or_else(|| format!("'{}' must be a list of plain values", key))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                out.insert(name, values.join(","));
+            }
+            toml_edit::Item::Value(value) => {
+                let value =
+                    scalar(value).ok_or_else(|| format!("Unsupported value for '{}'", key))?;
+                out.insert(name, value);
+            }
+            toml_edit::Item::ArrayOfTables(_) => {
+                return Err(format!("'{}' can't be an array of tables", key));
+            }
+            toml_edit::Item::None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// A TOML value as the environment variable would spell it
+fn scalar(value: &toml_edit::Value) -> Option<String> {
+    match value {
+        toml_edit::Value::String(s) => Some(s.value().clone()),
+        toml_edit::Value::Integer(i) => Some(i.value().to_string()),
+        toml_edit::Value::Float(f) => Some(f.value().to_string()),
+        toml_edit::Value::Boolean(b) => Some(b.value().to_string()),
+        toml_edit::Value::Datetime(d) => Some(d.value().to_string()),
+        toml_edit::Value::Array(_) | toml_edit::Value::InlineTable(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_settings_flatten_to_env_names() {
+        let source = Source::from_toml(
+            r#"
+            server_port = 9000
+            ALLOWED_ORIGINS = ["https://a.example", "https://b.example"]
+            cors_allow_credentials = true
+
+            [rate_limit]
+            requests = 50
+            window_secs = 30
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(source.file["SERVER_PORT"], "9000");
+        assert_eq!(
+            source.file["ALLOWED_ORIGINS"],
+            "https://a.example,https://b.example"
+        );
+        assert_eq!(source.file["CORS_ALLOW_CREDENTIALS"], "true");
+        assert_eq!(source.file["RATE_LIMIT_REQUESTS"], "50");
+        assert_eq!(source.file["RATE_LIMIT_WINDOW_SECS"], "30");
+    }
+
+    #[test]
+    fn test_config_from_toml_file() {
+        let source = Source::from_toml(
+            r#"
+            app_secret_keys = ["file-secret-new", "file-secret-old"]
+            rate_limit = { requests = 7 }
+            denied_countries = ["kp"]
+            geoip_db_path = "/data/GeoLite2-Country.mmdb"
+            rate_limt_window_secs = 5
+            "#,
+        )
+        .unwrap();
+        let config = Config::from_source(&source).unwrap();
+
+        assert_eq!(
+            config.app_secret_keys,
+            ["file-secret-new", "file-secret-old"]
+        );
+        assert_eq!(config.rate_limit_requests, 7);
+        assert_eq!(config.denied_countries, ["KP"]);
+        // Unset settings keep their defaults
+        assert_eq!(config.rate_limit_window_secs, 60);
+        // The misspelled key is reported
+        assert_eq!(source.unused(), ["RATE_LIMT_WINDOW_SECS"]);
+    }
+
+    #[test]
+    fn test_oidc_requires_allowed_subjects() {
+        let config = |extra: &str| {
+            let toml = format!(
+                "app_secret_key = \"secret\"\noidc_issuer = \"https://idp.example\"\noidc_audience = \"admin\"\n{}",
+                extra
+            );
+            Config::from_source(&Source::from_toml(&toml).unwrap())
+        };
+
+        assert!(config("").is_err());
+        assert!(config("oidc_allowed_subjects = \" , \"").is_err());
+        assert_eq!(
+            config("oidc_allowed_subjects = [\"alice\"]")
+                .unwrap()
+                .oidc_allowed_subjects,
+            ["alice"]
+        );
+        assert_eq!(
+            config("oidc_allowed_subjects = \"*\"")
+                .unwrap()
+                .oidc_allowed_subjects,
+            ["*"]
+        );
+    }
+
+    #[test]
+    fn test_blank_admin_secret_key_is_unset() {
+        let config = |key: &str| {
+            let toml = format!(
+                "app_secret_key = \"secret\"\nadmin_secret_key = \"{}\"",
+                key
+            );
+            Config::from_source(&Source::from_toml(&toml).unwrap()).unwrap()
+        };
+
+        assert_eq!(config("").admin_secret_key, None);
+        assert_eq!(config("  ").admin_secret_key, None);
+        assert!(!config("").admin_enabled());
+        assert_eq!(config("admin").admin_secret_key.as_deref(), Some("admin"));
+    }
+
+    #[test]
+    fn test_invalid_toml_is_rejected() {
+        assert!(Source::from_toml("server_port = ").is_err());
+        assert!(Source::from_toml("origins = [[\"nested\"]]").is_err());
+        assert!(Source::from_toml("[[servers]]\nport = 1").is_err());
+    }
+}
@@ -3,16 +3,110 @@
 /// This allows 16x headroom for growth
 pub const MAX_BACKUP_SIZE_BYTES: usize = 5_242_880;
 
+/// Largest client request body: a maximum-size backup plus its JSON envelope
+/// (IDs, signature, nonce, stats)
+pub const MAX_REQUEST_BODY_BYTES: usize = MAX_BACKUP_SIZE_BYTES + 64 * 1024;
+
 /// Warning threshold for large backups (1MB)
 /// Log when backups exceed this size for monitoring
 pub const WARN_BACKUP_SIZE_BYTES: usize = 1_048_576;
 
+/// Store responses warn once usage reaches this share of a limit (80%)
+pub const LIMIT_WARNING_PERCENT: u64 = 80;
+
 /// Maximum backup updates per hour per user
 pub const MAX_BACKUPS_PER_HOUR: i32 = 5;
 
 /// Maximum backup updates per day per user
 pub const MAX_BACKUPS_PER_DAY: i32 = 20;
 
+/// Identical re-uploads of a user's last blob allowed per day; further
+/// copies get 429 and count towards the abuse report
+pub const DUPLICATE_UPLOADS_PER_DAY: u32 = 10;
+
+/// Storage keys accepted by one `POST /api/backup/delete`
+pub const MAX_BULK_DELETE_KEYS: usize = 50;
+
+/// How long a chunked upload may stay open before it is discarded (1 hour)
+pub const UPLOAD_SESSION_TTL_SECS: i64 = 3600;
+
+/// Unfinished chunked uploads allowed per user
+pub const MAX_UPLOAD_SESSIONS_PER_USER: usize = 2;
+
+/// Open `/api/sync/ws` and `/api/sync/events` connections allowed per user
+pub const MAX_SYNC_SUBSCRIPTIONS_PER_USER: usize = 4;
+
+/// How often expired upload sessions are swept from the database (10 minutes)
+pub const UPLOAD_CLEANUP_INTERVAL_SECS: u64 = 600;
+
+/// `Retry-After` for a request refused by `MAX_IN_FLIGHT_PER_USER`; the
+/// upload it waits on normally finishes within a second or two
+pub const IN_FLIGHT_RETRY_AFTER_SECS: u64 = 2;
+
+/// Largest chunk accepted by `PUT /api/backup/chunks/{uploadId}` (1MB)
+pub const MAX_UPLOAD_CHUNK_BYTES: usize = 1_048_576;
+
+/// Smaller responses are sent uncompressed even when the client accepts it
+pub const COMPRESSION_MIN_BYTES: u16 = 1024;
+
+/// Largest replication request accepted: an account's backups with their
+/// versions, base64-encoded
+pub const MAX_REPLICATION_BODY_BYTES: usize = 64 * MAX_BACKUP_SIZE_BYTES;
+
+/// How often tombstones past their grace period are purged (1 hour)
+pub const TOMBSTONE_PURGE_INTERVAL_SECS: u64 = 3600;
+
+/// Accepted length of a signed request's `nonce`
+pub const MIN_NONCE_LEN: usize = 16;
+pub const MAX_NONCE_LEN: usize = 64;
+
+/// How often nonces whose timestamp window has passed are swept (5 minutes)
+pub const NONCE_CLEANUP_INTERVAL_SECS: u64 = 300;
+
+/// How long a rate-limit record is kept after its daily window ended (1 day)
+///
+/// A record past its windows behaves exactly like a missing one, so it only
+/// costs space; the margin keeps records that are still being read.
+pub const RATE_LIMIT_RETENTION_SECS: i64 = 86_400;
+
+/// How often expired rate-limit records are swept (1 hour)
+pub const RATE_LIMIT_CLEANUP_INTERVAL_SECS: u64 = 3600;
+
+/// How often ended per-network and duplicate-upload windows are dropped
+/// from memory (1 minute)
+pub const IN_MEMORY_PRUNE_INTERVAL_SECS: u64 = 60;
+
+/// How often registration attempt records whose window ended are swept
+/// (10 minutes)
+pub const REGISTRATION_ATTEMPT_CLEANUP_INTERVAL_SECS: u64 = 600;
+
+/// How often TLS_CERT_PATH / TLS_KEY_PATH are checked for a renewed
+/// certificate (1 minute)
+pub const TLS_RELOAD_INTERVAL_SECS: u64 = 60;
+
+/// How long a client may take to complete the TLS handshake (10 seconds)
+pub const TLS_HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+
+/// Shortest inactivity the account purge accepts, so a typo can't wipe
+/// active users
+pub const MIN_INACTIVE_PURGE_DAYS: u64 = 30;
+
+/// How often the inactive-account purge runs when enabled (1 day)
+pub const INACTIVE_PURGE_INTERVAL_SECS: u64 = 86_400;
+
+/// How long rejected-request events are kept for the abuse report
+pub const SECURITY_EVENT_RETENTION_DAYS: i64 = 30;
+
+/// Rejected-request events held in memory between flushes; further events
+/// are dropped (and counted) until the next flush
+pub const MAX_BUFFERED_SECURITY_EVENTS: usize = 1024;
+
+/// How often buffered rejected-request events are written in one batch
+pub const SECURITY_EVENT_FLUSH_INTERVAL_SECS: u64 = 5;
+
+/// How long an admin-authorized rekey stays usable (24 hours)
+pub const RECOVERY_GRANT_SECS: i64 = 86_400;
+
 /// Maximum age of timestamp in seconds (5 minutes)
 /// Prevents replay attacks
 pub const MAX_TIMESTAMP_AGE_SECS: i64 = 300;
@@ -27,9 +121,22 @@ pub const ERR_INVALID_USER_ID: &str = "Invalid user ID format";
 /// Error message for invalid storage key format
 pub const ERR_INVALID_STORAGE_KEY: &str = "Invalid storage key format";
 
+/// Error message for a malformed backup checksum
+pub const ERR_INVALID_CHECKSUM: &str = "Checksum must be a SHA-256 hash (64 hex characters)";
+
+/// Error message for a sync token that is not a version number
+pub const ERR_INVALID_SYNC_TOKEN: &str = "Invalid sync token";
+
+/// Error message for a malformed recovery contact hash
+pub const ERR_INVALID_RECOVERY_HASH: &str =
+    "Recovery hash must be a SHA-256 hash (64 hex characters)";
+
 /// Error message for timestamp validation failure
 pub const ERR_INVALID_TIMESTAMP: &str = "Timestamp too old or in the future";
 
+/// Error message for a malformed request nonce
+pub const ERR_INVALID_NONCE: &str = "Nonce must be 16-64 characters of letters, digits, - or _";
+
 /// Detailed error message for user ID validation in registration
 pub const ERR_USER_ID_MUST_BE_SHA256: &str =
     "User ID must be a valid SHA-256 hash (64 hex characters)";
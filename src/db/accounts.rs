@@ -0,0 +1,225 @@
+//! Removing whole accounts
+//!
+//! Shared by `DELETE /api/user` and the inactive-account purge, so both
+//! remove exactly the same records and honor `DELETION_GRACE_DAYS` the same
+//! way.
+
+use redb::{ReadableTable, WriteTransaction};
+
+use super::{tables, tombstones, versions};
+use crate::Result;
+use crate::models::{BackupRecord, UserRecord};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+/// What an inactive-account purge removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PurgeSummary {
+    pub user_ids: Vec<String>,
+    pub backups: usize,
+    /// Backup data removed, as in the stats export
+    pub bytes: u64,
+}
+
+/// Delete `user_id` and everything it owns, returning its backup keys
+///
+/// With `bury_until` a tombstone restorable until then is kept first. The
+/// caller checks the user exists.
+#[allow(clippy::result_large_err)]
+pub fn remove(
+    write_txn: &WriteTransaction,
+    user_id: &str,
+    now: i64,
+    bury_until: Option<i64>,
+) -> Result<Vec<String>> {
+    let backup_keys = backup_keys(write_txn, user_id)?;
+
+    // Keep a restorable copy during the grace period
+    if let Some(purge_at) = bury_until {
+        tombstones::bury(write_txn, user_id, &backup_keys, now, purge_at)?;
+    }
+
+    remove_records(write_txn, user_id, &backup_keys)?;
+    super::bump_daily_stats(write_txn, now, |s| s.deletions += 1)?;
+
+    Ok(backup_keys)
+}
+
+/// Remove every record of `user_id`, including its tombstone, without
+/// counting a deletion
+///
+/// Used where the account's state is about to be replaced wholesale, as on a
+/// replication standby.
+#[allow(clippy::result_large_err)]
+pub fn erase(write_txn: &WriteTransaction, user_id: &str) -> Result<()> {
+    let backup_keys = backup_keys(write_txn, user_id)?;
+    remove_records(write_txn, user_id, &backup_keys)?;
+    write_txn.open_table(tables::TOMBSTONES)?.remove(user_id)?;
+
+    Ok(())
+}
+
+/// The user's records and those of `backup_keys`
+#[allow(clippy::result_large_err)]
+fn remove_records(
+    write_txn: &WriteTransaction,
+    user_id: &str,
+    backup_keys: &[String],
+) -> Result<()> {
+    let mut backups = write_txn.open_table(tables::BACKUPS)?;
+    let mut sync_tokens = write_txn.open_table(tables::SYNC_TOKENS)?;
+    for key in backup_keys {
+        backups.remove(key.as_str())?;
+        sync_tokens.remove(key.as_str())?;
+        versions::remove_all(write_txn, key)?;
+    }
+    drop((backups, sync_tokens));
+
+    for table in [
+        tables::RATE_LIMITS,
+        tables::USER_BACKUPS,
+        tables::RECOVERY_GRANTS,
+        tables::USERS,
+    ] {
+        write_txn.open_table(table)?.remove(user_id)?;
+    }
+    write_txn
+        .open_table(tables::RECOVERY_CONTACTS)?
+        .remove(user_id)?;
+
+    Ok(())
+}
+
+/// Remove every account with no activity since `cutoff`
+///
+/// Activity is the newest backup update, or registration for an account that
+/// never stored one. Accounts are removed as by [`remove`].
+#[allow(clippy::result_large_err)]
+pub fn purge_inactive(
+    write_txn: &WriteTransaction,
+    cutoff: i64,
+    now: i64,
+    bury_until: Option<i64>,
+) -> Result<PurgeSummary> {
+    let mut inactive = Vec::new();
+    {
+        let users = write_txn.open_table(tables::USERS)?;
+        let backups = write_txn.open_table(tables::BACKUPS)?;
+        for entry in users.iter()? {
+            let (user_id, bytes) = entry?;
+            let (user, _): (UserRecord, _) =
+                bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+
+            let mut last_active = user.created_at;
+            let mut size = (0, 0);
+            for key in backup_keys(write_txn, user_id.value())? {
+                if let Some(bytes) = backups.get(key.as_str())? {
+                    let (backup, _): (BackupRecord, _) =
+                        bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                    last_active = last_active.max(backup.updated_at);
+                    size.0 += 1;
+                    size.1 += backup.encrypted_data.len() as u64;
+                }
+            }
+            if last_active < cutoff {
+                inactive.push((user_id.value().to_string(), size));
+            }
+        }
+    }
+
+    let mut summary = PurgeSummary::default();
+    for (user_id, (backups, bytes)) in inactive {
+        remove(write_txn, &user_id, now, bury_until)?;
+        summary.user_ids.push(user_id);
+        summary.backups += backups;
+        summary.bytes += bytes;
+    }
+
+    Ok(summary)
+}
+
+/// Storage keys indexed under `user_id`
+#[allow(clippy::result_large_err)]
+fn backup_keys(write_txn: &WriteTransaction, user_id: &str) -> Result<Vec<String>> {
+    Ok(write_txn
+        .open_table(tables::USER_BACKUPS)?
+        .get(user_id)?
+        .and_then(|b| {
+            bincode::serde::decode_from_slice::<Vec<String>, _>(b.value(), BINCODE_CONFIG)
+                .ok()
+                .map(|(v, _)| v)
+        })
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+
+    fn add_user(
+        write_txn: &WriteTransaction,
+        user_id: &str,
+        created_at: i64,
+        backup_at: Option<i64>,
+    ) {
+        let user =
+            bincode::serde::encode_to_vec(UserRecord { created_at }, BINCODE_CONFIG).unwrap();
+        write_txn
+            .open_table(tables::USERS)
+            .unwrap()
+            .insert(user_id, user.as_slice())
+            .unwrap();
+        let Some(updated_at) = backup_at else {
+            return;
+        };
+
+        let key = format!("{}-key", user_id);
+        let record = BackupRecord::new(user_id.to_string(), "x".repeat(10), created_at, updated_at);
+        let bytes = bincode::serde::encode_to_vec(&record, BINCODE_CONFIG).unwrap();
+        write_txn
+            .open_table(tables::BACKUPS)
+            .unwrap()
+            .insert(key.as_str(), bytes.as_slice())
+            .unwrap();
+        let keys = bincode::serde::encode_to_vec(vec![key], BINCODE_CONFIG).unwrap();
+        write_txn
+            .open_table(tables::USER_BACKUPS)
+            .unwrap()
+            .insert(user_id, keys.as_slice())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_purge_inactive() {
+        let db = open_in_memory_database().unwrap();
+        let write_txn = db.begin_write().unwrap();
+        add_user(&write_txn, "stale", 10, Some(20));
+        add_user(&write_txn, "active", 10, Some(200));
+        add_user(&write_txn, "never-stored", 10, None);
+        add_user(&write_txn, "new", 150, None);
+
+        let summary = purge_inactive(&write_txn, 100, 300, None).unwrap();
+        assert_eq!(summary.user_ids, ["never-stored", "stale"]);
+        assert_eq!(summary.backups, 1);
+        assert_eq!(summary.bytes, 10);
+
+        let users = write_txn.open_table(tables::USERS).unwrap();
+        assert!(users.get("stale").unwrap().is_none());
+        assert!(users.get("active").unwrap().is_some());
+        assert!(users.get("new").unwrap().is_some());
+        let backups = write_txn.open_table(tables::BACKUPS).unwrap();
+        assert!(backups.get("stale-key").unwrap().is_none());
+        assert!(backups.get("active-key").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_purge_keeps_tombstone_during_grace() {
+        let db = open_in_memory_database().unwrap();
+        let write_txn = db.begin_write().unwrap();
+        add_user(&write_txn, "stale", 10, Some(20));
+
+        purge_inactive(&write_txn, 100, 300, Some(1000)).unwrap();
+        assert!(tombstones::exists(&write_txn, "stale").unwrap());
+    }
+}
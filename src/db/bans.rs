@@ -0,0 +1,151 @@
+//! Admin bans on users and client networks
+//!
+//! Keys are `user:<user_id>` or `ip:<network>`, where the network is what
+//! [`client_network`] groups a client into, so banning one IPv6 address
+//! bans its whole /64.
+
+use redb::{ReadTransaction, ReadableTable, WriteTransaction};
+use std::net::IpAddr;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use super::tables;
+use crate::Result;
+use crate::models::BanRecord;
+use crate::security::client_network;
+
+const USER_PREFIX: &str = "user:";
+const IP_PREFIX: &str = "ip:";
+
+/// What a ban applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BanTarget {
+    User(String),
+    /// A client network, as returned by [`client_network`]
+    Network(String),
+}
+
+impl BanTarget {
+    pub fn ip(ip: IpAddr) -> Self {
+        BanTarget::Network(client_network(ip))
+    }
+
+    fn key(&self) -> String {
+        match self {
+            BanTarget::User(user_id) => format!("{USER_PREFIX}{user_id}"),
+            BanTarget::Network(network) => format!("{IP_PREFIX}{network}"),
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        if let Some(user_id) = key.strip_prefix(USER_PREFIX) {
+            Some(BanTarget::User(user_id.to_string()))
+        } else {
+            key.strip_prefix(IP_PREFIX)
+                .map(|network| BanTarget::Network(network.to_string()))
+        }
+    }
+}
+
+/// Add or replace the ban on `target`
+#[allow(clippy::result_large_err)]
+pub fn put(write_txn: &WriteTransaction, target: &BanTarget, record: &BanRecord) -> Result<()> {
+    let bytes = bincode::serde::encode_to_vec(record, BINCODE_CONFIG)?;
+    let mut bans = write_txn.open_table(tables::BANS)?;
+    bans.insert(target.key().as_str(), bytes.as_slice())?;
+
+    Ok(())
+}
+
+/// Lift the ban on `target`, returning whether there was one
+#[allow(clippy::result_large_err)]
+pub fn remove(write_txn: &WriteTransaction, target: &BanTarget) -> Result<bool> {
+    let mut bans = write_txn.open_table(tables::BANS)?;
+    let removed = bans.remove(target.key().as_str())?.is_some();
+
+    Ok(removed)
+}
+
+/// Every ban, in key order
+#[allow(clippy::result_large_err)]
+pub fn list(read_txn: &ReadTransaction) -> Result<Vec<(BanTarget, BanRecord)>> {
+    let bans = read_txn.open_table(tables::BANS)?;
+    let mut out = Vec::new();
+    for entry in bans.iter()? {
+        let (key, bytes) = entry?;
+        let Some(target) = BanTarget::from_key(key.value()) else {
+            continue;
+        };
+        let (record, _) = bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+        out.push((target, record));
+    }
+
+    Ok(out)
+}
+
+/// Whether the caller is banned, either as `user_id` or by `ip`'s network
+#[allow(clippy::result_large_err)]
+pub fn is_banned(
+    read_txn: &ReadTransaction,
+    user_id: Option<&str>,
+    ip: Option<IpAddr>,
+) -> Result<bool> {
+    let bans = read_txn.open_table(tables::BANS)?;
+    let targets = user_id
+        .map(|id| BanTarget::User(id.to_string()))
+        .into_iter()
+        .chain(ip.map(BanTarget::ip));
+    for target in targets {
+        if bans.get(target.key().as_str())?.is_some() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+    use redb::ReadableDatabase;
+
+    fn record() -> BanRecord {
+        BanRecord {
+            banned_at: 0,
+            banned_by: "test".to_string(),
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn test_ban_user_and_network() {
+        let db = open_in_memory_database().unwrap();
+        let user = "a".repeat(64);
+        let v6: IpAddr = "2001:db8:1:2::5".parse().unwrap();
+
+        let write_txn = db.begin_write().unwrap();
+        put(&write_txn, &BanTarget::User(user.clone()), &record()).unwrap();
+        put(&write_txn, &BanTarget::ip(v6), &record()).unwrap();
+        write_txn.commit().unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        assert!(is_banned(&read_txn, Some(&user), None).unwrap());
+        assert!(!is_banned(&read_txn, Some(&"b".repeat(64)), None).unwrap());
+        // The ban covers the whole /64
+        let neighbour: IpAddr = "2001:db8:1:2::9".parse().unwrap();
+        assert!(is_banned(&read_txn, None, Some(neighbour)).unwrap());
+        let elsewhere: IpAddr = "2001:db8:1:3::5".parse().unwrap();
+        assert!(!is_banned(&read_txn, None, Some(elsewhere)).unwrap());
+        assert_eq!(list(&read_txn).unwrap().len(), 2);
+        drop(read_txn);
+
+        let write_txn = db.begin_write().unwrap();
+        assert!(remove(&write_txn, &BanTarget::User(user.clone())).unwrap());
+        assert!(!remove(&write_txn, &BanTarget::User(user.clone())).unwrap());
+        write_txn.commit().unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        assert!(!is_banned(&read_txn, Some(&user), None).unwrap());
+    }
+}
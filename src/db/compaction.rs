@@ -0,0 +1,128 @@
+//! Shrinking the database file
+//!
+//! redb reuses freed pages but never hands them back to the filesystem, so
+//! the file stays as large as it ever was after accounts are deleted.
+//! Compaction moves live pages down and truncates the file. It needs the
+//! database to itself: new transactions wait while it runs, and it gives up
+//! if one is still open.
+
+use redb::CompactionError;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use super::Db;
+use crate::{AppError, AppState, Result};
+
+/// File sizes around a compaction
+#[derive(Debug, Clone, Copy)]
+pub struct CompactionReport {
+    pub before_bytes: u64,
+    pub after_bytes: u64,
+}
+
+/// Compact the database whose file is at `path` (used only to report sizes)
+///
+/// Fails with [`AppError::DatabaseBusy`] if a transaction is open or another
+/// handle to the `Database` exists outside `db`.
+#[allow(clippy::result_large_err)]
+pub fn compact(db: &Db, path: &Path) -> Result<CompactionReport> {
+    let file_size = || std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let before_bytes = file_size();
+
+    {
+        let mut guard = db.0.write().unwrap_or_else(|e| e.into_inner());
+        let database = Arc::get_mut(&mut guard).ok_or(AppError::DatabaseBusy)?;
+        match database.compact() {
+            Ok(_) => {}
+            Err(CompactionError::TransactionInProgress) => return Err(AppError::DatabaseBusy),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(CompactionReport {
+        before_bytes,
+        after_bytes: file_size(),
+    })
+}
+
+/// Compact every `interval`, logging how much was reclaimed
+pub fn spawn(state: AppState, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick is immediate; don't compact while the server starts
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let db = state.db.clone();
+            let path = state.config().database_path.clone();
+            match state.spawn_db(move || compact(&db, path.as_ref())).await {
+                Ok(Ok(report)) => tracing::info!(
+                    "Database compacted: {} -> {} bytes",
+                    report.before_bytes,
+                    report.after_bytes
+                ),
+                Ok(Err(e)) => tracing::warn!("Scheduled compaction failed: {}", e),
+                Err(e) => tracing::warn!("Scheduled compaction failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tables;
+    use crate::open_database;
+    use redb::ReadableDatabase;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_compaction_shrinks_file_after_deletes() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("compact.db");
+        let db = open_database(&path).unwrap();
+
+        let payload = vec![7u8; 64 * 1024];
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut users = write_txn.open_table(tables::USERS).unwrap();
+            for i in 0..200 {
+                users
+                    .insert(format!("{:064}", i).as_str(), payload.as_slice())
+                    .unwrap();
+            }
+        }
+        write_txn.commit().unwrap();
+
+        let write_txn = db.begin_write().unwrap();
+        write_txn
+            .open_table(tables::USERS)
+            .unwrap()
+            .retain(|_, _| false)
+            .unwrap();
+        write_txn.commit().unwrap();
+
+        let report = compact(&db, &path).unwrap();
+        assert!(report.after_bytes < report.before_bytes);
+
+        // Still usable afterwards
+        let read_txn = db.begin_read().unwrap();
+        assert!(read_txn.open_table(tables::USERS).is_ok());
+    }
+
+    #[test]
+    fn test_open_transaction_makes_it_busy() {
+        let db = crate::open_in_memory_database().unwrap();
+        let read_txn = db.begin_read().unwrap();
+
+        let err = compact(&db, Path::new("/nonexistent")).unwrap_err();
+        assert!(matches!(err, AppError::DatabaseBusy));
+
+        drop(read_txn);
+        assert!(compact(&db, Path::new("/nonexistent")).is_ok());
+    }
+}
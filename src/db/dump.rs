@@ -0,0 +1,587 @@
+//! Portable export and import of the whole database
+//!
+//! A dump is JSON Lines, so neither side has to hold the database in memory.
+//! The first line is a header:
+//!
+//! ```json
+//! {"format":"dailyreps-dump","version":1,"format_version":5,"exported_at":"2025-12-09T12:34:56Z"}
+//! ```
+//!
+//! Every other line is one table entry, grouped by table:
+//!
+//! ```json
+//! {"table":"users","key":"<user_id>","value":"<base64>"}
+//! ```
+//!
+//! Keys are JSON strings or numbers, and composite keys are arrays
+//! (`["<storage_key>", 2]`). Integer and string values are written as they
+//! are. Byte values are base64 of the stored bytes, which for record tables
+//! is the record's bincode (standard configuration) encoding at the header's
+//! `format_version`. Nothing depends on the redb file layout, so a dump moves
+//! between hosts and redb versions; an import of an older `format_version`
+//! is migrated the next time the database is opened.
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::{DateTime, Utc};
+use redb::{
+    Key, ReadTransaction, ReadableDatabase, ReadableTable, ReadableTableMetadata, TableDefinition,
+    TableHandle, Value, WriteTransaction,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::iter::Peekable;
+
+use super::migrations::{self, FORMAT_VERSION};
+use super::{Db, accounts, tables};
+use crate::error::{AppError, Result};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+/// `format` of the header line
+pub const DUMP_FORMAT: &str = "dailyreps-dump";
+
+/// Version of the dump layout described above
+pub const DUMP_VERSION: u32 = 1;
+
+/// First line of a dump
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DumpHeader {
+    pub format: String,
+    pub version: u32,
+    pub format_version: u64,
+    pub exported_at: DateTime<Utc>,
+}
+
+/// One table entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DumpEntry {
+    pub table: String,
+    pub key: Json,
+    pub value: Json,
+}
+
+/// Entries written or read, per table
+#[derive(Debug, Default)]
+pub struct DumpSummary {
+    pub tables: BTreeMap<String, u64>,
+}
+
+impl DumpSummary {
+    pub fn entries(&self) -> u64 {
+        self.tables.values().sum()
+    }
+}
+
+/// A key or value type that can be written to and read from a dump
+trait DumpField: Value + 'static {
+    type Owned;
+
+    fn to_json(value: Self::SelfType<'_>) -> Json;
+    fn from_json(json: &Json) -> Option<Self::Owned>;
+    fn borrow(owned: &Self::Owned) -> Self::SelfType<'_>;
+}
+
+impl DumpField for &'static str {
+    type Owned = String;
+
+    fn to_json(value: &str) -> Json {
+        Json::from(value)
+    }
+
+    fn from_json(json: &Json) -> Option<String> {
+        json.as_str().map(str::to_string)
+    }
+
+    fn borrow(owned: &String) -> &str {
+        owned
+    }
+}
+
+impl DumpField for &'static [u8] {
+    type Owned = Vec<u8>;
+
+    fn to_json(value: &[u8]) -> Json {
+        Json::from(BASE64_STANDARD.encode(value))
+    }
+
+    fn from_json(json: &Json) -> Option<Vec<u8>> {
+        BASE64_STANDARD.decode(json.as_str()?).ok()
+    }
+
+    fn borrow(owned: &Vec<u8>) -> &[u8] {
+        owned
+    }
+}
+
+impl DumpField for u64 {
+    type Owned = u64;
+
+    fn to_json(value: u64) -> Json {
+        Json::from(value)
+    }
+
+    fn from_json(json: &Json) -> Option<u64> {
+        json.as_u64()
+    }
+
+    fn borrow(owned: &u64) -> u64 {
+        *owned
+    }
+}
+
+impl DumpField for i64 {
+    type Owned = i64;
+
+    fn to_json(value: i64) -> Json {
+        Json::from(value)
+    }
+
+    fn from_json(json: &Json) -> Option<i64> {
+        json.as_i64()
+    }
+
+    fn borrow(owned: &i64) -> i64 {
+        *owned
+    }
+}
+
+impl DumpField for (&'static str, u64) {
+    type Owned = (String, u64);
+
+    fn to_json((name, n): (&str, u64)) -> Json {
+        Json::from(vec![Json::from(name), Json::from(n)])
+    }
+
+    fn from_json(json: &Json) -> Option<(String, u64)> {
+        match json.as_array()?.as_slice() {
+            [name, n] => Some((name.as_str()?.to_string(), n.as_u64()?)),
+            _ => None,
+        }
+    }
+
+    fn borrow((name, n): &(String, u64)) -> (&str, u64) {
+        (name, *n)
+    }
+}
+
+impl DumpField for (&'static str, u32) {
+    type Owned = (String, u32);
+
+    fn to_json((name, n): (&str, u32)) -> Json {
+        Json::from(vec![Json::from(name), Json::from(n)])
+    }
+
+    fn from_json(json: &Json) -> Option<(String, u32)> {
+        match json.as_array()?.as_slice() {
+            [name, n] => Some((name.as_str()?.to_string(), n.as_u64()?.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    fn borrow((name, n): &(String, u32)) -> (&str, u32) {
+        (name, *n)
+    }
+}
+
+/// Every table, in dump order
+macro_rules! dump_tables {
+    ($($table:ident),* $(,)?) => {
+        fn export_tables(
+            read_txn: &ReadTransaction,
+            out: &mut impl Write,
+            summary: &mut DumpSummary,
+        ) -> Result<()> {
+            $(export_table(read_txn, tables::$table, out, summary)?;)*
+            Ok(())
+        }
+
+        fn first_nonempty_table(write_txn: &WriteTransaction) -> Result<Option<String>> {
+            $(
+                if !write_txn.open_table(tables::$table)?.is_empty()? {
+                    return Ok(Some(tables::$table.name().to_string()));
+                }
+            )*
+            Ok(None)
+        }
+
+        fn import_table(
+            write_txn: &WriteTransaction,
+            name: &str,
+            lines: &mut Peekable<impl Iterator<Item = Result<DumpEntry>>>,
+            summary: &mut DumpSummary,
+        ) -> Result<()> {
+            $(
+                if name == tables::$table.name() {
+                    return import_entries(write_txn, tables::$table, lines, summary);
+                }
+            )*
+            Err(AppError::Dump(format!("unknown table '{}'", name)))
+        }
+    };
+}
+
+// META is left out: its only entry, the format version, is in the header
+dump_tables!(
+    USERS,
+    BACKUPS,
+    BACKUP_VERSIONS,
+    TOMBSTONES,
+    BANS,
+    NONCES,
+    UPLOAD_SESSIONS,
+    UPLOAD_CHUNKS,
+    RATE_LIMITS,
+    USER_BACKUPS,
+    SYNC_TOKENS,
+    SECURITY_EVENTS,
+    DAILY_STATS,
+    RECOVERY_CONTACTS,
+    RECOVERY_GRANTS,
+    REGISTRATION_ATTEMPTS,
+);
+
+fn write_line(out: &mut impl Write, line: &impl Serialize) -> Result<()> {
+    serde_json::to_writer(&mut *out, line).map_err(|e| AppError::Dump(e.to_string()))?;
+    out.write_all(b"\n")
+        .map_err(|e| AppError::Dump(e.to_string()))
+}
+
+fn export_table<K: Key + DumpField, V: DumpField>(
+    read_txn: &ReadTransaction,
+    definition: TableDefinition<K, V>,
+    out: &mut impl Write,
+    summary: &mut DumpSummary,
+) -> Result<()> {
+    let table = read_txn.open_table(definition)?;
+    let mut count = 0;
+    for entry in table.iter()? {
+        let (key, value) = entry?;
+        write_line(
+            out,
+            &DumpEntry {
+                table: definition.name().to_string(),
+                key: K::to_json(key.value()),
+                value: V::to_json(value.value()),
+            },
+        )?;
+        count += 1;
+    }
+    summary.tables.insert(definition.name().to_string(), count);
+
+    Ok(())
+}
+
+/// Write every table of `db` to `out`, as of a single read transaction
+#[allow(clippy::result_large_err)]
+pub fn export(db: &Db, mut out: impl Write, now: DateTime<Utc>) -> Result<DumpSummary> {
+    let read_txn = db.begin_read()?;
+    let format_version = read_txn
+        .open_table(tables::META)?
+        .get(migrations::FORMAT_VERSION_KEY)?
+        .map(|v| v.value())
+        .unwrap_or(migrations::UNVERSIONED);
+
+    write_line(
+        &mut out,
+        &DumpHeader {
+            format: DUMP_FORMAT.to_string(),
+            version: DUMP_VERSION,
+            format_version,
+            exported_at: now,
+        },
+    )?;
+
+    let mut summary = DumpSummary::default();
+    export_tables(&read_txn, &mut out, &mut summary)?;
+    out.flush().map_err(|e| AppError::Dump(e.to_string()))?;
+
+    Ok(summary)
+}
+
+fn import_entries<K: Key + DumpField, V: DumpField>(
+    write_txn: &WriteTransaction,
+    definition: TableDefinition<K, V>,
+    lines: &mut Peekable<impl Iterator<Item = Result<DumpEntry>>>,
+    summary: &mut DumpSummary,
+) -> Result<()> {
+    let mut table = write_txn.open_table(definition)?;
+    let mut count = 0;
+    while let Some(entry) = lines.next_if(|line| {
+        line.as_ref()
+            .map_or(true, |entry| entry.table == definition.name())
+    }) {
+        let entry = entry?;
+        let invalid = |what| {
+            AppError::Dump(format!(
+                "invalid {} in table '{}': {}",
+                what, entry.table, entry.key
+            ))
+        };
+        let key = K::from_json(&entry.key).ok_or_else(|| invalid("key"))?;
+        let value = V::from_json(&entry.value).ok_or_else(|| invalid("value"))?;
+        table.insert(K::borrow(&key), V::borrow(&value))?;
+        count += 1;
+    }
+    *summary
+        .tables
+        .entry(definition.name().to_string())
+        .or_default() += count;
+
+    Ok(())
+}
+
+/// Insert `entries`, consecutive entries of a table at a time
+#[allow(clippy::result_large_err)]
+fn import_all(
+    write_txn: &WriteTransaction,
+    entries: impl Iterator<Item = Result<DumpEntry>>,
+) -> Result<DumpSummary> {
+    let mut entries = entries.peekable();
+    let mut summary = DumpSummary::default();
+    loop {
+        let name = match entries.peek() {
+            Some(Ok(entry)) => entry.table.clone(),
+            Some(Err(_)) => {
+                entries.next().transpose()?;
+                continue;
+            }
+            None => break,
+        };
+        import_table(write_txn, &name, &mut entries, &mut summary)?;
+    }
+
+    Ok(summary)
+}
+
+/// Load a dump from `input` into `db`, which must hold no data yet
+///
+/// Everything is written in one transaction, so a dump that fails partway
+/// leaves the database empty.
+#[allow(clippy::result_large_err)]
+pub fn import(db: &Db, input: impl BufRead) -> Result<DumpSummary> {
+    let mut lines = input.lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| AppError::Dump("empty dump".to_string()))?
+        .map_err(|e| AppError::Dump(e.to_string()))?;
+    let header: DumpHeader =
+        serde_json::from_str(&header).map_err(|e| AppError::Dump(format!("bad header: {}", e)))?;
+
+    if header.format != DUMP_FORMAT || header.version != DUMP_VERSION {
+        return Err(AppError::Dump(format!(
+            "unsupported dump: {} version {}",
+            header.format, header.version
+        )));
+    }
+    if header.format_version > FORMAT_VERSION {
+        return Err(AppError::IncompatibleDatabase(format!(
+            "dump format version {} is newer than supported version {}",
+            header.format_version, FORMAT_VERSION
+        )));
+    }
+
+    let entries = lines
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(i, line)| {
+            let line = line.map_err(|e| AppError::Dump(e.to_string()))?;
+            serde_json::from_str::<DumpEntry>(&line)
+                .map_err(|e| AppError::Dump(format!("line {}: {}", i + 2, e)))
+        });
+
+    let write_txn = db.begin_write()?;
+    if let Some(table) = first_nonempty_table(&write_txn)? {
+        return Err(AppError::Dump(format!(
+            "refusing to import into a database with data (table '{}' is not empty)",
+            table
+        )));
+    }
+
+    let summary = import_all(&write_txn, entries)?;
+
+    write_txn
+        .open_table(tables::META)?
+        .insert(migrations::FORMAT_VERSION_KEY, header.format_version)?;
+    super::before_commit()?;
+    write_txn.commit()?;
+
+    Ok(summary)
+}
+
+/// Whether `table` holds records of a single account
+fn is_account_table(table: &str) -> bool {
+    [
+        tables::USERS.name(),
+        tables::BACKUPS.name(),
+        tables::BACKUP_VERSIONS.name(),
+        tables::TOMBSTONES.name(),
+        tables::RATE_LIMITS.name(),
+        tables::USER_BACKUPS.name(),
+        tables::SYNC_TOKENS.name(),
+        tables::RECOVERY_CONTACTS.name(),
+        tables::RECOVERY_GRANTS.name(),
+    ]
+    .contains(&table)
+}
+
+fn push_entry<K: Key + DumpField, V: DumpField>(
+    read_txn: &ReadTransaction,
+    definition: TableDefinition<K, V>,
+    key: &K::Owned,
+    out: &mut Vec<DumpEntry>,
+) -> Result<()> {
+    if let Some(value) = read_txn.open_table(definition)?.get(K::borrow(key))? {
+        out.push(DumpEntry {
+            table: definition.name().to_string(),
+            key: K::to_json(K::borrow(key)),
+            value: V::to_json(value.value()),
+        });
+    }
+
+    Ok(())
+}
+
+/// Entries holding `user_id`'s account, in dump form
+///
+/// The user record, its backups with their versions and sync tokens, rate
+/// limit, recovery contact and grant, and its tombstone if it was deleted.
+/// Empty once the account is gone for good.
+#[allow(clippy::result_large_err)]
+pub fn account_entries(read_txn: &ReadTransaction, user_id: &str) -> Result<Vec<DumpEntry>> {
+    let user_id = user_id.to_string();
+    let mut out = Vec::new();
+    push_entry(read_txn, tables::USERS, &user_id, &mut out)?;
+    push_entry(read_txn, tables::USER_BACKUPS, &user_id, &mut out)?;
+    push_entry(read_txn, tables::RATE_LIMITS, &user_id, &mut out)?;
+    push_entry(read_txn, tables::RECOVERY_CONTACTS, &user_id, &mut out)?;
+    push_entry(read_txn, tables::RECOVERY_GRANTS, &user_id, &mut out)?;
+    push_entry(read_txn, tables::TOMBSTONES, &user_id, &mut out)?;
+
+    let backup_keys: Vec<String> = match read_txn
+        .open_table(tables::USER_BACKUPS)?
+        .get(user_id.as_str())?
+    {
+        Some(bytes) => bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?.0,
+        None => Vec::new(),
+    };
+    let versions = read_txn.open_table(tables::BACKUP_VERSIONS)?;
+    for key in &backup_keys {
+        push_entry(read_txn, tables::BACKUPS, key, &mut out)?;
+        push_entry(read_txn, tables::SYNC_TOKENS, key, &mut out)?;
+        for entry in versions.range((key.as_str(), 0)..=(key.as_str(), u64::MAX))? {
+            let (version_key, value) = entry?;
+            out.push(DumpEntry {
+                table: tables::BACKUP_VERSIONS.name().to_string(),
+                key: <(&str, u64)>::to_json(version_key.value()),
+                value: <&[u8]>::to_json(value.value()),
+            });
+        }
+    }
+
+    Ok(out)
+}
+
+/// Replace everything stored for `user_id` with `entries` from
+/// [`account_entries`]
+#[allow(clippy::result_large_err)]
+pub fn replace_account(
+    write_txn: &WriteTransaction,
+    user_id: &str,
+    entries: Vec<DumpEntry>,
+) -> Result<()> {
+    if let Some(entry) = entries.iter().find(|e| !is_account_table(&e.table)) {
+        return Err(AppError::Dump(format!(
+            "table '{}' is not part of an account",
+            entry.table
+        )));
+    }
+
+    accounts::erase(write_txn, user_id)?;
+    import_all(write_txn, entries.into_iter().map(Ok))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+    use crate::seed::{SeedOptions, seed_database};
+
+    #[test]
+    fn test_round_trip() {
+        let source = open_in_memory_database().unwrap();
+        let options = SeedOptions {
+            users: 5,
+            ..SeedOptions::default()
+        };
+        seed_database(&source, &options).unwrap();
+        let write_txn = source.begin_write().unwrap();
+        write_txn
+            .open_table(tables::BACKUP_VERSIONS)
+            .unwrap()
+            .insert(("key", 3), [1u8, 2, 3].as_slice())
+            .unwrap();
+        write_txn.commit().unwrap();
+
+        let mut dump = Vec::new();
+        let exported = export(&source, &mut dump, Utc::now()).unwrap();
+        assert_eq!(exported.tables["users"], 5);
+
+        let target = open_in_memory_database().unwrap();
+        let imported = import(&target, dump.as_slice()).unwrap();
+        assert_eq!(imported.entries(), exported.entries());
+
+        // Exporting the copy gives the same entries
+        let mut again = Vec::new();
+        export(&target, &mut again, Utc::now()).unwrap();
+        let body = |dump: &[u8]| dump.splitn(2, |&b| b == b'\n').nth(1).unwrap().to_vec();
+        assert_eq!(body(&dump), body(&again));
+
+        // A second import would mix two databases
+        let err = import(&target, dump.as_slice()).unwrap_err();
+        assert!(matches!(err, AppError::Dump(_)));
+    }
+
+    #[test]
+    fn test_rejects_newer_format_and_bad_lines() {
+        let header = |format_version| {
+            serde_json::to_string(&DumpHeader {
+                format: DUMP_FORMAT.to_string(),
+                version: DUMP_VERSION,
+                format_version,
+                exported_at: Utc::now(),
+            })
+            .unwrap()
+        };
+
+        let db = open_in_memory_database().unwrap();
+        let newer = header(FORMAT_VERSION + 1);
+        assert!(matches!(
+            import(&db, newer.as_bytes()).unwrap_err(),
+            AppError::IncompatibleDatabase(_)
+        ));
+
+        let bad_key = format!(
+            "{}\n{}\n",
+            header(FORMAT_VERSION),
+            r#"{"table":"users","key":7,"value":""}"#
+        );
+        assert!(matches!(
+            import(&db, bad_key.as_bytes()).unwrap_err(),
+            AppError::Dump(_)
+        ));
+
+        // Nothing was written by the failed imports
+        let read_txn = db.begin_read().unwrap();
+        assert!(
+            read_txn
+                .open_table(tables::USERS)
+                .unwrap()
+                .is_empty()
+                .unwrap()
+        );
+    }
+}
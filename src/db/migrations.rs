@@ -0,0 +1,551 @@
+//! On-disk format versioning and startup migrations
+//!
+//! The format version lives in the `meta` table. Databases written before the
+//! marker existed are treated as version 1. Migrations run in order inside a
+//! single write transaction, so a crash mid-upgrade leaves the old format.
+
+use redb::{Database, ReadableDatabase, ReadableTable, WriteTransaction};
+use serde::Deserialize;
+
+use super::tables;
+use crate::error::{AppError, Result};
+use crate::models::{
+    BackupRecord, BackupVersionRecord, SecurityEventKind, SecurityEventRecord, TombstoneRecord,
+};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+/// Format version written by this server
+pub const FORMAT_VERSION: u64 = 5;
+
+pub(crate) const FORMAT_VERSION_KEY: &str = "format_version";
+
+/// Version assumed for databases without a marker
+pub(crate) const UNVERSIONED: u64 = 1;
+
+type Migration = fn(&WriteTransaction) -> Result<()>;
+
+/// `MIGRATIONS[i]` upgrades format `i + 1` to `i + 2`
+const MIGRATIONS: &[(&str, Migration)] = &[
+    ("add country to security events", add_security_event_country),
+    ("add checksums to backups", add_backup_checksums),
+    ("add checksums to backup versions", add_version_checksums),
+    ("add network to security events", add_security_event_network),
+];
+
+/// Read the format version and upgrade the database to [`FORMAT_VERSION`]
+///
+/// Refuses databases written by a newer server, since their records may not
+/// decode here.
+#[allow(clippy::result_large_err)]
+pub fn migrate(db: &Database) -> Result<()> {
+    let write_txn = db.begin_write()?;
+    {
+        let mut meta = write_txn.open_table(tables::META)?;
+        let version = meta
+            .get(FORMAT_VERSION_KEY)?
+            .map(|v| v.value())
+            .unwrap_or(UNVERSIONED);
+
+        if version > FORMAT_VERSION {
+            return Err(AppError::IncompatibleDatabase(format!(
+                "format version {} is newer than supported version {}",
+                version, FORMAT_VERSION
+            )));
+        }
+
+        for (from, (name, migration)) in (UNVERSIONED..).zip(MIGRATIONS) {
+            if from >= version {
+                tracing::info!(
+                    "Migrating database format {} -> {}: {}",
+                    from,
+                    from + 1,
+                    name
+                );
+                migration(&write_txn)?;
+            }
+        }
+
+        meta.insert(FORMAT_VERSION_KEY, FORMAT_VERSION)?;
+    }
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+/// Current format version of `db`, if it has a marker
+#[allow(clippy::result_large_err)]
+pub fn format_version(db: &Database) -> Result<Option<u64>> {
+    let read_txn = db.begin_read()?;
+    let meta = match read_txn.open_table(tables::META) {
+        Ok(meta) => meta,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(meta.get(FORMAT_VERSION_KEY)?.map(|v| v.value()))
+}
+
+/// Security event layout before GeoIP enrichment
+#[derive(Deserialize)]
+struct SecurityEventRecordV1 {
+    at: i64,
+    kind: SecurityEventKind,
+    user_id: Option<String>,
+}
+
+/// v1 -> v2: re-encode security events with an empty `country`
+///
+/// Records that already decode in full as the current layout are left alone,
+/// so the migration is safe to re-run.
+#[allow(clippy::result_large_err)]
+fn add_security_event_country(write_txn: &WriteTransaction) -> Result<()> {
+    let mut events = write_txn.open_table(tables::SECURITY_EVENTS)?;
+
+    let mut upgraded = Vec::new();
+    for entry in events.iter()? {
+        let (seq, bytes) = entry?;
+        let bytes = bytes.value();
+
+        let current: std::result::Result<(SecurityEventRecord, usize), _> =
+            bincode::serde::decode_from_slice(bytes, BINCODE_CONFIG);
+        if matches!(current, Ok((_, read)) if read == bytes.len()) {
+            continue;
+        }
+
+        let (old, _): (SecurityEventRecordV1, _) =
+            bincode::serde::decode_from_slice(bytes, BINCODE_CONFIG)?;
+        let record = SecurityEventRecord {
+            at: old.at,
+            kind: old.kind,
+            user_id: old.user_id,
+            country: None,
+            network: None,
+        };
+        upgraded.push((
+            seq.value(),
+            bincode::serde::encode_to_vec(&record, BINCODE_CONFIG)?,
+        ));
+    }
+
+    tracing::info!("Re-encoded {} security events", upgraded.len());
+    for (seq, bytes) in upgraded {
+        events.insert(seq, bytes.as_slice())?;
+    }
+
+    Ok(())
+}
+
+/// Security event layout before network grouping
+#[derive(Deserialize)]
+struct SecurityEventRecordV4 {
+    at: i64,
+    kind: SecurityEventKind,
+    user_id: Option<String>,
+    country: Option<String>,
+}
+
+/// v4 -> v5: re-encode security events with an empty `network`
+///
+/// Safe to re-run like the v1 -> v2 migration, which already writes the
+/// current layout.
+#[allow(clippy::result_large_err)]
+fn add_security_event_network(write_txn: &WriteTransaction) -> Result<()> {
+    let mut events = write_txn.open_table(tables::SECURITY_EVENTS)?;
+
+    let mut upgraded = Vec::new();
+    for entry in events.iter()? {
+        let (seq, bytes) = entry?;
+        let bytes = bytes.value();
+
+        let current: std::result::Result<(SecurityEventRecord, usize), _> =
+            bincode::serde::decode_from_slice(bytes, BINCODE_CONFIG);
+        if matches!(current, Ok((_, read)) if read == bytes.len()) {
+            continue;
+        }
+
+        let (old, _): (SecurityEventRecordV4, _) =
+            bincode::serde::decode_from_slice(bytes, BINCODE_CONFIG)?;
+        let record = SecurityEventRecord {
+            at: old.at,
+            kind: old.kind,
+            user_id: old.user_id,
+            country: old.country,
+            network: None,
+        };
+        upgraded.push((
+            seq.value(),
+            bincode::serde::encode_to_vec(&record, BINCODE_CONFIG)?,
+        ));
+    }
+
+    tracing::info!("Added networks to {} security events", upgraded.len());
+    for (seq, bytes) in upgraded {
+        events.insert(seq, bytes.as_slice())?;
+    }
+
+    Ok(())
+}
+
+/// Backup layout before integrity checksums
+#[derive(Deserialize)]
+struct BackupRecordV2 {
+    user_id: String,
+    #[serde(with = "crate::models::compressed")]
+    encrypted_data: String,
+    created_at: i64,
+    updated_at: i64,
+}
+
+/// Re-encode serialized backup `bytes` with a checksum, or `None` if they
+/// already have one
+#[allow(clippy::result_large_err)]
+fn backup_with_checksum(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    let current: std::result::Result<(BackupRecord, usize), _> =
+        bincode::serde::decode_from_slice(bytes, BINCODE_CONFIG);
+    if matches!(current, Ok((_, read)) if read == bytes.len()) {
+        return Ok(None);
+    }
+
+    let (old, _): (BackupRecordV2, _) = bincode::serde::decode_from_slice(bytes, BINCODE_CONFIG)?;
+    let record = BackupRecord::new(
+        old.user_id,
+        old.encrypted_data,
+        old.created_at,
+        old.updated_at,
+    );
+    Ok(Some(bincode::serde::encode_to_vec(
+        &record,
+        BINCODE_CONFIG,
+    )?))
+}
+
+/// v2 -> v3: give every backup, including those held in tombstones, the
+/// checksum of its data
+///
+/// Safe to re-run like the v1 -> v2 migration. Backups are hashed as they
+/// are now; corruption that happened before this upgrade goes undetected.
+#[allow(clippy::result_large_err)]
+fn add_backup_checksums(write_txn: &WriteTransaction) -> Result<()> {
+    let mut backups = write_txn.open_table(tables::BACKUPS)?;
+    let mut upgraded = Vec::new();
+    for entry in backups.iter()? {
+        let (key, bytes) = entry?;
+        if let Some(bytes) = backup_with_checksum(bytes.value())? {
+            upgraded.push((key.value().to_string(), bytes));
+        }
+    }
+
+    tracing::info!("Added checksums to {} backups", upgraded.len());
+    for (key, bytes) in upgraded {
+        backups.insert(key.as_str(), bytes.as_slice())?;
+    }
+    drop(backups);
+
+    let mut tombstones = write_txn.open_table(tables::TOMBSTONES)?;
+    let mut upgraded = Vec::new();
+    for entry in tombstones.iter()? {
+        let (user_id, bytes) = entry?;
+        let (mut tombstone, _): (TombstoneRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+        let mut changed = false;
+        for backup in &mut tombstone.backups {
+            if let Some(bytes) = backup_with_checksum(&backup.record)? {
+                backup.record = bytes;
+                changed = true;
+            }
+        }
+        if changed {
+            upgraded.push((
+                user_id.value().to_string(),
+                bincode::serde::encode_to_vec(&tombstone, BINCODE_CONFIG)?,
+            ));
+        }
+    }
+
+    for (user_id, bytes) in upgraded {
+        tombstones.insert(user_id.as_str(), bytes.as_slice())?;
+    }
+
+    Ok(())
+}
+
+/// Backup version layout before integrity checksums
+#[derive(Deserialize)]
+struct BackupVersionRecordV3 {
+    #[serde(with = "crate::models::compressed")]
+    encrypted_data: String,
+    stored_at: i64,
+}
+
+/// Re-encode a serialized backup version with a checksum, or `None` if it
+/// already has one
+#[allow(clippy::result_large_err)]
+fn version_with_checksum(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    let current: std::result::Result<(BackupVersionRecord, usize), _> =
+        bincode::serde::decode_from_slice(bytes, BINCODE_CONFIG);
+    if matches!(current, Ok((_, read)) if read == bytes.len()) {
+        return Ok(None);
+    }
+
+    let (old, _): (BackupVersionRecordV3, _) =
+        bincode::serde::decode_from_slice(bytes, BINCODE_CONFIG)?;
+    let record = BackupVersionRecord {
+        checksum: dailyreps_signing::checksum(old.encrypted_data.as_bytes()),
+        encrypted_data: old.encrypted_data,
+        stored_at: old.stored_at,
+    };
+    Ok(Some(bincode::serde::encode_to_vec(
+        &record,
+        BINCODE_CONFIG,
+    )?))
+}
+
+/// v3 -> v4: give every kept backup version, including those held in
+/// tombstones, the checksum of its data
+///
+/// Safe to re-run, and like v2 -> v3 it can't detect corruption that
+/// happened before the upgrade.
+#[allow(clippy::result_large_err)]
+fn add_version_checksums(write_txn: &WriteTransaction) -> Result<()> {
+    let mut versions = write_txn.open_table(tables::BACKUP_VERSIONS)?;
+    let mut upgraded = Vec::new();
+    for entry in versions.iter()? {
+        let (key, bytes) = entry?;
+        if let Some(bytes) = version_with_checksum(bytes.value())? {
+            let (storage_key, version) = key.value();
+            upgraded.push((storage_key.to_string(), version, bytes));
+        }
+    }
+
+    tracing::info!("Added checksums to {} backup versions", upgraded.len());
+    for (storage_key, version, bytes) in upgraded {
+        versions.insert((storage_key.as_str(), version), bytes.as_slice())?;
+    }
+    drop(versions);
+
+    let mut tombstones = write_txn.open_table(tables::TOMBSTONES)?;
+    let mut upgraded = Vec::new();
+    for entry in tombstones.iter()? {
+        let (user_id, bytes) = entry?;
+        let (mut tombstone, _): (TombstoneRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+        let mut changed = false;
+        for (_, version) in tombstone.backups.iter_mut().flat_map(|b| &mut b.versions) {
+            if let Some(bytes) = version_with_checksum(version)? {
+                *version = bytes;
+                changed = true;
+            }
+        }
+        if changed {
+            upgraded.push((
+                user_id.value().to_string(),
+                bincode::serde::encode_to_vec(&tombstone, BINCODE_CONFIG)?,
+            ));
+        }
+    }
+
+    for (user_id, bytes) in upgraded {
+        tombstones.insert(user_id.as_str(), bytes.as_slice())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redb::backends::InMemoryBackend;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct LegacyEvent {
+        at: i64,
+        kind: SecurityEventKind,
+        user_id: Option<String>,
+    }
+
+    fn empty_db() -> Database {
+        Database::builder()
+            .create_with_backend(InMemoryBackend::new())
+            .unwrap()
+    }
+
+    fn set_version(db: &Database, version: u64) {
+        let write_txn = db.begin_write().unwrap();
+        write_txn
+            .open_table(tables::META)
+            .unwrap()
+            .insert(FORMAT_VERSION_KEY, version)
+            .unwrap();
+        write_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn test_fresh_database_gets_current_version() {
+        let db = empty_db();
+        assert_eq!(format_version(&db).unwrap(), None);
+
+        migrate(&db).unwrap();
+        assert_eq!(format_version(&db).unwrap(), Some(FORMAT_VERSION));
+
+        // Re-running is a no-op
+        migrate(&db).unwrap();
+        assert_eq!(format_version(&db).unwrap(), Some(FORMAT_VERSION));
+    }
+
+    #[test]
+    fn test_unversioned_security_events_are_reencoded() {
+        let db = empty_db();
+        let legacy = LegacyEvent {
+            at: 1_700_000_000,
+            kind: SecurityEventKind::RateLimited,
+            user_id: Some("a".repeat(64)),
+        };
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut events = write_txn.open_table(tables::SECURITY_EVENTS).unwrap();
+            let bytes = bincode::serde::encode_to_vec(&legacy, BINCODE_CONFIG).unwrap();
+            events.insert(7, bytes.as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        migrate(&db).unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let events = read_txn.open_table(tables::SECURITY_EVENTS).unwrap();
+        let bytes = events.get(7).unwrap().unwrap();
+        let (record, read): (SecurityEventRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG).unwrap();
+        assert_eq!(read, bytes.value().len());
+        assert_eq!(record.at, legacy.at);
+        assert_eq!(record.kind, SecurityEventKind::RateLimited);
+        assert_eq!(record.user_id, legacy.user_id);
+        assert_eq!(record.country, None);
+    }
+
+    #[derive(Serialize)]
+    struct LegacyBackup {
+        user_id: String,
+        encrypted_data: String,
+        created_at: i64,
+        updated_at: i64,
+    }
+
+    #[test]
+    fn test_backups_get_checksums() {
+        let db = empty_db();
+        set_version(&db, 2);
+        let legacy = LegacyBackup {
+            user_id: "a".repeat(64),
+            encrypted_data: "ciphertext".to_string(),
+            created_at: 1,
+            updated_at: 2,
+        };
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut backups = write_txn.open_table(tables::BACKUPS).unwrap();
+            let bytes = bincode::serde::encode_to_vec(&legacy, BINCODE_CONFIG).unwrap();
+            backups.insert("k", bytes.as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        migrate(&db).unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let backups = read_txn.open_table(tables::BACKUPS).unwrap();
+        let bytes = backups.get("k").unwrap().unwrap();
+        let (record, read): (BackupRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG).unwrap();
+        assert_eq!(read, bytes.value().len());
+        assert_eq!(record.encrypted_data, "ciphertext");
+        assert_eq!(record.updated_at, 2);
+        assert!(record.verify());
+    }
+
+    #[derive(Serialize)]
+    struct LegacyVersion {
+        encrypted_data: String,
+        stored_at: i64,
+    }
+
+    #[test]
+    fn test_backup_versions_get_checksums() {
+        let db = empty_db();
+        set_version(&db, 3);
+        let legacy = LegacyVersion {
+            encrypted_data: "old-ciphertext".to_string(),
+            stored_at: 1,
+        };
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut versions = write_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+            let bytes = bincode::serde::encode_to_vec(&legacy, BINCODE_CONFIG).unwrap();
+            versions.insert(("k", 2), bytes.as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        migrate(&db).unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let versions = read_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+        let bytes = versions.get(("k", 2)).unwrap().unwrap();
+        let (record, read): (BackupVersionRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG).unwrap();
+        assert_eq!(read, bytes.value().len());
+        assert_eq!(record.encrypted_data, "old-ciphertext");
+        assert_eq!(record.stored_at, 1);
+        assert_eq!(
+            record.checksum,
+            dailyreps_signing::checksum(b"old-ciphertext")
+        );
+    }
+
+    #[derive(Serialize)]
+    struct CountryEvent {
+        at: i64,
+        kind: SecurityEventKind,
+        user_id: Option<String>,
+        country: Option<String>,
+    }
+
+    #[test]
+    fn test_security_events_get_network() {
+        let db = empty_db();
+        set_version(&db, 4);
+        let legacy = CountryEvent {
+            at: 1_700_000_000,
+            kind: SecurityEventKind::SignatureFailure,
+            user_id: Some("a".repeat(64)),
+            country: Some("NL".to_string()),
+        };
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut events = write_txn.open_table(tables::SECURITY_EVENTS).unwrap();
+            let bytes = bincode::serde::encode_to_vec(&legacy, BINCODE_CONFIG).unwrap();
+            events.insert(3, bytes.as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        migrate(&db).unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let events = read_txn.open_table(tables::SECURITY_EVENTS).unwrap();
+        let bytes = events.get(3).unwrap().unwrap();
+        let (record, read): (SecurityEventRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG).unwrap();
+        assert_eq!(read, bytes.value().len());
+        assert_eq!(record.kind, SecurityEventKind::SignatureFailure);
+        assert_eq!(record.country.as_deref(), Some("NL"));
+        assert_eq!(record.network, None);
+    }
+
+    #[test]
+    fn test_newer_format_is_refused() {
+        let db = empty_db();
+        set_version(&db, FORMAT_VERSION + 1);
+
+        let err = migrate(&db).unwrap_err();
+        assert!(matches!(err, AppError::IncompatibleDatabase(_)));
+        assert_eq!(format_version(&db).unwrap(), Some(FORMAT_VERSION + 1));
+    }
+}
@@ -1,17 +1,79 @@
+pub mod accounts;
+pub mod bans;
+pub mod compaction;
+pub mod dump;
+pub mod migrations;
+pub mod nonces;
+pub mod rate_limits;
+pub mod registration_attempts;
+pub mod restore;
+pub mod snapshot;
 pub mod tables;
+pub mod tasks;
+pub mod tombstones;
+pub mod uploads;
+pub mod versions;
 
-use redb::{Database, Error as RedbError};
+use chrono::Utc;
+use redb::{
+    CacheStats, Database, Error as RedbError, ReadTransaction, ReadableDatabase, ReadableTable,
+    TransactionError, WriteTransaction, backends::InMemoryBackend,
+};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::time::Duration;
+use tokio::task::JoinHandle;
 
-/// Database handle type (Arc-wrapped for sharing across handlers)
-pub type Db = Arc<Database>;
+use crate::models::DailyStatsRecord;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+/// Database handle, shared across handlers
+///
+/// Clones share one `Database`. Transactions are started under a read lock
+/// that [`compaction`] takes exclusively, since redb can only compact a
+/// database nothing else is using.
+#[derive(Debug, Clone)]
+pub struct Db(Arc<RwLock<Arc<Database>>>);
+
+impl Db {
+    pub fn new(db: Database) -> Self {
+        Self::from(Arc::new(db))
+    }
+
+    pub fn begin_write(&self) -> Result<WriteTransaction, TransactionError> {
+        self.database().begin_write()
+    }
+
+    fn database(&self) -> RwLockReadGuard<'_, Arc<Database>> {
+        self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ReadableDatabase for Db {
+    fn begin_read(&self) -> Result<ReadTransaction, TransactionError> {
+        self.database().begin_read()
+    }
+
+    fn cache_stats(&self) -> CacheStats {
+        self.database().cache_stats()
+    }
+}
+
+/// Share a database opened elsewhere; it can't be compacted while the
+/// caller keeps its own `Arc`
+impl From<Arc<Database>> for Db {
+    fn from(db: Arc<Database>) -> Self {
+        Self(Arc::new(RwLock::new(db)))
+    }
+}
 
 /// Open or create the redb database at the given path
 ///
-/// Creates all required tables on first run.
+/// Creates all required tables on first run and upgrades older on-disk
+/// formats. Fails if the database was written by a newer server version.
 #[allow(clippy::result_large_err)]
-pub fn open_database(path: impl AsRef<Path>) -> Result<Db, RedbError> {
+pub fn open_database(path: impl AsRef<Path>) -> crate::Result<Db> {
     tracing::info!("Opening database at: {:?}", path.as_ref());
 
     // Create parent directory if it doesn't exist
@@ -24,20 +86,130 @@ pub fn open_database(path: impl AsRef<Path>) -> Result<Db, RedbError> {
         })?;
     }
 
-    let db = Database::create(path)?;
+    let db = Database::create(path).map_err(RedbError::from)?;
+    migrations::migrate(&db)?;
+    init_tables(&db)?;
 
-    // Initialize tables on first run
+    tracing::info!("Database initialized successfully");
+
+    Ok(Db::new(db))
+}
+
+/// Create an empty database that lives only in memory
+///
+/// Used for tests and `--ephemeral` demo mode; everything is lost when the
+/// handle is dropped.
+#[allow(clippy::result_large_err)]
+pub fn open_in_memory_database() -> crate::Result<Db> {
+    tracing::info!("Opening in-memory database (data will not be persisted)");
+
+    let db = Database::builder()
+        .create_with_backend(InMemoryBackend::new())
+        .map_err(RedbError::from)?;
+    migrations::migrate(&db)?;
+    init_tables(&db)?;
+
+    Ok(Db::new(db))
+}
+
+/// Update today's row in the daily stats table within `write_txn`
+#[allow(clippy::result_large_err)]
+pub fn bump_daily_stats(
+    write_txn: &WriteTransaction,
+    now: i64,
+    update: impl FnOnce(&mut DailyStatsRecord),
+) -> crate::Result<()> {
+    let mut table = write_txn.open_table(tables::DAILY_STATS)?;
+    let key = DailyStatsRecord::key(now);
+
+    let mut record = match table.get(key.as_str())? {
+        Some(bytes) => bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?.0,
+        None => DailyStatsRecord::default(),
+    };
+    update(&mut record);
+
+    let bytes = bincode::serde::encode_to_vec(record, BINCODE_CONFIG)?;
+    table.insert(key.as_str(), bytes.as_slice())?;
+
+    Ok(())
+}
+
+/// Run `sweep` in its own write transaction every `interval`, logging what
+/// it removed
+///
+/// For periodic cleanup of expiring records (`sweep` gets the current Unix
+/// time and returns how many it deleted). Must be called from within a
+/// Tokio runtime.
+pub fn spawn_sweeper(
+    db: Db,
+    interval: Duration,
+    what: &'static str,
+    sweep: fn(&WriteTransaction, i64) -> crate::Result<usize>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            let db = db.clone();
+            let swept = tokio::task::spawn_blocking(move || -> crate::Result<usize> {
+                let write_txn = db.begin_write()?;
+                let swept = sweep(&write_txn, Utc::now().timestamp())?;
+                before_commit()?;
+                write_txn.commit()?;
+                Ok(swept)
+            })
+            .await;
+
+            match swept {
+                Ok(Ok(0)) => {}
+                Ok(Ok(n)) => tracing::info!("Removed {} {}", n, what),
+                Ok(Err(e)) => tracing::warn!("Failed to remove {}: {}", what, e),
+                Err(e) => tracing::warn!("Cleanup of {} failed: {}", what, e),
+            }
+        }
+    })
+}
+
+/// Fault-injection point run before every write commit
+///
+/// A no-op unless built with the `chaos` feature.
+#[inline]
+pub fn before_commit() -> crate::Result<()> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::before_commit()?;
+
+    Ok(())
+}
+
+/// Create tables if they don't exist by opening them
+#[allow(clippy::result_large_err)]
+fn init_tables(db: &Database) -> crate::Result<()> {
     let write_txn = db.begin_write()?;
     {
-        // Create tables if they don't exist by opening them
         let _ = write_txn.open_table(tables::USERS)?;
         let _ = write_txn.open_table(tables::BACKUPS)?;
+        let _ = write_txn.open_table(tables::BACKUP_VERSIONS)?;
+        let _ = write_txn.open_table(tables::TOMBSTONES)?;
+        let _ = write_txn.open_table(tables::BANS)?;
+        let _ = write_txn.open_table(tables::NONCES)?;
+        let _ = write_txn.open_table(tables::UPLOAD_SESSIONS)?;
+        let _ = write_txn.open_table(tables::UPLOAD_CHUNKS)?;
         let _ = write_txn.open_table(tables::RATE_LIMITS)?;
         let _ = write_txn.open_table(tables::USER_BACKUPS)?;
+        let _ = write_txn.open_table(tables::SYNC_TOKENS)?;
+        let _ = write_txn.open_table(tables::SECURITY_EVENTS)?;
+        let _ = write_txn.open_table(tables::DAILY_STATS)?;
+        let _ = write_txn.open_table(tables::RECOVERY_CONTACTS)?;
+        let _ = write_txn.open_table(tables::RECOVERY_GRANTS)?;
+        let _ = write_txn.open_table(tables::REGISTRATION_ATTEMPTS)?;
+        let _ = write_txn.open_table(tables::REPLICATION_QUEUE)?;
+        let _ = write_txn.open_table(tables::REPLICATION_APPLIED)?;
+        let _ = write_txn.open_table(tables::REPLICATION_DEAD_LETTERS)?;
+        let _ = write_txn.open_table(tables::META)?;
     }
     write_txn.commit()?;
 
-    tracing::info!("Database initialized successfully");
-
-    Ok(Arc::new(db))
+    Ok(())
 }
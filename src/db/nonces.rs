@@ -0,0 +1,59 @@
+//! Spent request nonces
+//!
+//! A signed request may carry a nonce to make it single-use. Each one is
+//! remembered in `nonces` until its request's timestamp falls out of the
+//! accepted window, after which the timestamp check rejects a replay anyway
+//! and the entry is swept.
+
+use redb::{ReadableTable, WriteTransaction};
+
+use super::tables;
+use crate::Result;
+
+/// Record `nonce` as used until `expires_at`, returning false if it was
+/// already used and hasn't expired at `now`
+#[allow(clippy::result_large_err)]
+pub fn claim(write_txn: &WriteTransaction, nonce: &str, expires_at: i64, now: i64) -> Result<bool> {
+    let mut nonces = write_txn.open_table(tables::NONCES)?;
+    if nonces.get(nonce)?.is_some_and(|e| e.value() > now) {
+        return Ok(false);
+    }
+    nonces.insert(nonce, expires_at)?;
+
+    Ok(true)
+}
+
+/// Delete every nonce expired before `now`, returning how many
+#[allow(clippy::result_large_err)]
+pub fn prune_expired(write_txn: &WriteTransaction, now: i64) -> Result<usize> {
+    let mut nonces = write_txn.open_table(tables::NONCES)?;
+    let mut pruned = 0;
+    nonces.retain(|_, expires_at| {
+        let expired = expires_at <= now;
+        pruned += usize::from(expired);
+        !expired
+    })?;
+
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+
+    #[test]
+    fn test_claim_once_until_expired() {
+        let db = open_in_memory_database().unwrap();
+        let write_txn = db.begin_write().unwrap();
+
+        assert!(claim(&write_txn, "nonce-a", 100, 10).unwrap());
+        assert!(!claim(&write_txn, "nonce-a", 100, 20).unwrap());
+        assert!(claim(&write_txn, "nonce-b", 200, 20).unwrap());
+
+        // Past its expiry the timestamp check takes over, so it's forgotten
+        assert_eq!(prune_expired(&write_txn, 150).unwrap(), 1);
+        assert!(claim(&write_txn, "nonce-a", 300, 150).unwrap());
+        assert!(!claim(&write_txn, "nonce-b", 300, 150).unwrap());
+    }
+}
@@ -0,0 +1,61 @@
+//! Garbage collection of per-user rate-limit records
+//!
+//! `rate_limits` gets a record on a user's first store and nothing ever
+//! removed it, so users who stopped syncing kept theirs forever. Once both
+//! windows are over a record counts nothing, and a store recreates it.
+
+use redb::WriteTransaction;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use super::tables;
+use crate::Result;
+use crate::constants::RATE_LIMIT_RETENTION_SECS;
+use crate::models::RateLimitRecord;
+
+/// Delete every record whose daily window ended more than
+/// [`RATE_LIMIT_RETENTION_SECS`] before `now`, returning how many
+#[allow(clippy::result_large_err)]
+pub fn prune_expired(write_txn: &WriteTransaction, now: i64) -> Result<usize> {
+    let mut rate_limits = write_txn.open_table(tables::RATE_LIMITS)?;
+    let mut pruned = 0;
+    rate_limits.retain(|_, bytes| {
+        // Undecodable records are left for whoever reads them to report
+        let expired =
+            bincode::serde::decode_from_slice::<RateLimitRecord, _>(bytes, BINCODE_CONFIG)
+                .is_ok_and(|(r, _)| r.day_reset_at + RATE_LIMIT_RETENTION_SECS <= now);
+        pruned += usize::from(expired);
+        !expired
+    })?;
+
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+    use redb::{ReadableTable, ReadableTableMetadata};
+
+    #[test]
+    fn test_prune_keeps_recent_windows() {
+        let db = open_in_memory_database().unwrap();
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(tables::RATE_LIMITS).unwrap();
+            for (user, started) in [("stale", 0), ("recent", 100_000)] {
+                let bytes =
+                    bincode::serde::encode_to_vec(RateLimitRecord::new(started), BINCODE_CONFIG)
+                        .unwrap();
+                table.insert(user, bytes.as_slice()).unwrap();
+            }
+        }
+
+        // "stale" ended its day at 86_400 and is past the retention margin
+        let now = 86_400 + RATE_LIMIT_RETENTION_SECS;
+        assert_eq!(prune_expired(&write_txn, now).unwrap(), 1);
+        let table = write_txn.open_table(tables::RATE_LIMITS).unwrap();
+        assert_eq!(table.len().unwrap(), 1);
+        assert!(table.get("recent").unwrap().is_some());
+    }
+}
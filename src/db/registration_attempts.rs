@@ -0,0 +1,59 @@
+//! Garbage collection of per-network registration attempt records
+//!
+//! A record only matters until its window ends; the next attempt from that
+//! network starts a new one. Pruning here keeps the table small without
+//! scanning it inside every registration.
+
+use redb::WriteTransaction;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use super::tables;
+use crate::Result;
+use crate::models::RegistrationAttemptRecord;
+
+/// Delete every record whose window ended by `now`, returning how many
+#[allow(clippy::result_large_err)]
+pub fn prune_expired(write_txn: &WriteTransaction, now: i64) -> Result<usize> {
+    let mut attempts = write_txn.open_table(tables::REGISTRATION_ATTEMPTS)?;
+    let mut pruned = 0;
+    attempts.retain(|_, bytes| {
+        // Undecodable records are left for whoever reads them to report
+        let expired = bincode::serde::decode_from_slice::<RegistrationAttemptRecord, _>(
+            bytes,
+            BINCODE_CONFIG,
+        )
+        .is_ok_and(|(r, _)| r.window_ends <= now);
+        pruned += usize::from(expired);
+        !expired
+    })?;
+
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+    use redb::{ReadableTable, ReadableTableMetadata};
+
+    #[test]
+    fn test_prune_keeps_open_windows() {
+        let db = open_in_memory_database().unwrap();
+        let write_txn = db.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(tables::REGISTRATION_ATTEMPTS).unwrap();
+            for (network, started) in [("stale", 0), ("recent", 1_000)] {
+                let record = RegistrationAttemptRecord::new(started, 300);
+                let bytes = bincode::serde::encode_to_vec(record, BINCODE_CONFIG).unwrap();
+                table.insert(network, bytes.as_slice()).unwrap();
+            }
+        }
+
+        // "stale" ended its window at 300; "recent" runs until 1_300
+        assert_eq!(prune_expired(&write_txn, 1_000).unwrap(), 1);
+        let table = write_txn.open_table(tables::REGISTRATION_ATTEMPTS).unwrap();
+        assert_eq!(table.len().unwrap(), 1);
+        assert!(table.get("recent").unwrap().is_some());
+    }
+}
@@ -0,0 +1,168 @@
+//! Startup recovery from a corrupted database file
+//!
+//! When enabled, a database that fails to open with a corruption error is
+//! renamed out of the way and replaced by the newest file in the snapshot
+//! directory (e.g. a copy taken by volume snapshots or cron).
+
+use redb::{Error as RedbError, StorageError};
+use std::path::{Path, PathBuf};
+
+use super::snapshot::PARTIAL_SUFFIX;
+use super::{Db, open_database};
+use crate::error::{AppError, Result};
+
+/// Whether `err` means the database file itself is damaged
+///
+/// redb reports an unrecognizable file header as an `InvalidData` IO error.
+pub fn is_corruption(err: &AppError) -> bool {
+    let storage = match err {
+        AppError::Database(RedbError::Corrupted(_)) => return true,
+        AppError::Database(RedbError::Io(e)) => {
+            return e.kind() == std::io::ErrorKind::InvalidData;
+        }
+        AppError::Storage(e) => e,
+        AppError::Table(redb::TableError::Storage(e)) => e,
+        AppError::Transaction(redb::TransactionError::Storage(e)) => e,
+        AppError::Commit(redb::CommitError::Storage(e)) => e,
+        _ => return false,
+    };
+    match storage {
+        StorageError::Corrupted(_) => true,
+        StorageError::Io(e) => e.kind() == std::io::ErrorKind::InvalidData,
+        _ => false,
+    }
+}
+
+/// Open the database, restoring from `snapshot_dir` if the file is corrupted
+///
+/// The corrupted file is kept as `<path>.corrupt-<unix timestamp>` for
+/// inspection. Any other error, or a corrupted file with no snapshot to
+/// restore, is returned unchanged.
+#[allow(clippy::result_large_err)]
+pub fn open_database_or_restore(path: impl AsRef<Path>, snapshot_dir: &Path) -> Result<Db> {
+    let path = path.as_ref();
+
+    let err = match open_database(path) {
+        Ok(db) => return Ok(db),
+        Err(e) if is_corruption(&e) => e,
+        Err(e) => return Err(e),
+    };
+
+    tracing::error!("DATABASE CORRUPTED at {:?}: {}", path, err);
+
+    let Some(snapshot) = latest_snapshot(snapshot_dir)? else {
+        tracing::error!(
+            "No snapshot found in {:?} - manual recovery required",
+            snapshot_dir
+        );
+        return Err(err);
+    };
+
+    let quarantined = quarantine_path(path);
+    std::fs::rename(path, &quarantined).map_err(RedbError::Io)?;
+    tracing::error!("Quarantined corrupted database as {:?}", quarantined);
+
+    std::fs::copy(&snapshot, path).map_err(RedbError::Io)?;
+    tracing::error!(
+        "RESTORED database from snapshot {:?} - writes since that snapshot are lost",
+        snapshot
+    );
+
+    open_database(path)
+}
+
+/// Most recently modified regular file in `dir`, skipping snapshots still
+/// being written
+#[allow(clippy::result_large_err)]
+fn latest_snapshot(dir: &Path) -> Result<Option<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(RedbError::Io(e).into()),
+    };
+
+    let mut latest: Option<(std::time::SystemTime, PathBuf)> = None;
+    for entry in entries {
+        let entry = entry.map_err(RedbError::Io)?;
+        let meta = entry.metadata().map_err(RedbError::Io)?;
+        if !meta.is_file()
+            || entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(PARTIAL_SUFFIX)
+        {
+            continue;
+        }
+        let modified = meta.modified().map_err(RedbError::Io)?;
+        if latest.as_ref().is_none_or(|(t, _)| modified > *t) {
+            latest = Some((modified, entry.path()));
+        }
+    }
+
+    Ok(latest.map(|(_, path)| path))
+}
+
+fn quarantine_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".corrupt-{}", chrono::Utc::now().timestamp()));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tables;
+    use redb::ReadableDatabase;
+    use tempfile::TempDir;
+
+    fn corrupt(path: &Path) {
+        std::fs::write(path, vec![0xAB; 8192]).unwrap();
+    }
+
+    #[test]
+    fn test_restores_latest_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let snapshots = dir.path().join("snapshots");
+        std::fs::create_dir(&snapshots).unwrap();
+
+        // A snapshot holding one user
+        let snapshot = snapshots.join("dailyreps.db");
+        {
+            let db = open_database(&snapshot).unwrap();
+            let write_txn = db.begin_write().unwrap();
+            write_txn
+                .open_table(tables::USERS)
+                .unwrap()
+                .insert("user", [1u8].as_slice())
+                .unwrap();
+            write_txn.commit().unwrap();
+        }
+
+        let path = dir.path().join("dailyreps.db");
+        corrupt(&path);
+        assert!(is_corruption(&open_database(&path).unwrap_err()));
+
+        let db = open_database_or_restore(&path, &snapshots).unwrap();
+        let read_txn = db.begin_read().unwrap();
+        let users = read_txn.open_table(tables::USERS).unwrap();
+        assert!(users.get("user").unwrap().is_some());
+
+        let quarantined = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().contains(".corrupt-"))
+            .count();
+        assert_eq!(quarantined, 1);
+    }
+
+    #[test]
+    fn test_no_snapshot_leaves_file_in_place() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("dailyreps.db");
+        corrupt(&path);
+
+        let err = open_database_or_restore(&path, &dir.path().join("missing")).unwrap_err();
+        assert!(is_corruption(&err));
+        assert!(path.exists());
+    }
+}
@@ -6,9 +6,77 @@ pub const USERS: TableDefinition<&str, &[u8]> = TableDefinition::new("users");
 /// Backups table: storage_key (SHA-256 hash) -> BackupRecord (serialized)
 pub const BACKUPS: TableDefinition<&str, &[u8]> = TableDefinition::new("backups");
 
+/// Backup versions: (storage_key, version) -> BackupVersionRecord (serialized)
+/// Overwritten backups, newest `BACKUP_VERSIONS_KEPT` per storage key
+pub const BACKUP_VERSIONS: TableDefinition<(&str, u64), &[u8]> =
+    TableDefinition::new("backup_versions");
+
+/// Tombstones: user_id -> TombstoneRecord (serialized)
+/// Deleted accounts kept for `DELETION_GRACE_DAYS` so the deletion can be undone
+pub const TOMBSTONES: TableDefinition<&str, &[u8]> = TableDefinition::new("tombstones");
+
+/// Bans: `user:<user_id>` or `ip:<client network>` -> BanRecord (serialized)
+/// Callers matching either are refused on register, store and retrieve
+pub const BANS: TableDefinition<&str, &[u8]> = TableDefinition::new("bans");
+
+/// Nonces: request nonce -> Unix timestamp it can be forgotten at
+/// Each signed request's nonce is accepted once while its timestamp is valid
+pub const NONCES: TableDefinition<&str, i64> = TableDefinition::new("nonces");
+
+/// Upload sessions: upload ID -> UploadSessionRecord (serialized)
+/// Chunked uploads in progress; expired ones are pruned when a new one starts
+pub const UPLOAD_SESSIONS: TableDefinition<&str, &[u8]> = TableDefinition::new("upload_sessions");
+
+/// Upload chunks: (upload ID, sequence) -> chunk bytes, removed with their session
+pub const UPLOAD_CHUNKS: TableDefinition<(&str, u32), &[u8]> =
+    TableDefinition::new("upload_chunks");
+
 /// Rate limits table: user_id -> RateLimitRecord (serialized)
 pub const RATE_LIMITS: TableDefinition<&str, &[u8]> = TableDefinition::new("rate_limits");
 
 /// User backups index: user_id -> Vec<storage_key>
 /// Used for cascade delete when a user is removed
 pub const USER_BACKUPS: TableDefinition<&str, &[u8]> = TableDefinition::new("user_backups");
+
+/// Security events: sequence number -> SecurityEventRecord (serialized)
+/// Rejected requests for the abuse report; pruned after SECURITY_EVENT_RETENTION_DAYS
+pub const SECURITY_EVENTS: TableDefinition<u64, &[u8]> = TableDefinition::new("security_events");
+
+/// Daily stats: UTC date (YYYY-MM-DD) -> DailyStatsRecord (serialized)
+/// Registration and deletion counts, rolled up as they happen
+pub const DAILY_STATS: TableDefinition<&str, &[u8]> = TableDefinition::new("daily_stats");
+
+/// Sync tokens: storage_key -> version counter, bumped on every store
+/// Lets a device detect that another device changed the backup since its last sync
+pub const SYNC_TOKENS: TableDefinition<&str, u64> = TableDefinition::new("sync_tokens");
+
+/// Recovery contacts: user_id -> salted hash of a recovery email/phone
+/// Bound at registration; never the plaintext contact
+pub const RECOVERY_CONTACTS: TableDefinition<&str, &str> =
+    TableDefinition::new("recovery_contacts");
+
+/// Recovery grants: user_id -> RecoveryGrantRecord (serialized)
+/// Pending admin-approved rekeys, removed once used
+pub const RECOVERY_GRANTS: TableDefinition<&str, &[u8]> = TableDefinition::new("recovery_grants");
+
+/// Registration attempts: keyed hash of the client network -> RegistrationAttemptRecord (serialized)
+/// Per-IP registration throttle that survives restarts; expired windows are pruned
+pub const REGISTRATION_ATTEMPTS: TableDefinition<&str, &[u8]> =
+    TableDefinition::new("registration_attempts");
+
+/// Replication queue: user_id -> Unix timestamp it was queued
+/// Accounts changed since the standby last acknowledged them (see `replication`)
+pub const REPLICATION_QUEUE: TableDefinition<&str, i64> = TableDefinition::new("replication_queue");
+
+/// Applied replication states: user_id -> `sent_at` of the last state applied
+/// On a standby, older or replayed states for the account are refused
+pub const REPLICATION_APPLIED: TableDefinition<&str, i64> =
+    TableDefinition::new("replication_applied");
+
+/// Replication dead letters: user_id -> Unix timestamp the standby refused it
+/// Accounts the standby rejected (4xx); queued again by their next change
+pub const REPLICATION_DEAD_LETTERS: TableDefinition<&str, i64> =
+    TableDefinition::new("replication_dead_letters");
+
+/// Metadata: key -> value; holds the on-disk `format_version`
+pub const META: TableDefinition<&str, u64> = TableDefinition::new("meta");
@@ -0,0 +1,125 @@
+//! Instrumented `spawn_blocking` for database work
+//!
+//! Every redb transaction runs on Tokio's blocking pool. Under load, requests
+//! can wait there for a free thread before their transaction even starts;
+//! these gauges make that queueing visible.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+
+use crate::metrics::{self, Metrics};
+
+/// Live counts of database tasks on the blocking pool
+#[derive(Debug, Default)]
+pub struct DbTaskStats {
+    queued: AtomicU64,
+    running: AtomicU64,
+    max_wait_ms: AtomicU64,
+}
+
+impl DbTaskStats {
+    /// Tasks spawned but not yet started
+    pub fn queued(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Tasks currently executing
+    pub fn running(&self) -> u64 {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Longest time a task waited for a thread since startup
+    pub fn max_wait_ms(&self) -> u64 {
+        self.max_wait_ms.load(Ordering::Relaxed)
+    }
+
+    /// Run `f` on the blocking pool, tracking its queue wait and run state
+    pub fn spawn<F, R>(self: &Arc<Self>, metrics: Arc<Metrics>, f: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let stats = Arc::clone(self);
+        stats.queued.fetch_add(1, Ordering::Relaxed);
+        stats.publish(&metrics);
+        let enqueued = Instant::now();
+
+        tokio::task::spawn_blocking(move || {
+            let wait = enqueued.elapsed();
+            stats.queued.fetch_sub(1, Ordering::Relaxed);
+            stats.running.fetch_add(1, Ordering::Relaxed);
+            stats
+                .max_wait_ms
+                .fetch_max(wait.as_millis() as u64, Ordering::Relaxed);
+            metrics.timing(metrics::DB_TASK_WAIT, wait);
+            stats.publish(&metrics);
+
+            // Decrements `running` even if `f` panics
+            let _running = Running { stats, metrics };
+            f()
+        })
+    }
+
+    fn publish(&self, metrics: &Metrics) {
+        metrics.gauge(metrics::DB_TASKS_QUEUED, self.queued());
+        metrics.gauge(metrics::DB_TASKS_RUNNING, self.running());
+        metrics.gauge(metrics::DB_TASK_MAX_WAIT, self.max_wait_ms());
+    }
+}
+
+struct Running {
+    stats: Arc<DbTaskStats>,
+    metrics: Arc<Metrics>,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.stats.running.fetch_sub(1, Ordering::Relaxed);
+        self.stats.publish(&self.metrics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_tracks_running_tasks() {
+        let stats = Arc::new(DbTaskStats::default());
+        let metrics = Arc::new(Metrics::default());
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let task = stats.spawn(Arc::clone(&metrics), move || {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            7
+        });
+
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(stats.running(), 1);
+        assert_eq!(stats.queued(), 0);
+        assert_eq!(metrics.counter(metrics::DB_TASKS_RUNNING), 1);
+
+        release_tx.send(()).unwrap();
+        assert_eq!(task.await.unwrap(), 7);
+        assert_eq!(stats.running(), 0);
+        assert_eq!(metrics.counter(metrics::DB_TASKS_RUNNING), 0);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_task_is_not_left_running() {
+        let stats = Arc::new(DbTaskStats::default());
+        let result = stats
+            .spawn(Arc::new(Metrics::default()), || panic!("boom"))
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(stats.running(), 0);
+    }
+}
@@ -0,0 +1,265 @@
+//! Soft-deleted accounts
+//!
+//! With `DELETION_GRACE_DAYS` set, deleting an account first copies
+//! everything it owns into `tombstones` in stored form, then removes it from
+//! the live tables as before. Until the tombstone is purged the user can put
+//! it all back with `POST /api/user/restore`, and the user ID can't be
+//! registered again.
+
+use redb::{ReadableTable, WriteTransaction};
+
+use super::tables;
+use crate::Result;
+use crate::error::AppError;
+use crate::models::{BackupRecord, TombstoneHead, TombstoneRecord, TombstonedBackup};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+/// Copy `user_id` and the backups under `backup_keys` into a tombstone kept
+/// until `purge_at`; the caller then deletes the live records
+#[allow(clippy::result_large_err)]
+pub fn bury(
+    write_txn: &WriteTransaction,
+    user_id: &str,
+    backup_keys: &[String],
+    now: i64,
+    purge_at: i64,
+) -> Result<()> {
+    let users = write_txn.open_table(tables::USERS)?;
+    let Some(user) = users.get(user_id)?.map(|b| b.value().to_vec()) else {
+        return Err(AppError::UserNotFound);
+    };
+    drop(users);
+
+    let backups = write_txn.open_table(tables::BACKUPS)?;
+    let sync_tokens = write_txn.open_table(tables::SYNC_TOKENS)?;
+    let versions = write_txn.open_table(tables::BACKUP_VERSIONS)?;
+    let mut buried = Vec::with_capacity(backup_keys.len());
+    for key in backup_keys {
+        let Some(record) = backups.get(key.as_str())?.map(|b| b.value().to_vec()) else {
+            continue;
+        };
+        let mut kept = Vec::new();
+        for entry in versions.range((key.as_str(), 0)..=(key.as_str(), u64::MAX))? {
+            let (version, bytes) = entry?;
+            kept.push((version.value().1, bytes.value().to_vec()));
+        }
+        buried.push(TombstonedBackup {
+            storage_key: key.clone(),
+            record,
+            sync_token: sync_tokens
+                .get(key.as_str())?
+                .map(|v| v.value())
+                .unwrap_or(0),
+            versions: kept,
+        });
+    }
+    drop((backups, sync_tokens, versions));
+
+    let rate_limit = write_txn
+        .open_table(tables::RATE_LIMITS)?
+        .get(user_id)?
+        .map(|b| b.value().to_vec());
+    let recovery_contact = write_txn
+        .open_table(tables::RECOVERY_CONTACTS)?
+        .get(user_id)?
+        .map(|c| c.value().to_string());
+
+    let tombstone = TombstoneRecord {
+        deleted_at: now,
+        purge_at,
+        user,
+        backups: buried,
+        rate_limit,
+        recovery_contact,
+    };
+    let bytes = bincode::serde::encode_to_vec(&tombstone, BINCODE_CONFIG)?;
+    write_txn
+        .open_table(tables::TOMBSTONES)?
+        .insert(user_id, bytes.as_slice())?;
+
+    Ok(())
+}
+
+/// Whether `user_id` was deleted and its tombstone not yet purged
+#[allow(clippy::result_large_err)]
+pub fn exists(write_txn: &WriteTransaction, user_id: &str) -> Result<bool> {
+    Ok(write_txn
+        .open_table(tables::TOMBSTONES)?
+        .get(user_id)?
+        .is_some())
+}
+
+/// Put a tombstoned account back, returning its tombstone
+///
+/// `storage_key` must be one of the account's backups, proving the caller
+/// knows the password just as for the deletion. A tombstone past its grace
+/// period at `now` counts as purged.
+#[allow(clippy::result_large_err)]
+pub fn restore(
+    write_txn: &WriteTransaction,
+    user_id: &str,
+    storage_key: &str,
+    now: i64,
+) -> Result<TombstoneRecord> {
+    let mut tombstones = write_txn.open_table(tables::TOMBSTONES)?;
+    let tombstone: TombstoneRecord = match tombstones.get(user_id)? {
+        Some(bytes) => bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?.0,
+        None => return Err(AppError::UserNotFound),
+    };
+    if tombstone.purge_at <= now {
+        return Err(AppError::UserNotFound);
+    }
+
+    let owns_key = tombstone.backups.iter().any(|b| {
+        b.storage_key == storage_key
+            && bincode::serde::decode_from_slice::<BackupRecord, _>(&b.record, BINCODE_CONFIG)
+                .is_ok_and(|(r, _)| r.user_id == user_id)
+    });
+    if !owns_key {
+        return Err(AppError::InvalidInput(
+            "Invalid credentials - storage key does not match user".to_string(),
+        ));
+    }
+
+    let mut users = write_txn.open_table(tables::USERS)?;
+    if users.get(user_id)?.is_some() {
+        return Err(AppError::UserAlreadyExists);
+    }
+    tombstones.remove(user_id)?;
+    drop(tombstones);
+    users.insert(user_id, tombstone.user.as_slice())?;
+    drop(users);
+
+    let mut backups = write_txn.open_table(tables::BACKUPS)?;
+    let mut sync_tokens = write_txn.open_table(tables::SYNC_TOKENS)?;
+    let mut versions = write_txn.open_table(tables::BACKUP_VERSIONS)?;
+    for backup in &tombstone.backups {
+        let key = backup.storage_key.as_str();
+        backups.insert(key, backup.record.as_slice())?;
+        sync_tokens.insert(key, backup.sync_token)?;
+        for (version, bytes) in &backup.versions {
+            versions.insert((key, *version), bytes.as_slice())?;
+        }
+    }
+    drop((backups, sync_tokens, versions));
+
+    let keys: Vec<&str> = tombstone
+        .backups
+        .iter()
+        .map(|b| b.storage_key.as_str())
+        .collect();
+    let keys_bytes = bincode::serde::encode_to_vec(&keys, BINCODE_CONFIG)?;
+    write_txn
+        .open_table(tables::USER_BACKUPS)?
+        .insert(user_id, keys_bytes.as_slice())?;
+
+    if let Some(rate_limit) = &tombstone.rate_limit {
+        write_txn
+            .open_table(tables::RATE_LIMITS)?
+            .insert(user_id, rate_limit.as_slice())?;
+    }
+    if let Some(contact) = &tombstone.recovery_contact {
+        write_txn
+            .open_table(tables::RECOVERY_CONTACTS)?
+            .insert(user_id, contact.as_str())?;
+    }
+
+    Ok(tombstone)
+}
+
+/// Delete every tombstone whose grace period ended before `now`, returning how many
+///
+/// Only each tombstone's [`TombstoneHead`] is decoded, not its backups.
+#[allow(clippy::result_large_err)]
+pub fn purge_expired(write_txn: &WriteTransaction, now: i64) -> Result<usize> {
+    let mut tombstones = write_txn.open_table(tables::TOMBSTONES)?;
+    let mut purged = 0;
+    tombstones.retain(|_, bytes| {
+        let expired = bincode::serde::decode_from_slice::<TombstoneHead, _>(bytes, BINCODE_CONFIG)
+            .is_ok_and(|(head, _)| head.purge_at <= now);
+        purged += usize::from(expired);
+        !expired
+    })?;
+
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+
+    #[test]
+    fn test_bury_restore_and_purge() {
+        let db = open_in_memory_database().unwrap();
+        let (user_id, key) = ("u".repeat(64), "k".repeat(64));
+        let record = BackupRecord::new(user_id.clone(), "ciphertext".to_string(), 1, 2);
+
+        let write_txn = db.begin_write().unwrap();
+        write_txn
+            .open_table(tables::USERS)
+            .unwrap()
+            .insert(user_id.as_str(), b"user".as_slice())
+            .unwrap();
+        let bytes = bincode::serde::encode_to_vec(&record, BINCODE_CONFIG).unwrap();
+        write_txn
+            .open_table(tables::BACKUPS)
+            .unwrap()
+            .insert(key.as_str(), bytes.as_slice())
+            .unwrap();
+        write_txn
+            .open_table(tables::SYNC_TOKENS)
+            .unwrap()
+            .insert(key.as_str(), 3)
+            .unwrap();
+
+        bury(&write_txn, &user_id, std::slice::from_ref(&key), 10, 100).unwrap();
+        write_txn
+            .open_table(tables::USERS)
+            .unwrap()
+            .remove(user_id.as_str())
+            .unwrap();
+        write_txn
+            .open_table(tables::BACKUPS)
+            .unwrap()
+            .remove(key.as_str())
+            .unwrap();
+        assert!(exists(&write_txn, &user_id).unwrap());
+
+        // Only a key the account owned restores it
+        assert!(restore(&write_txn, &user_id, &"x".repeat(64), 20).is_err());
+        assert!(restore(&write_txn, &user_id, &key, 100).is_err());
+        let tombstone = restore(&write_txn, &user_id, &key, 20).unwrap();
+        assert_eq!(tombstone.deleted_at, 10);
+        assert!(!exists(&write_txn, &user_id).unwrap());
+        assert!(
+            write_txn
+                .open_table(tables::BACKUPS)
+                .unwrap()
+                .get(key.as_str())
+                .unwrap()
+                .is_some()
+        );
+        let token = write_txn
+            .open_table(tables::SYNC_TOKENS)
+            .unwrap()
+            .get(key.as_str())
+            .unwrap()
+            .map(|v| v.value());
+        assert_eq!(token, Some(3));
+
+        bury(&write_txn, &user_id, std::slice::from_ref(&key), 10, 100).unwrap();
+        let head: TombstoneHead = {
+            let tombstones = write_txn.open_table(tables::TOMBSTONES).unwrap();
+            let bytes = tombstones.get(user_id.as_str()).unwrap().unwrap();
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)
+                .unwrap()
+                .0
+        };
+        assert_eq!((head.deleted_at, head.purge_at), (10, 100));
+        assert_eq!(purge_expired(&write_txn, 99).unwrap(), 0);
+        assert_eq!(purge_expired(&write_txn, 100).unwrap(), 1);
+        assert!(!exists(&write_txn, &user_id).unwrap());
+    }
+}
@@ -0,0 +1,197 @@
+//! Chunked upload sessions
+//!
+//! A session lives in `upload_sessions` and its chunks in `upload_chunks`,
+//! keyed by upload ID and sequence number, so a large backup never has to
+//! arrive in one request. Committing reads the chunks back in order.
+//! Every chunk extends the session, so an interrupted upload can resume from
+//! `received_bytes`; abandoned sessions are pruned once they expire.
+
+use redb::{ReadableTable, WriteTransaction};
+
+use super::tables;
+use crate::Result;
+use crate::models::UploadSessionRecord;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+/// Insert or replace the session `upload_id`
+#[allow(clippy::result_large_err)]
+pub fn put(
+    write_txn: &WriteTransaction,
+    upload_id: &str,
+    session: &UploadSessionRecord,
+) -> Result<()> {
+    let mut sessions = write_txn.open_table(tables::UPLOAD_SESSIONS)?;
+    let bytes = bincode::serde::encode_to_vec(session, BINCODE_CONFIG)?;
+    sessions.insert(upload_id, bytes.as_slice())?;
+
+    Ok(())
+}
+
+/// The session `upload_id`, unless it doesn't exist or expired before `now`
+#[allow(clippy::result_large_err)]
+pub fn get(
+    table: &impl ReadableTable<&'static str, &'static [u8]>,
+    upload_id: &str,
+    now: i64,
+) -> Result<Option<UploadSessionRecord>> {
+    let Some(bytes) = table.get(upload_id)? else {
+        return Ok(None);
+    };
+    let (session, _): (UploadSessionRecord, _) =
+        bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+
+    Ok((session.expires_at > now).then_some(session))
+}
+
+/// Expiry times of the live sessions of `user_id`, after deleting every
+/// session expired before `now`
+#[allow(clippy::result_large_err)]
+pub fn prune_and_list(write_txn: &WriteTransaction, user_id: &str, now: i64) -> Result<Vec<i64>> {
+    prune_expired(write_txn, now)?;
+
+    let sessions = write_txn.open_table(tables::UPLOAD_SESSIONS)?;
+    let mut live = Vec::new();
+    for entry in sessions.iter()? {
+        let (_, bytes) = entry?;
+        let (session, _): (UploadSessionRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+        if session.user_id == user_id {
+            live.push(session.expires_at);
+        }
+    }
+
+    Ok(live)
+}
+
+/// Delete every session (and its chunks) expired before `now`, returning how many
+#[allow(clippy::result_large_err)]
+pub fn prune_expired(write_txn: &WriteTransaction, now: i64) -> Result<usize> {
+    let mut expired = Vec::new();
+    {
+        let sessions = write_txn.open_table(tables::UPLOAD_SESSIONS)?;
+        for entry in sessions.iter()? {
+            let (id, bytes) = entry?;
+            let (session, _): (UploadSessionRecord, _) =
+                bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+            if session.expires_at <= now {
+                expired.push(id.value().to_string());
+            }
+        }
+    }
+
+    for upload_id in &expired {
+        remove(write_txn, upload_id)?;
+    }
+
+    Ok(expired.len())
+}
+
+/// Store chunk number `seq` of `upload_id`
+#[allow(clippy::result_large_err)]
+pub fn put_chunk(
+    write_txn: &WriteTransaction,
+    upload_id: &str,
+    seq: u32,
+    chunk: &[u8],
+) -> Result<()> {
+    let mut chunks = write_txn.open_table(tables::UPLOAD_CHUNKS)?;
+    chunks.insert((upload_id, seq), chunk)?;
+
+    Ok(())
+}
+
+/// All chunks of `upload_id` concatenated in order
+#[allow(clippy::result_large_err)]
+pub fn assemble(
+    table: &impl ReadableTable<(&'static str, u32), &'static [u8]>,
+    upload_id: &str,
+) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for entry in table.range((upload_id, 0)..=(upload_id, u32::MAX))? {
+        data.extend_from_slice(entry?.1.value());
+    }
+
+    Ok(data)
+}
+
+/// Delete the session `upload_id` and its chunks
+#[allow(clippy::result_large_err)]
+pub fn remove(write_txn: &WriteTransaction, upload_id: &str) -> Result<()> {
+    let mut sessions = write_txn.open_table(tables::UPLOAD_SESSIONS)?;
+    sessions.remove(upload_id)?;
+
+    let mut chunks = write_txn.open_table(tables::UPLOAD_CHUNKS)?;
+    chunks.retain_in((upload_id, 0)..=(upload_id, u32::MAX), |_, _| false)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+    use redb::ReadableDatabase;
+
+    fn session(user_id: &str, expires_at: i64) -> UploadSessionRecord {
+        UploadSessionRecord {
+            user_id: user_id.to_string(),
+            storage_key: "k".repeat(64),
+            expires_at,
+            chunks: 0,
+            received_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_assemble_and_remove() {
+        let db = open_in_memory_database().unwrap();
+        let (id, other) = ("a".repeat(32), "b".repeat(32));
+
+        let write_txn = db.begin_write().unwrap();
+        put(&write_txn, &id, &session("u", 100)).unwrap();
+        put_chunk(&write_txn, &id, 0, b"hello ").unwrap();
+        put_chunk(&write_txn, &id, 1, b"world").unwrap();
+        put_chunk(&write_txn, &other, 0, b"other").unwrap();
+        write_txn.commit().unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let chunks = read_txn.open_table(tables::UPLOAD_CHUNKS).unwrap();
+        assert_eq!(assemble(&chunks, &id).unwrap(), b"hello world");
+        drop(chunks);
+        drop(read_txn);
+
+        let write_txn = db.begin_write().unwrap();
+        remove(&write_txn, &id).unwrap();
+        write_txn.commit().unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let sessions = read_txn.open_table(tables::UPLOAD_SESSIONS).unwrap();
+        assert!(get(&sessions, &id, 0).unwrap().is_none());
+        let chunks = read_txn.open_table(tables::UPLOAD_CHUNKS).unwrap();
+        assert!(assemble(&chunks, &id).unwrap().is_empty());
+        assert_eq!(assemble(&chunks, &other).unwrap(), b"other");
+    }
+
+    #[test]
+    fn test_prune_expired_sessions() {
+        let db = open_in_memory_database().unwrap();
+        let (live, expired, foreign) = ("a".repeat(32), "b".repeat(32), "c".repeat(32));
+
+        let write_txn = db.begin_write().unwrap();
+        put(&write_txn, &live, &session("u", 200)).unwrap();
+        put(&write_txn, &expired, &session("u", 100)).unwrap();
+        put(&write_txn, &foreign, &session("v", 200)).unwrap();
+        put_chunk(&write_txn, &expired, 0, b"stale").unwrap();
+        assert_eq!(prune_and_list(&write_txn, "u", 150).unwrap(), [200]);
+        write_txn.commit().unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let sessions = read_txn.open_table(tables::UPLOAD_SESSIONS).unwrap();
+        assert!(get(&sessions, &live, 150).unwrap().is_some());
+        assert!(get(&sessions, &live, 200).unwrap().is_none());
+        assert!(sessions.get(expired.as_str()).unwrap().is_none());
+        let chunks = read_txn.open_table(tables::UPLOAD_CHUNKS).unwrap();
+        assert!(assemble(&chunks, &expired).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+//! Version history of overwritten backups
+//!
+//! Each store moves the backup it replaces into `backup_versions`, keyed by
+//! storage key and the version (sync token) it had, so a client that uploaded
+//! a corrupted backup can fetch an earlier one. Only the newest
+//! `BACKUP_VERSIONS_KEPT` per storage key are kept; history follows the
+//! backup through rekeys and is deleted with it.
+
+use redb::{ReadableTable, Table, WriteTransaction};
+
+use super::tables;
+use crate::Result;
+use crate::models::{BackupRecord, BackupVersionRecord};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+type VersionTable<'txn> = Table<'txn, (&'static str, u64), &'static [u8]>;
+
+/// Keep `record`, stored as `version`, in the history of `storage_key`,
+/// dropping versions beyond the newest `keep` (none are kept when 0)
+#[allow(clippy::result_large_err)]
+pub fn archive(
+    write_txn: &WriteTransaction,
+    storage_key: &str,
+    version: u64,
+    record: &BackupRecord,
+    keep: u64,
+) -> Result<()> {
+    if keep == 0 {
+        return Ok(());
+    }
+
+    let mut versions = write_txn.open_table(tables::BACKUP_VERSIONS)?;
+    let archived = BackupVersionRecord {
+        encrypted_data: record.encrypted_data.clone(),
+        stored_at: record.updated_at,
+        checksum: record.checksum.clone(),
+    };
+    let bytes = bincode::serde::encode_to_vec(&archived, BINCODE_CONFIG)?;
+    versions.insert((storage_key, version), bytes.as_slice())?;
+
+    let expired: Vec<u64> = version_numbers(&versions, storage_key)?
+        .into_iter()
+        .rev()
+        .skip(keep as usize)
+        .collect();
+    for old in expired {
+        versions.remove((storage_key, old))?;
+    }
+
+    Ok(())
+}
+
+/// Versions kept for `storage_key`, newest first
+#[allow(clippy::result_large_err)]
+pub fn list(
+    table: &impl ReadableTable<(&'static str, u64), &'static [u8]>,
+    storage_key: &str,
+) -> Result<Vec<(u64, BackupVersionRecord)>> {
+    let mut versions = Vec::new();
+    for entry in table.range((storage_key, 0)..=(storage_key, u64::MAX))? {
+        let (key, bytes) = entry?;
+        let (record, _) = bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+        versions.push((key.value().1, record));
+    }
+    versions.reverse();
+
+    Ok(versions)
+}
+
+/// One kept version of `storage_key`
+#[allow(clippy::result_large_err)]
+pub fn get(
+    table: &impl ReadableTable<(&'static str, u64), &'static [u8]>,
+    storage_key: &str,
+    version: u64,
+) -> Result<Option<BackupVersionRecord>> {
+    table
+        .get((storage_key, version))?
+        .map(|bytes| {
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)
+                .map(|(record, _)| record)
+                .map_err(Into::into)
+        })
+        .transpose()
+}
+
+/// Delete the whole history of `storage_key`
+#[allow(clippy::result_large_err)]
+pub fn remove_all(write_txn: &WriteTransaction, storage_key: &str) -> Result<()> {
+    let mut versions = write_txn.open_table(tables::BACKUP_VERSIONS)?;
+    for version in version_numbers(&versions, storage_key)? {
+        versions.remove((storage_key, version))?;
+    }
+
+    Ok(())
+}
+
+/// Move the history of `old_key` to `new_key` (after a rekey)
+#[allow(clippy::result_large_err)]
+pub fn rename(write_txn: &WriteTransaction, old_key: &str, new_key: &str) -> Result<()> {
+    let mut versions = write_txn.open_table(tables::BACKUP_VERSIONS)?;
+    for version in version_numbers(&versions, old_key)? {
+        let bytes = versions
+            .remove((old_key, version))?
+            .map(|bytes| bytes.value().to_vec());
+        if let Some(bytes) = bytes {
+            versions.insert((new_key, version), bytes.as_slice())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Version numbers kept for `storage_key`, oldest first
+#[allow(clippy::result_large_err)]
+fn version_numbers(versions: &VersionTable, storage_key: &str) -> Result<Vec<u64>> {
+    let mut numbers = Vec::new();
+    for entry in versions.range((storage_key, 0)..=(storage_key, u64::MAX))? {
+        numbers.push(entry?.0.value().1);
+    }
+
+    Ok(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+    use redb::ReadableDatabase;
+
+    fn record(data: &str, at: i64) -> BackupRecord {
+        BackupRecord::new("a".repeat(64), data.to_string(), 0, at)
+    }
+
+    #[test]
+    fn test_archive_keeps_newest_versions() {
+        let db = open_in_memory_database().unwrap();
+        let key = "k".repeat(64);
+        let other = "o".repeat(64);
+
+        let write_txn = db.begin_write().unwrap();
+        for version in 1..=5 {
+            archive(&write_txn, &key, version, &record("v", version as i64), 3).unwrap();
+        }
+        archive(&write_txn, &other, 1, &record("x", 1), 3).unwrap();
+        write_txn.commit().unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let table = read_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+        let kept: Vec<u64> = list(&table, &key).unwrap().iter().map(|v| v.0).collect();
+        assert_eq!(kept, [5, 4, 3]);
+        assert_eq!(get(&table, &key, 4).unwrap().unwrap().stored_at, 4);
+        assert!(get(&table, &key, 1).unwrap().is_none());
+        assert_eq!(list(&table, &other).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rename_and_remove() {
+        let db = open_in_memory_database().unwrap();
+        let (old_key, new_key) = ("a".repeat(64), "b".repeat(64));
+
+        let write_txn = db.begin_write().unwrap();
+        archive(&write_txn, &old_key, 1, &record("v1", 1), 5).unwrap();
+        archive(&write_txn, &old_key, 2, &record("v2", 2), 5).unwrap();
+        rename(&write_txn, &old_key, &new_key).unwrap();
+        write_txn.commit().unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let table = read_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+        assert!(list(&table, &old_key).unwrap().is_empty());
+        assert_eq!(list(&table, &new_key).unwrap().len(), 2);
+        drop(table);
+        drop(read_txn);
+
+        let write_txn = db.begin_write().unwrap();
+        remove_all(&write_txn, &new_key).unwrap();
+        write_txn.commit().unwrap();
+
+        let read_txn = db.begin_read().unwrap();
+        let table = read_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+        assert!(list(&table, &new_key).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+//! Detection of clients re-uploading the same blob over and over
+//!
+//! A sync loop that ignores `POST /api/backup/check` keeps sending the
+//! identical encrypted blob, costing a signature check and a write each time.
+//! [`DuplicateTracker`] remembers the checksum of each user's last stored
+//! upload and refuses further identical copies once a daily allowance is used
+//! up. Only uploads that were actually stored count, so a client retrying
+//! after a 429 or 409 doesn't use up its allowance.
+//!
+//! At most [`MAX_TRACKED_USERS`] users are tracked; ended windows are swept on
+//! a timer (see [`DuplicateTracker::spawn_pruner`]), and while the map is full
+//! new users simply go untracked.
+
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::constants::DUPLICATE_UPLOADS_PER_DAY;
+use crate::error::{AppError, RateLimitHit, RateLimitKind, Result};
+
+/// Users tracked at once; uploads from further users aren't tracked
+pub const MAX_TRACKED_USERS: usize = 50_000;
+
+const WINDOW_SECS: i64 = 86_400;
+
+#[derive(Debug)]
+struct Entry {
+    checksum: String,
+    /// Identical uploads after the first in the current window
+    repeats: u32,
+    window_ends: i64,
+}
+
+/// Last upload checksum and repeat count per user (in memory only)
+#[derive(Debug, Default)]
+pub struct DuplicateTracker {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl DuplicateTracker {
+    /// Refuse an upload of `checksum` by `user_id` if storing it would
+    /// re-send the same blob more than [`DUPLICATE_UPLOADS_PER_DAY`] times
+    ///
+    /// Counts nothing; call [`DuplicateTracker::record`] once it is stored.
+    #[allow(clippy::result_large_err)]
+    pub fn check(&self, user_id: &str, checksum: &str, now: i64) -> Result<()> {
+        let entries = self
+            .entries
+            .lock()
+            .expect("duplicate tracker lock poisoned");
+
+        let Some(entry) = entries.get(user_id) else {
+            return Ok(());
+        };
+        if now >= entry.window_ends
+            || entry.checksum != checksum
+            || entry.repeats < DUPLICATE_UPLOADS_PER_DAY
+        {
+            return Ok(());
+        }
+
+        tracing::warn!(
+            "Identical backup re-uploaded {} times today",
+            entry.repeats + 1
+        );
+        Err(AppError::RateLimitExceeded(RateLimitHit {
+            kind: RateLimitKind::Duplicate,
+            limit: DUPLICATE_UPLOADS_PER_DAY as u64,
+            window_secs: WINDOW_SECS as u64,
+            retry_after_secs: (entry.window_ends - now) as u64,
+        }))
+    }
+
+    /// Note a stored upload of `checksum` by `user_id`
+    pub fn record(&self, user_id: &str, checksum: &str, now: i64) {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("duplicate tracker lock poisoned");
+
+        if entries.len() >= MAX_TRACKED_USERS && !entries.contains_key(user_id) {
+            return;
+        }
+
+        let entry = entries.entry(user_id.to_string()).or_insert_with(|| Entry {
+            checksum: String::new(),
+            repeats: 0,
+            window_ends: now + WINDOW_SECS,
+        });
+
+        if now >= entry.window_ends {
+            entry.repeats = 0;
+            entry.window_ends = now + WINDOW_SECS;
+        }
+
+        if entry.checksum == checksum {
+            entry.repeats = entry.repeats.saturating_add(1);
+        } else {
+            entry.checksum = checksum.to_string();
+            entry.repeats = 0;
+        }
+    }
+
+    /// Drop users whose window ended by `now`, returning how many
+    pub fn prune(&self, now: i64) -> usize {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("duplicate tracker lock poisoned");
+        let before = entries.len();
+        entries.retain(|_, e| e.window_ends > now);
+        before - entries.len()
+    }
+
+    /// Prune ended windows every `interval`
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn_pruner(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                ticker.tick().await;
+                let pruned = self.prune(Utc::now().timestamp());
+                if pruned > 0 {
+                    tracing::debug!("Pruned {} duplicate-upload windows", pruned);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check, then record if allowed, like a store
+    fn upload(tracker: &DuplicateTracker, user: &str, checksum: &str, now: i64) -> Result<()> {
+        tracker.check(user, checksum, now)?;
+        tracker.record(user, checksum, now);
+        Ok(())
+    }
+
+    #[test]
+    fn test_refuses_repeated_identical_uploads() {
+        let tracker = DuplicateTracker::default();
+        let now = 1_000_000;
+
+        // The first copy plus the allowed repeats
+        for i in 0..=DUPLICATE_UPLOADS_PER_DAY as i64 {
+            assert!(upload(&tracker, "user", "aaaa", now + i).is_ok());
+        }
+        match upload(&tracker, "user", "aaaa", now + 100) {
+            Err(AppError::RateLimitExceeded(hit)) => {
+                assert_eq!(hit.kind, RateLimitKind::Duplicate);
+                assert_eq!(hit.retry_after_secs, WINDOW_SECS as u64 - 100);
+            }
+            other => panic!("expected duplicate limit, got {:?}", other),
+        }
+
+        // Other users and changed data are unaffected
+        assert!(upload(&tracker, "other", "aaaa", now + 100).is_ok());
+        assert!(upload(&tracker, "user", "bbbb", now + 101).is_ok());
+    }
+
+    #[test]
+    fn test_checks_alone_count_nothing() {
+        let tracker = DuplicateTracker::default();
+        let now = 1_000_000;
+        tracker.record("user", "aaaa", now);
+
+        // Attempts refused elsewhere (429, 409) are checked but never recorded
+        for _ in 0..=DUPLICATE_UPLOADS_PER_DAY * 2 {
+            assert!(tracker.check("user", "aaaa", now).is_ok());
+        }
+        assert!(upload(&tracker, "user", "aaaa", now).is_ok());
+    }
+
+    #[test]
+    fn test_window_resets_daily() {
+        let tracker = DuplicateTracker::default();
+        let now = 1_000_000;
+        for _ in 0..=DUPLICATE_UPLOADS_PER_DAY + 1 {
+            let _ = upload(&tracker, "user", "aaaa", now);
+        }
+        assert!(upload(&tracker, "user", "aaaa", now).is_err());
+        assert!(upload(&tracker, "user", "aaaa", now + WINDOW_SECS).is_ok());
+    }
+
+    #[test]
+    fn test_full_map_stops_tracking_new_users() {
+        let tracker = DuplicateTracker::default();
+        let now = 1_000_000;
+        for i in 0..MAX_TRACKED_USERS {
+            tracker.record(&i.to_string(), "aaaa", now);
+        }
+
+        // Untracked users are never refused, and the map doesn't grow
+        for _ in 0..=DUPLICATE_UPLOADS_PER_DAY + 1 {
+            assert!(upload(&tracker, "new", "aaaa", now).is_ok());
+        }
+        assert_eq!(tracker.entries.lock().unwrap().len(), MAX_TRACKED_USERS);
+
+        // Once the windows end, the pruner frees the room
+        assert_eq!(tracker.prune(now + WINDOW_SECS), MAX_TRACKED_USERS);
+        tracker.record("new", "aaaa", now + WINDOW_SECS);
+        assert_eq!(tracker.entries.lock().unwrap().len(), 1);
+    }
+}
@@ -1,11 +1,15 @@
 use axum::{
     Json,
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
-use serde_json::json;
+use serde::Serialize;
+use serde_json::{Value, json};
 use thiserror::Error;
 
+use crate::constants::IN_FLIGHT_RETRY_AFTER_SECS;
+use crate::read_only::DiskFull;
+
 /// Application error type
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -24,6 +28,9 @@ pub enum AppError {
     #[error("Commit error: {0}")]
     Commit(#[from] redb::CommitError),
 
+    #[error("Compaction error: {0}")]
+    Compaction(#[from] redb::CompactionError),
+
     #[error("Serialization error: {0}")]
     Serialization(#[from] bincode::error::EncodeError),
 
@@ -33,6 +40,21 @@ pub enum AppError {
     #[error("Task join error: {0}")]
     TaskJoin(#[from] tokio::task::JoinError),
 
+    #[error("Random number generator error: {0}")]
+    Random(#[from] getrandom::Error),
+
+    #[error("Archive error: {0}")]
+    Archive(String),
+
+    #[error("Encoding error: {0}")]
+    Encoding(String),
+
+    #[error("Incompatible database: {0}")]
+    IncompatibleDatabase(String),
+
+    #[error("Dump error: {0}")]
+    Dump(String),
+
     #[error("User already exists")]
     UserAlreadyExists,
 
@@ -51,16 +73,227 @@ pub enum AppError {
     #[error("Invalid signature")]
     InvalidSignature,
 
-    #[error("Rate limit exceeded")]
-    RateLimitExceeded,
+    /// Signed request whose nonce was already used
+    #[error("Replayed request")]
+    ReplayedRequest,
+
+    #[error("Rate limit exceeded ({} limit)", .0.kind.as_str())]
+    RateLimitExceeded(RateLimitHit),
+
+    #[error("Backup changed on another device")]
+    SyncConflict(Box<SyncConflict>),
+
+    #[error("Too many concurrent requests")]
+    TooManyInFlight,
 
     #[error("Unauthorized")]
     Unauthorized,
+
+    #[error("Region not allowed")]
+    RegionNotAllowed,
+
+    #[error("Network blocked for registration")]
+    NetworkBlocked,
+
+    /// Caller's user ID or network is on the admin ban list
+    #[error("Banned")]
+    Banned,
+
+    #[error("Captcha verification failed")]
+    CaptchaFailed,
+
+    #[error("Unsupported media type")]
+    UnsupportedMediaType,
+
+    #[error("Server is read-only")]
+    ReadOnly,
+
+    /// Operation needs the database to itself and something else was using it
+    #[error("Database is busy")]
+    DatabaseBusy,
+
+    /// Writes turned off by an operator for maintenance
+    #[error("Server is in maintenance mode")]
+    Maintenance,
+
+    #[error("Backup failed its integrity check")]
+    CorruptBackup,
+
+    #[error("Upload session not found")]
+    UploadNotFound,
+
+    /// Seconds until the user's oldest unfinished upload expires
+    #[error("Too many unfinished uploads")]
+    TooManyUploads(u64),
+
+    /// Chunk sent for an offset other than the bytes already received
+    #[error("Upload offset mismatch (server has {0} bytes)")]
+    UploadOffsetMismatch(u64),
+}
+
+impl AppError {
+    /// Whether this is a redb write that failed because the disk is full
+    pub fn is_disk_full(&self) -> bool {
+        let io = match self {
+            AppError::Database(redb::Error::Io(e)) => e,
+            AppError::Storage(redb::StorageError::Io(e))
+            | AppError::Commit(redb::CommitError::Storage(redb::StorageError::Io(e)))
+            | AppError::Table(redb::TableError::Storage(redb::StorageError::Io(e)))
+            | AppError::Transaction(redb::TransactionError::Storage(redb::StorageError::Io(e))) => {
+                e
+            }
+            _ => return false,
+        };
+        io.kind() == std::io::ErrorKind::StorageFull || io.raw_os_error() == Some(ENOSPC)
+    }
+
+    /// Stable machine-readable code, sent as `code` in error bodies
+    ///
+    /// Clients branch on these instead of the human-readable `error`, so an
+    /// existing code must never change meaning; add a new one instead.
+    pub fn code(&self) -> &'static str {
+        if self.is_disk_full() {
+            return "STORAGE_FULL";
+        }
+
+        match self {
+            AppError::Database(_)
+            | AppError::Transaction(_)
+            | AppError::Table(_)
+            | AppError::Storage(_)
+            | AppError::Commit(_)
+            | AppError::Compaction(_)
+            | AppError::Serialization(_)
+            | AppError::Deserialization(_)
+            | AppError::TaskJoin(_)
+            | AppError::Random(_)
+            | AppError::Archive(_)
+            | AppError::Encoding(_)
+            | AppError::IncompatibleDatabase(_)
+            | AppError::Dump(_) => "INTERNAL_ERROR",
+            AppError::UserAlreadyExists => "USER_EXISTS",
+            AppError::UserNotFound => "USER_NOT_FOUND",
+            AppError::BackupNotFound => "BACKUP_NOT_FOUND",
+            AppError::InvalidInput(_) => "INVALID_INPUT",
+            AppError::PayloadTooLarge => "QUOTA_EXCEEDED",
+            AppError::InvalidSignature => "INVALID_SIGNATURE",
+            AppError::ReplayedRequest => "REPLAYED_REQUEST",
+            AppError::RateLimitExceeded(_) => "RATE_LIMITED",
+            AppError::SyncConflict(_) => "SYNC_CONFLICT",
+            AppError::TooManyInFlight => "TOO_MANY_IN_FLIGHT",
+            AppError::Unauthorized => "UNAUTHORIZED",
+            AppError::RegionNotAllowed => "REGION_NOT_ALLOWED",
+            AppError::NetworkBlocked => "NETWORK_BLOCKED",
+            AppError::Banned => "BANNED",
+            AppError::CaptchaFailed => "CAPTCHA_FAILED",
+            AppError::UnsupportedMediaType => "UNSUPPORTED_MEDIA_TYPE",
+            AppError::ReadOnly => "READ_ONLY",
+            AppError::DatabaseBusy => "DATABASE_BUSY",
+            AppError::Maintenance => "MAINTENANCE",
+            AppError::CorruptBackup => "BACKUP_CORRUPT",
+            AppError::UploadNotFound => "UPLOAD_NOT_FOUND",
+            AppError::TooManyUploads(_) => "TOO_MANY_UPLOADS",
+            AppError::UploadOffsetMismatch(_) => "UPLOAD_OFFSET_MISMATCH",
+        }
+    }
+}
+
+/// `ENOSPC` on Linux and macOS, for platforms that don't map it to `StorageFull`
+const ENOSPC: i32 = 28;
+
+/// Both sides of a store rejected for a stale sync token
+///
+/// Returned with 409 so the client can show what changed, merge `current`
+/// with its own version, and store again using `current.sync_token`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncConflict {
+    pub current: ConflictCurrent,
+    pub base: ConflictBase,
+}
+
+/// The server's copy of the backup
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictCurrent {
+    pub data: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+    #[serde(rename = "syncToken")]
+    pub sync_token: String,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: u64,
+    /// Hex SHA-256 of `data`
+    pub checksum: String,
+}
+
+/// The version the client's upload was based on
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictBase {
+    #[serde(rename = "syncToken")]
+    pub sync_token: String,
+    /// When the base was stored, while it is still in the version history
+    #[serde(rename = "updatedAt", skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+/// Which per-user backup limit a store ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKind {
+    Hourly,
+    Daily,
+    /// Minimum spacing between backups (`MIN_BACKUP_INTERVAL_SECS`)
+    Interval,
+    /// The same blob re-uploaded too often (`DUPLICATE_UPLOADS_PER_DAY`)
+    Duplicate,
+    /// Registrations from one network (`REGISTER_RATE_LIMIT_REQUESTS`)
+    Registration,
+    /// Any `/api` requests from one network (`RATE_LIMIT_REQUESTS`)
+    Network,
+}
+
+impl RateLimitKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RateLimitKind::Hourly => "hourly",
+            RateLimitKind::Daily => "daily",
+            RateLimitKind::Interval => "interval",
+            RateLimitKind::Duplicate => "duplicate",
+            RateLimitKind::Registration => "registration",
+            RateLimitKind::Network => "network",
+        }
+    }
+}
+
+/// A refused backup, returned with 429 so the client can show when the next
+/// sync is possible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitHit {
+    pub kind: RateLimitKind,
+    /// Backups allowed per window (1 for the interval limit)
+    pub limit: u64,
+    /// Window length in seconds
+    pub window_secs: u64,
+    /// Seconds until a backup will be accepted again
+    pub retry_after_secs: u64,
 }
 
 /// Implement IntoResponse to convert AppError into HTTP responses
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let code = self.code();
+        if self.is_disk_full() {
+            // Tagged so `guard_writes` can switch the server to read-only
+            tracing::error!("Disk full: {:?}", self);
+            let body = error_body(
+                code,
+                json!({
+                    "error": "Server storage is full - try again later"
+                }),
+            );
+            let mut response = (StatusCode::SERVICE_UNAVAILABLE, body).into_response();
+            response.extensions_mut().insert(DiskFull);
+            return response;
+        }
+
         let (status, error_message) = match self {
             AppError::Database(ref e) => {
                 tracing::error!("Database error: {:?}", e);
@@ -82,6 +315,10 @@ impl IntoResponse for AppError {
                 tracing::error!("Commit error: {:?}", e);
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
             }
+            AppError::Compaction(ref e) => {
+                tracing::error!("Compaction error: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
             AppError::Serialization(ref e) => {
                 tracing::error!("Serialization error: {:?}", e);
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
@@ -94,6 +331,26 @@ impl IntoResponse for AppError {
                 tracing::error!("Task join error: {:?}", e);
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
             }
+            AppError::Random(ref e) => {
+                tracing::error!("Random number generator error: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            AppError::Archive(ref e) => {
+                tracing::error!("Archive error: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            AppError::Encoding(ref e) => {
+                tracing::error!("Encoding error: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            AppError::IncompatibleDatabase(ref e) => {
+                tracing::error!("Incompatible database: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            AppError::Dump(ref e) => {
+                tracing::error!("Dump error: {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
             AppError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AppError::UserNotFound => (StatusCode::UNAUTHORIZED, "User not found"),
             AppError::BackupNotFound => (StatusCode::NOT_FOUND, "Backup not found"),
@@ -106,20 +363,131 @@ impl IntoResponse for AppError {
                 StatusCode::UNAUTHORIZED,
                 "Invalid signature - data must come from official app",
             ),
-            AppError::RateLimitExceeded => (
-                StatusCode::TOO_MANY_REQUESTS,
-                "Rate limit exceeded - too many requests",
+            AppError::ReplayedRequest => (
+                StatusCode::CONFLICT,
+                "Request was already processed - sign it again with a new nonce",
             ),
+            AppError::RateLimitExceeded(hit) => {
+                let body = error_body(
+                    code,
+                    json!({
+                        "error": "Rate limit exceeded - too many requests",
+                        "limitType": hit.kind.as_str(),
+                        "limit": hit.limit,
+                        "window": hit.window_secs,
+                        "retryAfterSecs": hit.retry_after_secs,
+                    }),
+                );
+                let retry_after = [(header::RETRY_AFTER, hit.retry_after_secs.to_string())];
+                return (StatusCode::TOO_MANY_REQUESTS, retry_after, body).into_response();
+            }
+            AppError::SyncConflict(conflict) => {
+                // Carries the server's version so the client can merge
+                let body = error_body(
+                    code,
+                    json!({
+                        "error": "Backup was changed on another device - merge and retry",
+                        "current": conflict.current,
+                        "base": conflict.base,
+                    }),
+                );
+                return (StatusCode::CONFLICT, body).into_response();
+            }
+            AppError::UploadOffsetMismatch(received_bytes) => {
+                // Tells the client where to resume
+                let body = error_body(
+                    code,
+                    json!({
+                        "error": "Chunk offset does not match the bytes received - resume from receivedBytes",
+                        "receivedBytes": received_bytes,
+                    }),
+                );
+                return (StatusCode::CONFLICT, body).into_response();
+            }
+            AppError::TooManyInFlight => {
+                return too_many_requests(
+                    code,
+                    "Too many concurrent requests for this user",
+                    IN_FLIGHT_RETRY_AFTER_SECS,
+                );
+            }
+            AppError::TooManyUploads(retry_after_secs) => {
+                return too_many_requests(
+                    code,
+                    "Too many unfinished uploads for this user",
+                    retry_after_secs,
+                );
+            }
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            AppError::RegionNotAllowed => (
+                StatusCode::FORBIDDEN,
+                "Service is not available in your region",
+            ),
+            AppError::NetworkBlocked => (
+                StatusCode::FORBIDDEN,
+                "Registration is not available from this network",
+            ),
+            AppError::Banned => (StatusCode::FORBIDDEN, "Access denied"),
+            AppError::CaptchaFailed => (StatusCode::FORBIDDEN, "Captcha verification failed"),
+            AppError::UnsupportedMediaType => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Unsupported content type - use JSON, MessagePack, CBOR, or protobuf",
+            ),
+            AppError::ReadOnly => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Server is temporarily read-only - backups can still be retrieved",
+            ),
+            AppError::DatabaseBusy => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Database is busy - try again shortly",
+            ),
+            AppError::Maintenance => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Server is under maintenance - backups can still be retrieved, try saving again later",
+            ),
+            AppError::CorruptBackup => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Stored backup failed its integrity check",
+            ),
+            AppError::UploadNotFound => {
+                (StatusCode::NOT_FOUND, "Upload session not found or expired")
+            }
         };
 
-        let body = Json(json!({
-            "error": error_message
-        }));
+        let body = error_body(
+            code,
+            json!({
+                "error": error_message
+            }),
+        );
 
         (status, body).into_response()
     }
 }
 
+/// 429 with a `Retry-After` header, repeated as `retryAfterSecs`
+fn too_many_requests(code: &str, message: &str, retry_after_secs: u64) -> Response {
+    let body = error_body(
+        code,
+        json!({
+            "error": message,
+            "retryAfterSecs": retry_after_secs,
+        }),
+    );
+    let retry_after = [(header::RETRY_AFTER, retry_after_secs.to_string())];
+    (StatusCode::TOO_MANY_REQUESTS, retry_after, body).into_response()
+}
+
+/// JSON error body with its `code`, tagged with the request ID when there is one
+fn error_body(code: &str, mut body: Value) -> Json<Value> {
+    if let Some(fields) = body.as_object_mut() {
+        fields.insert("code".to_string(), Value::String(code.to_string()));
+    }
+    if let (Some(id), Some(fields)) = (crate::request_id::current(), body.as_object_mut()) {
+        fields.insert("requestId".to_string(), Value::String(id));
+    }
+    Json(body)
+}
+
 /// Result type alias for application results
 pub type Result<T> = std::result::Result<T, AppError>;
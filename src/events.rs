@@ -0,0 +1,106 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before it starts lagging
+const FEED_CAPACITY: usize = 1024;
+
+/// Kind of mutation recorded in the change feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Register,
+    Store,
+    Delete,
+    /// Some of a user's backups deleted, the account kept
+    Prune,
+    /// A deleted account brought back during its grace period
+    Restore,
+    /// A backup moved to a new storage key after account recovery
+    Rekey,
+}
+
+/// One committed mutation
+///
+/// Storage keys are deliberately left out: they grant read access to a
+/// user's backup and have no place in an analytics feed.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    /// Monotonic sequence number (resets on restart)
+    pub seq: u64,
+    #[serde(rename = "type")]
+    pub kind: ChangeKind,
+    pub user_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<usize>,
+    pub at: String,
+}
+
+/// In-process change-data-capture feed
+///
+/// Handlers publish after their write transaction commits; subscribers
+/// (the admin SSE stream) each get their own bounded buffer. Publishing with
+/// no subscribers is a no-op.
+#[derive(Debug)]
+pub struct ChangeFeed {
+    sender: broadcast::Sender<ChangeEvent>,
+    seq: AtomicU64,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(FEED_CAPACITY);
+        Self {
+            sender,
+            seq: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ChangeFeed {
+    /// Record a committed mutation
+    pub fn publish(&self, kind: ChangeKind, user_id: impl Into<String>, size_bytes: Option<usize>) {
+        let event = ChangeEvent {
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) + 1,
+            kind,
+            user_id: user_id.into(),
+            size_bytes,
+            at: Utc::now().to_rfc3339(),
+        };
+
+        // Err only means nobody is listening
+        let _ = self.sender.send(event);
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscriber_receives_events_in_order() {
+        let feed = ChangeFeed::default();
+        let mut rx = feed.subscribe();
+
+        feed.publish(ChangeKind::Register, "a", None);
+        feed.publish(ChangeKind::Store, "a", Some(42));
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!((first.seq, first.kind), (1, ChangeKind::Register));
+
+        let second = rx.recv().await.unwrap();
+        assert_eq!((second.seq, second.kind), (2, ChangeKind::Store));
+        assert_eq!(second.size_bytes, Some(42));
+    }
+
+    #[test]
+    fn test_publish_without_subscribers_is_noop() {
+        ChangeFeed::default().publish(ChangeKind::Delete, "a", None);
+    }
+}
@@ -0,0 +1,179 @@
+//! Country lookup for rejected requests
+//!
+//! When `GEOIP_DB_PATH` points at a MaxMind Country database, security events
+//! are tagged with the country of the client's IP so the abuse report can show
+//! where attacks come from. Only rejected requests are looked up, and only the
+//! ISO country code is kept; legitimate traffic is never located.
+//!
+//! The same database backs the optional country access policy
+//! (`ALLOWED_COUNTRIES` / `DENIED_COUNTRIES`), which does look up every
+//! `/api` request but only to admit or refuse it.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::request::Parts,
+    middleware::Next,
+    response::Response,
+};
+use maxminddb::{MaxMindDbError, Reader, geoip2};
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::routes::api_version::ApiVersion;
+use crate::{AppState, metrics, proxy};
+
+/// A loaded MaxMind (GeoLite2/GeoIP2) Country database
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    /// Load the database at `path` into memory
+    pub fn open(path: &str) -> Result<Self, MaxMindDbError> {
+        Ok(Self {
+            reader: Reader::open_readfile(path)?,
+        })
+    }
+
+    /// ISO 3166-1 alpha-2 code for `ip`, if the database knows it
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let result = self.reader.lookup(ip).ok()?;
+        let country = result.decode::<geoip2::Country>().ok()??;
+        country.country.iso_code.map(str::to_string)
+    }
+}
+
+impl std::fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GeoIp")
+            .field("database_type", &self.reader.metadata().database_type)
+            .finish()
+    }
+}
+
+/// The client's IP address, if known
+///
+/// The TCP peer address, or the address forwarded by a proxy (see
+/// [`proxy`] for when `CLIENT_IP_HEADER` and `TRUSTED_PROXIES` are believed).
+/// Handlers pass it along to the security event log and the ban check; it is
+/// never stored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip());
+
+        Ok(ClientIp(proxy::client_ip(
+            &state.config(),
+            peer,
+            &parts.headers,
+        )))
+    }
+}
+
+/// Whether the country access policy admits `country`
+///
+/// A denied country is always refused. With an allowlist, anything not on
+/// it is refused, including IPs the database can't place, since the
+/// allowlist usually reflects a legal restriction.
+pub fn country_allowed(config: &Config, country: Option<&str>) -> bool {
+    if let Some(country) = country
+        && config.denied_countries.iter().any(|c| c == country)
+    {
+        return false;
+    }
+
+    config.allowed_countries.is_empty()
+        || country.is_some_and(|country| config.allowed_countries.iter().any(|c| c == country))
+}
+
+/// Refuse `/api` requests from countries outside the access policy
+///
+/// With `COUNTRY_POLICY_REGISTRATION_ONLY` (the default) only registration is
+/// refused and every other `/api` route is let through without a lookup.
+/// Those routes answer only to an existing account's credentials, so users
+/// who signed up before the policy, or while travelling, keep access to
+/// their backups, but nothing here checks that the caller has an account.
+pub async fn enforce_country_policy(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    request: Request,
+    next: Next,
+) -> crate::Result<Response> {
+    let config = &state.config();
+    if config.allowed_countries.is_empty() && config.denied_countries.is_empty() {
+        return Ok(next.run(request).await);
+    }
+
+    let registering =
+        ApiVersion::of_path(request.uri().path()).is_some_and(|(_, path)| path == "/api/register");
+    if config.country_policy_registration_only && !registering {
+        return Ok(next.run(request).await);
+    }
+
+    let country = state
+        .geoip
+        .as_ref()
+        .zip(ip.0)
+        .and_then(|(geoip, ip)| geoip.country(ip));
+    if !country_allowed(config, country.as_deref()) {
+        tracing::info!(
+            "Request from {} refused by country policy",
+            country.as_deref().unwrap_or("unknown country")
+        );
+        state.metrics.incr(metrics::REGION_BLOCKED);
+        return Err(AppError::RegionNotAllowed);
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_config;
+
+    fn policy(allowed: &[&str], denied: &[&str]) -> Config {
+        Config {
+            allowed_countries: allowed.iter().map(|c| c.to_string()).collect(),
+            denied_countries: denied.iter().map(|c| c.to_string()).collect(),
+            ..test_config()
+        }
+    }
+
+    #[test]
+    fn test_country_allowed() {
+        let open = policy(&[], &[]);
+        assert!(country_allowed(&open, Some("US")));
+        assert!(country_allowed(&open, None));
+
+        let allow = policy(&["NL", "BE"], &[]);
+        assert!(country_allowed(&allow, Some("NL")));
+        assert!(!country_allowed(&allow, Some("US")));
+        assert!(!country_allowed(&allow, None));
+
+        let deny = policy(&[], &["KP"]);
+        assert!(!country_allowed(&deny, Some("KP")));
+        assert!(country_allowed(&deny, Some("NL")));
+        assert!(country_allowed(&deny, None));
+
+        // Deny wins over allow
+        assert!(!country_allowed(&policy(&["NL"], &["NL"]), Some("NL")));
+    }
+
+    #[test]
+    fn test_open_missing_database_fails() {
+        assert!(GeoIp::open("/nonexistent/GeoLite2-Country.mmdb").is_err());
+    }
+}
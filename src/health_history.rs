@@ -0,0 +1,76 @@
+//! Recent `/health` results, kept in memory for `/admin/health/history`
+//!
+//! A database hiccup that clears before anyone looks only shows up as a
+//! failed probe in the load balancer; this keeps the last few results (and
+//! why they failed) visible on the server itself.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Health checks remembered
+pub const HEALTH_HISTORY_LEN: usize = 256;
+
+/// One `/health` result
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthSample {
+    /// When the check ran (Unix timestamp)
+    pub at: i64,
+    pub healthy: bool,
+    /// Database check duration
+    pub latency_ms: u64,
+    /// What went wrong, for unhealthy samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Fixed-size ring of the most recent health checks
+#[derive(Debug, Default)]
+pub struct HealthHistory {
+    samples: Mutex<VecDeque<HealthSample>>,
+}
+
+impl HealthHistory {
+    /// Append a result, dropping the oldest once full
+    pub fn record(&self, sample: HealthSample) {
+        let mut samples = self.samples.lock().expect("health history lock poisoned");
+        if samples.len() == HEALTH_HISTORY_LEN {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// All remembered results, newest first
+    pub fn recent(&self) -> Vec<HealthSample> {
+        let samples = self.samples.lock().expect("health history lock poisoned");
+        samples.iter().rev().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(at: i64, healthy: bool) -> HealthSample {
+        HealthSample {
+            at,
+            healthy,
+            latency_ms: 1,
+            error: (!healthy).then(|| "database unavailable".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_keeps_newest_samples() {
+        let history = HealthHistory::default();
+        for at in 0..HEALTH_HISTORY_LEN as i64 + 10 {
+            history.record(sample(at, at % 2 == 0));
+        }
+
+        let recent = history.recent();
+        assert_eq!(recent.len(), HEALTH_HISTORY_LEN);
+        assert_eq!(recent[0].at, HEALTH_HISTORY_LEN as i64 + 9);
+        assert_eq!(recent.last().unwrap().at, 10);
+        assert!(recent[0].error.is_some());
+    }
+}
@@ -0,0 +1,120 @@
+//! Per-user concurrent request limiting
+//!
+//! A buggy client loop firing dozens of parallel uploads would otherwise queue
+//! them all behind redb's single writer. Handlers take an [`InFlightGuard`]
+//! once the caller is authenticated and hold it until they return; requests
+//! beyond the limit are rejected with 429 instead of waiting.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{AppError, Result};
+
+/// Counts requests currently being handled per user
+#[derive(Debug, Default)]
+pub struct InFlightLimiter {
+    max_per_user: usize,
+    counts: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl InFlightLimiter {
+    /// Allow at most `max_per_user` concurrent requests per user (0 = unlimited)
+    pub fn new(max_per_user: usize) -> Self {
+        Self {
+            max_per_user,
+            counts: Arc::default(),
+        }
+    }
+
+    /// Reserve a slot for `user_id`, released when the guard is dropped
+    #[allow(clippy::result_large_err)]
+    pub fn acquire(&self, user_id: &str) -> Result<InFlightGuard> {
+        if self.max_per_user > 0 {
+            let mut counts = self.counts.lock().expect("in-flight lock poisoned");
+            let count = counts.entry(user_id.to_string()).or_insert(0);
+            if *count >= self.max_per_user {
+                tracing::warn!(
+                    "Concurrent request limit reached: {}/{}",
+                    count,
+                    self.max_per_user
+                );
+                return Err(AppError::TooManyInFlight);
+            }
+            *count += 1;
+        }
+
+        Ok(InFlightGuard {
+            counts: (self.max_per_user > 0).then(|| Arc::clone(&self.counts)),
+            user_id: user_id.to_string(),
+        })
+    }
+
+    /// Requests currently in flight for `user_id`
+    pub fn in_flight(&self, user_id: &str) -> usize {
+        let counts = self.counts.lock().expect("in-flight lock poisoned");
+        counts.get(user_id).copied().unwrap_or(0)
+    }
+}
+
+/// A reserved slot; dropping it frees the slot
+#[derive(Debug)]
+pub struct InFlightGuard {
+    counts: Option<Arc<Mutex<HashMap<String, usize>>>>,
+    user_id: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let Some(counts) = &self.counts else {
+            return;
+        };
+        let mut counts = counts.lock().expect("in-flight lock poisoned");
+        if let Some(count) = counts.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_per_user() {
+        let limiter = InFlightLimiter::new(2);
+
+        let a1 = limiter.acquire("alice").unwrap();
+        let _a2 = limiter.acquire("alice").unwrap();
+        assert!(matches!(
+            limiter.acquire("alice"),
+            Err(AppError::TooManyInFlight)
+        ));
+
+        // Other users are unaffected
+        let _b1 = limiter.acquire("bob").unwrap();
+
+        drop(a1);
+        assert_eq!(limiter.in_flight("alice"), 1);
+        assert!(limiter.acquire("alice").is_ok());
+    }
+
+    #[test]
+    fn test_released_users_are_forgotten() {
+        let limiter = InFlightLimiter::new(1);
+        drop(limiter.acquire("alice").unwrap());
+
+        assert_eq!(limiter.in_flight("alice"), 0);
+        assert!(limiter.counts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_zero_disables_limit() {
+        let limiter = InFlightLimiter::new(0);
+        let guards: Vec<_> = (0..10).map(|_| limiter.acquire("alice").unwrap()).collect();
+        assert_eq!(guards.len(), 10);
+        assert_eq!(limiter.in_flight("alice"), 0);
+    }
+}
@@ -0,0 +1,51 @@
+//! Background jobs watched by the readiness probe
+//!
+//! Sweepers, the inactive-account purge and the blocklist refresher loop
+//! forever, so a finished task means one panicked or gave up. Handles are
+//! registered here at startup and `/health/ready` reports any that stopped.
+
+use std::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/// Long-running tasks spawned at startup
+#[derive(Debug, Default)]
+pub struct BackgroundJobs {
+    jobs: Mutex<Vec<(&'static str, JoinHandle<()>)>>,
+}
+
+impl BackgroundJobs {
+    /// Watch `handle` under `name`
+    pub fn track(&self, name: &'static str, handle: JoinHandle<()>) {
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((name, handle));
+    }
+
+    /// Names of tracked jobs that are no longer running
+    pub fn stopped(&self) -> Vec<&'static str> {
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|(_, handle)| handle.is_finished())
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_reports_finished_jobs() {
+        let jobs = BackgroundJobs::default();
+        jobs.track("forever", tokio::spawn(std::future::pending::<()>()));
+        jobs.track("done", tokio::spawn(async {}));
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(jobs.stopped(), ["done"]);
+    }
+}
@@ -2,30 +2,172 @@
 //!
 //! This module exports the core types and functions for testing and reuse.
 
+pub mod blocklist;
+pub mod captcha;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod config;
 pub mod constants;
 pub mod db;
+pub mod duplicates;
 pub mod error;
+pub mod events;
+pub mod geoip;
+pub mod health_history;
+pub mod in_flight;
+pub mod jobs;
+pub mod metrics;
 pub mod models;
+#[cfg(feature = "netsim")]
+pub mod netsim;
+pub mod network_limit;
+pub mod notifier;
+pub mod oidc;
+pub mod proto;
+pub mod proxy;
+pub mod purge;
+pub mod read_only;
+pub mod replication;
+pub mod request_id;
 pub mod routes;
+pub mod runtime_stats;
 pub mod security;
+pub mod security_events;
+pub mod seed;
+pub mod server;
+pub mod telemetry;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod tls;
 
+pub use blocklist::Blocklist;
 pub use config::Config;
-pub use db::{Db, open_database};
+pub use db::{Db, open_database, open_in_memory_database};
 pub use error::{AppError, Result};
+pub use events::ChangeFeed;
+pub use geoip::{ClientIp, GeoIp};
+pub use in_flight::InFlightLimiter;
+pub use metrics::Metrics;
+pub use notifier::Notifier;
+pub use read_only::WriteGate;
 
-use std::sync::Arc;
+use captcha::CaptchaVerifier;
+use oidc::OidcVerifier;
+use std::sync::{Arc, RwLock};
 
 /// Application state shared across all handlers
 #[derive(Clone)]
 pub struct AppState {
     pub db: Db,
-    pub config: Config,
+    /// Current configuration; read it with [`AppState::config`]
+    config: Arc<RwLock<Arc<Config>>>,
+    pub metrics: Arc<Metrics>,
+    pub captcha: Option<CaptchaVerifier>,
+    pub oidc: Option<Arc<OidcVerifier>>,
+    pub notifier: Arc<Notifier>,
+    pub events: Arc<ChangeFeed>,
+    pub in_flight: Arc<InFlightLimiter>,
+    /// Rejected requests waiting to be written for the abuse report
+    pub security_events: Arc<security_events::SecurityEventBuffer>,
+    /// Open sync push connections per user, capped separately from
+    /// `in_flight` so idle devices don't hold up uploads
+    pub subscriptions: Arc<InFlightLimiter>,
+    pub telemetry: Arc<telemetry::Telemetry>,
+    pub geoip: Option<Arc<GeoIp>>,
+    pub blocklist: Arc<Blocklist>,
+    pub writes: Arc<WriteGate>,
+    pub health_history: Arc<health_history::HealthHistory>,
+    pub db_tasks: Arc<db::tasks::DbTaskStats>,
+    pub duplicates: Arc<duplicates::DuplicateTracker>,
+    pub network_limiter: Arc<network_limit::NetworkLimiter>,
+    pub jobs: Arc<jobs::BackgroundJobs>,
 }
 
 impl AppState {
     /// Create a new AppState with the given database and configuration
-    pub fn new(db: Arc<redb::Database>, config: Config) -> Self {
-        Self { db, config }
+    pub fn new(db: impl Into<Db>, config: Config) -> Self {
+        let captcha = config
+            .captcha_secret_key
+            .clone()
+            .map(|secret| CaptchaVerifier::new(secret, config.captcha_verify_url.clone()));
+
+        let oidc = match (&config.oidc_issuer, &config.oidc_audience) {
+            (Some(issuer), Some(audience)) => Some(Arc::new(OidcVerifier::new(
+                issuer.clone(),
+                audience.clone(),
+                config.oidc_allowed_subjects.clone(),
+            ))),
+            _ => None,
+        };
+
+        let in_flight = Arc::new(InFlightLimiter::new(config.max_in_flight_per_user));
+
+        let writes = Arc::new(WriteGate::default());
+        writes.set_maintenance(config.maintenance_mode);
+
+        Self {
+            db: db.into(),
+            config: Arc::new(RwLock::new(Arc::new(config))),
+            metrics: Arc::new(Metrics::default()),
+            captcha,
+            oidc,
+            notifier: Arc::new(Notifier::disabled()),
+            events: Arc::new(ChangeFeed::default()),
+            in_flight,
+            security_events: Arc::default(),
+            subscriptions: Arc::new(InFlightLimiter::new(
+                constants::MAX_SYNC_SUBSCRIPTIONS_PER_USER,
+            )),
+            telemetry: Arc::default(),
+            geoip: None,
+            blocklist: Arc::default(),
+            writes,
+            health_history: Arc::default(),
+            db_tasks: Arc::default(),
+            duplicates: Arc::default(),
+            network_limiter: Arc::default(),
+            jobs: Arc::default(),
+        }
+    }
+
+    /// The configuration in effect
+    ///
+    /// A snapshot: hold on to it for a request's duration so settings can't
+    /// change halfway through, and call again for the next one.
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Apply the reloadable settings of `new` (see [`Config::with_reloaded`])
+    ///
+    /// Returns the names of the settings that changed. Everything else keeps
+    /// its startup value.
+    pub fn apply_config(&self, new: &Config) -> Vec<&'static str> {
+        let mut current = self.config.write().unwrap_or_else(|e| e.into_inner());
+        let (merged, changed) = current.with_reloaded(new);
+        if !changed.is_empty() {
+            *current = Arc::new(merged);
+            tracing::info!("Reloaded configuration: {}", changed.join(", "));
+        }
+        changed
+    }
+
+    /// Re-read the configuration (see [`Config::load`]) and apply the
+    /// reloadable settings
+    pub fn reload_config(&self) -> std::result::Result<Vec<&'static str>, String> {
+        let new = Config::load()?;
+        Ok(self.apply_config(&new))
+    }
+
+    /// Run database work on the blocking pool, instrumented (see [`db::tasks`])
+    pub fn spawn_db<F, R>(&self, f: F) -> tokio::task::JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.db_tasks.spawn(Arc::clone(&self.metrics), f)
     }
 }
@@ -1,13 +1,35 @@
-use axum::{
-    Router,
-    routing::{delete, get, post},
-};
 use std::net::SocketAddr;
-use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use dailyreps_backup_server::{AppState, Config, open_database, routes::*};
+use dailyreps_backup_server::{
+    AppState, Config, GeoIp, Metrics, Notifier,
+    constants::{
+        IN_MEMORY_PRUNE_INTERVAL_SECS, INACTIVE_PURGE_INTERVAL_SECS, NONCE_CLEANUP_INTERVAL_SECS,
+        RATE_LIMIT_CLEANUP_INTERVAL_SECS, REGISTRATION_ATTEMPT_CLEANUP_INTERVAL_SECS,
+        SECURITY_EVENT_FLUSH_INTERVAL_SECS, TLS_RELOAD_INTERVAL_SECS,
+        TOMBSTONE_PURGE_INTERVAL_SECS, UPLOAD_CLEANUP_INTERVAL_SECS,
+    },
+    db::{
+        self, dump, nonces, rate_limits, registration_attempts, restore::open_database_or_restore,
+        tombstones, uploads,
+    },
+    metrics::StatsdSink,
+    open_database, open_in_memory_database, purge, replication,
+    routes::{RouterOptions, build_router, cors_layer},
+    security_events,
+    seed::{SeedOptions, seed_database},
+    server::{self, ServerOptions},
+    tls::{CertReloader, TlsListener},
+};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -23,7 +45,7 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Starting DailyReps Backup Server...");
 
     // Load configuration
-    let config = Config::from_env().map_err(|e| anyhow::anyhow!(e))?;
+    let mut config = Config::load().map_err(|e| anyhow::anyhow!(e))?;
 
     tracing::info!(
         "Environment: {}, Server: {}",
@@ -31,56 +53,339 @@ async fn main() -> anyhow::Result<()> {
         config.server_address()
     );
 
-    // Open or create the embedded database
-    let db = open_database(&config.database_path)?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    // Configure CORS - parse origins and fail fast on invalid config
-    let allowed_origins: Vec<_> = config
-        .allowed_origins
-        .iter()
-        .map(|s| {
-            s.parse()
-                .map_err(|e| anyhow::anyhow!("Invalid CORS origin '{}': {}", s, e))
-        })
-        .collect::<Result<_, _>>()?;
-
-    let cors = CorsLayer::new()
-        .allow_origin(allowed_origins)
-        .allow_methods([
-            axum::http::Method::GET,
-            axum::http::Method::POST,
-            axum::http::Method::DELETE,
-        ])
-        .allow_headers(Any);
+    // `seed [--users N] [--seed S]` fills the database with demo data and exits
+    if args.first().map(String::as_str) == Some("seed") {
+        let options = parse_seed_args(&args[1..])?;
+        let db = open_database(&config.database_path)?;
+        let summary = seed_database(&db, &options)?;
+        println!(
+            "Seeded {} users ({} bytes) into {} - usernames demo-{}-<n>, password '{}'",
+            summary.users,
+            summary.total_bytes,
+            config.database_path,
+            options.seed,
+            dailyreps_backup_server::seed::DEMO_PASSWORD
+        );
+        return Ok(());
+    }
+
+    // `export --out FILE` / `import --in FILE` write and load a portable dump
+    // (gzipped if FILE ends in `.gz`) and exit
+    if args.first().map(String::as_str) == Some("export") {
+        let path = parse_file_arg(&args[1..], "--out")?;
+        let db = open_database(&config.database_path)?;
+        let summary = export_dump(&db, &path)?;
+        println!(
+            "Exported {} entries from {} to {}",
+            summary.entries(),
+            config.database_path,
+            path
+        );
+        return Ok(());
+    }
+    if args.first().map(String::as_str) == Some("import") {
+        let path = parse_file_arg(&args[1..], "--in")?;
+        let db = open_database(&config.database_path)?;
+        let summary = import_dump(&db, &path)?;
+        println!(
+            "Imported {} entries from {} into {}",
+            summary.entries(),
+            path,
+            config.database_path
+        );
+        return Ok(());
+    }
+
+    // Open or create the embedded database (`--ephemeral` keeps it in memory)
+    let ephemeral = args.iter().any(|arg| arg == "--ephemeral");
+    let db = if ephemeral {
+        tracing::warn!("Ephemeral mode: all data is discarded on shutdown");
+        // DATABASE_PATH isn't the live database, so copying it would be wrong
+        config.snapshot_dir = None;
+        open_in_memory_database()?
+    } else if let Some(snapshot_dir) = &config.db_restore_snapshot_dir {
+        open_database_or_restore(&config.database_path, snapshot_dir.as_ref())?
+    } else {
+        open_database(&config.database_path)?
+    };
 
     // Create app state
-    let state = AppState {
-        db,
-        config: config.clone(),
+    let mut state = AppState::new(db, config.clone());
+
+    // Configure CORS - parse origins and fail fast on invalid config
+    let cors = cors_layer(&state).map_err(|e| anyhow::anyhow!(e))?;
+
+    // Push metrics to StatsD/DogStatsD if configured
+    if let Some(addr) = &config.statsd_addr {
+        let sink = StatsdSink::connect(addr, &config.statsd_prefix, config.statsd_tags.clone())
+            .map_err(|e| anyhow::anyhow!("Invalid STATSD_ADDR '{}': {}", addr, e))?;
+        tracing::info!("StatsD metrics enabled: {}", addr);
+        state.metrics = Arc::new(Metrics::new(Some(sink)));
+    }
+
+    // Tag abuse events with a country if a GeoIP database is configured
+    if let Some(path) = &config.geoip_db_path {
+        let geoip = GeoIp::open(path)
+            .map_err(|e| anyhow::anyhow!("Invalid GEOIP_DB_PATH '{}': {}", path, e))?;
+        tracing::info!("GeoIP enrichment of security events enabled: {}", path);
+        state.geoip = Some(Arc::new(geoip));
+    }
+
+    // Refuse registrations from Tor exits/datacenters if lists are configured
+    if !config.registration_blocklist_urls.is_empty() {
+        tracing::info!(
+            "Registration blocklist enabled ({} lists)",
+            config.registration_blocklist_urls.len()
+        );
+        let refresher = Arc::clone(&state.blocklist).spawn_refresher(
+            config.registration_blocklist_urls.clone(),
+            Duration::from_secs(config.blocklist_refresh_secs),
+        );
+        state.jobs.track("blocklist refresh", refresher);
+    }
+
+    // Send operator alerts to a chat webhook if configured
+    if let Some(url) = &config.alert_webhook_url {
+        tracing::info!("Alert webhook enabled ({:?})", config.alert_webhook_kind);
+        state.notifier = Arc::new(Notifier::spawn(
+            url.clone(),
+            config.alert_webhook_kind,
+            config.environment.clone(),
+        ));
+    }
+
+    // Sweep abandoned chunked uploads, spent nonces, stale rate-limit and
+    // registration attempt records and tombstones past their grace period (watched by /health/ready, like the other background jobs)
+    state.jobs.track(
+        "upload sweeper",
+        db::spawn_sweeper(
+            state.db.clone(),
+            Duration::from_secs(UPLOAD_CLEANUP_INTERVAL_SECS),
+            "expired upload sessions",
+            uploads::prune_expired,
+        ),
+    );
+    state.jobs.track(
+        "tombstone sweeper",
+        db::spawn_sweeper(
+            state.db.clone(),
+            Duration::from_secs(TOMBSTONE_PURGE_INTERVAL_SECS),
+            "purged tombstones",
+            tombstones::purge_expired,
+        ),
+    );
+    state.jobs.track(
+        "nonce sweeper",
+        db::spawn_sweeper(
+            state.db.clone(),
+            Duration::from_secs(NONCE_CLEANUP_INTERVAL_SECS),
+            "expired nonces",
+            nonces::prune_expired,
+        ),
+    );
+    state.jobs.track(
+        "rate limit sweeper",
+        db::spawn_sweeper(
+            state.db.clone(),
+            Duration::from_secs(RATE_LIMIT_CLEANUP_INTERVAL_SECS),
+            "expired rate-limit records",
+            rate_limits::prune_expired,
+        ),
+    );
+    state.jobs.track(
+        "registration attempt sweeper",
+        db::spawn_sweeper(
+            state.db.clone(),
+            Duration::from_secs(REGISTRATION_ATTEMPT_CLEANUP_INTERVAL_SECS),
+            "expired registration attempts",
+            registration_attempts::prune_expired,
+        ),
+    );
+
+    // Forget per-network request and duplicate-upload windows that have ended
+    let pruner = Arc::clone(&state.network_limiter)
+        .spawn_pruner(Duration::from_secs(IN_MEMORY_PRUNE_INTERVAL_SECS));
+    state.jobs.track("network limiter pruner", pruner);
+    let pruner = Arc::clone(&state.duplicates)
+        .spawn_pruner(Duration::from_secs(IN_MEMORY_PRUNE_INTERVAL_SECS));
+    state.jobs.track("duplicate tracker pruner", pruner);
+
+    // Write rejected-request events for the abuse report in batches
+    state.jobs.track(
+        "security event flush",
+        security_events::spawn(
+            state.clone(),
+            Duration::from_secs(SECURITY_EVENT_FLUSH_INTERVAL_SECS),
+        ),
+    );
+
+    // Purge abandoned accounts if configured
+    if let Some(days) = config.inactive_purge_days {
+        tracing::info!("Inactive account purge enabled: {} days", days);
+        let handle = purge::spawn(
+            state.clone(),
+            days,
+            Duration::from_secs(INACTIVE_PURGE_INTERVAL_SECS),
+        );
+        state.jobs.track("inactive purge", handle);
+    }
+
+    // Return space freed by deletions to the filesystem if configured
+    if let Some(hours) = config.compaction_interval_hours {
+        tracing::info!("Scheduled compaction enabled: every {} hours", hours);
+        let handle = db::compaction::spawn(state.clone(), Duration::from_secs(hours * 3600));
+        state.jobs.track("compaction", handle);
+    }
+
+    // Forward committed account changes to a warm standby if configured
+    if let (Some(url), Some(secret)) = (&config.replica_url, &config.replication_secret) {
+        tracing::info!("Replication to standby enabled: {}", url);
+        let handle = replication::spawn(state.clone(), url.clone(), secret.clone());
+        state.jobs.track("replication", handle);
+    }
+
+    // Re-read the reloadable settings on SIGHUP
+    #[cfg(unix)]
+    state
+        .jobs
+        .track("config reload", spawn_reload_on_hangup(state.clone()));
+
+    if config.maintenance_mode {
+        tracing::warn!("Maintenance mode: writes are refused until turned off");
+    }
+
+    // Serve HTTPS directly if configured, picking up renewed certificates
+    let certs = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert), Some(key)) => {
+            let certs = Arc::new(CertReloader::load(cert, key)?);
+            let handle = certs
+                .clone()
+                .spawn_watcher(Duration::from_secs(TLS_RELOAD_INTERVAL_SECS));
+            state.jobs.track("tls certificate reload", handle);
+            Some(certs)
+        }
+        _ => None,
     };
 
-    // Build router
-    let mut app = Router::new()
-        .route("/health", get(health_check))
-        .route("/api/register", post(register_user))
-        .route("/api/backup", post(store_backup).get(retrieve_backup))
-        .route("/api/user", delete(delete_user))
-        .route("/admin/stats", get(admin_stats))
-        .layer(cors)
-        .with_state(state);
-
-    // Add request logging if enabled
+    // Build router (request logging if enabled)
     if config.log_requests {
         tracing::info!("Request logging enabled");
-        app = app.layer(TraceLayer::new_for_http());
     }
 
+    let events_state = state.clone();
+    let app = build_router(
+        state,
+        RouterOptions {
+            cors: Some(cors),
+            log_requests: config.log_requests,
+        },
+    );
+
     // Start server
     let addr: SocketAddr = config.server_address().parse()?;
-    tracing::info!("Server listening on {}", addr);
-
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+
+    let options = ServerOptions::from_config(&config);
+    match certs {
+        Some(certs) => {
+            tracing::info!("Server listening on {} (TLS)", addr);
+            let listener = TlsListener::new(listener, certs, options)?;
+            server::serve(listener, app, options.without_connection_limit()).await;
+        }
+        None => {
+            tracing::info!("Server listening on {}", addr);
+            server::serve(listener, app, options).await;
+        }
+    }
+
+    // Keep the rejected requests logged since the last flush
+    if let Err(e) = security_events::flush(&events_state).await {
+        tracing::error!("Failed to record security events: {}", e);
+    }
 
     Ok(())
 }
+
+/// Reload the configuration (see [`AppState::reload_config`]) on each SIGHUP
+#[cfg(unix)]
+fn spawn_reload_on_hangup(state: AppState) -> tokio::task::JoinHandle<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            match state.reload_config() {
+                Ok(changed) if changed.is_empty() => {
+                    tracing::info!("SIGHUP: no reloadable settings changed")
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("SIGHUP: keeping the current configuration: {}", e),
+            }
+        }
+    })
+}
+
+/// Parse `--users N` and `--seed S` for the `seed` subcommand
+fn parse_seed_args(args: &[String]) -> anyhow::Result<SeedOptions> {
+    let mut options = SeedOptions::default();
+    let mut args = args.iter();
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Missing value for '{}'", flag))?;
+        match flag.as_str() {
+            "--users" => options.users = value.parse()?,
+            "--seed" => options.seed = value.parse()?,
+            other => anyhow::bail!("Unknown seed option '{}'", other),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Parse `FLAG FILE` for the `export` and `import` subcommands
+fn parse_file_arg(args: &[String], flag: &str) -> anyhow::Result<String> {
+    match args {
+        [f, path] if f == flag => Ok(path.clone()),
+        _ => anyhow::bail!("Usage: {} FILE", flag),
+    }
+}
+
+fn is_gzip(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| ext == "gz")
+}
+
+fn export_dump(db: &db::Db, path: &str) -> anyhow::Result<dump::DumpSummary> {
+    let file = BufWriter::new(File::create(path)?);
+    let now = chrono::Utc::now();
+    let summary = if is_gzip(path) {
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let summary = dump::export(db, &mut encoder, now)?;
+        encoder.finish()?.flush()?;
+        summary
+    } else {
+        dump::export(db, file, now)?
+    };
+
+    Ok(summary)
+}
+
+fn import_dump(db: &db::Db, path: &str) -> anyhow::Result<dump::DumpSummary> {
+    let file = File::open(path)?;
+    let summary = if is_gzip(path) {
+        dump::import(db, BufReader::new(flate2::read::GzDecoder::new(file)))?
+    } else {
+        dump::import(db, BufReader::new(file))?
+    };
+
+    Ok(summary)
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::net::UdpSocket;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Counter: users registered
+pub const REGISTRATIONS: &str = "registrations";
+
+/// Counter: backups stored
+pub const BACKUPS_STORED: &str = "backups.stored";
+
+/// Counter: backups retrieved
+pub const BACKUPS_RETRIEVED: &str = "backups.retrieved";
+
+/// Counter: uploads skipped because the client already matched the stored data
+pub const UPLOADS_SKIPPED: &str = "backups.uploads_skipped";
+
+/// Counter: reads that found a backup not matching its stored checksum
+pub const BACKUPS_CORRUPT: &str = "backups.corrupt";
+
+/// Counter: backups deleted individually (not with their account)
+pub const BACKUPS_DELETED: &str = "backups.deleted";
+
+/// Counter: users deleted
+pub const USERS_DELETED: &str = "users.deleted";
+
+/// Counter: users removed by the inactive-account purge
+pub const USERS_PURGED: &str = "users.purged";
+
+/// Counter: deleted users restored within the grace period
+pub const USERS_RESTORED: &str = "users.restored";
+
+/// Counter: requests rejected by the per-user rate limiter
+pub const RATE_LIMITED: &str = "rate_limited";
+
+/// Counter: uploads refused as repeated identical blobs
+pub const DUPLICATE_UPLOADS: &str = "backups.duplicates_refused";
+
+/// Counter: requests rejected for an invalid HMAC signature
+pub const SIGNATURE_FAILURES: &str = "signature_failures";
+
+/// Counter: requests rejected by the country access policy
+pub const REGION_BLOCKED: &str = "region_blocked";
+
+/// Counter: registrations refused by the network blocklist
+pub const REGISTRATIONS_BLOCKED: &str = "registrations.blocked";
+
+/// Counter: requests refused because the user or network is banned
+pub const BANNED_REQUESTS: &str = "requests.banned";
+
+/// Counter: rejected-request events dropped because the buffer was full
+pub const SECURITY_EVENTS_DROPPED: &str = "security_events.dropped";
+
+/// Counter: times the server switched to read-only after a disk-full error
+pub const READ_ONLY_TRIPS: &str = "read_only.trips";
+
+/// Counter: account states acknowledged by the replication standby
+pub const REPLICATION_SENT: &str = "replication.sent";
+
+/// Counter: failed deliveries to the replication standby
+pub const REPLICATION_FAILURES: &str = "replication.failures";
+
+/// Counter: account states the replication standby refused (4xx); they
+/// move to the dead-letter table without holding up other accounts
+pub const REPLICATION_REJECTED: &str = "replication.rejected";
+
+/// Gauge: accounts in the replication dead-letter table
+pub const REPLICATION_DEAD_LETTERS: &str = "replication.dead_letters";
+
+/// Gauge: accounts waiting to be sent to the replication standby
+pub const REPLICATION_PENDING: &str = "replication.pending";
+
+/// Timer: time spent handling a backup store
+pub const STORE_DURATION: &str = "backups.store_ms";
+
+/// Timer: time spent handling a backup retrieval
+pub const RETRIEVE_DURATION: &str = "backups.retrieve_ms";
+
+/// Gauge: database tasks waiting for a blocking-pool thread
+pub const DB_TASKS_QUEUED: &str = "db.tasks.queued";
+
+/// Gauge: database tasks currently running on the blocking pool
+pub const DB_TASKS_RUNNING: &str = "db.tasks.running";
+
+/// Gauge: longest blocking-pool wait of any database task since startup
+pub const DB_TASK_MAX_WAIT: &str = "db.tasks.max_wait_ms";
+
+/// Timer: time a database task waited for a blocking-pool thread
+pub const DB_TASK_WAIT: &str = "db.tasks.wait_ms";
+
+/// In-process metrics registry
+///
+/// Counters and gauges are always kept in memory. When a StatsD sink is
+/// configured, every counter increment, gauge update and timer sample is also
+/// pushed over UDP.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<&'static str, u64>>,
+    sink: Option<StatsdSink>,
+}
+
+impl Metrics {
+    /// Create a metrics registry with an optional StatsD sink
+    pub fn new(sink: Option<StatsdSink>) -> Self {
+        Self {
+            counters: Mutex::new(BTreeMap::new()),
+            sink,
+        }
+    }
+
+    /// Increment a counter by one
+    pub fn incr(&self, name: &'static str) {
+        self.add(name, 1);
+    }
+
+    /// Increment a counter by `n`
+    pub fn add(&self, name: &'static str, n: u64) {
+        if let Ok(mut counters) = self.counters.lock() {
+            *counters.entry(name).or_insert(0) += n;
+        }
+
+        if let Some(sink) = &self.sink {
+            sink.send(name, n, "c");
+        }
+    }
+
+    /// Set a gauge to its current value
+    pub fn gauge(&self, name: &'static str, value: u64) {
+        if let Ok(mut counters) = self.counters.lock() {
+            counters.insert(name, value);
+        }
+
+        if let Some(sink) = &self.sink {
+            sink.send(name, value, "g");
+        }
+    }
+
+    /// Record a timer sample
+    pub fn timing(&self, name: &'static str, elapsed: Duration) {
+        if let Some(sink) = &self.sink {
+            sink.send(name, elapsed.as_millis() as u64, "ms");
+        }
+    }
+
+    /// Current value of a counter or gauge
+    pub fn counter(&self, name: &str) -> u64 {
+        self.counters
+            .lock()
+            .ok()
+            .and_then(|c| c.get(name).copied())
+            .unwrap_or(0)
+    }
+
+    /// Snapshot of all counters and gauges
+    pub fn snapshot(&self) -> BTreeMap<&'static str, u64> {
+        self.counters.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+}
+
+/// Push sink speaking the StatsD line protocol (with DogStatsD tags)
+#[derive(Debug)]
+pub struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+    tags: Vec<String>,
+}
+
+impl StatsdSink {
+    /// Bind a local UDP socket and point it at the StatsD agent
+    pub fn connect(addr: &str, prefix: &str, tags: Vec<String>) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        // Never let a slow or missing agent stall a request handler
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            prefix: prefix.to_string(),
+            tags,
+        })
+    }
+
+    /// Format a single metric line
+    fn format(&self, name: &str, value: u64, kind: &str) -> String {
+        let mut line = if self.prefix.is_empty() {
+            format!("{}:{}|{}", name, value, kind)
+        } else {
+            format!("{}.{}:{}|{}", self.prefix, name, value, kind)
+        };
+
+        if !self.tags.is_empty() {
+            line.push_str("|#");
+            line.push_str(&self.tags.join(","));
+        }
+
+        line
+    }
+
+    /// Send a metric line, dropping it if the socket is not ready
+    fn send(&self, name: &str, value: u64, kind: &str) {
+        let line = self.format(name, value, kind);
+        if let Err(e) = self.socket.send(line.as_bytes()) {
+            tracing::debug!("Failed to send StatsD metric: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_without_sink() {
+        let metrics = Metrics::default();
+        metrics.incr(REGISTRATIONS);
+        metrics.incr(REGISTRATIONS);
+        metrics.incr(BACKUPS_STORED);
+
+        assert_eq!(metrics.counter(REGISTRATIONS), 2);
+        assert_eq!(metrics.counter(BACKUPS_STORED), 1);
+        assert_eq!(metrics.counter(USERS_DELETED), 0);
+    }
+
+    #[test]
+    fn test_statsd_line_format() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = agent.local_addr().unwrap().to_string();
+
+        let sink = StatsdSink::connect(&addr, "dailyreps", vec![]).unwrap();
+        assert_eq!(
+            sink.format("registrations", 1, "c"),
+            "dailyreps.registrations:1|c"
+        );
+
+        let tagged = StatsdSink::connect(&addr, "", vec!["env:test".to_string()]).unwrap();
+        assert_eq!(
+            tagged.format("backups.store_ms", 12, "ms"),
+            "backups.store_ms:12|ms|#env:test"
+        );
+    }
+
+    #[test]
+    fn test_statsd_sink_pushes_metrics() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let addr = agent.local_addr().unwrap().to_string();
+
+        let sink = StatsdSink::connect(&addr, "dailyreps", vec![]).unwrap();
+        let metrics = Metrics::new(Some(sink));
+        metrics.incr(BACKUPS_STORED);
+
+        let mut buf = [0u8; 256];
+        let len = agent.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"dailyreps.backups.stored:1|c");
+    }
+}
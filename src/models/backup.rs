@@ -6,12 +6,47 @@ use serde::{Deserialize, Serialize};
 pub struct BackupRecord {
     /// User ID this backup belongs to
     pub user_id: String,
-    /// Encrypted data blob (base64 encoded from client)
+    /// Encrypted data blob (base64 encoded from client), zstd-compressed at rest
+    #[serde(with = "super::compressed")]
     pub encrypted_data: String,
     /// When the backup was created (Unix timestamp)
     pub created_at: i64,
     /// When the backup was last updated (Unix timestamp)
     pub updated_at: i64,
+    /// Hex SHA-256 of `encrypted_data`, checked on every read
+    pub checksum: String,
+}
+
+impl BackupRecord {
+    /// A record for `encrypted_data` with its checksum
+    pub fn new(user_id: String, encrypted_data: String, created_at: i64, updated_at: i64) -> Self {
+        let checksum = dailyreps_signing::checksum(encrypted_data.as_bytes());
+        BackupRecord {
+            user_id,
+            encrypted_data,
+            created_at,
+            updated_at,
+            checksum,
+        }
+    }
+
+    /// Whether `encrypted_data` still matches the checksum taken when it was stored
+    pub fn verify(&self) -> bool {
+        dailyreps_signing::checksum(self.encrypted_data.as_bytes()) == self.checksum
+    }
+}
+
+/// An overwritten backup kept in the version history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupVersionRecord {
+    /// Encrypted data blob as it was stored, zstd-compressed at rest
+    #[serde(with = "super::compressed")]
+    pub encrypted_data: String,
+    /// When this version was stored (Unix timestamp)
+    pub stored_at: i64,
+    /// The backup's checksum when it was archived, checked when the version
+    /// is served
+    pub checksum: String,
 }
 
 /// Backup model for API responses
@@ -48,17 +83,17 @@ mod tests {
 
         // Invalid length
         let invalid_key = "abc123";
-        assert!(!Backup::validate_storage_key(&invalid_key));
+        assert!(!Backup::validate_storage_key(invalid_key));
     }
 
     #[test]
     fn test_backup_record_serialization() {
-        let record = BackupRecord {
-            user_id: "a".repeat(64),
-            encrypted_data: "SGVsbG8gV29ybGQ=".to_string(),
-            created_at: 1733788800,
-            updated_at: 1733788800,
-        };
+        let record = BackupRecord::new(
+            "a".repeat(64),
+            "SGVsbG8gV29ybGQ=".to_string(),
+            1733788800,
+            1733788800,
+        );
 
         // Verify bincode serialization works
         let config = bincode::config::standard();
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// An admin ban on a user or client network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanRecord {
+    /// When the ban was added (Unix timestamp)
+    pub banned_at: i64,
+    /// Admin identity that added it
+    pub banned_by: String,
+    pub reason: Option<String>,
+}
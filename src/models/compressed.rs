@@ -0,0 +1,122 @@
+//! zstd compression of stored backup data
+//!
+//! Used as `#[serde(with = "compressed")]` on backup payload fields, so the
+//! data is compressed on its way into redb and handlers only ever see the
+//! original string.
+//!
+//! Stored bytes start with [`MARKER`] and a codec byte, then the compressed
+//! data. Records written before compression hold the plain UTF-8 string,
+//! which can never start with `0xFF`, so both decode without a migration.
+//! Data that doesn't shrink is stored plain.
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::Serializer;
+use std::fmt;
+
+/// First byte of a tagged payload; never valid at the start of UTF-8
+const MARKER: u8 = 0xFF;
+
+/// Codec byte after [`MARKER`]
+const CODEC_ZSTD: u8 = 1;
+
+/// zstd level: most of the ratio of higher levels at a fraction of the CPU
+const ZSTD_LEVEL: i32 = 3;
+
+pub fn serialize<S: Serializer>(data: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    match zstd::bulk::compress(data.as_bytes(), ZSTD_LEVEL) {
+        Ok(compressed) if compressed.len() + 2 < data.len() => {
+            let mut tagged = Vec::with_capacity(compressed.len() + 2);
+            tagged.extend_from_slice(&[MARKER, CODEC_ZSTD]);
+            tagged.extend_from_slice(&compressed);
+            serializer.serialize_bytes(&tagged)
+        }
+        _ => serializer.serialize_bytes(data.as_bytes()),
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    deserializer.deserialize_bytes(StoredDataVisitor)
+}
+
+struct StoredDataVisitor;
+
+impl<'de> Visitor<'de> for StoredDataVisitor {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a stored backup payload")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<String, E> {
+        match bytes {
+            [MARKER, CODEC_ZSTD, compressed @ ..] => {
+                let mut decoder = zstd::stream::Decoder::new(compressed).map_err(E::custom)?;
+                let mut data = String::new();
+                std::io::Read::read_to_string(&mut decoder, &mut data).map_err(E::custom)?;
+                Ok(data)
+            }
+            [MARKER, codec, ..] => Err(E::custom(format!("unknown backup codec {}", codec))),
+            plain => String::from_utf8(plain.to_vec()).map_err(E::custom),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, data: &str) -> Result<String, E> {
+        Ok(data.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::BackupRecord;
+
+    const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+    /// `BackupRecord` with its payload written as a plain string, as before compression
+    #[derive(serde::Serialize)]
+    struct PlainBackupRecord {
+        user_id: String,
+        encrypted_data: String,
+        created_at: i64,
+        updated_at: i64,
+        checksum: String,
+    }
+
+    fn record(data: &str) -> BackupRecord {
+        BackupRecord::new("a".repeat(64), data.to_string(), 1, 2)
+    }
+
+    #[test]
+    fn test_compresses_and_round_trips() {
+        let data = "QUJD".repeat(10_000);
+        let bytes = bincode::serde::encode_to_vec(record(&data), BINCODE_CONFIG).unwrap();
+        assert!(bytes.len() < data.len() / 10);
+
+        let (decoded, _): (BackupRecord, _) =
+            bincode::serde::decode_from_slice(&bytes, BINCODE_CONFIG).unwrap();
+        assert_eq!(decoded.encrypted_data, data);
+    }
+
+    #[test]
+    fn test_small_data_stays_plain() {
+        let bytes = bincode::serde::encode_to_vec(record("tiny"), BINCODE_CONFIG).unwrap();
+        let (decoded, _): (BackupRecord, _) =
+            bincode::serde::decode_from_slice(&bytes, BINCODE_CONFIG).unwrap();
+        assert_eq!(decoded.encrypted_data, "tiny");
+    }
+
+    #[test]
+    fn test_reads_records_written_before_compression() {
+        let old = PlainBackupRecord {
+            user_id: "a".repeat(64),
+            encrypted_data: "legacy-ciphertext".to_string(),
+            created_at: 1,
+            updated_at: 2,
+            checksum: dailyreps_signing::checksum(b"legacy-ciphertext"),
+        };
+        let bytes = bincode::serde::encode_to_vec(&old, BINCODE_CONFIG).unwrap();
+        let (decoded, _): (BackupRecord, _) =
+            bincode::serde::decode_from_slice(&bytes, BINCODE_CONFIG).unwrap();
+        assert_eq!(decoded.encrypted_data, "legacy-ciphertext");
+        assert_eq!(decoded.updated_at, 2);
+    }
+}
@@ -0,0 +1,39 @@
+use chrono::{DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// Account activity for one UTC day, kept in the daily stats table
+///
+/// Deletions can't be reconstructed from the other tables (the user is
+/// gone), so both counters are rolled up as they happen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyStatsRecord {
+    pub registrations: u64,
+    pub deletions: u64,
+}
+
+impl DailyStatsRecord {
+    /// Table key for the UTC day containing `timestamp` (`YYYY-MM-DD`, so
+    /// keys sort chronologically)
+    pub fn key(timestamp: i64) -> String {
+        Self::day(timestamp).to_string()
+    }
+
+    /// UTC calendar day of a Unix timestamp
+    pub fn day(timestamp: i64) -> NaiveDate {
+        DateTime::from_timestamp(timestamp, 0)
+            .unwrap_or_default()
+            .date_naive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_is_utc_date() {
+        // 2025-12-09T23:59:59Z and the next second
+        assert_eq!(DailyStatsRecord::key(1_765_324_799), "2025-12-09");
+        assert_eq!(DailyStatsRecord::key(1_765_324_800), "2025-12-10");
+    }
+}
@@ -1,7 +1,22 @@
 pub mod backup;
+pub mod ban;
+pub(crate) mod compressed;
+pub mod daily_stats;
 pub mod rate_limit;
+pub mod recovery;
+pub mod registration_attempt;
+pub mod security_event;
+pub mod tombstone;
+pub mod upload_session;
 pub mod user;
 
-pub use backup::{Backup, BackupRecord};
+pub use backup::{Backup, BackupRecord, BackupVersionRecord};
+pub use ban::BanRecord;
+pub use daily_stats::DailyStatsRecord;
 pub use rate_limit::RateLimitRecord;
+pub use recovery::{Recovery, RecoveryGrantRecord};
+pub use registration_attempt::RegistrationAttemptRecord;
+pub use security_event::{SecurityEventKind, SecurityEventRecord};
+pub use tombstone::{TombstoneHead, TombstoneRecord, TombstonedBackup};
+pub use upload_session::UploadSessionRecord;
 pub use user::{User, UserRecord};
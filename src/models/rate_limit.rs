@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::constants::{MAX_BACKUPS_PER_DAY, MAX_BACKUPS_PER_HOUR};
-use crate::error::{AppError, Result};
+use crate::error::{AppError, RateLimitHit, RateLimitKind, Result};
 
 /// Rate limit record for tracking backup frequency per user
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,8 +32,11 @@ impl RateLimitRecord {
 
     /// Check if rate limits allow a new backup, and update counters if allowed
     /// Returns Ok(()) if allowed, Err(RateLimitExceeded) if not
+    ///
+    /// `min_interval_secs` is the required spacing since the previous backup
+    /// (0 = none). A clock that went backwards doesn't count as too soon.
     #[allow(clippy::result_large_err)]
-    pub fn check_and_increment(&mut self, now: i64) -> Result<()> {
+    pub fn check_and_increment(&mut self, now: i64, min_interval_secs: u64) -> Result<()> {
         // Reset counters if time windows have expired
         if now >= self.hour_reset_at {
             self.backups_this_hour = 0;
@@ -46,13 +49,34 @@ impl RateLimitRecord {
         }
 
         // Check limits before incrementing
+        if let Some(last) = self.last_backup_at
+            && (0..min_interval_secs as i64).contains(&(now - last))
+        {
+            tracing::warn!(
+                "Backup interval too short: {}s < {}s",
+                now - last,
+                min_interval_secs
+            );
+            return Err(AppError::RateLimitExceeded(RateLimitHit {
+                kind: RateLimitKind::Interval,
+                limit: 1,
+                window_secs: min_interval_secs,
+                retry_after_secs: (last + min_interval_secs as i64 - now) as u64,
+            }));
+        }
+
         if self.backups_this_hour >= MAX_BACKUPS_PER_HOUR as u32 {
             tracing::warn!(
                 "Hourly rate limit would be exceeded: {}/{}",
                 self.backups_this_hour,
                 MAX_BACKUPS_PER_HOUR
             );
-            return Err(AppError::RateLimitExceeded);
+            return Err(AppError::RateLimitExceeded(RateLimitHit {
+                kind: RateLimitKind::Hourly,
+                limit: MAX_BACKUPS_PER_HOUR as u64,
+                window_secs: 3600,
+                retry_after_secs: (self.hour_reset_at - now) as u64,
+            }));
         }
 
         if self.backups_today >= MAX_BACKUPS_PER_DAY as u32 {
@@ -61,7 +85,12 @@ impl RateLimitRecord {
                 self.backups_today,
                 MAX_BACKUPS_PER_DAY
             );
-            return Err(AppError::RateLimitExceeded);
+            return Err(AppError::RateLimitExceeded(RateLimitHit {
+                kind: RateLimitKind::Daily,
+                limit: MAX_BACKUPS_PER_DAY as u64,
+                window_secs: 86400,
+                retry_after_secs: (self.day_reset_at - now) as u64,
+            }));
         }
 
         // Increment counters
@@ -76,6 +105,7 @@ impl RateLimitRecord {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_new_rate_limit_record() {
@@ -95,7 +125,7 @@ mod tests {
         let mut record = RateLimitRecord::new(now);
 
         // First backup should succeed
-        assert!(record.check_and_increment(now).is_ok());
+        assert!(record.check_and_increment(now, 0).is_ok());
         assert_eq!(record.backups_this_hour, 1);
         assert_eq!(record.backups_today, 1);
         assert_eq!(record.last_backup_at, Some(now));
@@ -108,14 +138,18 @@ mod tests {
 
         // Use up hourly limit
         for _ in 0..MAX_BACKUPS_PER_HOUR {
-            assert!(record.check_and_increment(now).is_ok());
+            assert!(record.check_and_increment(now, 0).is_ok());
         }
 
-        // Next should fail
-        assert!(matches!(
-            record.check_and_increment(now),
-            Err(AppError::RateLimitExceeded)
-        ));
+        // Next should fail, retryable once the hour window resets
+        match record.check_and_increment(now + 600, 0) {
+            Err(AppError::RateLimitExceeded(hit)) => {
+                assert_eq!(hit.kind, RateLimitKind::Hourly);
+                assert_eq!(hit.limit, MAX_BACKUPS_PER_HOUR as u64);
+                assert_eq!(hit.retry_after_secs, 3000);
+            }
+            other => panic!("expected hourly limit, got {:?}", other),
+        }
     }
 
     #[test]
@@ -125,15 +159,37 @@ mod tests {
 
         // Use up hourly limit
         for _ in 0..MAX_BACKUPS_PER_HOUR {
-            assert!(record.check_and_increment(now).is_ok());
+            assert!(record.check_and_increment(now, 0).is_ok());
         }
 
         // After hour resets, should succeed again
         let after_reset = now + 3601;
-        assert!(record.check_and_increment(after_reset).is_ok());
+        assert!(record.check_and_increment(after_reset, 0).is_ok());
         assert_eq!(record.backups_this_hour, 1);
     }
 
+    #[test]
+    fn test_min_interval() {
+        let now = 1000000;
+        let mut record = RateLimitRecord::new(now);
+
+        assert!(record.check_and_increment(now, 60).is_ok());
+        match record.check_and_increment(now + 45, 60) {
+            Err(AppError::RateLimitExceeded(hit)) => {
+                assert_eq!(hit.kind, RateLimitKind::Interval);
+                assert_eq!(hit.window_secs, 60);
+                assert_eq!(hit.retry_after_secs, 15);
+            }
+            other => panic!("expected interval limit, got {:?}", other),
+        }
+        // A refused attempt doesn't use up the budget or restart the interval
+        assert_eq!(record.backups_this_hour, 1);
+        assert!(record.check_and_increment(now + 60, 60).is_ok());
+
+        // Clock went backwards
+        assert!(record.check_and_increment(now - 10, 60).is_ok());
+    }
+
     #[test]
     fn test_daily_rate_limit() {
         let mut now = 1000000;
@@ -142,11 +198,11 @@ mod tests {
         // Use up daily limit (resetting hourly as needed)
         for i in 0..MAX_BACKUPS_PER_DAY {
             // Move time forward past hourly reset if needed
-            if i > 0 && i as u32 % MAX_BACKUPS_PER_HOUR as u32 == 0 {
+            if i > 0 && (i as u32).is_multiple_of(MAX_BACKUPS_PER_HOUR as u32) {
                 now += 3601;
             }
             assert!(
-                record.check_and_increment(now).is_ok(),
+                record.check_and_increment(now, 0).is_ok(),
                 "Backup {} should succeed",
                 i
             );
@@ -157,8 +213,104 @@ mod tests {
 
         // Should still fail because daily limit reached
         assert!(matches!(
-            record.check_and_increment(now),
-            Err(AppError::RateLimitExceeded)
+            record.check_and_increment(now, 0),
+            Err(AppError::RateLimitExceeded(_))
         ));
     }
+
+    /// Reference model of the fixed-window limiter, written independently of
+    /// `RateLimitRecord`: each window starts at the first request after the
+    /// previous one expired, and `window_start + len` is already outside it.
+    #[derive(Debug)]
+    struct Window {
+        start: i64,
+        len: i64,
+        count: u32,
+    }
+
+    impl Window {
+        fn roll(&mut self, now: i64) {
+            if now - self.start >= self.len {
+                self.start = now;
+                self.count = 0;
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Model {
+        hour: Window,
+        day: Window,
+    }
+
+    impl Model {
+        fn new(now: i64) -> Self {
+            Self {
+                hour: Window {
+                    start: now,
+                    len: 3600,
+                    count: 0,
+                },
+                day: Window {
+                    start: now,
+                    len: 86400,
+                    count: 0,
+                },
+            }
+        }
+
+        fn request(&mut self, now: i64) -> bool {
+            self.hour.roll(now);
+            self.day.roll(now);
+
+            let allowed = self.hour.count < MAX_BACKUPS_PER_HOUR as u32
+                && self.day.count < MAX_BACKUPS_PER_DAY as u32;
+            if allowed {
+                self.hour.count += 1;
+                self.day.count += 1;
+            }
+            allowed
+        }
+    }
+
+    /// Time steps biased towards the interesting spots: bursts, exact window
+    /// boundaries (and one second either side), and clocks going backwards
+    fn step() -> impl Strategy<Value = i64> {
+        prop_oneof![
+            4 => 0i64..120,
+            1 => Just(3599i64),
+            1 => Just(3600i64),
+            1 => Just(3601i64),
+            1 => Just(86399i64),
+            1 => Just(86400i64),
+            1 => 0i64..20_000,
+            1 => -600i64..0,
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_matches_reference_model(
+            start in 0i64..2_000_000_000,
+            steps in proptest::collection::vec(step(), 1..300),
+        ) {
+            let mut record = RateLimitRecord::new(start);
+            let mut model = Model::new(start);
+            let mut now = start;
+
+            for (i, delta) in steps.into_iter().enumerate() {
+                now += delta;
+                let expected = model.request(now);
+                let actual = record.check_and_increment(now, 0).is_ok();
+
+                prop_assert_eq!(actual, expected, "request {} at t={}: {:?} vs {:?}", i, now, record, model);
+                prop_assert_eq!(record.backups_this_hour, model.hour.count);
+                prop_assert_eq!(record.backups_today, model.day.count);
+                prop_assert_eq!(record.hour_reset_at, model.hour.start + 3600);
+                prop_assert_eq!(record.day_reset_at, model.day.start + 86400);
+                prop_assert!(record.backups_this_hour <= MAX_BACKUPS_PER_HOUR as u32);
+                prop_assert!(record.backups_today <= MAX_BACKUPS_PER_DAY as u32);
+            }
+        }
+    }
 }
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// Helpers for recovery contact hashes (see `dailyreps_signing::recovery_hash`)
+pub struct Recovery;
+
+impl Recovery {
+    /// Validate recovery hash format (64 hex characters)
+    pub fn validate_hash(hash: &str) -> bool {
+        hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+    }
+}
+
+/// An admin's approval for one rekey of a user's account
+///
+/// Created once support has matched the user's recovery contact; consumed by
+/// `POST /api/recovery/rekey` or ignored after `expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryGrantRecord {
+    /// When the admin authorized the rekey (Unix timestamp)
+    pub authorized_at: i64,
+    /// Rekey must happen before this (Unix timestamp)
+    pub expires_at: i64,
+    /// Admin identity that approved it, for the audit trail
+    pub authorized_by: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_hash() {
+        let hash = dailyreps_signing::recovery_hash(&"a".repeat(64), "me@example.com");
+        assert!(Recovery::validate_hash(&hash));
+        assert!(!Recovery::validate_hash("me@example.com"));
+        assert!(!Recovery::validate_hash(&"g".repeat(64)));
+    }
+}
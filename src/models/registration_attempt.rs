@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, RateLimitHit, RateLimitKind, Result};
+
+/// Registration attempts from one client network (see
+/// [`crate::security::hash_client_ip`]) in the current window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationAttemptRecord {
+    /// Attempts made in the current window
+    pub attempts: u32,
+    /// Unix timestamp when the window resets
+    pub window_ends: i64,
+}
+
+impl RegistrationAttemptRecord {
+    /// Create a record whose window starts now
+    pub fn new(now: i64, window_secs: u64) -> Self {
+        Self {
+            attempts: 0,
+            window_ends: now + window_secs as i64,
+        }
+    }
+
+    /// Count an attempt, refusing it once `limit` attempts were made this window
+    #[allow(clippy::result_large_err)]
+    pub fn check_and_increment(&mut self, now: i64, limit: u64, window_secs: u64) -> Result<()> {
+        if now >= self.window_ends {
+            *self = Self::new(now, window_secs);
+        }
+
+        if u64::from(self.attempts) >= limit {
+            tracing::warn!(
+                "Registration limit reached for a network: {}/{}",
+                self.attempts,
+                limit
+            );
+            return Err(AppError::RateLimitExceeded(RateLimitHit {
+                kind: RateLimitKind::Registration,
+                limit,
+                window_secs,
+                retry_after_secs: (self.window_ends - now) as u64,
+            }));
+        }
+
+        self.attempts += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_and_window_reset() {
+        let now = 1_000_000;
+        let mut record = RegistrationAttemptRecord::new(now, 300);
+
+        for _ in 0..3 {
+            assert!(record.check_and_increment(now, 3, 300).is_ok());
+        }
+        match record.check_and_increment(now + 100, 3, 300) {
+            Err(AppError::RateLimitExceeded(hit)) => {
+                assert_eq!(hit.kind, RateLimitKind::Registration);
+                assert_eq!(hit.retry_after_secs, 200);
+            }
+            other => panic!("expected registration limit, got {:?}", other),
+        }
+
+        assert!(record.check_and_increment(now + 300, 3, 300).is_ok());
+        assert_eq!(record.attempts, 1);
+    }
+}
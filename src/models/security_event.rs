@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+/// What kind of request was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SecurityEventKind {
+    /// Backup or concurrency limit hit (429)
+    RateLimited,
+    /// Invalid HMAC signature or stale timestamp (401)
+    SignatureFailure,
+    /// Identical blob re-uploaded past the daily allowance (429)
+    DuplicateUpload,
+}
+
+impl SecurityEventKind {
+    /// Weight used to rank offenders: a bad signature means a client that
+    /// isn't the official app, which is worse than an overeager real one
+    pub fn severity(self) -> u64 {
+        match self {
+            SecurityEventKind::RateLimited => 1,
+            SecurityEventKind::SignatureFailure => 5,
+            SecurityEventKind::DuplicateUpload => 2,
+        }
+    }
+}
+
+/// A rejected request, stored for the abuse report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityEventRecord {
+    /// When the request was rejected (Unix timestamp)
+    pub at: i64,
+    pub kind: SecurityEventKind,
+    /// Claimed user ID, when it was a well-formed hash
+    pub user_id: Option<String>,
+    /// ISO country code of the client IP, when GeoIP is configured
+    pub country: Option<String>,
+    /// Keyed hash of the client's network (see
+    /// [`crate::security::hash_client_ip`]), when the IP is known
+    pub network: Option<String>,
+}
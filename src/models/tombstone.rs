@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+/// A deleted account kept for the grace period so the deletion can be undone
+///
+/// Records are held in their stored (serialized) form and put back unchanged
+/// on restore. The timestamps must stay the leading fields: [`TombstoneHead`]
+/// decodes just them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TombstoneRecord {
+    /// Unix timestamp of the deletion
+    pub deleted_at: i64,
+    /// Unix timestamp after which the data is purged for good
+    pub purge_at: i64,
+    /// Serialized `UserRecord`
+    pub user: Vec<u8>,
+    pub backups: Vec<TombstonedBackup>,
+    /// Serialized `RateLimitRecord`, so delete-and-restore can't reset limits
+    pub rate_limit: Option<Vec<u8>>,
+    pub recovery_contact: Option<String>,
+}
+
+/// The leading fields of a serialized [`TombstoneRecord`]
+///
+/// Decoding only these lets the purge check a tombstone's deadline without
+/// decoding the account's backups.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct TombstoneHead {
+    pub deleted_at: i64,
+    pub purge_at: i64,
+}
+
+/// One backup slot of a [`TombstoneRecord`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TombstonedBackup {
+    pub storage_key: String,
+    /// Serialized `BackupRecord`
+    pub record: Vec<u8>,
+    pub sync_token: u64,
+    /// `(version, serialized BackupVersionRecord)`
+    pub versions: Vec<(u64, Vec<u8>)>,
+}
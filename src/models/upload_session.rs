@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// A chunked upload in progress
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSessionRecord {
+    pub user_id: String,
+    /// Where the backup is stored on commit
+    pub storage_key: String,
+    /// Unix timestamp after which the session is discarded
+    pub expires_at: i64,
+    /// Chunks received so far (the next chunk's sequence number)
+    pub chunks: u32,
+    /// Bytes received so far
+    pub received_bytes: u64,
+}
+
+impl UploadSessionRecord {
+    /// Validate an upload ID (32 hex characters)
+    pub fn validate_id(id: &str) -> bool {
+        id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit())
+    }
+}
@@ -36,7 +36,7 @@ mod tests {
 
         // Too short
         let short_id = "abc123";
-        assert!(!User::validate_id(&short_id));
+        assert!(!User::validate_id(short_id));
 
         // Too long
         let long_id = "a".repeat(65);
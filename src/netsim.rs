@@ -0,0 +1,148 @@
+//! Slow-network simulation for development (`netsim` feature)
+//!
+//! Never enable this feature in production builds. Settings come from
+//! environment variables, read once at first use:
+//!
+//! - `NETSIM_LATENCY_MS` - delay added before every request is handled
+//! - `NETSIM_JITTER_MS` - extra random delay, uniform in `0..=jitter`
+//! - `NETSIM_BANDWIDTH_KBPS` - response bodies are streamed at this rate
+//!   (kilobits per second; 0 = unlimited)
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    middleware::Next,
+    response::Response,
+};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_stream::StreamExt;
+
+/// Interval between throttled body chunks
+const CHUNK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Active network simulation settings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetsimConfig {
+    pub latency: Duration,
+    pub jitter: Duration,
+    pub bandwidth_kbps: u64,
+}
+
+impl NetsimConfig {
+    /// Read settings with `lookup` (the environment in production use)
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let number = |name: &str| {
+            lookup(name)
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+
+        Self {
+            latency: Duration::from_millis(number("NETSIM_LATENCY_MS")),
+            jitter: Duration::from_millis(number("NETSIM_JITTER_MS")),
+            bandwidth_kbps: number("NETSIM_BANDWIDTH_KBPS"),
+        }
+    }
+
+    /// Bytes sent per [`CHUNK_INTERVAL`] at the configured bandwidth
+    fn chunk_size(&self) -> Option<usize> {
+        if self.bandwidth_kbps == 0 {
+            return None;
+        }
+
+        let bytes_per_sec = self.bandwidth_kbps * 1000 / 8;
+        let per_chunk = bytes_per_sec * CHUNK_INTERVAL.as_millis() as u64 / 1000;
+        Some(per_chunk.max(1) as usize)
+    }
+}
+
+/// Process-wide settings, loaded from the environment on first use
+pub fn config() -> &'static NetsimConfig {
+    static CONFIG: OnceLock<NetsimConfig> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let config = NetsimConfig::from_lookup(|name| std::env::var(name).ok());
+        tracing::warn!("Network simulation enabled: {:?}", config);
+        config
+    })
+}
+
+/// Middleware adding latency/jitter and throttling the response body
+pub async fn simulate(request: Request, next: Next) -> Response {
+    let config = config();
+
+    let delay = config.latency + jitter(config.jitter);
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+
+    let response = next.run(request).await;
+
+    let Some(chunk_size) = config.chunk_size() else {
+        return response;
+    };
+
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Netsim: failed to buffer response body: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+
+    let chunks: Vec<Bytes> = bytes
+        .chunks(chunk_size)
+        .map(Bytes::copy_from_slice)
+        .collect();
+    let stream = tokio_stream::iter(chunks)
+        .throttle(CHUNK_INTERVAL)
+        .map(Ok::<_, std::convert::Infallible>);
+
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// Pseudo-random delay in `0..=max` (clock-derived; fine for simulation)
+fn jitter(max: Duration) -> Duration {
+    let max_ms = max.as_millis() as u64;
+    if max_ms == 0 {
+        return Duration::ZERO;
+    }
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    Duration::from_millis(nanos % (max_ms + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_lookup() {
+        let config = NetsimConfig::from_lookup(|name| match name {
+            "NETSIM_LATENCY_MS" => Some("300".to_string()),
+            "NETSIM_JITTER_MS" => Some("50".to_string()),
+            "NETSIM_BANDWIDTH_KBPS" => Some("384".to_string()),
+            _ => None,
+        });
+
+        assert_eq!(config.latency, Duration::from_millis(300));
+        assert_eq!(config.jitter, Duration::from_millis(50));
+        // 384 kbit/s = 48000 B/s = 4800 B per 100ms
+        assert_eq!(config.chunk_size(), Some(4800));
+    }
+
+    #[test]
+    fn test_unlimited_bandwidth_by_default() {
+        assert_eq!(NetsimConfig::from_lookup(|_| None).chunk_size(), None);
+    }
+
+    #[test]
+    fn test_jitter_within_bounds() {
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+        assert!(jitter(Duration::from_millis(20)) <= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,223 @@
+//! Per-network request rate limiting
+//!
+//! The per-user backup limits only apply once a request names a user, so a
+//! client hammering `/api/register` or `GET /api/backup` with made-up IDs
+//! would otherwise never be slowed down. [`NetworkLimiter`] counts every
+//! `/api` request per client network (IPv4 address or IPv6 /64, see
+//! [`client_network`]) in a fixed window of `RATE_LIMIT_WINDOW_SECS`, and
+//! refuses requests beyond `RATE_LIMIT_REQUESTS` with 429. Counts are kept in
+//! memory only; a restart resets them.
+//!
+//! At most [`MAX_TRACKED_NETWORKS`] networks are tracked. Ended windows are
+//! swept on a timer (see [`NetworkLimiter::spawn_pruner`]); while the map is
+//! full of open windows, requests from networks not already in it are
+//! refused, so a client cycling through addresses can't grow it unbounded.
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::error::{AppError, RateLimitHit, RateLimitKind, Result};
+use crate::security::client_network;
+use crate::{AppState, ClientIp, metrics};
+
+/// Networks tracked at once; new networks are refused beyond this
+pub const MAX_TRACKED_NETWORKS: usize = 100_000;
+
+/// Key shared by requests whose client IP is unknown
+const UNKNOWN_NETWORK: &str = "unknown";
+
+#[derive(Debug)]
+struct Window {
+    requests: u64,
+    ends: i64,
+}
+
+/// Requests per client network in the current window (in memory only)
+///
+/// The limit is passed to each check rather than stored, so a config reload
+/// applies to the next request.
+#[derive(Debug, Default)]
+pub struct NetworkLimiter {
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl NetworkLimiter {
+    /// Count a request from `network`, refusing it past `limit` requests per
+    /// `window_secs` (a `limit` of 0 is unlimited)
+    #[allow(clippy::result_large_err)]
+    pub fn check(&self, network: &str, limit: u64, window_secs: u64, now: i64) -> Result<()> {
+        if limit == 0 {
+            return Ok(());
+        }
+
+        let mut windows = self.windows.lock().expect("network limiter lock poisoned");
+        if windows.len() >= MAX_TRACKED_NETWORKS && !windows.contains_key(network) {
+            tracing::warn!("Network limiter full; refusing an untracked network");
+            return Err(AppError::RateLimitExceeded(RateLimitHit {
+                kind: RateLimitKind::Network,
+                limit,
+                window_secs,
+                retry_after_secs: window_secs,
+            }));
+        }
+
+        let window = windows.entry(network.to_string()).or_insert(Window {
+            requests: 0,
+            ends: now + window_secs as i64,
+        });
+        if now >= window.ends {
+            window.requests = 0;
+            window.ends = now + window_secs as i64;
+        }
+
+        if window.requests >= limit {
+            return Err(AppError::RateLimitExceeded(RateLimitHit {
+                kind: RateLimitKind::Network,
+                limit,
+                window_secs,
+                retry_after_secs: (window.ends - now) as u64,
+            }));
+        }
+        window.requests += 1;
+
+        Ok(())
+    }
+
+    /// Drop networks whose window ended by `now`, returning how many
+    pub fn prune(&self, now: i64) -> usize {
+        let mut windows = self.windows.lock().expect("network limiter lock poisoned");
+        let before = windows.len();
+        windows.retain(|_, w| w.ends > now);
+        before - windows.len()
+    }
+
+    /// Prune ended windows every `interval`
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn_pruner(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                ticker.tick().await;
+                let pruned = self.prune(Utc::now().timestamp());
+                if pruned > 0 {
+                    tracing::debug!("Pruned {} network rate-limit windows", pruned);
+                }
+            }
+        })
+    }
+}
+
+/// Refuse `/api` requests from a network over its request budget
+///
+/// Behind a proxy, requests without the client IP header share one budget,
+/// as they do for registration. Without a known IP and without a proxy
+/// (in-process callers, tests) nothing is counted. Refusals only increment
+/// the `rate_limited` metric: writing each one to the security event log
+/// would hand a flooding client a database write per request.
+pub async fn limit_per_network(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    let config = state.config();
+    let network = match ip.0 {
+        Some(ip) => Some(client_network(ip)),
+        None if config.client_ip_header.is_some() => Some(UNKNOWN_NETWORK.to_string()),
+        None => None,
+    };
+
+    if let Some(network) = network {
+        state
+            .network_limiter
+            .check(
+                &network,
+                config.rate_limit_requests,
+                config.rate_limit_window_secs,
+                Utc::now().timestamp(),
+            )
+            .inspect_err(|_| state.metrics.incr(metrics::RATE_LIMITED))?;
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_each_network_per_window() {
+        let limiter = NetworkLimiter::default();
+        limiter.check("192.0.2.1", 2, 60, 100).unwrap();
+        limiter.check("192.0.2.1", 2, 60, 110).unwrap();
+        match limiter.check("192.0.2.1", 2, 60, 120) {
+            Err(AppError::RateLimitExceeded(hit)) => {
+                assert_eq!(hit.kind, RateLimitKind::Network);
+                assert_eq!(hit.retry_after_secs, 40);
+            }
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+
+        // Other networks have their own budget
+        limiter.check("192.0.2.2", 2, 60, 120).unwrap();
+
+        // A new window starts fresh
+        limiter.check("192.0.2.1", 2, 60, 160).unwrap();
+
+        // A lowered limit applies at once
+        limiter.check("192.0.2.3", 2, 60, 160).unwrap();
+        assert!(limiter.check("192.0.2.3", 1, 60, 161).is_err());
+    }
+
+    #[test]
+    fn test_full_map_refuses_new_networks() {
+        let limiter = NetworkLimiter::default();
+        {
+            let mut windows = limiter.windows.lock().unwrap();
+            for i in 0..MAX_TRACKED_NETWORKS {
+                windows.insert(
+                    i.to_string(),
+                    Window {
+                        requests: 1,
+                        ends: 160,
+                    },
+                );
+            }
+        }
+
+        // Every window is still open: new networks are refused, tracked
+        // ones keep their budget, and the map doesn't grow
+        match limiter.check("new", 10, 60, 100) {
+            Err(AppError::RateLimitExceeded(hit)) => {
+                assert_eq!(hit.kind, RateLimitKind::Network);
+                assert_eq!(hit.retry_after_secs, 60);
+            }
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+        limiter.check("0", 10, 60, 100).unwrap();
+        assert_eq!(limiter.windows.lock().unwrap().len(), MAX_TRACKED_NETWORKS);
+
+        // Once the windows end, the pruner frees the room
+        assert_eq!(limiter.prune(160), MAX_TRACKED_NETWORKS);
+        limiter.check("new", 10, 60, 160).unwrap();
+    }
+
+    #[test]
+    fn test_zero_limit_is_unlimited() {
+        let limiter = NetworkLimiter::default();
+        for _ in 0..1000 {
+            limiter.check("192.0.2.1", 0, 60, 100).unwrap();
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Minimum time between two alerts of the same kind
+const ALERT_COOLDOWN: Duration = Duration::from_secs(600);
+
+/// Pending alerts buffered for the dispatcher; extras are dropped
+const ALERT_QUEUE_SIZE: usize = 100;
+
+/// Timeout for a single webhook delivery
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Chat platform the webhook belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookKind {
+    Slack,
+    Discord,
+    /// Matrix via a hookshot-style generic webhook
+    Matrix,
+}
+
+impl std::str::FromStr for WebhookKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "slack" => Ok(WebhookKind::Slack),
+            "discord" => Ok(WebhookKind::Discord),
+            "matrix" => Ok(WebhookKind::Matrix),
+            other => Err(format!("Unknown webhook kind '{}'", other)),
+        }
+    }
+}
+
+/// Category of an operator alert (used for cooldown grouping)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    /// Requests failing HMAC verification
+    SignatureFailures,
+    /// Database volume full; server switched to read-only
+    DiskFull,
+}
+
+impl AlertKind {
+    fn title(self) -> &'static str {
+        match self {
+            AlertKind::SignatureFailures => "Signature verification failures",
+            AlertKind::DiskFull => "Disk full, server is read-only",
+        }
+    }
+}
+
+/// Sends formatted operator alerts to a chat webhook
+///
+/// Alerts are queued and delivered by a background task so request handlers
+/// never wait on the chat provider. Each alert kind is throttled to one
+/// message per cooldown period.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    sender: Option<mpsc::Sender<(AlertKind, String)>>,
+    last_sent: Mutex<HashMap<AlertKind, Instant>>,
+}
+
+impl Notifier {
+    /// Create a notifier that is a no-op (no webhook configured)
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Create a notifier and spawn its delivery task
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn(url: String, kind: WebhookKind, environment: String) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<(AlertKind, String)>(ALERT_QUEUE_SIZE);
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        tokio::spawn(async move {
+            while let Some((alert, message)) = receiver.recv().await {
+                let payload = format_payload(kind, &environment, alert, &message);
+                let result = client
+                    .post(&url)
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status());
+                if let Err(e) = result {
+                    tracing::error!("Failed to deliver alert webhook: {}", e);
+                }
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Queue an alert, unless one of the same kind was sent recently
+    pub fn alert(&self, kind: AlertKind, message: impl Into<String>) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        if let Ok(mut last_sent) = self.last_sent.lock() {
+            let now = Instant::now();
+            if let Some(at) = last_sent.get(&kind)
+                && now.duration_since(*at) < ALERT_COOLDOWN
+            {
+                return;
+            }
+            last_sent.insert(kind, now);
+        }
+
+        if sender.try_send((kind, message.into())).is_err() {
+            tracing::warn!("Alert queue full, dropping {:?} alert", kind);
+        }
+    }
+}
+
+/// Build the platform-specific webhook body
+fn format_payload(kind: WebhookKind, environment: &str, alert: AlertKind, message: &str) -> Value {
+    let text = format!(
+        "[dailyreps-backup-server/{}] {}: {}",
+        environment,
+        alert.title(),
+        message
+    );
+
+    match kind {
+        WebhookKind::Slack => json!({ "text": text }),
+        WebhookKind::Discord => json!({ "content": text }),
+        WebhookKind::Matrix => json!({ "text": text, "username": "dailyreps-backup-server" }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_kind_from_str() {
+        assert_eq!("slack".parse::<WebhookKind>(), Ok(WebhookKind::Slack));
+        assert_eq!("Discord".parse::<WebhookKind>(), Ok(WebhookKind::Discord));
+        assert_eq!("matrix".parse::<WebhookKind>(), Ok(WebhookKind::Matrix));
+        assert!("teams".parse::<WebhookKind>().is_err());
+    }
+
+    #[test]
+    fn test_format_payload() {
+        let slack = format_payload(
+            WebhookKind::Slack,
+            "production",
+            AlertKind::SignatureFailures,
+            "3 failures",
+        );
+        assert_eq!(
+            slack["text"],
+            "[dailyreps-backup-server/production] Signature verification failures: 3 failures"
+        );
+
+        let discord = format_payload(
+            WebhookKind::Discord,
+            "staging",
+            AlertKind::SignatureFailures,
+            "x",
+        );
+        assert!(discord["content"].as_str().unwrap().contains("staging"));
+    }
+
+    #[tokio::test]
+    async fn test_alert_cooldown() {
+        let (sender, mut receiver) = mpsc::channel(10);
+        let notifier = Notifier {
+            sender: Some(sender),
+            last_sent: Mutex::new(HashMap::new()),
+        };
+
+        notifier.alert(AlertKind::SignatureFailures, "first");
+        notifier.alert(AlertKind::SignatureFailures, "second");
+
+        assert_eq!(receiver.recv().await.unwrap().1, "first");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_disabled_notifier_is_noop() {
+        Notifier::disabled().alert(AlertKind::SignatureFailures, "ignored");
+    }
+}
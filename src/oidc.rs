@@ -0,0 +1,222 @@
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{AlgorithmParameters, JwkSet},
+};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// How long a fetched JWKS is trusted before it is refreshed
+const JWKS_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Least time between fetches from the provider, so tokens with unknown key
+/// IDs can't make every request fetch discovery and the JWKS again
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Timeout for discovery and JWKS requests to the provider
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Only asymmetric algorithms are accepted; a JWKS is public, so shared-secret
+/// (HS*) tokens can never be trusted from it
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Subset of the OpenID provider metadata we need
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    jwks_uri: String,
+}
+
+/// Claims checked on admin tokens
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminClaims {
+    /// Subject (the individual admin's identity at the provider)
+    pub sub: String,
+    /// Optional human-readable identity for audit logs
+    pub email: Option<String>,
+}
+
+/// Why an admin token was rejected
+#[derive(Debug, thiserror::Error)]
+pub enum OidcError {
+    #[error("malformed token: {0}")]
+    Malformed(String),
+    #[error("unsupported algorithm {0:?}")]
+    UnsupportedAlgorithm(Algorithm),
+    #[error("unknown signing key")]
+    UnknownKey,
+    #[error("token validation failed: {0}")]
+    Invalid(#[from] jsonwebtoken::errors::Error),
+    #[error("subject {0} is not an allowed admin")]
+    SubjectNotAllowed(String),
+    #[error("provider unavailable: {0}")]
+    Provider(String),
+}
+
+/// Keys fetched from the provider
+#[derive(Debug, Default)]
+struct JwksCache {
+    /// The last JWKS fetched, and when
+    keys: Option<(JwkSet, Instant)>,
+    /// When a fetch was last started, successful or not
+    last_attempt: Option<Instant>,
+}
+
+/// Validates bearer JWTs issued by an OIDC provider for admin access
+#[derive(Debug)]
+pub struct OidcVerifier {
+    issuer: String,
+    audience: String,
+    allowed_subjects: Vec<String>,
+    client: reqwest::Client,
+    jwks: RwLock<JwksCache>,
+}
+
+impl OidcVerifier {
+    /// Create a verifier; keys are fetched lazily on first use
+    pub fn new(issuer: String, audience: String, allowed_subjects: Vec<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            audience,
+            allowed_subjects,
+            client,
+            jwks: RwLock::new(JwksCache::default()),
+        }
+    }
+
+    /// Validate a bearer token and return its claims
+    ///
+    /// Checks signature, issuer, audience, expiry, and that the subject is on
+    /// the admin allow list (`*` allows any).
+    pub async fn verify(&self, token: &str) -> Result<AdminClaims, OidcError> {
+        let header = decode_header(token).map_err(|e| OidcError::Malformed(e.to_string()))?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(OidcError::UnsupportedAlgorithm(header.alg));
+        }
+        let kid = header
+            .kid
+            .ok_or_else(|| OidcError::Malformed("missing kid".to_string()))?;
+
+        let key = self.decoding_key(&kid).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = decode::<AdminClaims>(token, &key, &validation)?.claims;
+
+        let allowed = self
+            .allowed_subjects
+            .iter()
+            .any(|s| s == "*" || *s == claims.sub);
+        if !allowed {
+            return Err(OidcError::SubjectNotAllowed(claims.sub));
+        }
+
+        Ok(claims)
+    }
+
+    /// Find the decoding key for a key ID, refreshing the JWKS if it is stale
+    /// or does not contain the key (the provider may have rotated keys)
+    ///
+    /// Refreshes at most once per `JWKS_MIN_REFRESH_INTERVAL`; in between, a
+    /// key missing from the cached set is unknown.
+    async fn decoding_key(&self, kid: &str) -> Result<DecodingKey, OidcError> {
+        if let Some(key) = Self::cached_key(&*self.jwks.read().await, kid)? {
+            return Ok(key);
+        }
+
+        // Held across the fetch so concurrent misses wait for it instead of
+        // starting their own
+        let mut cache = self.jwks.write().await;
+        if let Some(key) = Self::cached_key(&cache, kid)? {
+            return Ok(key);
+        }
+        if cache
+            .last_attempt
+            .is_some_and(|at| at.elapsed() < JWKS_MIN_REFRESH_INTERVAL)
+        {
+            return match &cache.keys {
+                Some((jwks, _)) => Self::key_from_set(jwks, kid)?.ok_or(OidcError::UnknownKey),
+                None => Err(OidcError::UnknownKey),
+            };
+        }
+
+        cache.last_attempt = Some(Instant::now());
+        let jwks = self.fetch_jwks().await?;
+        let key = Self::key_from_set(&jwks, kid)?;
+        cache.keys = Some((jwks, Instant::now()));
+
+        key.ok_or(OidcError::UnknownKey)
+    }
+
+    /// The key for `kid` from a cached JWKS that is still fresh
+    fn cached_key(cache: &JwksCache, kid: &str) -> Result<Option<DecodingKey>, OidcError> {
+        match &cache.keys {
+            Some((jwks, fetched_at)) if fetched_at.elapsed() < JWKS_CACHE_TTL => {
+                Self::key_from_set(jwks, kid)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Build a decoding key for `kid`, rejecting symmetric keys
+    fn key_from_set(jwks: &JwkSet, kid: &str) -> Result<Option<DecodingKey>, OidcError> {
+        let Some(jwk) = jwks.find(kid) else {
+            return Ok(None);
+        };
+
+        if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) {
+            return Err(OidcError::UnknownKey);
+        }
+
+        DecodingKey::from_jwk(jwk)
+            .map(Some)
+            .map_err(|e| OidcError::Malformed(e.to_string()))
+    }
+
+    /// Fetch the provider's JWKS via OpenID discovery
+    async fn fetch_jwks(&self) -> Result<JwkSet, OidcError> {
+        let discovery_url = format!("{}/.well-known/openid-configuration", self.issuer);
+        let metadata: ProviderMetadata = self
+            .client
+            .get(&discovery_url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| OidcError::Provider(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| OidcError::Provider(e.to_string()))?;
+
+        let jwks: JwkSet = self
+            .client
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| OidcError::Provider(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| OidcError::Provider(e.to_string()))?;
+
+        tracing::info!("Fetched {} OIDC signing keys", jwks.keys.len());
+
+        Ok(jwks)
+    }
+}
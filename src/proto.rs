@@ -0,0 +1,88 @@
+//! Protobuf messages for the backup endpoints
+//!
+//! Mirrors `proto/backup.proto`. The structs are written with prost's derive
+//! macros directly rather than generated at build time, so building the
+//! server does not require `protoc`. Keep field numbers in sync with the
+//! `.proto` file.
+
+/// `dailyreps.v1.StoreBackupRequest`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StoreBackupRequest {
+    #[prost(string, tag = "1")]
+    pub user_id: String,
+    #[prost(string, tag = "2")]
+    pub storage_key: String,
+    #[prost(string, tag = "3")]
+    pub data: String,
+    #[prost(string, tag = "4")]
+    pub signature: String,
+    #[prost(int64, tag = "5")]
+    pub timestamp: i64,
+    /// Empty when the client has no token
+    #[prost(string, tag = "6")]
+    pub sync_token: String,
+    #[prost(map = "string, string", tag = "7")]
+    pub stats: std::collections::HashMap<String, String>,
+    /// Empty when the request has no nonce
+    #[prost(string, tag = "8")]
+    pub nonce: String,
+    /// 0 when the client doesn't set one (v1)
+    #[prost(uint32, tag = "9")]
+    pub sig_version: u32,
+}
+
+/// `dailyreps.v1.StoreBackupResponse`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StoreBackupResponse {
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    #[prost(string, tag = "2")]
+    pub updated_at: String,
+    #[prost(string, tag = "3")]
+    pub sync_token: String,
+    #[prost(message, repeated, tag = "4")]
+    pub warnings: Vec<StoreWarning>,
+    #[prost(string, tag = "5")]
+    pub checksum: String,
+}
+
+/// `dailyreps.v1.StoreWarning`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StoreWarning {
+    /// `storage_quota`, `hourly_limit`, or `daily_limit`
+    #[prost(string, tag = "1")]
+    pub code: String,
+    #[prost(uint64, tag = "2")]
+    pub used: u64,
+    #[prost(uint64, tag = "3")]
+    pub limit: u64,
+    #[prost(string, tag = "4")]
+    pub message: String,
+}
+
+/// `dailyreps.v1.RetrieveBackupResponse`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RetrieveBackupResponse {
+    #[prost(string, tag = "1")]
+    pub data: String,
+    #[prost(string, tag = "2")]
+    pub updated_at: String,
+    #[prost(string, tag = "3")]
+    pub sync_token: String,
+    #[prost(string, tag = "4")]
+    pub checksum: String,
+}
+
+/// Conversion from a decoded protobuf message into an API type
+pub trait FromProto: Sized {
+    type Proto: prost::Message + Default;
+
+    fn from_proto(proto: Self::Proto) -> Self;
+}
+
+/// Conversion from an API type into a protobuf message
+pub trait IntoProto {
+    type Proto: prost::Message;
+
+    fn into_proto(self) -> Self::Proto;
+}
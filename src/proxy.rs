@@ -0,0 +1,241 @@
+//! Client IP resolution behind reverse proxies
+//!
+//! Behind nginx or Cloudflare the TCP peer is the proxy, and the client's
+//! address travels in `X-Forwarded-For` (or `Forwarded`, or a single-value
+//! header like `Fly-Client-IP`). Those headers are whatever the client sent
+//! plus one entry per proxy, so only entries appended by proxies we run can be
+//! believed. With `TRUSTED_PROXIES` set, the forwarding header is honoured only
+//! when the peer is inside a trusted range, and the client is the rightmost
+//! entry that isn't itself a trusted proxy; anything to its left was written by
+//! the client and is ignored.
+//!
+//! Without `TRUSTED_PROXIES`, `CLIENT_IP_HEADER` is believed from any peer and
+//! its first entry is used, which is only safe when the server can't be reached
+//! except through the proxy.
+
+use std::net::IpAddr;
+
+use axum::http::HeaderMap;
+
+use crate::blocklist::{parse_range, to_u128};
+use crate::config::Config;
+
+/// Header read from trusted proxies when `CLIENT_IP_HEADER` isn't set
+const DEFAULT_FORWARDING_HEADER: &str = "x-forwarded-for";
+
+/// `CLIENT_IP_HEADER` value selecting RFC 7239 `Forwarded` syntax
+const FORWARDED: &str = "forwarded";
+
+/// IP ranges whose forwarding headers are believed (`TRUSTED_PROXIES`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    /// Inclusive ranges, IPv4 stored as IPv4-mapped IPv6 (as in the blocklist)
+    ranges: Vec<(u128, u128)>,
+}
+
+impl TrustedProxies {
+    /// Parse a comma-separated list of IPs and CIDR ranges
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let ranges = value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|entry| {
+                parse_range(entry)
+                    .ok_or_else(|| format!("Invalid range '{}' in TRUSTED_PROXIES", entry))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { ranges })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let value = to_u128(ip);
+        self.ranges
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&value))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+/// The client's address, given the TCP peer and the request headers
+pub fn client_ip(config: &Config, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+    if config.trusted_proxies.is_empty() {
+        return match &config.client_ip_header {
+            Some(header) => forwarded_chain(header, headers).into_iter().next()?,
+            None => peer,
+        };
+    }
+
+    // Only a trusted proxy may speak for someone else
+    let peer = peer?;
+    if !config.trusted_proxies.contains(peer) {
+        return Some(peer);
+    }
+
+    let header = config
+        .client_ip_header
+        .as_deref()
+        .unwrap_or(DEFAULT_FORWARDING_HEADER);
+
+    // Walk back from the hop nearest to us while it's one of our proxies.
+    // An unreadable entry ends the walk at the last address we can vouch for.
+    let mut client = peer;
+    for hop in forwarded_chain(header, headers).into_iter().rev() {
+        match hop {
+            Some(ip) if config.trusted_proxies.contains(ip) => client = ip,
+            Some(ip) => return Some(ip),
+            None => break,
+        }
+    }
+    Some(client)
+}
+
+/// Every entry of `header` across all its lines, nearest the client first
+fn forwarded_chain(header: &str, headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let parse = if header == FORWARDED {
+        parse_forwarded_element
+    } else {
+        parse_forwarded_ip
+    };
+
+    headers
+        .get_all(header)
+        .iter()
+        .flat_map(|value| {
+            value
+                .to_str()
+                .unwrap_or_default()
+                .split(',')
+                .map(parse)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// One address of an `X-Forwarded-For`-style list
+fn parse_forwarded_ip(entry: &str) -> Option<IpAddr> {
+    entry.trim().parse().ok()
+}
+
+/// The `for=` address of one RFC 7239 `Forwarded` element
+///
+/// Handles quoting, bracketed IPv6 and ports (`for="[2001:db8::1]:4711"`);
+/// obfuscated identifiers and `unknown` yield `None`.
+fn parse_forwarded_element(element: &str) -> Option<IpAddr> {
+    let node = element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("for")
+            .then(|| value.trim().trim_matches('"'))
+    })?;
+
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.split_once(':')?.0.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_config;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn headers(name: &'static str, values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_untrusted_header_uses_first_entry() {
+        let mut config = test_config();
+        let xff = headers("x-forwarded-for", &["203.0.113.7, 10.0.0.1"]);
+        assert_eq!(
+            client_ip(&config, Some(ip("10.0.0.1")), &xff),
+            Some(ip("10.0.0.1"))
+        );
+
+        config.client_ip_header = Some("x-forwarded-for".to_string());
+        assert_eq!(client_ip(&config, None, &xff), Some(ip("203.0.113.7")));
+        assert_eq!(client_ip(&config, None, &HeaderMap::new()), None);
+        let garbage = headers("x-forwarded-for", &["unknown"]);
+        assert_eq!(client_ip(&config, None, &garbage), None);
+    }
+
+    #[test]
+    fn test_trusted_proxies_skip_spoofed_entries() {
+        let mut config = test_config();
+        config.trusted_proxies = TrustedProxies::parse("10.0.0.0/8, 2001:db8:ffff::1").unwrap();
+        let proxy = Some(ip("10.0.0.2"));
+
+        // The client prepended a fake address; the proxy appended the real one
+        let xff = headers("x-forwarded-for", &["1.1.1.1, 203.0.113.7"]);
+        assert_eq!(client_ip(&config, proxy, &xff), Some(ip("203.0.113.7")));
+
+        // Chained proxies are walked past, across header lines
+        let chained = headers("x-forwarded-for", &["203.0.113.7, 10.0.0.9", "10.0.0.5"]);
+        assert_eq!(client_ip(&config, proxy, &chained), Some(ip("203.0.113.7")));
+
+        // Anyone else's header is ignored
+        let direct = Some(ip("198.51.100.1"));
+        assert_eq!(client_ip(&config, direct, &xff), direct);
+
+        // A proxy that didn't forward anything is the client
+        assert_eq!(client_ip(&config, proxy, &HeaderMap::new()), proxy);
+        let garbage = headers("x-forwarded-for", &["203.0.113.7, junk, 10.0.0.9"]);
+        assert_eq!(client_ip(&config, proxy, &garbage), Some(ip("10.0.0.9")));
+
+        // IPv6 proxies, seen as IPv4-mapped on dual-stack sockets too
+        let v6 = Some(ip("2001:db8:ffff::1"));
+        assert_eq!(client_ip(&config, v6, &xff), Some(ip("203.0.113.7")));
+        let mapped = Some(ip("::ffff:10.0.0.2"));
+        assert_eq!(client_ip(&config, mapped, &xff), Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let mut config = test_config();
+        config.trusted_proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        config.client_ip_header = Some(FORWARDED.to_string());
+
+        let forwarded = headers(
+            "forwarded",
+            &[r#"for=1.1.1.1, for="[2001:db8::7]:4711";proto=https, For=10.0.0.3:80"#],
+        );
+        assert_eq!(
+            client_ip(&config, Some(ip("10.0.0.2")), &forwarded),
+            Some(ip("2001:db8::7"))
+        );
+
+        assert_eq!(
+            parse_forwarded_element("for=192.0.2.60;proto=http"),
+            Some(ip("192.0.2.60"))
+        );
+        assert_eq!(parse_forwarded_element("for=_hidden"), None);
+        assert_eq!(parse_forwarded_element(r#"for="unknown""#), None);
+        assert_eq!(parse_forwarded_element("by=10.0.0.1"), None);
+    }
+
+    #[test]
+    fn test_parse_trusted_proxies() {
+        assert!(TrustedProxies::parse("").unwrap().is_empty());
+        let proxies = TrustedProxies::parse("192.0.2.1,172.16.0.0/12").unwrap();
+        assert!(proxies.contains(ip("192.0.2.1")));
+        assert!(proxies.contains(ip("172.31.255.255")));
+        assert!(!proxies.contains(ip("172.32.0.0")));
+        assert!(TrustedProxies::parse("10.0.0.0/33").is_err());
+        assert!(TrustedProxies::parse("nginx").is_err());
+    }
+}
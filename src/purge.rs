@@ -0,0 +1,69 @@
+//! Inactive-account purge
+//!
+//! Removes accounts whose last backup update (or registration, if they never
+//! stored one) is older than a cutoff, so abandoned accounts stop holding
+//! storage. Runs daily when `INACTIVE_PURGE_DAYS` is set and on demand via
+//! `POST /admin/purge`. Purged accounts go through the same path as
+//! `DELETE /api/user`, tombstones included.
+
+use chrono::Utc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::db::accounts::{self, PurgeSummary};
+use crate::events::ChangeKind;
+use crate::{AppState, Result, metrics};
+
+/// Purge accounts inactive for more than `inactive_days`, logging a summary
+pub async fn purge_inactive(state: &AppState, inactive_days: u64) -> Result<PurgeSummary> {
+    let db = state.db.clone();
+    let grace_secs = state.config().deletion_grace_days as i64 * 86_400;
+    let summary = state
+        .spawn_db(move || -> Result<PurgeSummary> {
+            let now = Utc::now().timestamp();
+            let cutoff = now - inactive_days as i64 * 86_400;
+            let bury_until = (grace_secs > 0).then_some(now + grace_secs);
+
+            let write_txn = db.begin_write()?;
+            let summary = accounts::purge_inactive(&write_txn, cutoff, now, bury_until)?;
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+            Ok(summary)
+        })
+        .await??;
+
+    if !summary.user_ids.is_empty() {
+        tracing::info!(
+            "Purged {} accounts inactive for {}+ days ({} backups, {} bytes)",
+            summary.user_ids.len(),
+            inactive_days,
+            summary.backups,
+            summary.bytes
+        );
+    }
+    state
+        .metrics
+        .add(metrics::USERS_PURGED, summary.user_ids.len() as u64);
+    for user_id in &summary.user_ids {
+        state
+            .events
+            .publish(ChangeKind::Delete, user_id.clone(), None);
+    }
+
+    Ok(summary)
+}
+
+/// Run [`purge_inactive`] every `interval`
+pub fn spawn(state: AppState, inactive_days: u64, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = purge_inactive(&state, inactive_days).await {
+                tracing::warn!("Inactive account purge failed: {}", e);
+            }
+        }
+    })
+}
@@ -0,0 +1,166 @@
+//! Read-only mode after the disk fills up
+//!
+//! Once a write fails because the volume is out of space, every further store
+//! would fail the same way. [`guard_writes`] wraps the mutating routes: it
+//! flips the server into read-only mode on the first disk-full error, alerts
+//! operators, and then answers writes with 503 while retrievals keep working.
+//! An operator leaves read-only mode with `POST /admin/writes/resume` after
+//! freeing space.
+//!
+//! The same gate also holds maintenance mode, which operators switch on
+//! (`MAINTENANCE_MODE` or `POST /admin/maintenance`) to keep writes out
+//! during a database migration.
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+use crate::AppState;
+use crate::error::{AppError, Result};
+use crate::metrics;
+use crate::notifier::AlertKind;
+
+/// Response extension marking a request that failed on a full disk
+#[derive(Debug, Clone, Copy)]
+pub struct DiskFull;
+
+/// Whether the server is accepting writes
+#[derive(Debug, Default)]
+pub struct WriteGate {
+    /// When read-only mode started (Unix timestamp), 0 while writable
+    read_only_since: AtomicI64,
+    /// Writes turned off by an operator
+    maintenance: AtomicBool,
+}
+
+impl WriteGate {
+    /// When read-only mode started, if the server is read-only
+    pub fn read_only_since(&self) -> Option<i64> {
+        match self.read_only_since.load(Ordering::Relaxed) {
+            0 => None,
+            since => Some(since),
+        }
+    }
+
+    /// Enter read-only mode; returns false if it was already read-only
+    pub fn trip(&self, now: i64) -> bool {
+        self.read_only_since
+            .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Accept writes again; returns false if the server was not read-only
+    pub fn resume(&self) -> bool {
+        self.read_only_since.swap(0, Ordering::Relaxed) != 0
+    }
+
+    /// Whether maintenance mode is on
+    pub fn in_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
+    }
+
+    /// Turn maintenance mode on or off, returning whether it was on
+    pub fn set_maintenance(&self, enabled: bool) -> bool {
+        self.maintenance.swap(enabled, Ordering::Relaxed)
+    }
+}
+
+/// Reject writes while read-only or in maintenance, and enter read-only mode
+/// on a disk-full error
+pub async fn guard_writes(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    if state.writes.in_maintenance() {
+        return Err(AppError::Maintenance);
+    }
+
+    if state.writes.read_only_since().is_some() {
+        return Err(AppError::ReadOnly);
+    }
+
+    let response = next.run(request).await;
+
+    if response.extensions().get::<DiskFull>().is_some()
+        && state.writes.trip(chrono::Utc::now().timestamp())
+    {
+        tracing::error!("Disk full - server is now READ-ONLY until writes are resumed");
+        state.metrics.incr(metrics::READ_ONLY_TRIPS);
+        state.notifier.alert(
+            AlertKind::DiskFull,
+            "Database volume is full; stores and registrations are refused until space \
+             is freed and POST /admin/writes/resume is called",
+        );
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trip_and_resume() {
+        let gate = WriteGate::default();
+        assert_eq!(gate.read_only_since(), None);
+        assert!(!gate.resume());
+
+        assert!(gate.trip(1_700_000_000));
+        assert!(!gate.trip(1_700_000_100));
+        assert_eq!(gate.read_only_since(), Some(1_700_000_000));
+
+        assert!(gate.resume());
+        assert_eq!(gate.read_only_since(), None);
+    }
+
+    #[test]
+    fn test_maintenance_toggle() {
+        let gate = WriteGate::default();
+        assert!(!gate.in_maintenance());
+
+        assert!(!gate.set_maintenance(true));
+        assert!(gate.in_maintenance());
+        // Independent of disk-full read-only mode
+        assert_eq!(gate.read_only_since(), None);
+
+        assert!(gate.set_maintenance(false));
+        assert!(!gate.in_maintenance());
+    }
+
+    #[test]
+    fn test_disk_full_errors_are_detected() {
+        let full = || std::io::Error::from(std::io::ErrorKind::StorageFull);
+        let enospc = || std::io::Error::from_raw_os_error(28);
+
+        assert!(AppError::Database(redb::Error::Io(full())).is_disk_full());
+        assert!(
+            AppError::Commit(redb::CommitError::Storage(redb::StorageError::Io(enospc())))
+                .is_disk_full()
+        );
+        assert!(
+            !AppError::Storage(redb::StorageError::Io(std::io::Error::from(
+                std::io::ErrorKind::PermissionDenied
+            )))
+            .is_disk_full()
+        );
+        assert!(!AppError::BackupNotFound.is_disk_full());
+    }
+
+    #[test]
+    fn test_disk_full_response_is_tagged() {
+        use axum::response::IntoResponse;
+
+        let full = std::io::Error::from(std::io::ErrorKind::StorageFull);
+        let response = AppError::Storage(redb::StorageError::Io(full)).into_response();
+        assert_eq!(
+            response.status(),
+            axum::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert!(response.extensions().get::<DiskFull>().is_some());
+    }
+}
@@ -0,0 +1,530 @@
+//! Asynchronous replication to a warm standby
+//!
+//! With `REPLICA_URL` set, every account named in the change feed is queued
+//! in `REPLICATION_QUEUE`, and its current records are sent to the standby's
+//! `/internal/replicate`, signed with `REPLICATION_SECRET`. An account leaves
+//! the queue once the standby acknowledges it, so changes made while the
+//! standby is unreachable (or this server is restarted) are sent when it is
+//! back. Each message carries the account's whole state, so sending an
+//! account twice, or once for several changes, leaves the same result. The
+//! standby only applies a state newer than the last it applied for the
+//! account, so a replayed or delayed message can't roll it back.
+//!
+//! The change feed is drained by a task that only queues accounts; delivery
+//! runs in its own task, woken when something was queued and every
+//! [`RETRY_INTERVAL`], so a slow standby can't make the feed overflow. An
+//! account the standby refuses (4xx) moves to `REPLICATION_DEAD_LETTERS`
+//! instead of being re-sent on every flush; its next change queues it again.
+//!
+//! Only accounts are replicated. Security events, stats, bans, nonces and
+//! upload sessions stay local to each server.
+
+use axum::http::header;
+use chrono::Utc;
+use redb::{ReadableDatabase, ReadableTable, ReadableTableMetadata};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::db::{Db, dump, tables};
+use crate::error::{AppError, Result};
+use crate::{AppState, metrics};
+
+/// Path the standby accepts account states on
+pub const REPLICATE_PATH: &str = "/internal/replicate";
+
+/// Header carrying the hex HMAC-SHA256 of the request body
+pub const SIGNATURE_HEADER: &str = "x-replication-signature";
+
+/// How often to retry while the standby is unreachable
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Timeout for a single delivery
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One account's records, as of `sent_at`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountState {
+    pub user_id: String,
+    /// Unix time in milliseconds; the standby refuses stale messages like
+    /// stale requests, and any not newer than the account's last applied one
+    pub sent_at: i64,
+    /// Empty once the account is gone for good
+    pub entries: Vec<dump::DumpEntry>,
+}
+
+/// Signature of a replication request body
+pub fn sign(body: &[u8], secret: &str) -> String {
+    dailyreps_signing::sign(body, secret.as_bytes())
+}
+
+/// Queue `user_ids` for sending
+///
+/// An account queued again gets a later `queued_at` even within the same
+/// second, so a delivery in flight can tell it was re-queued (see [`dequeue`]).
+/// A dead-lettered account leaves the dead-letter table, since the change
+/// may be what makes the standby accept it.
+#[allow(clippy::result_large_err)]
+fn enqueue(db: &Db, user_ids: &[String], now: i64) -> Result<()> {
+    let write_txn = db.begin_write()?;
+    {
+        let mut queue = write_txn.open_table(tables::REPLICATION_QUEUE)?;
+        let mut dead_letters = write_txn.open_table(tables::REPLICATION_DEAD_LETTERS)?;
+        for user_id in user_ids {
+            let previous = queue.get(user_id.as_str())?.map(|t| t.value());
+            let queued_at = previous.map_or(now, |t| now.max(t + 1));
+            queue.insert(user_id.as_str(), queued_at)?;
+            dead_letters.remove(user_id.as_str())?;
+        }
+    }
+    crate::db::before_commit()?;
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+/// Every account, live or tombstoned, for a full resync
+#[allow(clippy::result_large_err)]
+fn all_accounts(db: &Db) -> Result<Vec<String>> {
+    let read_txn = db.begin_read()?;
+    let mut user_ids = Vec::new();
+    for entry in read_txn.open_table(tables::USERS)?.iter()? {
+        user_ids.push(entry?.0.value().to_string());
+    }
+    for entry in read_txn.open_table(tables::TOMBSTONES)?.iter()? {
+        user_ids.push(entry?.0.value().to_string());
+    }
+
+    Ok(user_ids)
+}
+
+/// Queued accounts and when they were queued, oldest first
+#[allow(clippy::result_large_err)]
+fn pending(db: &Db) -> Result<Vec<(String, i64)>> {
+    let read_txn = db.begin_read()?;
+    let queue = read_txn.open_table(tables::REPLICATION_QUEUE)?;
+    let mut queued = Vec::with_capacity(queue.len()? as usize);
+    for entry in queue.iter()? {
+        let (user_id, queued_at) = entry?;
+        queued.push((queued_at.value(), user_id.value().to_string()));
+    }
+    queued.sort();
+
+    Ok(queued
+        .into_iter()
+        .map(|(queued_at, user_id)| (user_id, queued_at))
+        .collect())
+}
+
+/// Remove `user_id` from the queue, unless it was queued again after
+/// `queued_at` and so has changes the standby hasn't seen
+#[allow(clippy::result_large_err)]
+fn dequeue(db: &Db, user_id: &str, queued_at: i64) -> Result<()> {
+    let write_txn = db.begin_write()?;
+    {
+        let mut queue = write_txn.open_table(tables::REPLICATION_QUEUE)?;
+        let current = queue.get(user_id)?.map(|t| t.value());
+        if current == Some(queued_at) {
+            queue.remove(user_id)?;
+        }
+    }
+    crate::db::before_commit()?;
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+/// Move `user_id` from the queue to the dead-letter table, unless it was
+/// queued again after `queued_at`; returns how many accounts are dead-lettered
+#[allow(clippy::result_large_err)]
+fn dead_letter(db: &Db, user_id: &str, queued_at: i64, now: i64) -> Result<u64> {
+    let write_txn = db.begin_write()?;
+    let count = {
+        let mut queue = write_txn.open_table(tables::REPLICATION_QUEUE)?;
+        let mut dead_letters = write_txn.open_table(tables::REPLICATION_DEAD_LETTERS)?;
+        let current = queue.get(user_id)?.map(|t| t.value());
+        if current == Some(queued_at) {
+            queue.remove(user_id)?;
+            dead_letters.insert(user_id, now)?;
+        }
+        dead_letters.len()?
+    };
+    crate::db::before_commit()?;
+    write_txn.commit()?;
+
+    Ok(count)
+}
+
+/// `user_id`'s current state, ready to send
+#[allow(clippy::result_large_err)]
+fn account_state(db: &Db, user_id: &str, now: i64) -> Result<AccountState> {
+    let read_txn = db.begin_read()?;
+    Ok(AccountState {
+        user_id: user_id.to_string(),
+        sent_at: now,
+        entries: dump::account_entries(&read_txn, user_id)?,
+    })
+}
+
+/// Replace the account in `state` on this (standby) server
+///
+/// Fails with [`AppError::ReplayedRequest`] unless `state` is newer than the
+/// last one applied for the account.
+#[allow(clippy::result_large_err)]
+pub fn apply(db: &Db, state: AccountState) -> Result<()> {
+    let write_txn = db.begin_write()?;
+    {
+        let mut applied = write_txn.open_table(tables::REPLICATION_APPLIED)?;
+        let last = applied.get(state.user_id.as_str())?.map(|t| t.value());
+        if last.is_some_and(|last| state.sent_at <= last) {
+            tracing::warn!("Refused a replication state no newer than the last applied");
+            return Err(AppError::ReplayedRequest);
+        }
+        applied.insert(state.user_id.as_str(), state.sent_at)?;
+    }
+    dump::replace_account(&write_txn, &state.user_id, state.entries)?;
+    crate::db::before_commit()?;
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+/// Queue `user_ids` for the sender
+async fn queue(state: &AppState, user_ids: Vec<String>) {
+    let db = state.db.clone();
+    let now = Utc::now().timestamp();
+    match state.spawn_db(move || enqueue(&db, &user_ids, now)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::error!("Failed to queue accounts for replication: {}", e),
+        Err(e) => tracing::error!("Failed to queue accounts for replication: {}", e),
+    }
+}
+
+/// Queue every account, after missing changes
+async fn queue_all(state: &AppState) {
+    let db = state.db.clone();
+    match state.spawn_db(move || all_accounts(&db)).await {
+        Ok(Ok(user_ids)) => queue(state, user_ids).await,
+        Ok(Err(e)) => tracing::error!("Failed to list accounts for replication: {}", e),
+        Err(e) => tracing::error!("Failed to list accounts for replication: {}", e),
+    }
+}
+
+/// Forwards queued accounts to the standby
+struct Sender {
+    state: AppState,
+    client: reqwest::Client,
+    url: String,
+    secret: String,
+    /// Whether the last delivery succeeded
+    reachable: bool,
+}
+
+impl Sender {
+    async fn deliver(&self, user_id: &str, queued_at: i64) -> anyhow::Result<()> {
+        let db = self.state.db.clone();
+        let (owned_id, now) = (user_id.to_string(), Utc::now().timestamp_millis());
+        let message = self
+            .state
+            .spawn_db(move || account_state(&db, &owned_id, now))
+            .await??;
+        let body = serde_json::to_vec(&message)?;
+
+        self.client
+            .post(format!("{}{}", self.url, REPLICATE_PATH))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(&body, &self.secret))
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let db = self.state.db.clone();
+        let owned_id = user_id.to_string();
+        self.state
+            .spawn_db(move || dequeue(&db, &owned_id, queued_at))
+            .await??;
+
+        Ok(())
+    }
+
+    /// Send every queued account
+    ///
+    /// Stops at the first account the standby can't take (unreachable or
+    /// 5xx), since the rest would fail too. One it refuses (4xx) is moved to
+    /// the dead-letter table, so it can't hold up every account behind it or
+    /// be re-sent on every flush.
+    async fn flush(&mut self) {
+        let db = self.state.db.clone();
+        let queued = match self.state.spawn_db(move || pending(&db)).await {
+            Ok(Ok(queued)) => queued,
+            Ok(Err(e)) => return tracing::error!("Failed to read replication queue: {}", e),
+            Err(e) => return tracing::error!("Failed to read replication queue: {}", e),
+        };
+
+        let (mut remaining, mut unavailable) = (queued.len(), false);
+        for (user_id, queued_at) in &queued {
+            let Err(e) = self.deliver(user_id, *queued_at).await else {
+                self.state.metrics.incr(metrics::REPLICATION_SENT);
+                remaining -= 1;
+                continue;
+            };
+
+            let rejected = e
+                .downcast_ref::<reqwest::Error>()
+                .and_then(reqwest::Error::status)
+                .filter(|status| status.is_client_error());
+            if let Some(status) = rejected {
+                self.state.metrics.incr(metrics::REPLICATION_REJECTED);
+                tracing::error!(
+                    "Replication standby rejected an account ({}), dead-lettering it",
+                    status
+                );
+                let db = self.state.db.clone();
+                let (owned_id, queued_at) = (user_id.clone(), *queued_at);
+                let now = Utc::now().timestamp();
+                match self
+                    .state
+                    .spawn_db(move || dead_letter(&db, &owned_id, queued_at, now))
+                    .await
+                {
+                    Ok(Ok(count)) => {
+                        remaining -= 1;
+                        self.state
+                            .metrics
+                            .gauge(metrics::REPLICATION_DEAD_LETTERS, count);
+                    }
+                    Ok(Err(e)) => tracing::error!("Failed to dead-letter an account: {}", e),
+                    Err(e) => tracing::error!("Failed to dead-letter an account: {}", e),
+                }
+                continue;
+            }
+
+            self.state.metrics.incr(metrics::REPLICATION_FAILURES);
+            if self.reachable {
+                tracing::warn!(
+                    "Replication standby unreachable, {} accounts queued: {}",
+                    remaining,
+                    e
+                );
+            }
+            self.reachable = false;
+            unavailable = true;
+            break;
+        }
+        self.state
+            .metrics
+            .gauge(metrics::REPLICATION_PENDING, remaining as u64);
+
+        if !unavailable && !self.reachable {
+            tracing::info!("Replication standby caught up");
+            self.reachable = true;
+        }
+    }
+}
+
+/// Replicate committed changes to the standby at `url` until shutdown
+pub fn spawn(state: AppState, url: String, secret: String) -> JoinHandle<()> {
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let mut events = state.events.subscribe();
+    let queued = Arc::new(Notify::new());
+
+    // Delivery: woken when something is queued, and on the retry timer
+    let mut sender = Sender {
+        state: state.clone(),
+        client,
+        url,
+        secret,
+        reachable: true,
+    };
+    let wake = Arc::clone(&queued);
+    let delivery = tokio::spawn(async move {
+        let mut retry = tokio::time::interval(RETRY_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = wake.notified() => {
+                    // While the standby is down, only the retry timer sends
+                    if !sender.reachable {
+                        continue;
+                    }
+                }
+                _ = retry.tick() => {}
+            }
+
+            sender.flush().await;
+        }
+    });
+
+    // The feed only queues, so it keeps up however slow delivery is
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => queue(&state, vec![event.user_id]).await,
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(
+                        "Replication fell {} changes behind, resyncing every account",
+                        missed
+                    );
+                    queue_all(&state).await;
+                }
+                Err(RecvError::Closed) => break,
+            }
+            queued.notify_one();
+        }
+        delivery.abort();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+    use crate::seed::{SeedOptions, seed_database};
+
+    #[test]
+    fn test_apply_replaces_account() {
+        let primary = open_in_memory_database().unwrap();
+        let options = SeedOptions {
+            users: 2,
+            ..SeedOptions::default()
+        };
+        seed_database(&primary, &options).unwrap();
+        let user_ids = all_accounts(&primary).unwrap();
+
+        let standby = open_in_memory_database().unwrap();
+        for user_id in &user_ids {
+            apply(&standby, account_state(&primary, user_id, 0).unwrap()).unwrap();
+        }
+        for user_id in &user_ids {
+            let entries = |db| {
+                let read_txn = Db::begin_read(db).unwrap();
+                serde_json::to_string(&dump::account_entries(&read_txn, user_id).unwrap()).unwrap()
+            };
+            assert_eq!(entries(&primary), entries(&standby));
+        }
+
+        // An account that is gone is removed from the standby too
+        let gone = AccountState {
+            user_id: user_ids[0].clone(),
+            sent_at: 1,
+            entries: Vec::new(),
+        };
+        apply(&standby, gone).unwrap();
+        assert_eq!(all_accounts(&standby).unwrap(), [user_ids[1].clone()]);
+
+        // Replaying the older state can't bring it back
+        let replayed = account_state(&primary, &user_ids[0], 0).unwrap();
+        assert!(matches!(
+            apply(&standby, replayed),
+            Err(AppError::ReplayedRequest)
+        ));
+        assert_eq!(all_accounts(&standby).unwrap(), [user_ids[1].clone()]);
+    }
+
+    #[test]
+    fn test_queue_is_oldest_first() {
+        let db = open_in_memory_database().unwrap();
+        enqueue(&db, &["b".to_string()], 2).unwrap();
+        enqueue(&db, &["a".to_string()], 3).unwrap();
+        enqueue(&db, &["c".to_string()], 1).unwrap();
+        let queued = pending(&db).unwrap();
+        let user_ids: Vec<_> = queued.iter().map(|(user_id, _)| user_id.as_str()).collect();
+        assert_eq!(user_ids, ["c", "b", "a"]);
+
+        dequeue(&db, "b", 2).unwrap();
+        assert_eq!(
+            pending(&db).unwrap(),
+            [("c".to_string(), 1), ("a".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn test_dequeue_keeps_account_queued_again() {
+        let db = open_in_memory_database().unwrap();
+        enqueue(&db, &["a".to_string()], 5).unwrap();
+        let [(_, queued_at)] = pending(&db).unwrap()[..] else {
+            panic!("expected one queued account");
+        };
+
+        // Changed again within the same second while being delivered
+        enqueue(&db, &["a".to_string()], 5).unwrap();
+        dequeue(&db, "a", queued_at).unwrap();
+        assert_eq!(pending(&db).unwrap(), [("a".to_string(), 6)]);
+
+        dequeue(&db, "a", 6).unwrap();
+        assert!(pending(&db).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush_skips_rejected_account() {
+        use axum::{Router, http::StatusCode, routing::post};
+
+        // A standby that refuses one account and takes the rest
+        let standby = Router::new().route(
+            REPLICATE_PATH,
+            post(|body: axum::body::Bytes| async move {
+                let account: AccountState = serde_json::from_slice(&body).unwrap();
+                match account.user_id.as_str() {
+                    "a" => StatusCode::PAYLOAD_TOO_LARGE,
+                    _ => StatusCode::NO_CONTENT,
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, standby).await });
+
+        let db = open_in_memory_database().unwrap();
+        enqueue(&db, &["a".to_string()], 1).unwrap();
+        enqueue(&db, &["b".to_string()], 2).unwrap();
+        let state = AppState::new(db.clone(), crate::test_utils::test_config());
+        let mut sender = Sender {
+            state: state.clone(),
+            client: reqwest::Client::new(),
+            url,
+            secret: "secret".to_string(),
+            reachable: true,
+        };
+
+        sender.flush().await;
+        assert!(pending(&db).unwrap().is_empty());
+        assert!(sender.reachable);
+        assert_eq!(state.metrics.counter(metrics::REPLICATION_SENT), 1);
+        assert_eq!(state.metrics.counter(metrics::REPLICATION_REJECTED), 1);
+        assert_eq!(state.metrics.counter(metrics::REPLICATION_FAILURES), 0);
+        assert_eq!(state.metrics.counter(metrics::REPLICATION_DEAD_LETTERS), 1);
+
+        // The rejected account is not re-sent by later flushes
+        sender.flush().await;
+        assert_eq!(state.metrics.counter(metrics::REPLICATION_REJECTED), 1);
+
+        // Its next change queues it again
+        enqueue(&db, &["a".to_string()], 3).unwrap();
+        assert_eq!(pending(&db).unwrap(), [("a".to_string(), 3)]);
+        let read_txn = db.begin_read().unwrap();
+        let dead_letters = read_txn
+            .open_table(tables::REPLICATION_DEAD_LETTERS)
+            .unwrap();
+        assert!(dead_letters.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_dead_letter_keeps_account_queued_again() {
+        let db = open_in_memory_database().unwrap();
+        enqueue(&db, &["a".to_string()], 5).unwrap();
+        enqueue(&db, &["a".to_string()], 5).unwrap();
+
+        // Rejected as queued at 5, but it changed since
+        assert_eq!(dead_letter(&db, "a", 5, 7).unwrap(), 0);
+        assert_eq!(pending(&db).unwrap(), [("a".to_string(), 6)]);
+
+        assert_eq!(dead_letter(&db, "a", 6, 7).unwrap(), 1);
+        assert!(pending(&db).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+//! Per-request IDs for correlating logs with bug reports
+//!
+//! Every request gets a random UUID. It is recorded on a tracing span around
+//! the handler, returned in `X-Request-Id`, and added to error bodies as
+//! `requestId`, so a user can quote it and the matching log lines can be found.
+
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use tracing::Instrument;
+
+/// Response header carrying the request ID
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// ID of the request being handled, if called from within [`assign`]
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// A random (version 4) UUID
+fn new_request_id() -> Result<String, getrandom::Error> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Middleware giving each request an ID, its span and response header
+pub async fn assign(request: Request, next: Next) -> Response {
+    let id = match new_request_id() {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::error!("Could not generate request ID: {:?}", e);
+            return next.run(request).await;
+        }
+    };
+
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request).instrument(span))
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_ids_are_v4_uuids() {
+        let (a, b) = (new_request_id().unwrap(), new_request_id().unwrap());
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 36);
+        let groups: Vec<&str> = a.split('-').collect();
+        assert_eq!(
+            groups.iter().map(|g| g.len()).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert!(groups[2].starts_with('4'));
+        assert!(matches!(&groups[3][..1], "8" | "9" | "a" | "b"));
+    }
+
+    #[tokio::test]
+    async fn test_current_only_inside_scope() {
+        assert_eq!(current(), None);
+        let seen = REQUEST_ID
+            .scope("abc".to_string(), async { current() })
+            .await;
+        assert_eq!(seen.as_deref(), Some("abc"));
+    }
+}
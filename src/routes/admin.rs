@@ -1,19 +1,33 @@
 use axum::{
     Json,
     extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
 };
-use redb::{ReadableDatabase, ReadableTableMetadata};
+use chrono::{DateTime, NaiveDate, Utc};
+use redb::{ReadableDatabase, ReadableTable, ReadableTableMetadata};
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
 use std::fs;
+use std::ops::Bound;
 
-use crate::{AppError, AppState, db::tables, error::Result};
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
 
-/// Query parameters for admin stats endpoint
-#[derive(Debug, Deserialize)]
-pub struct AdminQuery {
-    /// Admin secret key for authentication
-    pub key: String,
-}
+use crate::constants::MIN_INACTIVE_PURGE_DAYS;
+use crate::health_history::HealthSample;
+use crate::models::{BackupRecord, DailyStatsRecord, User, UserRecord};
+use crate::purge;
+use crate::routes::AdminAuth;
+use crate::runtime_stats::RuntimeStats;
+use crate::security::{open_cursor, pepper_user_id, seal_cursor};
+use crate::{
+    AppError, AppState,
+    db::{compaction, rate_limits, snapshot, tables},
+    error::Result,
+    security_events,
+    security_events::{CountrySummary, NetworkSummary},
+};
 
 /// Database statistics response
 #[derive(Debug, Serialize)]
@@ -24,6 +38,196 @@ pub struct AdminStatsResponse {
     pub database_size_human: String,
 }
 
+/// Query parameters for admin stats export endpoint
+#[derive(Debug, Deserialize)]
+pub struct AdminExportQuery {
+    /// Output format: `json` (default) or `csv`
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Time range to include, e.g. `30d`, or `all` (default)
+    pub range: Option<String>,
+}
+
+/// Supported export formats
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Activity rolled up for a single UTC day
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DailyRollup {
+    pub date: String,
+    pub registrations: u64,
+    pub backups_updated: u64,
+}
+
+/// Storage summary for a single user
+#[derive(Debug, Clone, Serialize)]
+pub struct UserSummary {
+    pub user_id: String,
+    pub created_at: String,
+    pub backup_count: u64,
+    pub total_bytes: u64,
+    pub last_backup_at: Option<String>,
+}
+
+/// Exported statistics report
+#[derive(Debug, Serialize)]
+pub struct AdminStatsExport {
+    pub generated_at: String,
+    pub range_days: Option<i64>,
+    pub daily: Vec<DailyRollup>,
+    pub users: Vec<UserSummary>,
+}
+
+/// Query parameters for the largest-backups report
+#[derive(Debug, Deserialize)]
+pub struct LargestBackupsQuery {
+    /// Number of records to return (default 20, max 100)
+    pub limit: Option<usize>,
+}
+
+/// One entry in the largest-backups report
+#[derive(Debug, Clone, Serialize)]
+pub struct LargeBackup {
+    /// First characters of the owner's user ID, enough to correlate with the
+    /// stats export without listing full identifiers
+    pub owner: String,
+    /// Stored (compressed) size of the record, i.e. its disk use
+    pub size_bytes: u64,
+    pub size_human: String,
+    pub updated_at: String,
+}
+
+/// Largest stored backups, biggest first
+#[derive(Debug, Serialize)]
+pub struct LargestBackupsResponse {
+    pub backups: Vec<LargeBackup>,
+}
+
+/// Query parameters for the top-abusers report
+#[derive(Debug, Deserialize)]
+pub struct AbuseQuery {
+    /// Time window, e.g. `7d` (default 1d), or `all` for everything retained
+    pub range: Option<String>,
+    /// Number of offenders to return (default 20, max 100)
+    pub limit: Option<usize>,
+}
+
+/// Top-abusers report
+#[derive(Debug, Serialize)]
+pub struct AbuseReport {
+    pub range_days: Option<i64>,
+    pub offenders: Vec<Offender>,
+    /// Rejections per client country; `null` covers events without a GeoIP match
+    pub countries: Vec<CountrySummary>,
+    /// Rejections per client network; `null` covers events without a client IP
+    pub networks: Vec<NetworkSummary>,
+}
+
+/// One row of the top-abusers report
+#[derive(Debug, Serialize)]
+pub struct Offender {
+    /// Claimed user ID; `null` for requests without a well-formed one
+    pub user_id: Option<String>,
+    pub rate_limit_hits: u64,
+    pub signature_failures: u64,
+    pub duplicate_uploads: u64,
+    pub severity: u64,
+    pub last_seen_at: String,
+}
+
+/// Query parameters for the user listing
+#[derive(Debug, Deserialize)]
+pub struct UsersQuery {
+    /// `nextCursor` of the previous page; absent for the first page
+    pub cursor: Option<String>,
+    /// Users per page (default 100, max 1000)
+    pub limit: Option<usize>,
+}
+
+/// One account in the user listing
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminUser {
+    /// User ID keyed with the app secret (see [`pepper_user_id`])
+    pub id: String,
+    pub created_at: String,
+    pub backup_count: u64,
+    /// Sum of the user's backup sizes, as in the stats export
+    pub total_bytes: u64,
+}
+
+/// One page of the user listing, in user ID order
+#[derive(Debug, Serialize)]
+pub struct UsersResponse {
+    pub users: Vec<AdminUser>,
+    /// Pass as `cursor` for the next page; absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Query parameters for the signups time series
+#[derive(Debug, Deserialize)]
+pub struct SignupsQuery {
+    /// Number of days up to and including today (default 90)
+    pub days: Option<i64>,
+}
+
+/// Registrations and deletions on one UTC day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SignupDay {
+    pub date: String,
+    pub registrations: u64,
+    pub deletions: u64,
+}
+
+/// Daily signups time series, oldest first, one entry per day
+#[derive(Debug, Serialize)]
+pub struct SignupsResponse {
+    pub days: Vec<SignupDay>,
+}
+
+/// Default and maximum `days` for the signups series
+const DEFAULT_SIGNUP_DAYS: i64 = 90;
+const MAX_SIGNUP_DAYS: i64 = 3650;
+
+/// Default and maximum `limit` for the admin top-N reports
+const DEFAULT_REPORT_LIMIT: usize = 20;
+const MAX_REPORT_LIMIT: usize = 100;
+
+/// Default and maximum `limit` for the user listing
+const DEFAULT_USERS_PAGE: usize = 100;
+const MAX_USERS_PAGE: usize = 1000;
+
+/// Characters of the user ID shown as a backup's owner
+const OWNER_PREFIX_LEN: usize = 8;
+
+/// Parse a range like `30d` into a number of days (`all` means unbounded)
+fn parse_range_days(range: Option<&str>) -> Result<Option<i64>> {
+    let range = match range {
+        None | Some("all") => return Ok(None),
+        Some(r) => r,
+    };
+
+    range
+        .strip_suffix('d')
+        .and_then(|days| days.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .map(Some)
+        .ok_or_else(|| AppError::InvalidInput("Invalid range - use e.g. 30d or all".to_string()))
+}
+
+/// UTC calendar day of a Unix timestamp
+fn day_of(timestamp: i64) -> NaiveDate {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .date_naive()
+}
+
 /// Format bytes into human-readable string
 fn format_bytes(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -44,48 +248,36 @@ fn format_bytes(bytes: u64) -> String {
 /// Admin stats endpoint
 ///
 /// Returns database statistics for monitoring and diagnostics.
-/// Requires admin secret key passed as query parameter.
+/// Requires admin authentication (see [`AdminAuth`]).
 ///
-/// GET /admin/stats?key=<admin_secret_key>
+/// GET /admin/stats
 pub async fn admin_stats(
     State(state): State<AppState>,
-    Query(params): Query<AdminQuery>,
+    _admin: AdminAuth,
 ) -> Result<Json<AdminStatsResponse>> {
-    // Check if admin endpoints are enabled
-    let admin_key = state
-        .config
-        .admin_secret_key
-        .as_ref()
-        .ok_or(AppError::Unauthorized)?;
-
-    // Verify the provided key matches
-    if params.key != *admin_key {
-        tracing::warn!("Invalid admin key attempt");
-        return Err(AppError::Unauthorized);
-    }
-
     // Get database file size
-    let db_path = state.config.database_path.clone();
+    let db_path = state.config().database_path.clone();
     let database_size_bytes = fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0);
 
     // Count records in database
     let db = state.db.clone();
-    let (user_count, backup_count) = tokio::task::spawn_blocking(move || -> Result<(u64, u64)> {
-        let read_txn = db.begin_read()?;
+    let (user_count, backup_count) = state
+        .spawn_db(move || -> Result<(u64, u64)> {
+            let read_txn = db.begin_read()?;
 
-        let user_count = match read_txn.open_table(tables::USERS) {
-            Ok(table) => table.len()?,
-            Err(_) => 0,
-        };
+            let user_count = match read_txn.open_table(tables::USERS) {
+                Ok(table) => table.len()?,
+                Err(_) => 0,
+            };
 
-        let backup_count = match read_txn.open_table(tables::BACKUPS) {
-            Ok(table) => table.len()?,
-            Err(_) => 0,
-        };
+            let backup_count = match read_txn.open_table(tables::BACKUPS) {
+                Ok(table) => table.len()?,
+                Err(_) => 0,
+            };
 
-        Ok((user_count, backup_count))
-    })
-    .await??;
+            Ok((user_count, backup_count))
+        })
+        .await??;
 
     tracing::info!(
         "Admin stats requested: {} users, {} backups, {} database",
@@ -101,3 +293,790 @@ pub async fn admin_stats(
         database_size_human: format_bytes(database_size_bytes),
     }))
 }
+
+/// Admin metrics endpoint
+///
+/// Returns the in-process counters accumulated since startup, plus opt-in
+/// telemetry rollups as `telemetry.<key>.<value>`.
+///
+/// GET /admin/metrics
+pub async fn admin_metrics(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+) -> Json<BTreeMap<String, u64>> {
+    let mut snapshot: BTreeMap<String, u64> = state
+        .metrics
+        .snapshot()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    snapshot.extend(state.telemetry.snapshot());
+    Json(snapshot)
+}
+
+/// Process resource usage
+///
+/// RSS, open file descriptors, Tokio queue depths and (with the `jemalloc`
+/// feature) allocator stats, for diagnosing memory or fd exhaustion on small
+/// hosts without shell access.
+///
+/// GET /admin/runtime
+pub async fn admin_runtime(_admin: AdminAuth) -> Json<RuntimeStats> {
+    Json(RuntimeStats::collect())
+}
+
+/// Recent health-check results
+#[derive(Debug, Serialize)]
+pub struct HealthHistoryResponse {
+    pub checks: usize,
+    pub failures: usize,
+    /// Newest first
+    pub samples: Vec<HealthSample>,
+}
+
+/// The last `/health` results with timestamps and failure reasons
+///
+/// Kept in memory only (lost on restart), so transient database errors that
+/// cleared on their own can still be inspected.
+///
+/// GET /admin/health/history
+pub async fn admin_health_history(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+) -> Json<HealthHistoryResponse> {
+    let samples = state.health_history.recent();
+    Json(HealthHistoryResponse {
+        checks: samples.len(),
+        failures: samples.iter().filter(|s| !s.healthy).count(),
+        samples,
+    })
+}
+
+/// Response from resuming writes
+#[derive(Debug, Serialize)]
+pub struct ResumeWritesResponse {
+    /// False if the server was not read-only
+    pub resumed: bool,
+}
+
+/// Leave read-only mode after disk space has been freed
+///
+/// If the disk is still full, the next failed write switches the server back
+/// to read-only and alerts again.
+///
+/// POST /admin/writes/resume
+pub async fn admin_resume_writes(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+) -> Json<ResumeWritesResponse> {
+    let resumed = state.writes.resume();
+    if resumed {
+        tracing::warn!(admin = %admin.identity, "Writes resumed");
+    }
+    Json(ResumeWritesResponse { resumed })
+}
+
+/// What a rate-limit cleanup removed
+#[derive(Debug, Serialize)]
+pub struct RateLimitPruneResponse {
+    /// Records whose windows had long expired
+    pub removed: usize,
+    /// Records still held
+    pub remaining: u64,
+}
+
+/// Remove expired rate-limit records now
+///
+/// The same cleanup runs every hour; this reports how many it reclaims.
+///
+/// POST /admin/rate-limits/prune
+pub async fn admin_prune_rate_limits(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+) -> Result<Json<RateLimitPruneResponse>> {
+    let db = state.db.clone();
+    let response = state
+        .spawn_db(move || -> Result<RateLimitPruneResponse> {
+            let write_txn = db.begin_write()?;
+            let removed = rate_limits::prune_expired(&write_txn, Utc::now().timestamp())?;
+            let remaining = write_txn.open_table(tables::RATE_LIMITS)?.len()?;
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+            Ok(RateLimitPruneResponse { removed, remaining })
+        })
+        .await??;
+
+    if response.removed > 0 {
+        tracing::info!("Removed {} expired rate-limit records", response.removed);
+    }
+    Ok(Json(response))
+}
+
+/// A snapshot just written
+#[derive(Debug, Serialize)]
+pub struct SnapshotResponse {
+    pub path: String,
+    pub size_bytes: u64,
+    pub size_human: String,
+}
+
+/// Copy the database to a timestamped file in `SNAPSHOT_DIR`
+///
+/// The copy is consistent as of the last commit: writes wait while it is
+/// taken, reads don't.
+///
+/// POST /admin/snapshot
+pub async fn admin_snapshot(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+) -> Result<Json<SnapshotResponse>> {
+    let Some(dir) = state.config().snapshot_dir.clone() else {
+        return Err(AppError::InvalidInput(
+            "Snapshots are not configured - set SNAPSHOT_DIR".to_string(),
+        ));
+    };
+
+    let db = state.db.clone();
+    let db_path = state.config().database_path.clone();
+    let snapshot = state
+        .spawn_db(move || snapshot::write_snapshot(&db, db_path.as_ref(), dir.as_ref(), Utc::now()))
+        .await??;
+
+    tracing::info!(
+        admin = %admin.identity,
+        "Database snapshot written to {:?} ({} bytes)",
+        snapshot.path,
+        snapshot.size_bytes
+    );
+    Ok(Json(SnapshotResponse {
+        path: snapshot.path.to_string_lossy().into_owned(),
+        size_bytes: snapshot.size_bytes,
+        size_human: format_bytes(snapshot.size_bytes),
+    }))
+}
+
+/// File sizes around a compaction
+#[derive(Debug, Serialize)]
+pub struct CompactResponse {
+    pub before_bytes: u64,
+    pub after_bytes: u64,
+    pub reclaimed_bytes: u64,
+}
+
+/// Compact the database file now
+///
+/// Returns space freed by deletions to the filesystem. Requests wait while it
+/// runs; 503 if a transaction was still open, in which case retry.
+///
+/// POST /admin/compact
+pub async fn admin_compact(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+) -> Result<Json<CompactResponse>> {
+    tracing::warn!(admin = %admin.identity, "Database compaction requested");
+
+    let db = state.db.clone();
+    let db_path = state.config().database_path.clone();
+    let report = state
+        .spawn_db(move || compaction::compact(&db, db_path.as_ref()))
+        .await??;
+
+    tracing::info!(
+        "Database compacted: {} -> {} bytes",
+        report.before_bytes,
+        report.after_bytes
+    );
+    Ok(Json(CompactResponse {
+        before_bytes: report.before_bytes,
+        after_bytes: report.after_bytes,
+        reclaimed_bytes: report.before_bytes.saturating_sub(report.after_bytes),
+    }))
+}
+
+/// Maintenance mode toggle
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+}
+
+/// Maintenance mode after the toggle
+#[derive(Debug, Serialize)]
+pub struct MaintenanceResponse {
+    pub maintenance: bool,
+}
+
+/// Turn maintenance mode on or off
+///
+/// While on, registrations, stores and deletes get 503 with a maintenance
+/// message and retrievals keep working, so the database can be migrated
+/// safely. Starts on with `MAINTENANCE_MODE=true`.
+///
+/// POST /admin/maintenance
+pub async fn admin_maintenance(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+    Json(payload): Json<MaintenanceRequest>,
+) -> Json<MaintenanceResponse> {
+    if state.writes.set_maintenance(payload.enabled) != payload.enabled {
+        tracing::warn!(
+            admin = %admin.identity,
+            "Maintenance mode {}",
+            if payload.enabled { "enabled" } else { "disabled" }
+        );
+    }
+    Json(MaintenanceResponse {
+        maintenance: payload.enabled,
+    })
+}
+
+/// Settings changed by a config reload
+#[derive(Debug, Serialize)]
+pub struct ConfigReloadResponse {
+    /// Names of the reloaded settings whose value changed
+    pub changed: Vec<&'static str>,
+}
+
+/// Re-read the configuration and apply the reloadable settings
+///
+/// Same as sending the process SIGHUP. Rate limits, CORS origins and the
+/// admin key take effect for the next request; open connections and uploads
+/// carry on. A config that fails to load is refused with 400 and the current
+/// one stays in effect.
+///
+/// POST /admin/config/reload
+pub async fn admin_reload_config(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+) -> Result<Json<ConfigReloadResponse>> {
+    let changed = state.reload_config().map_err(AppError::InvalidInput)?;
+    tracing::warn!(admin = %admin.identity, "Configuration reloaded");
+    Ok(Json(ConfigReloadResponse { changed }))
+}
+
+/// Query parameters for the inactive-account purge
+#[derive(Debug, Deserialize)]
+pub struct PurgeQuery {
+    /// Remove accounts with no backup update in this many days (at least 30)
+    pub inactive_days: u64,
+}
+
+/// What the inactive-account purge removed
+#[derive(Debug, Serialize)]
+pub struct PurgeResponse {
+    pub purged_users: usize,
+    pub backups_removed: usize,
+    pub bytes_freed: u64,
+    /// Until when purged accounts can still be restored; absent with
+    /// `DELETION_GRACE_DAYS=0`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restorable_until: Option<String>,
+}
+
+/// Purge inactive accounts now
+///
+/// Same as the daily job enabled by `INACTIVE_PURGE_DAYS`, with the
+/// threshold given per call.
+///
+/// POST /admin/purge?inactive_days=365
+pub async fn admin_purge(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+    Query(params): Query<PurgeQuery>,
+) -> Result<Json<PurgeResponse>> {
+    if params.inactive_days < MIN_INACTIVE_PURGE_DAYS {
+        return Err(AppError::InvalidInput(format!(
+            "inactive_days must be at least {}",
+            MIN_INACTIVE_PURGE_DAYS
+        )));
+    }
+
+    tracing::warn!(
+        admin = %admin.identity,
+        "Inactive account purge requested: {} days",
+        params.inactive_days
+    );
+    let summary = purge::purge_inactive(&state, params.inactive_days).await?;
+
+    let grace_days = state.config().deletion_grace_days as i64;
+    Ok(Json(PurgeResponse {
+        purged_users: summary.user_ids.len(),
+        backups_removed: summary.backups,
+        bytes_freed: summary.bytes,
+        restorable_until: (grace_days > 0 && !summary.user_ids.is_empty())
+            .then(|| (Utc::now() + chrono::Duration::days(grace_days)).to_rfc3339()),
+    }))
+}
+
+/// Largest backups report
+///
+/// Lists the biggest stored records with an abbreviated owner ID, so users
+/// approaching the size limit can be spotted before they hit it.
+///
+/// GET /admin/backups/largest?limit=20
+pub async fn admin_largest_backups(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    Query(params): Query<LargestBackupsQuery>,
+) -> Result<Json<LargestBackupsResponse>> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_REPORT_LIMIT)
+        .clamp(1, MAX_REPORT_LIMIT);
+
+    let db = state.db.clone();
+    let largest = state
+        .spawn_db(move || -> Result<Vec<(u64, BackupRecord)>> {
+            let read_txn = db.begin_read()?;
+            let backups = read_txn.open_table(tables::BACKUPS)?;
+
+            // Min-heap of the `limit` largest seen so far
+            let mut heap: BinaryHeap<Reverse<(u64, String)>> = BinaryHeap::with_capacity(limit + 1);
+            for entry in backups.iter()? {
+                let (key, bytes) = entry?;
+                // Ranked by stored (compressed) size, i.e. disk use; decode only the winners
+                heap.push(Reverse((
+                    bytes.value().len() as u64,
+                    key.value().to_string(),
+                )));
+                if heap.len() > limit {
+                    heap.pop();
+                }
+            }
+
+            // Sorted ascending by `Reverse`, i.e. biggest first
+            let mut largest = Vec::with_capacity(heap.len());
+            for Reverse((size, key)) in heap.into_sorted_vec() {
+                if let Some(bytes) = backups.get(key.as_str())? {
+                    let (record, _): (BackupRecord, _) =
+                        bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                    largest.push((size, record));
+                }
+            }
+            Ok(largest)
+        })
+        .await??;
+
+    let backups = largest
+        .into_iter()
+        .map(|(size_bytes, record)| LargeBackup {
+            owner: record.user_id.chars().take(OWNER_PREFIX_LEN).collect(),
+            size_bytes,
+            size_human: format_bytes(size_bytes),
+            updated_at: crate::routes::timestamp_to_rfc3339(record.updated_at),
+        })
+        .collect();
+
+    Ok(Json(LargestBackupsResponse { backups }))
+}
+
+/// Top-abusers report
+///
+/// Summarizes rejected requests per claimed user and per client network
+/// over a time window, most severe first (a signature failure weighs 5, a
+/// rate-limit hit 1). Events are kept for 30 days. With a GeoIP database
+/// configured, the report also breaks rejections down by client country.
+/// Buffered events are flushed first, so the report includes them.
+///
+/// GET /admin/abuse/top?range=7d&limit=20
+pub async fn admin_abuse_top(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    Query(params): Query<AbuseQuery>,
+) -> Result<Json<AbuseReport>> {
+    let range_days = parse_range_days(Some(params.range.as_deref().unwrap_or("1d")))?;
+    let since = range_days
+        .map(|days| Utc::now().timestamp() - days * 86400)
+        .unwrap_or(i64::MIN);
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_REPORT_LIMIT)
+        .clamp(1, MAX_REPORT_LIMIT);
+
+    security_events::flush(&state).await?;
+    let db = state.db.clone();
+    let (offenders, countries, networks) = state
+        .spawn_db(move || -> Result<_> {
+            Ok((
+                security_events::top_offenders(&db, since, limit)?,
+                security_events::top_countries(&db, since, limit)?,
+                security_events::top_networks(&db, since, limit)?,
+            ))
+        })
+        .await??;
+
+    Ok(Json(AbuseReport {
+        range_days,
+        offenders: offenders
+            .into_iter()
+            .map(|o| Offender {
+                user_id: o.user_id,
+                rate_limit_hits: o.rate_limit_hits,
+                signature_failures: o.signature_failures,
+                duplicate_uploads: o.duplicate_uploads,
+                severity: o.severity,
+                last_seen_at: crate::routes::timestamp_to_rfc3339(o.last_seen_at),
+            })
+            .collect(),
+        countries,
+        networks,
+    }))
+}
+
+/// Registration analytics
+///
+/// Daily registration and deletion counts from the daily stats table,
+/// with days without activity filled in as zeros so the series charts
+/// directly.
+///
+/// GET /admin/signups?days=90
+pub async fn admin_signups(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    Query(params): Query<SignupsQuery>,
+) -> Result<Json<SignupsResponse>> {
+    let days = params.days.unwrap_or(DEFAULT_SIGNUP_DAYS);
+    if !(1..=MAX_SIGNUP_DAYS).contains(&days) {
+        return Err(AppError::InvalidInput(format!(
+            "days must be between 1 and {}",
+            MAX_SIGNUP_DAYS
+        )));
+    }
+
+    let today = day_of(Utc::now().timestamp());
+    let first = today - chrono::Days::new(days as u64 - 1);
+
+    let db = state.db.clone();
+    let recorded = state
+        .spawn_db(move || -> Result<BTreeMap<String, DailyStatsRecord>> {
+            let read_txn = db.begin_read()?;
+            let table = read_txn.open_table(tables::DAILY_STATS)?;
+
+            let mut recorded = BTreeMap::new();
+            let (start, end) = (first.to_string(), today.to_string());
+            for entry in table.range(start.as_str()..=end.as_str())? {
+                let (date, bytes) = entry?;
+                let (record, _): (DailyStatsRecord, _) =
+                    bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                recorded.insert(date.value().to_string(), record);
+            }
+            Ok(recorded)
+        })
+        .await??;
+
+    let days = first
+        .iter_days()
+        .take_while(|day| *day <= today)
+        .map(|day| {
+            let date = day.to_string();
+            let record = recorded.get(&date).copied().unwrap_or_default();
+            SignupDay {
+                date,
+                registrations: record.registrations,
+                deletions: record.deletions,
+            }
+        })
+        .collect();
+
+    Ok(Json(SignupsResponse { days }))
+}
+
+/// Paginated user listing
+///
+/// Walks `users` in key order from the cursor, so each page costs the same
+/// however large the database is. IDs are peppered; the cursor is the last
+/// raw ID of the page, sealed with the app secret (see [`seal_cursor`]) so
+/// it can't be used to confirm a guessed username either.
+///
+/// GET /admin/users?cursor=...&limit=100
+pub async fn admin_users(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    Query(params): Query<UsersQuery>,
+) -> Result<Json<UsersResponse>> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_USERS_PAGE)
+        .clamp(1, MAX_USERS_PAGE);
+    let config = state.config();
+    let secret = config.app_secret_key();
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(|c| {
+            open_cursor(c, secret)
+                .filter(|id| User::validate_id(id))
+                .ok_or_else(|| AppError::InvalidInput("Invalid cursor".to_string()))
+        })
+        .transpose()?;
+
+    let db = state.db.clone();
+    // Raw IDs until the page is complete, so the last one can be the cursor
+    let (mut users, more) = state
+        .spawn_db(move || -> Result<(Vec<AdminUser>, bool)> {
+            let read_txn = db.begin_read()?;
+            let users = read_txn.open_table(tables::USERS)?;
+            let user_backups = read_txn.open_table(tables::USER_BACKUPS)?;
+            let backups = read_txn.open_table(tables::BACKUPS)?;
+
+            let start = match &cursor {
+                Some(cursor) => Bound::Excluded(cursor.as_str()),
+                None => Bound::Unbounded,
+            };
+            let mut range = users.range::<&str>((start, Bound::Unbounded))?;
+            let mut page = Vec::with_capacity(limit);
+            for entry in range.by_ref().take(limit) {
+                let (user_id, bytes) = entry?;
+                let user_id = user_id.value().to_string();
+                let (record, _): (UserRecord, _) =
+                    bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+
+                let keys: Vec<String> = match user_backups.get(user_id.as_str())? {
+                    Some(bytes) => {
+                        bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?.0
+                    }
+                    None => Vec::new(),
+                };
+                let (mut count, mut total) = (0, 0);
+                for key in &keys {
+                    if let Some(bytes) = backups.get(key.as_str())? {
+                        let (backup, _): (BackupRecord, _) =
+                            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                        count += 1;
+                        total += backup.encrypted_data.len() as u64;
+                    }
+                }
+                page.push(AdminUser {
+                    id: user_id,
+                    created_at: crate::routes::timestamp_to_rfc3339(record.created_at),
+                    backup_count: count,
+                    total_bytes: total,
+                });
+            }
+            Ok((page, range.next().is_some()))
+        })
+        .await??;
+
+    let next_cursor = match users.last() {
+        Some(last) if more => Some(seal_cursor(&last.id, secret)?),
+        _ => None,
+    };
+    for user in &mut users {
+        user.id = pepper_user_id(&user.id, secret);
+    }
+
+    Ok(Json(UsersResponse { users, next_cursor }))
+}
+
+/// Admin stats export endpoint
+///
+/// Returns a downloadable report of daily activity rollups and per-user
+/// storage summaries, as JSON or CSV.
+///
+/// GET /admin/stats/export?format=csv|json&range=30d
+pub async fn admin_stats_export(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    Query(params): Query<AdminExportQuery>,
+) -> Result<Response> {
+    let range_days = parse_range_days(params.range.as_deref())?;
+    let now = Utc::now();
+    let since = range_days.map(|days| now.timestamp() - days * 86400);
+
+    let db = state.db.clone();
+    let (daily, users) = state
+        .spawn_db(move || -> Result<(Vec<DailyRollup>, Vec<UserSummary>)> {
+            let read_txn = db.begin_read()?;
+            let in_range = |ts: i64| since.is_none_or(|since| ts >= since);
+
+            let mut daily: BTreeMap<NaiveDate, DailyRollup> = BTreeMap::new();
+            // user_id -> (created_at, last_backup_at, summary)
+            let mut summaries: BTreeMap<String, (i64, Option<i64>, UserSummary)> = BTreeMap::new();
+
+            let users = read_txn.open_table(tables::USERS)?;
+            for entry in users.iter()? {
+                let (user_id, bytes) = entry?;
+                let (record, _): (UserRecord, _) =
+                    bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+
+                if in_range(record.created_at) {
+                    daily
+                        .entry(day_of(record.created_at))
+                        .or_default()
+                        .registrations += 1;
+                }
+
+                summaries.insert(
+                    user_id.value().to_string(),
+                    (
+                        record.created_at,
+                        None,
+                        UserSummary {
+                            user_id: user_id.value().to_string(),
+                            created_at: crate::routes::timestamp_to_rfc3339(record.created_at),
+                            backup_count: 0,
+                            total_bytes: 0,
+                            last_backup_at: None,
+                        },
+                    ),
+                );
+            }
+
+            let backups = read_txn.open_table(tables::BACKUPS)?;
+            for entry in backups.iter()? {
+                let (_, bytes) = entry?;
+                let (record, _): (BackupRecord, _) =
+                    bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+
+                if in_range(record.updated_at) {
+                    daily
+                        .entry(day_of(record.updated_at))
+                        .or_default()
+                        .backups_updated += 1;
+                }
+
+                if let Some((_, last, summary)) = summaries.get_mut(&record.user_id) {
+                    summary.backup_count += 1;
+                    summary.total_bytes += record.encrypted_data.len() as u64;
+                    *last = Some(last.unwrap_or(0).max(record.updated_at));
+                }
+            }
+
+            let daily = daily
+                .into_iter()
+                .map(|(date, rollup)| DailyRollup {
+                    date: date.to_string(),
+                    ..rollup
+                })
+                .collect();
+
+            // Only report users that were active (created or updated) within the range
+            let users = summaries
+                .into_values()
+                .filter(|(created, last, _)| in_range(*created) || last.is_some_and(in_range))
+                .map(|(_, last, mut summary)| {
+                    summary.last_backup_at = last.map(crate::routes::timestamp_to_rfc3339);
+                    summary
+                })
+                .collect();
+
+            Ok((daily, users))
+        })
+        .await??;
+
+    tracing::info!(
+        "Admin stats export requested: {} days, {} users",
+        daily.len(),
+        users.len()
+    );
+
+    let export = AdminStatsExport {
+        generated_at: now.to_rfc3339(),
+        range_days,
+        daily,
+        users,
+    };
+    let filename_date = now.format("%Y-%m-%d");
+
+    let response = match params.format {
+        ExportFormat::Json => (
+            [(
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"dailyreps-stats-{}.json\"",
+                    filename_date
+                ),
+            )],
+            Json(export),
+        )
+            .into_response(),
+        ExportFormat::Csv => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"dailyreps-stats-{}.csv\"",
+                        filename_date
+                    ),
+                ),
+            ],
+            export_to_csv(&export),
+        )
+            .into_response(),
+    };
+
+    Ok(response)
+}
+
+/// Render an export as CSV: a daily rollup block followed by a per-user block
+///
+/// All fields are hashes, numbers, or timestamps, so no quoting is needed.
+fn export_to_csv(export: &AdminStatsExport) -> String {
+    let mut csv = String::from("date,registrations,backups_updated\n");
+    for day in &export.daily {
+        csv.push_str(&format!(
+            "{},{},{}\n",
+            day.date, day.registrations, day.backups_updated
+        ));
+    }
+
+    csv.push_str("\nuser_id,created_at,backup_count,total_bytes,last_backup_at\n");
+    for user in &export.users {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            user.user_id,
+            user.created_at,
+            user.backup_count,
+            user.total_bytes,
+            user.last_backup_at.as_deref().unwrap_or("")
+        ));
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range_days() {
+        assert_eq!(parse_range_days(None).unwrap(), None);
+        assert_eq!(parse_range_days(Some("all")).unwrap(), None);
+        assert_eq!(parse_range_days(Some("30d")).unwrap(), Some(30));
+        assert!(parse_range_days(Some("30")).is_err());
+        assert!(parse_range_days(Some("0d")).is_err());
+        assert!(parse_range_days(Some("-5d")).is_err());
+    }
+
+    #[test]
+    fn test_export_to_csv() {
+        let export = AdminStatsExport {
+            generated_at: "2025-12-09T00:00:00+00:00".to_string(),
+            range_days: None,
+            daily: vec![DailyRollup {
+                date: "2025-12-09".to_string(),
+                registrations: 2,
+                backups_updated: 1,
+            }],
+            users: vec![UserSummary {
+                user_id: "a".repeat(64),
+                created_at: "2025-12-09T00:00:00+00:00".to_string(),
+                backup_count: 1,
+                total_bytes: 42,
+                last_backup_at: None,
+            }],
+        };
+
+        let csv = export_to_csv(&export);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "date,registrations,backups_updated");
+        assert_eq!(lines[1], "2025-12-09,2,1");
+        assert_eq!(lines[2], "");
+        assert_eq!(
+            lines[3],
+            "user_id,created_at,backup_count,total_bytes,last_backup_at"
+        );
+        assert!(lines[4].ends_with(",2025-12-09T00:00:00+00:00,1,42,"));
+    }
+}
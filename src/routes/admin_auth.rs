@@ -0,0 +1,69 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use subtle::ConstantTimeEq;
+
+use crate::{AppError, AppState};
+
+/// Proof that the caller is an authenticated admin
+///
+/// Accepts `Authorization: Bearer <token>` where the token is either the
+/// static `ADMIN_SECRET_KEY` or an OIDC token (when `OIDC_ISSUER` is
+/// configured). A header rather than a query parameter keeps the key out of
+/// access logs. Every admin handler takes this extractor, so no admin route
+/// can forget to authenticate.
+#[derive(Debug, Clone)]
+pub struct AdminAuth {
+    /// Who authenticated: the OIDC subject/email, or `static-key`
+    pub identity: String,
+}
+
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(parts).ok_or(AppError::Unauthorized)?;
+
+        // 1. Static shared key (disabled unless ADMIN_SECRET_KEY is set),
+        //    compared in constant time
+        if let Some(admin_key) = &state.config().admin_secret_key
+            && bool::from(token.as_bytes().ct_eq(admin_key.as_bytes()))
+        {
+            return Ok(AdminAuth {
+                identity: "static-key".to_string(),
+            });
+        }
+
+        // 2. OIDC token, validated against the provider's keys
+        let Some(oidc) = &state.oidc else {
+            tracing::warn!("Invalid admin key attempt");
+            return Err(AppError::Unauthorized);
+        };
+        match oidc.verify(token).await {
+            Ok(claims) => {
+                let identity = claims.email.unwrap_or(claims.sub);
+                tracing::info!(admin = %identity, "Admin access: {}", parts.uri.path());
+                Ok(AdminAuth { identity })
+            }
+            Err(e) => {
+                tracing::warn!("Rejected admin bearer token: {}", e);
+                Err(AppError::Unauthorized)
+            }
+        }
+    }
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` header
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
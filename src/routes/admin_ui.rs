@@ -0,0 +1,17 @@
+use axum::response::Html;
+
+/// Dashboard page, bundled into the binary at compile time
+const ADMIN_UI_HTML: &str = include_str!("../../static/admin.html");
+
+/// Admin web UI
+///
+/// Serves a static dashboard that renders the admin JSON endpoints. The page
+/// itself holds no data, so it is served without auth (a browser navigation
+/// can't send a bearer header); it reads the key from the URL fragment, which
+/// never reaches the server, or prompts for it, and sends it as
+/// `Authorization: Bearer` on every API call.
+///
+/// GET /admin/ui#key=<admin_secret_key>
+pub async fn admin_ui() -> Html<&'static str> {
+    Html(ADMIN_UI_HTML)
+}
@@ -0,0 +1,107 @@
+//! API versioning
+//!
+//! Client routes are served under `/api/v{n}/...`, one route table per
+//! [`ApiVersion`]. A breaking change (a new body format, new signature rules)
+//! ships as a new version whose table differs from the previous one, while
+//! apps built against an older version keep working. The unversioned `/api/...`
+//! paths are aliases of v1, for apps released before versioning.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// Response header naming the API version that served the request
+pub const API_VERSION_HEADER: HeaderName = HeaderName::from_static("api-version");
+
+/// A version of the client API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ApiVersion {
+    /// The original API, also served unversioned under `/api`
+    V1,
+    /// `POST /backup` takes the raw encrypted body with headers instead of JSON
+    V2,
+}
+
+impl ApiVersion {
+    /// Every served version, oldest first
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    /// Version served by the unversioned `/api/...` aliases
+    pub const LEGACY: ApiVersion = ApiVersion::V1;
+
+    pub fn number(self) -> u32 {
+        match self {
+            ApiVersion::V1 => 1,
+            ApiVersion::V2 => 2,
+        }
+    }
+
+    /// Path prefix the version is mounted under
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/api/v1",
+            ApiVersion::V2 => "/api/v2",
+        }
+    }
+
+    /// The version a request path belongs to, and the path as the legacy
+    /// `/api/...` alias would spell it
+    ///
+    /// `None` for paths outside `/api`.
+    pub fn of_path(path: &str) -> Option<(ApiVersion, String)> {
+        for version in ApiVersion::ALL {
+            if let Some(rest) = path.strip_prefix(version.prefix())
+                && (rest.is_empty() || rest.starts_with('/'))
+            {
+                return Some((version, format!("/api{}", rest)));
+            }
+        }
+
+        path.starts_with("/api/")
+            .then(|| (ApiVersion::LEGACY, path.to_string()))
+    }
+}
+
+/// Record the version serving a request
+///
+/// Handlers can take `Extension<ApiVersion>` to branch on it, and clients see
+/// it in the `api-version` response header.
+pub async fn tag_version(version: ApiVersion, mut request: Request, next: Next) -> Response {
+    request.extensions_mut().insert(version);
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(API_VERSION_HEADER, HeaderValue::from(version.number()));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_of_path() {
+        assert_eq!(
+            ApiVersion::of_path("/api/v1/register"),
+            Some((ApiVersion::V1, "/api/register".to_string()))
+        );
+        assert_eq!(
+            ApiVersion::of_path("/api/v2/backup"),
+            Some((ApiVersion::V2, "/api/backup".to_string()))
+        );
+        assert_eq!(
+            ApiVersion::of_path("/api/register"),
+            Some((ApiVersion::V1, "/api/register".to_string()))
+        );
+        // Only whole segments count as a version
+        assert_eq!(
+            ApiVersion::of_path("/api/v1x/register"),
+            Some((ApiVersion::V1, "/api/v1x/register".to_string()))
+        );
+        assert_eq!(ApiVersion::of_path("/health"), None);
+        assert_eq!(ApiVersion::of_path("/admin/stats"), None);
+    }
+}
@@ -0,0 +1,202 @@
+use axum::{
+    Json,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use redb::ReadableDatabase;
+use serde::{Deserialize, Serialize};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use crate::config::SigningScope;
+use crate::constants::{ERR_INVALID_STORAGE_KEY, ERR_INVALID_USER_ID};
+use crate::db::tables;
+use crate::error::{AppError, Result};
+use crate::models::{Backup, BackupRecord, User};
+use crate::routes::{record_signature_failure, timestamp_to_rfc3339, validate_signed_request};
+use crate::{AppState, ClientIp};
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "storageKey")]
+    pub storage_key: String,
+    pub signature: String,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+/// Manifest entry describing one backup in the archive
+#[derive(Debug, Serialize)]
+pub struct ArchiveEntry {
+    pub file: String,
+    #[serde(rename = "storageKey")]
+    pub storage_key: String,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: usize,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+/// Archive manifest (`manifest.json` at the root of the tar)
+#[derive(Debug, Serialize)]
+pub struct ArchiveManifest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "generatedAt")]
+    pub generated_at: String,
+    pub backups: Vec<ArchiveEntry>,
+}
+
+/// Export all of a user's backups as a tar archive
+///
+/// The archive contains `manifest.json` plus one `backups/<storageKey>.b64`
+/// file per backup, holding the encrypted data exactly as stored.
+///
+/// # Security
+/// - Requires HMAC signature verification (over the storage key)
+/// - Requires timestamp validation
+/// - Verifies storage key belongs to user (proves password knowledge)
+pub async fn export_archive(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    Json(payload): Json<ArchiveRequest>,
+) -> Result<Response> {
+    // 1. Validate formats
+    if !User::validate_id(&payload.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if !Backup::validate_storage_key(&payload.storage_key) {
+        return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
+    }
+
+    // 2. Verify HMAC signature and timestamp
+    validate_signed_request(
+        &state,
+        &payload.storage_key,
+        &payload.signature,
+        payload.timestamp,
+        payload.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Store),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &payload.user_id, ip))?;
+
+    let db = state.db.clone();
+    let user_id = payload.user_id.clone();
+    let storage_key = payload.storage_key.clone();
+
+    let archive = state
+        .spawn_db(move || -> Result<Vec<u8>> {
+            let read_txn = db.begin_read()?;
+
+            // 3. Verify the storage key belongs to this user
+            let backups = read_txn.open_table(tables::BACKUPS)?;
+            let owned = backups
+                .get(storage_key.as_str())?
+                .map(|b| {
+                    bincode::serde::decode_from_slice::<BackupRecord, _>(b.value(), BINCODE_CONFIG)
+                })
+                .transpose()?
+                .is_some_and(|(record, _)| record.user_id == user_id);
+            if !owned {
+                tracing::warn!("Archive attempt with mismatched storage key");
+                return Err(AppError::BackupNotFound);
+            }
+
+            // 4. Collect every backup in the user's index
+            let user_backups = read_txn.open_table(tables::USER_BACKUPS)?;
+            let keys: Vec<String> = user_backups
+                .get(user_id.as_str())?
+                .and_then(|b| {
+                    bincode::serde::decode_from_slice::<Vec<String>, _>(b.value(), BINCODE_CONFIG)
+                        .ok()
+                        .map(|(v, _)| v)
+                })
+                .unwrap_or_default();
+
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut manifest = ArchiveManifest {
+                user_id: user_id.clone(),
+                generated_at: Utc::now().to_rfc3339(),
+                backups: Vec::with_capacity(keys.len()),
+            };
+
+            for key in keys {
+                let Some(bytes) = backups.get(key.as_str())? else {
+                    continue;
+                };
+                let (record, _): (BackupRecord, _) =
+                    bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+
+                let file = format!("backups/{}.b64", key);
+                append_file(
+                    &mut builder,
+                    &file,
+                    record.encrypted_data.as_bytes(),
+                    record.updated_at,
+                )?;
+
+                manifest.backups.push(ArchiveEntry {
+                    file,
+                    storage_key: key,
+                    size_bytes: record.encrypted_data.len(),
+                    created_at: timestamp_to_rfc3339(record.created_at),
+                    updated_at: timestamp_to_rfc3339(record.updated_at),
+                });
+            }
+
+            let manifest_json = serde_json::to_vec_pretty(&manifest)
+                .map_err(|e| AppError::Archive(e.to_string()))?;
+            append_file(
+                &mut builder,
+                "manifest.json",
+                &manifest_json,
+                Utc::now().timestamp(),
+            )?;
+
+            builder
+                .into_inner()
+                .map_err(|e| AppError::Archive(e.to_string()))
+        })
+        .await??;
+
+    tracing::info!("Archive exported: {} bytes", archive.len());
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-tar"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"dailyreps-backups.tar\"",
+            ),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+/// Append a regular file to the tar
+fn append_file(
+    builder: &mut tar::Builder<Vec<u8>>,
+    path: &str,
+    data: &[u8],
+    mtime: i64,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime.max(0) as u64);
+    header.set_cksum();
+
+    builder
+        .append_data(&mut header, path, data)
+        .map_err(|e| AppError::Archive(e.to_string()))
+}
@@ -1,19 +1,34 @@
 use axum::{
     Json,
+    body::Bytes,
     extract::{Query, State},
+    http::HeaderMap,
 };
+use base64::{Engine, prelude::BASE64_STANDARD};
 use chrono::Utc;
 use redb::{ReadableDatabase, ReadableTable};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::time::Instant;
 
 const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
 
-use crate::AppState;
+use crate::config::SigningScope;
 use crate::constants::*;
-use crate::db::tables;
-use crate::error::{AppError, Result};
+use crate::db::{tables, versions};
+use crate::error::{AppError, ConflictBase, ConflictCurrent, Result, SyncConflict};
+use crate::events::ChangeKind;
+use crate::metrics;
 use crate::models::{Backup, BackupRecord, RateLimitRecord, User};
-use crate::routes::{timestamp_to_rfc3339, validate_signed_request};
+use crate::proto::{self, FromProto, IntoProto};
+use crate::routes::codec::{AcceptFormat, Encoded, Negotiated};
+use crate::routes::{
+    SigVersion, ensure_not_banned, record_duplicate_upload, record_rate_limited,
+    record_signature_failure, timestamp_to_rfc3339, validate_signed_request,
+};
+use crate::telemetry::Telemetry;
+use crate::{AppState, ClientIp};
 
 #[derive(Debug, Deserialize)]
 pub struct StoreBackupRequest {
@@ -24,6 +39,67 @@ pub struct StoreBackupRequest {
     pub data: String,
     pub signature: String,
     pub timestamp: i64,
+    /// Token from the client's last store or retrieve; a stale one is rejected
+    /// with 409 instead of overwriting another device's backup
+    #[serde(rename = "syncToken", default)]
+    pub sync_token: Option<String>,
+    /// Opt-in anonymized usage buckets, covered by the signature and only
+    /// ever aggregated (see [`crate::telemetry`])
+    #[serde(default)]
+    pub stats: BTreeMap<String, String>,
+    /// Optional single-use value; see [`validate_signed_request`]
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// 2 to sign every field rather than just `data` and `stats`
+    #[serde(rename = "sigVersion", default)]
+    pub sig_version: Option<u32>,
+}
+
+impl StoreBackupRequest {
+    /// Bytes the signature covers under the request's `sigVersion`
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn signed_payload(&self) -> Result<Vec<u8>> {
+        let stats = self.stats.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        match SigVersion::parse(self.sig_version)? {
+            SigVersion::V1 => Ok(dailyreps_signing::backup_payload(&self.data, stats)),
+            SigVersion::V2 => Ok(canonical_store_payload(
+                &self.user_id,
+                &self.storage_key,
+                &self.data,
+                self.timestamp,
+                self.sync_token.as_deref(),
+                stats,
+            )),
+        }
+    }
+}
+
+/// The `sigVersion: 2` payload of a backup store, shared by every route that
+/// stores one so none of them lets identity, timestamp or sync token be swapped
+pub(crate) fn canonical_store_payload<'a>(
+    user_id: &str,
+    storage_key: &str,
+    data: &str,
+    timestamp: i64,
+    sync_token: Option<&str>,
+    stats: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Vec<u8> {
+    let timestamp = timestamp.to_string();
+    let mut fields: Vec<(Cow<str>, &str)> = vec![
+        ("userId".into(), user_id),
+        ("storageKey".into(), storage_key),
+        ("data".into(), data),
+        ("timestamp".into(), &timestamp),
+    ];
+    if let Some(token) = sync_token {
+        fields.push(("syncToken".into(), token));
+    }
+    fields.extend(
+        stats
+            .into_iter()
+            .map(|(k, v)| (format!("stats.{}", k).into(), v)),
+    );
+    dailyreps_signing::canonical_payload(fields)
 }
 
 #[derive(Debug, Serialize)]
@@ -31,6 +107,88 @@ pub struct StoreBackupResponse {
     pub success: bool,
     #[serde(rename = "updatedAt")]
     pub updated_at: String,
+    #[serde(rename = "syncToken")]
+    pub sync_token: String,
+    /// Hex SHA-256 of the stored data, as returned by `GET /api/backup`
+    pub checksum: String,
+    /// Limits this user is close to, so the app can say so before uploads
+    /// start failing
+    pub warnings: Vec<StoreWarning>,
+}
+
+/// Which limit a [`StoreWarning`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCode {
+    /// Backup size vs. `MAX_BACKUP_SIZE_BYTES` (bytes)
+    StorageQuota,
+    /// Uploads this hour vs. `MAX_BACKUPS_PER_HOUR`
+    HourlyLimit,
+    /// Uploads today vs. `MAX_BACKUPS_PER_DAY`
+    DailyLimit,
+}
+
+impl WarningCode {
+    fn as_str(self) -> &'static str {
+        match self {
+            WarningCode::StorageQuota => "storage_quota",
+            WarningCode::HourlyLimit => "hourly_limit",
+            WarningCode::DailyLimit => "daily_limit",
+        }
+    }
+}
+
+/// A limit the user has used at least `LIMIT_WARNING_PERCENT` of
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoreWarning {
+    pub code: WarningCode,
+    pub used: u64,
+    pub limit: u64,
+    pub message: String,
+}
+
+impl StoreWarning {
+    /// A warning when `used` has reached the warning threshold of `limit`
+    fn check(code: WarningCode, used: u64, limit: u64) -> Option<Self> {
+        (used * 100 >= limit * LIMIT_WARNING_PERCENT).then(|| {
+            let what = match code {
+                WarningCode::StorageQuota => "of the maximum backup size",
+                WarningCode::HourlyLimit => "of this hour's uploads",
+                WarningCode::DailyLimit => "of today's uploads",
+            };
+            StoreWarning {
+                code,
+                used,
+                limit,
+                message: format!("Used {}% {}", used * 100 / limit, what),
+            }
+        })
+    }
+
+    /// Warnings for a store of `payload_size` bytes, given the user's rate
+    /// limit counters after it
+    pub(crate) fn collect(payload_size: usize, rate_record: &RateLimitRecord) -> Vec<Self> {
+        [
+            StoreWarning::check(
+                WarningCode::StorageQuota,
+                payload_size as u64,
+                MAX_BACKUP_SIZE_BYTES as u64,
+            ),
+            StoreWarning::check(
+                WarningCode::HourlyLimit,
+                rate_record.backups_this_hour.into(),
+                MAX_BACKUPS_PER_HOUR as u64,
+            ),
+            StoreWarning::check(
+                WarningCode::DailyLimit,
+                rate_record.backups_today.into(),
+                MAX_BACKUPS_PER_DAY as u64,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -39,6 +197,9 @@ pub struct RetrieveBackupParams {
     pub user_id: String,
     #[serde(rename = "storageKey")]
     pub storage_key: String,
+    /// An earlier version from `GET /api/backup/versions` instead of the
+    /// current backup
+    pub version: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -46,6 +207,65 @@ pub struct RetrieveBackupResponse {
     pub data: String,
     #[serde(rename = "updatedAt")]
     pub updated_at: String,
+    #[serde(rename = "syncToken")]
+    pub sync_token: String,
+    /// Hex SHA-256 of `data`, verified against the stored checksum before
+    /// it is sent, for end-to-end checks by the client
+    pub checksum: String,
+}
+
+impl FromProto for StoreBackupRequest {
+    type Proto = proto::StoreBackupRequest;
+
+    fn from_proto(p: Self::Proto) -> Self {
+        StoreBackupRequest {
+            user_id: p.user_id,
+            storage_key: p.storage_key,
+            data: p.data,
+            signature: p.signature,
+            timestamp: p.timestamp,
+            sync_token: Some(p.sync_token).filter(|t| !t.is_empty()),
+            stats: p.stats.into_iter().collect(),
+            nonce: Some(p.nonce).filter(|n| !n.is_empty()),
+            sig_version: Some(p.sig_version).filter(|v| *v != 0),
+        }
+    }
+}
+
+impl IntoProto for StoreBackupResponse {
+    type Proto = proto::StoreBackupResponse;
+
+    fn into_proto(self) -> Self::Proto {
+        proto::StoreBackupResponse {
+            success: self.success,
+            updated_at: self.updated_at,
+            sync_token: self.sync_token,
+            checksum: self.checksum,
+            warnings: self
+                .warnings
+                .into_iter()
+                .map(|w| proto::StoreWarning {
+                    code: w.code.as_str().to_string(),
+                    used: w.used,
+                    limit: w.limit,
+                    message: w.message,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl IntoProto for RetrieveBackupResponse {
+    type Proto = proto::RetrieveBackupResponse;
+
+    fn into_proto(self) -> Self::Proto {
+        proto::RetrieveBackupResponse {
+            data: self.data,
+            updated_at: self.updated_at,
+            sync_token: self.sync_token,
+            checksum: self.checksum,
+        }
+    }
 }
 
 /// Store or update encrypted backup
@@ -55,20 +275,174 @@ pub struct RetrieveBackupResponse {
 /// 2. Timestamp validation: Prevents replay attacks
 /// 3. Rate limiting: Max 5/hour, 20/day per user
 /// 4. Size limit: Maximum 5MB payload
+/// 5. Sync token: a stale `syncToken` gets 409 with the current backup
+///    (omitting it keeps last-write-wins for older clients)
+///
+/// Accepts JSON, MessagePack, CBOR, or protobuf bodies and answers in the format
+/// requested by `Accept` (JSON by default).
 pub async fn store_backup(
     State(state): State<AppState>,
-    Json(payload): Json<StoreBackupRequest>,
-) -> Result<Json<StoreBackupResponse>> {
-    // 1. Verify HMAC signature and timestamp
+    ip: ClientIp,
+    AcceptFormat(format): AcceptFormat,
+    Negotiated(payload): Negotiated<StoreBackupRequest>,
+) -> Result<Encoded<StoreBackupResponse>> {
+    let started = Instant::now();
+
+    // 1. Verify HMAC signature (over data and any stats, or every field for
+    //    sigVersion 2) and timestamp
+    let signed = payload.signed_payload()?;
     validate_signed_request(
-        &payload.data,
+        &state,
+        &signed,
         &payload.signature,
         payload.timestamp,
-        &state.config.app_secret_key,
-    )?;
+        payload.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Store),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &payload.user_id, ip))?;
+
+    let sync_token = parse_sync_token(payload.sync_token.as_deref())?;
+    Telemetry::validate(&payload.stats)?;
+
+    let stored = persist_backup(
+        &state,
+        payload.user_id,
+        payload.storage_key,
+        payload.data,
+        sync_token,
+        started,
+        ip,
+    )
+    .await?;
+
+    state.telemetry.record(&payload.stats);
+
+    Ok(Encoded::new(format, stored.into_response()))
+}
+
+/// Store a backup uploaded as a raw `application/octet-stream` body
+///
+/// Identity and signature travel in headers (`X-User-Id`, `X-Storage-Key`,
+/// `X-Signature`, `X-Timestamp`, optional `X-Sync-Token`, `X-Nonce` and
+/// `X-Sig-Version`) so the blob avoids base64-inside-JSON overhead on the
+/// wire. The signature is an HMAC over the raw body bytes, or with
+/// `X-Sig-Version: 2` over the same fields as `POST /api/backup`, with `data`
+/// the body's base64. The blob is stored base64-encoded, so `GET /api/backup`
+/// returns it in the same shape as a JSON upload.
+pub async fn store_backup_raw(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<StoreBackupResponse>> {
+    let started = Instant::now();
+
+    let header_str = |name: &str| -> Result<String> {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| AppError::InvalidInput(format!("Missing or invalid {} header", name)))
+    };
+
+    let user_id = header_str("x-user-id")?;
+    let storage_key = header_str("x-storage-key")?;
+    let signature = header_str("x-signature")?;
+    let timestamp: i64 = header_str("x-timestamp")?
+        .parse()
+        .map_err(|_| AppError::InvalidInput("Missing or invalid x-timestamp header".to_string()))?;
+
+    let nonce = headers.get("x-nonce").and_then(|v| v.to_str().ok());
+    let raw_sync_token = headers
+        .get("x-sync-token")
+        .map(|v| v.to_str().unwrap_or_default());
+    let sig_version = headers
+        .get("x-sig-version")
+        .map(|v| {
+            v.to_str().ok().and_then(|v| v.parse().ok()).ok_or_else(|| {
+                AppError::InvalidInput("Missing or invalid x-sig-version header".to_string())
+            })
+        })
+        .transpose()?;
+
+    let data = BASE64_STANDARD.encode(&body);
+
+    // 1. Verify HMAC signature (over the raw bytes, or every field for
+    //    sigVersion 2) and timestamp
+    let signed = match SigVersion::parse(sig_version)? {
+        SigVersion::V1 => body.to_vec(),
+        SigVersion::V2 => {
+            canonical_store_payload(&user_id, &storage_key, &data, timestamp, raw_sync_token, [])
+        }
+    };
+    validate_signed_request(
+        &state,
+        &signed,
+        &signature,
+        timestamp,
+        nonce,
+        &state.config().signing_secrets(SigningScope::Store),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &user_id, ip))?;
+
+    let sync_token = parse_sync_token(raw_sync_token)?;
+
+    let stored =
+        persist_backup(&state, user_id, storage_key, data, sync_token, started, ip).await?;
+
+    Ok(Json(stored.into_response()))
+}
 
+/// Result of a successful [`persist_backup`]
+pub(crate) struct Stored {
+    updated_at: i64,
+    version: u64,
+    checksum: String,
+    warnings: Vec<StoreWarning>,
+}
+
+impl Stored {
+    pub(crate) fn into_response(self) -> StoreBackupResponse {
+        StoreBackupResponse {
+            success: true,
+            updated_at: timestamp_to_rfc3339(self.updated_at),
+            sync_token: self.version.to_string(),
+            checksum: self.checksum,
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// Parse an optional client sync token (the decimal version counter)
+#[allow(clippy::result_large_err)]
+pub(crate) fn parse_sync_token(token: Option<&str>) -> Result<Option<u64>> {
+    token
+        .map(|t| {
+            t.parse()
+                .map_err(|_| AppError::InvalidInput(ERR_INVALID_SYNC_TOKEN.to_string()))
+        })
+        .transpose()
+}
+
+/// Validate and write a backup whose signature has already been verified
+///
+/// Enforces size limits, identifier formats, concurrent-request and rate
+/// limits, user existence, and the sync token (when given), then upserts the
+/// record, the user's backup index, and the slot's version in one
+/// transaction.
+pub(crate) async fn persist_backup(
+    state: &AppState,
+    user_id: String,
+    storage_key: String,
+    data: String,
+    sync_token: Option<u64>,
+    started: Instant,
+    ip: ClientIp,
+) -> Result<Stored> {
     // 2. Check payload size
-    let payload_size = payload.data.len();
+    let payload_size = data.len();
     if payload_size > MAX_BACKUP_SIZE_BYTES {
         tracing::warn!(
             "Payload too large: {} bytes (max: {})",
@@ -83,107 +457,217 @@ pub async fn store_backup(
     }
 
     // 3. Validate user ID and storage key formats
-    if !User::validate_id(&payload.user_id) {
+    if !User::validate_id(&user_id) {
         return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
     }
 
-    if !Backup::validate_storage_key(&payload.storage_key) {
+    if !Backup::validate_storage_key(&storage_key) {
         return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
     }
 
+    ensure_not_banned(state, Some(&user_id), ip).await?;
+
+    // Refuse a client stuck re-sending the same blob before it costs a write
+    let checksum = dailyreps_signing::checksum(data.as_bytes());
+    state
+        .duplicates
+        .check(&user_id, &checksum, Utc::now().timestamp())
+        .inspect_err(|_| record_duplicate_upload(state, &user_id, ip))?;
+
+    // Held until the write finishes so parallel uploads can't pile up on the writer
+    let _in_flight = state
+        .in_flight
+        .acquire(&user_id)
+        .inspect_err(|_| record_rate_limited(state, &user_id, ip))?;
+
     let db = state.db.clone();
-    let user_id = payload.user_id.clone();
-    let storage_key = payload.storage_key.clone();
-    let data = payload.data.clone();
-
-    let updated_at = tokio::task::spawn_blocking(move || -> Result<i64> {
-        let now = Utc::now().timestamp();
-
-        let write_txn = db.begin_write()?;
-        {
-            // 4. Verify user exists
-            let users = write_txn.open_table(tables::USERS)?;
-            if users.get(user_id.as_str())?.is_none() {
-                tracing::warn!("Backup attempt for non-existent user");
-                return Err(AppError::UserNotFound);
-            }
-            drop(users);
-
-            // 5. Check and update rate limits
-            let mut rate_limits = write_txn.open_table(tables::RATE_LIMITS)?;
-            let mut rate_record = match rate_limits.get(user_id.as_str())? {
-                Some(bytes) => {
-                    let (record, _): (RateLimitRecord, _) =
-                        bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
-                    record
+    let owner = user_id.clone();
+    let min_interval = state.config().min_backup_interval_secs;
+    let versions_kept = state.config().backup_versions_kept;
+
+    let stored = state
+        .spawn_db(move || -> Result<Stored> {
+            let now = Utc::now().timestamp();
+
+            let write_txn = db.begin_write()?;
+            let (version, warnings, checksum) = {
+                // 4. Verify user exists
+                let users = write_txn.open_table(tables::USERS)?;
+                if users.get(user_id.as_str())?.is_none() {
+                    tracing::warn!("Backup attempt for non-existent user");
+                    return Err(AppError::UserNotFound);
+                }
+                drop(users);
+
+                // 5. Reject stale sync tokens before spending a rate-limit slot
+                let mut sync_tokens = write_txn.open_table(tables::SYNC_TOKENS)?;
+                let current_version = sync_tokens
+                    .get(storage_key.as_str())?
+                    .map(|v| v.value())
+                    .unwrap_or(0);
+                let backups = write_txn.open_table(tables::BACKUPS)?;
+                let existing: Option<BackupRecord> = backups
+                    .get(storage_key.as_str())?
+                    .map(|b| {
+                        bincode::serde::decode_from_slice(b.value(), BINCODE_CONFIG)
+                            .map(|(r, _)| r)
+                            .map_err(AppError::from)
+                    })
+                    .transpose()?;
+                drop(backups);
+
+                if let (Some(token), Some(current)) = (sync_token, &existing)
+                    && token != current_version
+                {
+                    // The conflict body carries the stored data, so it is only
+                    // for the backup's owner
+                    if current.user_id != user_id {
+                        return Err(AppError::BackupNotFound);
+                    }
+                    tracing::info!(
+                        "Sync conflict: client token {} vs current {}",
+                        token,
+                        current_version
+                    );
+                    let base_stored_at = versions::get(
+                        &write_txn.open_table(tables::BACKUP_VERSIONS)?,
+                        &storage_key,
+                        token,
+                    )?
+                    .map(|base| base.stored_at);
+                    return Err(AppError::SyncConflict(Box::new(SyncConflict {
+                        current: ConflictCurrent {
+                            data: current.encrypted_data.clone(),
+                            updated_at: timestamp_to_rfc3339(current.updated_at),
+                            sync_token: current_version.to_string(),
+                            size_bytes: current.encrypted_data.len() as u64,
+                            checksum: current.checksum.clone(),
+                        },
+                        base: ConflictBase {
+                            sync_token: token.to_string(),
+                            updated_at: base_stored_at.map(timestamp_to_rfc3339),
+                        },
+                    })));
                 }
-                None => RateLimitRecord::new(now),
-            };
-
-            rate_record.check_and_increment(now)?;
 
-            let rate_bytes = bincode::serde::encode_to_vec(&rate_record, BINCODE_CONFIG)?;
-            rate_limits.insert(user_id.as_str(), rate_bytes.as_slice())?;
-            drop(rate_limits);
+                // 6. Check and update rate limits
+                let mut rate_limits = write_txn.open_table(tables::RATE_LIMITS)?;
+                let mut rate_record = match rate_limits.get(user_id.as_str())? {
+                    Some(bytes) => {
+                        let (record, _): (RateLimitRecord, _) =
+                            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                        record
+                    }
+                    None => RateLimitRecord::new(now),
+                };
+
+                rate_record.check_and_increment(now, min_interval)?;
+
+                let warnings = StoreWarning::collect(payload_size, &rate_record);
+
+                let rate_bytes = bincode::serde::encode_to_vec(&rate_record, BINCODE_CONFIG)?;
+                rate_limits.insert(user_id.as_str(), rate_bytes.as_slice())?;
+                drop(rate_limits);
+
+                // 7. Keep the backup being replaced, then upsert and bump its version
+                if let Some(previous) = &existing {
+                    versions::archive(
+                        &write_txn,
+                        &storage_key,
+                        current_version,
+                        previous,
+                        versions_kept,
+                    )?;
+                }
 
-            // 6. Upsert backup
-            let mut backups = write_txn.open_table(tables::BACKUPS)?;
-            let created_at = backups
-                .get(storage_key.as_str())?
-                .and_then(|b| {
-                    bincode::serde::decode_from_slice::<BackupRecord, _>(b.value(), BINCODE_CONFIG)
+                let mut backups = write_txn.open_table(tables::BACKUPS)?;
+                let created_at = existing.map(|r| r.created_at).unwrap_or(now);
+
+                let backup_record = BackupRecord {
+                    user_id: user_id.clone(),
+                    encrypted_data: data,
+                    created_at,
+                    updated_at: now,
+                    checksum: checksum.clone(),
+                };
+                let backup_bytes = bincode::serde::encode_to_vec(&backup_record, BINCODE_CONFIG)?;
+                backups.insert(storage_key.as_str(), backup_bytes.as_slice())?;
+                drop(backups);
+
+                let version = current_version + 1;
+                sync_tokens.insert(storage_key.as_str(), version)?;
+                drop(sync_tokens);
+
+                // 8. Update user_backups index
+                let mut user_backups = write_txn.open_table(tables::USER_BACKUPS)?;
+                let mut keys: Vec<String> = user_backups
+                    .get(user_id.as_str())?
+                    .and_then(|b| {
+                        bincode::serde::decode_from_slice::<Vec<String>, _>(
+                            b.value(),
+                            BINCODE_CONFIG,
+                        )
                         .ok()
-                        .map(|(r, _)| r)
-                })
-                .map(|r| r.created_at)
-                .unwrap_or(now);
+                        .map(|(v, _)| v)
+                    })
+                    .unwrap_or_default();
 
-            let backup_record = BackupRecord {
-                user_id: user_id.clone(),
-                encrypted_data: data,
-                created_at,
-                updated_at: now,
+                if !keys.contains(&storage_key) {
+                    keys.push(storage_key.clone());
+                    let keys_bytes = bincode::serde::encode_to_vec(&keys, BINCODE_CONFIG)?;
+                    user_backups.insert(user_id.as_str(), keys_bytes.as_slice())?;
+                }
+
+                (version, warnings, checksum)
             };
-            let backup_bytes = bincode::serde::encode_to_vec(&backup_record, BINCODE_CONFIG)?;
-            backups.insert(storage_key.as_str(), backup_bytes.as_slice())?;
-            drop(backups);
-
-            // 7. Update user_backups index
-            let mut user_backups = write_txn.open_table(tables::USER_BACKUPS)?;
-            let mut keys: Vec<String> = user_backups
-                .get(user_id.as_str())?
-                .and_then(|b| {
-                    bincode::serde::decode_from_slice::<Vec<String>, _>(b.value(), BINCODE_CONFIG)
-                        .ok()
-                        .map(|(v, _)| v)
-                })
-                .unwrap_or_default();
+            crate::db::before_commit()?;
+            write_txn.commit()?;
 
-            if !keys.contains(&storage_key) {
-                keys.push(storage_key.clone());
-                let keys_bytes = bincode::serde::encode_to_vec(&keys, BINCODE_CONFIG)?;
-                user_backups.insert(user_id.as_str(), keys_bytes.as_slice())?;
+            Ok(Stored {
+                updated_at: now,
+                version,
+                checksum,
+                warnings,
+            })
+        })
+        .await?
+        .inspect_err(|e| {
+            if matches!(e, AppError::RateLimitExceeded(_)) {
+                record_rate_limited(state, &owner, ip);
             }
-        }
-        write_txn.commit()?;
-
-        Ok(now)
-    })
-    .await??;
+        })?;
 
     tracing::info!("Backup stored: {} bytes", payload_size);
-
-    Ok(Json(StoreBackupResponse {
-        success: true,
-        updated_at: timestamp_to_rfc3339(updated_at),
-    }))
+    // Only stored uploads count towards the duplicate allowance
+    state
+        .duplicates
+        .record(&owner, &stored.checksum, stored.updated_at);
+    state.metrics.incr(metrics::BACKUPS_STORED);
+    state
+        .metrics
+        .timing(metrics::STORE_DURATION, started.elapsed());
+    state
+        .events
+        .publish(ChangeKind::Store, owner, Some(payload_size));
+
+    Ok(stored)
 }
 
 /// Retrieve encrypted backup
+///
+/// With `version`, returns that earlier version from the history instead
+/// (see `GET /api/backup/versions`); `syncToken` is still the current one, so
+/// storing the old data with it rolls the backup back.
+///
+/// Answers in the format requested by `Accept` (JSON by default).
 pub async fn retrieve_backup(
     State(state): State<AppState>,
+    AcceptFormat(format): AcceptFormat,
+    ip: ClientIp,
     Query(params): Query<RetrieveBackupParams>,
-) -> Result<Json<RetrieveBackupResponse>> {
+) -> Result<Encoded<RetrieveBackupResponse>> {
+    let started = Instant::now();
+
     if !User::validate_id(&params.user_id) {
         return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
     }
@@ -192,37 +676,83 @@ pub async fn retrieve_backup(
         return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
     }
 
+    ensure_not_banned(&state, Some(&params.user_id), ip).await?;
+
     let db = state.db.clone();
     let user_id = params.user_id.clone();
     let storage_key = params.storage_key.clone();
 
-    let result = tokio::task::spawn_blocking(move || -> Result<BackupRecord> {
-        let read_txn = db.begin_read()?;
-        let backups = read_txn.open_table(tables::BACKUPS)?;
+    let requested = params.version;
 
-        let record: BackupRecord = backups
-            .get(storage_key.as_str())?
-            .map(|b| {
-                bincode::serde::decode_from_slice(b.value(), BINCODE_CONFIG)
-                    .map(|(r, _)| r)
-                    .map_err(AppError::from)
-            })
-            .transpose()?
-            .ok_or_else(|| AppError::BackupNotFound)?;
+    let (result, version) = state
+        .spawn_db(move || -> Result<(BackupRecord, u64)> {
+            let read_txn = db.begin_read()?;
+            let backups = read_txn.open_table(tables::BACKUPS)?;
 
-        // Verify user_id matches
-        if record.user_id != user_id {
-            return Err(AppError::BackupNotFound);
-        }
+            let record: BackupRecord = backups
+                .get(storage_key.as_str())?
+                .map(|b| {
+                    bincode::serde::decode_from_slice(b.value(), BINCODE_CONFIG)
+                        .map(|(r, _)| r)
+                        .map_err(AppError::from)
+                })
+                .transpose()?
+                .ok_or_else(|| AppError::BackupNotFound)?;
 
-        Ok(record)
-    })
-    .await??;
+            // Verify user_id matches
+            if record.user_id != user_id {
+                return Err(AppError::BackupNotFound);
+            }
 
-    tracing::info!("Backup retrieved: {} bytes", result.encrypted_data.len());
+            let version = read_txn
+                .open_table(tables::SYNC_TOKENS)?
+                .get(storage_key.as_str())?
+                .map(|v| v.value())
+                .unwrap_or(0);
+
+            // An older version replaces the data; the sync token stays current
+            // so storing it again rolls back
+            if let Some(requested) = requested.filter(|&v| v != version) {
+                let versions = read_txn.open_table(tables::BACKUP_VERSIONS)?;
+                let old = versions::get(&versions, &storage_key, requested)?
+                    .ok_or(AppError::BackupNotFound)?;
+                // Keep the archived checksum so a corrupted version fails `verify`
+                return Ok((
+                    BackupRecord {
+                        user_id: record.user_id,
+                        encrypted_data: old.encrypted_data,
+                        created_at: record.created_at,
+                        updated_at: old.stored_at,
+                        checksum: old.checksum,
+                    },
+                    version,
+                ));
+            }
+
+            Ok((record, version))
+        })
+        .await??;
 
-    Ok(Json(RetrieveBackupResponse {
-        data: result.encrypted_data,
-        updated_at: timestamp_to_rfc3339(result.updated_at),
-    }))
+    // Catch on-disk corruption instead of handing the client undecryptable data
+    if !result.verify() {
+        tracing::error!("Backup failed its integrity check");
+        state.metrics.incr(metrics::BACKUPS_CORRUPT);
+        return Err(AppError::CorruptBackup);
+    }
+
+    tracing::info!("Backup retrieved: {} bytes", result.encrypted_data.len());
+    state.metrics.incr(metrics::BACKUPS_RETRIEVED);
+    state
+        .metrics
+        .timing(metrics::RETRIEVE_DURATION, started.elapsed());
+
+    Ok(Encoded::new(
+        format,
+        RetrieveBackupResponse {
+            data: result.encrypted_data,
+            updated_at: timestamp_to_rfc3339(result.updated_at),
+            sync_token: version.to_string(),
+            checksum: result.checksum,
+        },
+    ))
 }
@@ -0,0 +1,204 @@
+use axum::{Json, extract::State};
+use chrono::Utc;
+use redb::{ReadableDatabase, ReadableTable};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+use crate::db::bans::{self, BanTarget};
+use crate::error::{AppError, Result};
+use crate::metrics;
+use crate::models::BanRecord;
+use crate::routes::{AdminAuth, timestamp_to_rfc3339};
+use crate::security::pepper_user_id;
+use crate::{AppState, ClientIp, db::tables};
+
+/// Refuse the request with 403 if `user_id` or the client's network is banned
+pub async fn ensure_not_banned(
+    state: &AppState,
+    user_id: Option<&str>,
+    ip: ClientIp,
+) -> Result<()> {
+    let db = state.db.clone();
+    let user_id = user_id.map(str::to_string);
+    let banned = state
+        .spawn_db(move || -> Result<bool> {
+            let read_txn = db.begin_read()?;
+            bans::is_banned(&read_txn, user_id.as_deref(), ip.0)
+        })
+        .await??;
+
+    if banned {
+        tracing::info!("Request refused from banned caller");
+        state.metrics.incr(metrics::BANNED_REQUESTS);
+        return Err(AppError::Banned);
+    }
+
+    Ok(())
+}
+
+/// Who to ban or unban: a peppered user ID (as listed by `/admin/users`) or
+/// an IP address, whose whole network is covered
+#[derive(Debug, Deserialize)]
+pub struct BanRequest {
+    pub user: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BanResponse {
+    pub success: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnbanResponse {
+    /// False if there was no such ban
+    pub removed: bool,
+}
+
+/// One entry of the ban list
+#[derive(Debug, Serialize)]
+pub struct BanEntry {
+    /// Peppered user ID, for user bans
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// IPv4 address or IPv6 /64, for network bans
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    pub banned_at: String,
+    pub banned_by: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BansResponse {
+    pub bans: Vec<BanEntry>,
+}
+
+/// Turn a ban request into its target, finding the user behind a peppered ID
+async fn resolve_target(state: &AppState, payload: &BanRequest) -> Result<BanTarget> {
+    let peppered = match (&payload.user, payload.ip) {
+        (Some(user), None) => user.clone(),
+        (None, Some(ip)) => return Ok(BanTarget::ip(ip)),
+        _ => {
+            return Err(AppError::InvalidInput(
+                "Specify exactly one of user or ip".to_string(),
+            ));
+        }
+    };
+
+    let db = state.db.clone();
+    let secret = state.config().app_secret_key().to_string();
+    state
+        .spawn_db(move || -> Result<BanTarget> {
+            let read_txn = db.begin_read()?;
+            let users = read_txn.open_table(tables::USERS)?;
+            for entry in users.iter()? {
+                let (user_id, _) = entry?;
+                let user_id = user_id.value();
+                if pepper_user_id(user_id, &secret) == peppered {
+                    return Ok(BanTarget::User(user_id.to_string()));
+                }
+            }
+            Err(AppError::UserNotFound)
+        })
+        .await?
+}
+
+/// Ban a user or network
+///
+/// Banned callers get 403 on register, store and retrieve until the ban is
+/// lifted. Their data is left in place.
+///
+/// POST /admin/bans
+pub async fn admin_ban(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+    Json(payload): Json<BanRequest>,
+) -> Result<Json<BanResponse>> {
+    let target = resolve_target(&state, &payload).await?;
+
+    let db = state.db.clone();
+    let record = BanRecord {
+        banned_at: Utc::now().timestamp(),
+        banned_by: admin.identity.clone(),
+        reason: payload.reason,
+    };
+    state
+        .spawn_db(move || -> Result<()> {
+            let write_txn = db.begin_write()?;
+            bans::put(&write_txn, &target, &record)?;
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+            Ok(())
+        })
+        .await??;
+
+    tracing::warn!(admin = %admin.identity, "Ban added");
+    Ok(Json(BanResponse { success: true }))
+}
+
+/// Lift a ban
+///
+/// DELETE /admin/bans
+pub async fn admin_unban(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+    Json(payload): Json<BanRequest>,
+) -> Result<Json<UnbanResponse>> {
+    let target = resolve_target(&state, &payload).await?;
+
+    let db = state.db.clone();
+    let removed = state
+        .spawn_db(move || -> Result<bool> {
+            let write_txn = db.begin_write()?;
+            let removed = bans::remove(&write_txn, &target)?;
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+            Ok(removed)
+        })
+        .await??;
+
+    if removed {
+        tracing::warn!(admin = %admin.identity, "Ban lifted");
+    }
+    Ok(Json(UnbanResponse { removed }))
+}
+
+/// List every ban, with user IDs peppered
+///
+/// GET /admin/bans
+pub async fn admin_list_bans(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+) -> Result<Json<BansResponse>> {
+    let db = state.db.clone();
+    let list = state
+        .spawn_db(move || -> Result<Vec<(BanTarget, BanRecord)>> {
+            let read_txn = db.begin_read()?;
+            bans::list(&read_txn)
+        })
+        .await??;
+
+    let config = state.config();
+    let secret = config.app_secret_key();
+    let bans = list
+        .into_iter()
+        .map(|(target, record)| {
+            let (user, network) = match target {
+                BanTarget::User(user_id) => (Some(pepper_user_id(&user_id, secret)), None),
+                BanTarget::Network(network) => (None, Some(network)),
+            };
+            BanEntry {
+                user,
+                network,
+                banned_at: timestamp_to_rfc3339(record.banned_at),
+                banned_by: record.banned_by,
+                reason: record.reason,
+            }
+        })
+        .collect();
+
+    Ok(Json(BansResponse { bans }))
+}
@@ -0,0 +1,94 @@
+use axum::{Json, extract::State};
+use redb::ReadableDatabase;
+use serde::{Deserialize, Serialize};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use crate::AppState;
+use crate::constants::{ERR_INVALID_CHECKSUM, ERR_INVALID_STORAGE_KEY, ERR_INVALID_USER_ID};
+use crate::db::tables;
+use crate::error::{AppError, Result};
+use crate::metrics;
+use crate::models::{Backup, BackupRecord, User};
+use crate::routes::timestamp_to_rfc3339;
+
+#[derive(Debug, Deserialize)]
+pub struct CheckBackupRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "storageKey")]
+    pub storage_key: String,
+    /// Hex sha256 of the `data` the client is about to upload
+    pub checksum: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckBackupResponse {
+    /// True when the stored backup is byte-for-byte what the client holds
+    pub unchanged: bool,
+    #[serde(rename = "updatedAt", skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+/// Ask whether an upload would change anything
+///
+/// The client sends only the checksum of its prospective upload (see
+/// `dailyreps_signing::checksum`). When it matches the stored data the client
+/// can skip `POST /api/backup` and keep its rate-limit slot. Like
+/// `GET /api/backup`, knowing the storage key is the credential; nothing is
+/// written and no rate limit is consumed.
+pub async fn check_backup(
+    State(state): State<AppState>,
+    Json(payload): Json<CheckBackupRequest>,
+) -> Result<Json<CheckBackupResponse>> {
+    if !User::validate_id(&payload.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if !Backup::validate_storage_key(&payload.storage_key) {
+        return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
+    }
+
+    if !is_sha256_hex(&payload.checksum) {
+        return Err(AppError::InvalidInput(ERR_INVALID_CHECKSUM.to_string()));
+    }
+
+    let db = state.db.clone();
+    let storage_key = payload.storage_key;
+
+    let record = state
+        .spawn_db(move || -> Result<Option<BackupRecord>> {
+            let read_txn = db.begin_read()?;
+            let backups = read_txn.open_table(tables::BACKUPS)?;
+
+            backups
+                .get(storage_key.as_str())?
+                .map(|b| {
+                    bincode::serde::decode_from_slice(b.value(), BINCODE_CONFIG)
+                        .map(|(r, _)| r)
+                        .map_err(AppError::from)
+                })
+                .transpose()
+        })
+        .await??
+        // A storage key belonging to someone else is treated as "no backup"
+        .filter(|record| record.user_id == payload.user_id);
+
+    // A corrupted backup never counts as unchanged, so the next upload repairs it
+    let unchanged = record.as_ref().is_some_and(|record| {
+        record.checksum.eq_ignore_ascii_case(&payload.checksum) && record.verify()
+    });
+
+    if unchanged {
+        state.metrics.incr(metrics::UPLOADS_SKIPPED);
+    }
+
+    Ok(Json(CheckBackupResponse {
+        unchanged,
+        updated_at: record.map(|r| timestamp_to_rfc3339(r.updated_at)),
+    }))
+}
+
+fn is_sha256_hex(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
@@ -0,0 +1,360 @@
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, Query, State},
+};
+use chrono::Utc;
+use redb::{ReadableDatabase, ReadableTable};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::config::SigningScope;
+use crate::constants::*;
+use crate::db::{tables, uploads};
+use crate::error::{AppError, Result};
+use crate::models::{Backup, UploadSessionRecord, User};
+use crate::routes::backup::{
+    StoreBackupResponse, canonical_store_payload, parse_sync_token, persist_backup,
+};
+use crate::routes::{
+    SigVersion, ensure_not_banned, record_signature_failure, timestamp_to_rfc3339,
+    validate_signed_request,
+};
+use crate::{AppState, ClientIp};
+
+#[derive(Debug, Deserialize)]
+pub struct StartUploadRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "storageKey")]
+    pub storage_key: String,
+    /// HMAC of `storageKey` (store scope)
+    pub signature: String,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StartUploadResponse {
+    #[serde(rename = "uploadId")]
+    pub upload_id: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: String,
+    /// Largest body accepted per chunk
+    #[serde(rename = "maxChunkBytes")]
+    pub max_chunk_bytes: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadChunkParams {
+    /// Bytes the client believes the server has; a mismatch gets 409 with
+    /// the actual count instead of appending out of place
+    pub offset: Option<u64>,
+}
+
+/// Progress of an upload, returned after each chunk and by the status endpoint
+#[derive(Debug, Serialize)]
+pub struct UploadStatusResponse {
+    #[serde(rename = "receivedBytes")]
+    pub received_bytes: u64,
+    pub chunks: u32,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: String,
+}
+
+impl From<UploadSessionRecord> for UploadStatusResponse {
+    fn from(session: UploadSessionRecord) -> Self {
+        UploadStatusResponse {
+            received_bytes: session.received_bytes,
+            chunks: session.chunks,
+            expires_at: timestamp_to_rfc3339(session.expires_at),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommitUploadRequest {
+    /// HMAC of the assembled data, as for `POST /api/backup`
+    pub signature: String,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(rename = "syncToken", default)]
+    pub sync_token: Option<String>,
+    /// 2 to sign the session's `userId` and `storageKey`, the assembled
+    /// `data`, `timestamp` and `syncToken`, as `POST /api/backup` does
+    #[serde(rename = "sigVersion", default)]
+    pub sig_version: Option<u32>,
+}
+
+/// Start a chunked upload
+///
+/// For backups too large to send comfortably in one request. The returned
+/// `uploadId` names the session for `PUT /api/backup/chunks/{uploadId}` and
+/// `POST /api/backup/chunks/{uploadId}/commit`; sessions expire
+/// `UPLOAD_SESSION_TTL_SECS` after their last chunk and each user may have
+/// `MAX_UPLOAD_SESSIONS_PER_USER` open at once.
+///
+/// # Security
+/// - Requires HMAC signature (store scope) over `storageKey` and timestamp validation
+/// - The upload ID is 128 random bits; nothing is stored until a commit
+///   signed over the assembled data
+pub async fn start_upload(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    Json(payload): Json<StartUploadRequest>,
+) -> Result<Json<StartUploadResponse>> {
+    validate_signed_request(
+        &state,
+        &payload.storage_key,
+        &payload.signature,
+        payload.timestamp,
+        payload.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Store),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &payload.user_id, ip))?;
+
+    if !User::validate_id(&payload.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if !Backup::validate_storage_key(&payload.storage_key) {
+        return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
+    }
+
+    ensure_not_banned(&state, Some(&payload.user_id), ip).await?;
+
+    let upload_id = new_upload_id()?;
+    let db = state.db.clone();
+    let id = upload_id.clone();
+
+    let expires_at = state
+        .spawn_db(move || -> Result<i64> {
+            let now = Utc::now().timestamp();
+            let write_txn = db.begin_write()?;
+
+            let users = write_txn.open_table(tables::USERS)?;
+            if users.get(payload.user_id.as_str())?.is_none() {
+                return Err(AppError::UserNotFound);
+            }
+            drop(users);
+
+            let live = uploads::prune_and_list(&write_txn, &payload.user_id, now)?;
+            if live.len() >= MAX_UPLOAD_SESSIONS_PER_USER {
+                // A slot frees up when the oldest session expires
+                let next_expiry = live.into_iter().min().unwrap_or(now);
+                return Err(AppError::TooManyUploads((next_expiry - now).max(0) as u64));
+            }
+
+            let session = UploadSessionRecord {
+                user_id: payload.user_id,
+                storage_key: payload.storage_key,
+                expires_at: now + UPLOAD_SESSION_TTL_SECS,
+                chunks: 0,
+                received_bytes: 0,
+            };
+            uploads::put(&write_txn, &id, &session)?;
+
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+            Ok(session.expires_at)
+        })
+        .await??;
+
+    Ok(Json(StartUploadResponse {
+        upload_id,
+        expires_at: timestamp_to_rfc3339(expires_at),
+        max_chunk_bytes: MAX_UPLOAD_CHUNK_BYTES,
+    }))
+}
+
+/// How much of an upload the server has
+///
+/// A client whose connection dropped mid-chunk calls this and resumes with
+/// `PUT /api/backup/chunks/{uploadId}?offset=<receivedBytes>`. Each chunk is
+/// stored atomically, so `receivedBytes` never counts half a chunk.
+pub async fn upload_status(
+    State(state): State<AppState>,
+    Path(upload_id): Path<String>,
+) -> Result<Json<UploadStatusResponse>> {
+    if !UploadSessionRecord::validate_id(&upload_id) {
+        return Err(AppError::UploadNotFound);
+    }
+
+    let db = state.db.clone();
+
+    let session = state
+        .spawn_db(move || -> Result<UploadSessionRecord> {
+            let read_txn = db.begin_read()?;
+            let sessions = read_txn.open_table(tables::UPLOAD_SESSIONS)?;
+            uploads::get(&sessions, &upload_id, Utc::now().timestamp())?
+                .ok_or(AppError::UploadNotFound)
+        })
+        .await??;
+
+    Ok(Json(session.into()))
+}
+
+/// Append the request body to an upload
+///
+/// Chunks are stored in the order they arrive; the assembled backup may not
+/// exceed `MAX_BACKUP_SIZE_BYTES`. With `offset`, a chunk is only appended
+/// if it starts where the received data ends, so a retried chunk can't be
+/// stored twice. Every chunk extends the session by `UPLOAD_SESSION_TTL_SECS`.
+pub async fn upload_chunk(
+    State(state): State<AppState>,
+    Path(upload_id): Path<String>,
+    Query(params): Query<UploadChunkParams>,
+    body: Bytes,
+) -> Result<Json<UploadStatusResponse>> {
+    if !UploadSessionRecord::validate_id(&upload_id) {
+        return Err(AppError::UploadNotFound);
+    }
+
+    if body.is_empty() {
+        return Err(AppError::InvalidInput("Chunk is empty".to_string()));
+    }
+
+    let db = state.db.clone();
+
+    let session = state
+        .spawn_db(move || -> Result<UploadSessionRecord> {
+            let now = Utc::now().timestamp();
+            let write_txn = db.begin_write()?;
+
+            let sessions = write_txn.open_table(tables::UPLOAD_SESSIONS)?;
+            let mut session =
+                uploads::get(&sessions, &upload_id, now)?.ok_or(AppError::UploadNotFound)?;
+            drop(sessions);
+
+            if let Some(offset) = params.offset
+                && offset != session.received_bytes
+            {
+                return Err(AppError::UploadOffsetMismatch(session.received_bytes));
+            }
+
+            let received_bytes = session.received_bytes + body.len() as u64;
+            if received_bytes > MAX_BACKUP_SIZE_BYTES as u64 {
+                return Err(AppError::PayloadTooLarge);
+            }
+
+            uploads::put_chunk(&write_txn, &upload_id, session.chunks, &body)?;
+            session.chunks += 1;
+            session.received_bytes = received_bytes;
+            session.expires_at = now + UPLOAD_SESSION_TTL_SECS;
+            uploads::put(&write_txn, &upload_id, &session)?;
+
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+            Ok(session)
+        })
+        .await??;
+
+    Ok(Json(session.into()))
+}
+
+/// Store the assembled upload as the backup and close the session
+///
+/// The data goes through the same checks as `POST /api/backup` (signature,
+/// rate limits, sync token). A rejected commit leaves the session open so it
+/// can be retried until it expires.
+pub async fn commit_upload(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    Path(upload_id): Path<String>,
+    Json(payload): Json<CommitUploadRequest>,
+) -> Result<Json<StoreBackupResponse>> {
+    let started = Instant::now();
+
+    if !UploadSessionRecord::validate_id(&upload_id) {
+        return Err(AppError::UploadNotFound);
+    }
+
+    let db = state.db.clone();
+    let id = upload_id.clone();
+
+    let (session, data) = state
+        .spawn_db(move || -> Result<(UploadSessionRecord, Vec<u8>)> {
+            let read_txn = db.begin_read()?;
+            let sessions = read_txn.open_table(tables::UPLOAD_SESSIONS)?;
+            let session = uploads::get(&sessions, &id, Utc::now().timestamp())?
+                .ok_or(AppError::UploadNotFound)?;
+            let chunks = read_txn.open_table(tables::UPLOAD_CHUNKS)?;
+            Ok((session, uploads::assemble(&chunks, &id)?))
+        })
+        .await??;
+
+    let data = String::from_utf8(data)
+        .map_err(|_| AppError::InvalidInput("Upload is not valid UTF-8".to_string()))?;
+
+    let signed = match SigVersion::parse(payload.sig_version)? {
+        SigVersion::V1 => data.as_bytes().to_vec(),
+        SigVersion::V2 => canonical_store_payload(
+            &session.user_id,
+            &session.storage_key,
+            &data,
+            payload.timestamp,
+            payload.sync_token.as_deref(),
+            [],
+        ),
+    };
+    validate_signed_request(
+        &state,
+        &signed,
+        &payload.signature,
+        payload.timestamp,
+        payload.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Store),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &session.user_id, ip))?;
+
+    let sync_token = parse_sync_token(payload.sync_token.as_deref())?;
+
+    let stored = persist_backup(
+        &state,
+        session.user_id,
+        session.storage_key,
+        data,
+        sync_token,
+        started,
+        ip,
+    )
+    .await?;
+
+    let db = state.db.clone();
+    state
+        .spawn_db(move || -> Result<()> {
+            let write_txn = db.begin_write()?;
+            uploads::remove(&write_txn, &upload_id)?;
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+            Ok(())
+        })
+        .await??;
+
+    Ok(Json(stored.into_response()))
+}
+
+/// 128 random bits as 32 hex characters
+#[allow(clippy::result_large_err)]
+fn new_upload_id() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_ids_are_valid_and_unique() {
+        let (a, b) = (new_upload_id().unwrap(), new_upload_id().unwrap());
+        assert!(UploadSessionRecord::validate_id(&a));
+        assert_ne!(a, b);
+        assert!(!UploadSessionRecord::validate_id("not-an-id"));
+    }
+}
@@ -0,0 +1,263 @@
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{HeaderMap, HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use prost::Message;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::error::AppError;
+use crate::proto::{FromProto, IntoProto};
+
+/// Wire formats supported by the backup endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    MsgPack,
+    Cbor,
+    Protobuf,
+}
+
+impl WireFormat {
+    /// Map a media type (parameters ignored) to a wire format
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next()?.trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/json" => Some(WireFormat::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(WireFormat::MsgPack)
+            }
+            "application/cbor" => Some(WireFormat::Cbor),
+            "application/x-protobuf" | "application/protobuf" => Some(WireFormat::Protobuf),
+            _ => None,
+        }
+    }
+
+    /// Canonical content type for responses
+    pub fn content_type(self) -> &'static str {
+        match self {
+            WireFormat::Json => "application/json",
+            WireFormat::MsgPack => "application/msgpack",
+            WireFormat::Cbor => "application/cbor",
+            WireFormat::Protobuf => "application/x-protobuf",
+        }
+    }
+
+    /// Request body format from `Content-Type` (JSON when absent)
+    fn from_content_type(headers: &HeaderMap) -> Result<Self, AppError> {
+        match headers.get(header::CONTENT_TYPE) {
+            None => Ok(WireFormat::Json),
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(Self::from_media_type)
+                .ok_or(AppError::UnsupportedMediaType),
+        }
+    }
+
+    /// Preferred response format from `Accept` (first supported entry wins,
+    /// JSON when nothing matches)
+    fn from_accept(headers: &HeaderMap) -> Self {
+        headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|accept| accept.split(',').find_map(Self::from_media_type))
+            .unwrap_or_default()
+    }
+
+    /// Deserialize a body in this format
+    fn decode<T: DeserializeOwned + FromProto>(self, body: &[u8]) -> Result<T, String> {
+        match self {
+            WireFormat::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+            WireFormat::MsgPack => rmp_serde::from_slice(body).map_err(|e| e.to_string()),
+            WireFormat::Cbor => ciborium::from_reader(body).map_err(|e| e.to_string()),
+            WireFormat::Protobuf => T::Proto::decode(body)
+                .map(T::from_proto)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Serialize a value in this format
+    fn encode<T: Serialize + IntoProto>(self, value: T) -> Result<Vec<u8>, String> {
+        match self {
+            WireFormat::Json => serde_json::to_vec(&value).map_err(|e| e.to_string()),
+            // Named encoding keeps field names so payloads mirror the JSON shape
+            WireFormat::MsgPack => rmp_serde::to_vec_named(&value).map_err(|e| e.to_string()),
+            WireFormat::Cbor => {
+                let mut out = Vec::new();
+                ciborium::into_writer(&value, &mut out).map_err(|e| e.to_string())?;
+                Ok(out)
+            }
+            WireFormat::Protobuf => Ok(value.into_proto().encode_to_vec()),
+        }
+    }
+}
+
+/// Request body decoded according to its `Content-Type`
+///
+/// Drop-in replacement for `Json<T>` that also accepts MessagePack, CBOR,
+/// and protobuf.
+#[derive(Debug)]
+pub struct Negotiated<T>(pub T);
+
+impl<S, T> FromRequest<S> for Negotiated<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + FromProto,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = WireFormat::from_content_type(req.headers())?;
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| match e.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+                _ => AppError::InvalidInput(e.body_text()),
+            })?;
+
+        format
+            .decode(&body)
+            .map(Negotiated)
+            .map_err(|e| AppError::InvalidInput(format!("Invalid request body: {}", e)))
+    }
+}
+
+/// Response format requested by the client's `Accept` header
+#[derive(Debug, Clone, Copy)]
+pub struct AcceptFormat(pub WireFormat);
+
+impl<S: Send + Sync> FromRequestParts<S> for AcceptFormat {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(AcceptFormat(WireFormat::from_accept(&parts.headers)))
+    }
+}
+
+/// Response body encoded in the negotiated format
+#[derive(Debug)]
+pub struct Encoded<T> {
+    pub format: WireFormat,
+    pub value: T,
+}
+
+impl<T> Encoded<T> {
+    pub fn new(format: WireFormat, value: T) -> Self {
+        Self { format, value }
+    }
+}
+
+impl<T: Serialize + IntoProto> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        match self.format.encode(self.value) {
+            Ok(body) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(self.format.content_type()),
+                )],
+                body,
+            )
+                .into_response(),
+            Err(e) => AppError::Encoding(e).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Sample {
+        #[serde(rename = "userId")]
+        user_id: String,
+        timestamp: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct SampleProto {
+        #[prost(string, tag = "1")]
+        user_id: String,
+        #[prost(int64, tag = "2")]
+        timestamp: i64,
+    }
+
+    impl FromProto for Sample {
+        type Proto = SampleProto;
+
+        fn from_proto(proto: SampleProto) -> Self {
+            Sample {
+                user_id: proto.user_id,
+                timestamp: proto.timestamp,
+            }
+        }
+    }
+
+    impl IntoProto for Sample {
+        type Proto = SampleProto;
+
+        fn into_proto(self) -> SampleProto {
+            SampleProto {
+                user_id: self.user_id,
+                timestamp: self.timestamp,
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_media_type() {
+        assert_eq!(
+            WireFormat::from_media_type("application/json; charset=utf-8"),
+            Some(WireFormat::Json)
+        );
+        assert_eq!(
+            WireFormat::from_media_type("application/x-msgpack"),
+            Some(WireFormat::MsgPack)
+        );
+        assert_eq!(
+            WireFormat::from_media_type("Application/CBOR"),
+            Some(WireFormat::Cbor)
+        );
+        assert_eq!(
+            WireFormat::from_media_type("application/x-protobuf"),
+            Some(WireFormat::Protobuf)
+        );
+        assert_eq!(WireFormat::from_media_type("text/plain"), None);
+    }
+
+    #[test]
+    fn test_from_accept_prefers_first_supported() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("text/html, application/cbor, application/json"),
+        );
+        assert_eq!(WireFormat::from_accept(&headers), WireFormat::Cbor);
+
+        headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+        assert_eq!(WireFormat::from_accept(&headers), WireFormat::Json);
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        let sample = Sample {
+            user_id: "a".repeat(64),
+            timestamp: 1733788800,
+        };
+
+        for format in [
+            WireFormat::Json,
+            WireFormat::MsgPack,
+            WireFormat::Cbor,
+            WireFormat::Protobuf,
+        ] {
+            let bytes = format.encode(sample.clone()).unwrap();
+            let decoded: Sample = format.decode(&bytes).unwrap();
+            assert_eq!(decoded, sample);
+        }
+    }
+}
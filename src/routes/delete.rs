@@ -1,15 +1,21 @@
 use axum::{Json, extract::State};
+use chrono::Utc;
 use redb::ReadableTable;
 use serde::{Deserialize, Serialize};
 
 const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
 
-use crate::AppState;
-use crate::constants::{ERR_INVALID_STORAGE_KEY, ERR_INVALID_USER_ID};
-use crate::db::tables;
+use crate::config::SigningScope;
+use crate::constants::{ERR_INVALID_STORAGE_KEY, ERR_INVALID_USER_ID, MAX_BULK_DELETE_KEYS};
+use crate::db::{accounts, tables, tombstones, versions};
 use crate::error::{AppError, Result};
+use crate::events::ChangeKind;
+use crate::metrics;
 use crate::models::{Backup, BackupRecord, User};
-use crate::routes::validate_signed_request;
+use crate::routes::{
+    record_rate_limited, record_signature_failure, timestamp_to_rfc3339, validate_signed_request,
+};
+use crate::{AppState, ClientIp};
 
 #[derive(Debug, Deserialize)]
 pub struct DeleteUserRequest {
@@ -19,21 +25,31 @@ pub struct DeleteUserRequest {
     pub storage_key: String,
     pub signature: String,
     pub timestamp: i64,
+    #[serde(default)]
+    pub nonce: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct DeleteUserResponse {
     pub success: bool,
     pub message: String,
+    /// Until when `POST /api/user/restore` can undo the deletion; absent when
+    /// the data was deleted immediately (`DELETION_GRACE_DAYS=0`)
+    #[serde(rename = "restorableUntil", skip_serializing_if = "Option::is_none")]
+    pub restorable_until: Option<String>,
 }
 
 /// Delete user and all associated data
 ///
-/// This endpoint permanently deletes:
+/// With `DELETION_GRACE_DAYS` set (default 7) everything below is first kept
+/// in a tombstone that `POST /api/user/restore` can put back until it is
+/// purged. This endpoint removes:
 /// - User record
-/// - All backup data
+/// - All backup data and version history
 /// - Rate limit records
+/// - Sync tokens
 /// - User backups index
+/// - Recovery contact and any pending rekey grant
 ///
 /// # Security
 /// - Requires HMAC signature verification
@@ -41,6 +57,7 @@ pub struct DeleteUserResponse {
 /// - Verifies storage key belongs to user (proves password knowledge)
 pub async fn delete_user(
     State(state): State<AppState>,
+    ip: ClientIp,
     Json(payload): Json<DeleteUserRequest>,
 ) -> Result<Json<DeleteUserResponse>> {
     // 1. Validate formats
@@ -54,85 +71,313 @@ pub async fn delete_user(
 
     // 2. Verify HMAC signature and timestamp
     validate_signed_request(
+        &state,
         &payload.storage_key,
         &payload.signature,
         payload.timestamp,
-        &state.config.app_secret_key,
-    )?;
+        payload.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Delete),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &payload.user_id, ip))?;
+
+    let _in_flight = state
+        .in_flight
+        .acquire(&payload.user_id)
+        .inspect_err(|_| record_rate_limited(&state, &payload.user_id, ip))?;
 
     let db = state.db.clone();
     let user_id = payload.user_id.clone();
     let storage_key = payload.storage_key.clone();
+    let grace_secs = state.config().deletion_grace_days as i64 * 86_400;
 
-    tokio::task::spawn_blocking(move || -> Result<()> {
-        let write_txn = db.begin_write()?;
-        {
-            // 3. Verify user exists
-            let mut users = write_txn.open_table(tables::USERS)?;
-            if users.get(user_id.as_str())?.is_none() {
-                tracing::warn!("Delete attempt for non-existent user");
-                return Err(AppError::UserNotFound);
-            }
+    let restorable_until = state
+        .spawn_db(move || -> Result<Option<i64>> {
+            let now = Utc::now().timestamp();
+            let restorable_until = (grace_secs > 0).then_some(now + grace_secs);
+
+            let write_txn = db.begin_write()?;
+            {
+                // 3. Verify user exists
+                let users = write_txn.open_table(tables::USERS)?;
+                if users.get(user_id.as_str())?.is_none() {
+                    tracing::warn!("Delete attempt for non-existent user");
+                    return Err(AppError::UserNotFound);
+                }
+                drop(users);
 
-            // 4. Verify the storage key belongs to this user
-            let backups_table = write_txn.open_table(tables::BACKUPS)?;
-            if let Some(backup_bytes) = backups_table.get(storage_key.as_str())? {
-                let (backup, _): (BackupRecord, _) =
-                    bincode::serde::decode_from_slice(backup_bytes.value(), BINCODE_CONFIG)?;
-                if backup.user_id != user_id {
-                    tracing::warn!("Delete attempt with mismatched storage key");
+                // 4. Verify the storage key belongs to this user
+                let backups_table = write_txn.open_table(tables::BACKUPS)?;
+                if let Some(backup_bytes) = backups_table.get(storage_key.as_str())? {
+                    let (backup, _): (BackupRecord, _) =
+                        bincode::serde::decode_from_slice(backup_bytes.value(), BINCODE_CONFIG)?;
+                    if backup.user_id != user_id {
+                        tracing::warn!("Delete attempt with mismatched storage key");
+                        return Err(AppError::InvalidInput(
+                            "Invalid credentials - storage key does not match user".to_string(),
+                        ));
+                    }
+                } else {
+                    tracing::warn!("Delete attempt with invalid storage key");
                     return Err(AppError::InvalidInput(
                         "Invalid credentials - storage key does not match user".to_string(),
                     ));
                 }
-            } else {
-                tracing::warn!("Delete attempt with invalid storage key");
-                return Err(AppError::InvalidInput(
-                    "Invalid credentials - storage key does not match user".to_string(),
-                ));
-            }
-            drop(backups_table);
-
-            // 5. Get all backup keys for this user
-            let mut user_backups = write_txn.open_table(tables::USER_BACKUPS)?;
-            let backup_keys: Vec<String> = user_backups
-                .get(user_id.as_str())?
-                .and_then(|b| {
-                    bincode::serde::decode_from_slice::<Vec<String>, _>(b.value(), BINCODE_CONFIG)
-                        .ok()
-                        .map(|(v, _)| v)
-                })
-                .unwrap_or_default();
+                drop(backups_table);
 
-            // 6. Delete all backups
-            let mut backups = write_txn.open_table(tables::BACKUPS)?;
-            for key in &backup_keys {
-                backups.remove(key.as_str())?;
+                // 5. Delete backups, versions, sync tokens, rate limits,
+                //    recovery contact/grant and the user, keeping a tombstone
+                //    during the grace period
+                accounts::remove(&write_txn, &user_id, now, restorable_until)?;
             }
-            drop(backups);
+            crate::db::before_commit()?;
+            write_txn.commit()?;
 
-            // 7. Delete rate limits
-            let mut rate_limits = write_txn.open_table(tables::RATE_LIMITS)?;
-            rate_limits.remove(user_id.as_str())?;
-            drop(rate_limits);
+            tracing::info!("User and all associated data deleted");
 
-            // 8. Delete user_backups index
-            user_backups.remove(user_id.as_str())?;
-            drop(user_backups);
+            Ok(restorable_until)
+        })
+        .await??;
 
-            // 9. Delete user
-            users.remove(user_id.as_str())?;
-        }
-        write_txn.commit()?;
+    state.metrics.incr(metrics::USERS_DELETED);
+    state
+        .events
+        .publish(ChangeKind::Delete, payload.user_id, None);
 
-        tracing::info!("User and all associated data deleted");
+    let message = match restorable_until {
+        Some(_) => "User and all associated data deleted - restorable until restorableUntil",
+        None => "User and all associated data permanently deleted",
+    };
 
-        Ok(())
-    })
-    .await??;
+    Ok(Json(DeleteUserResponse {
+        success: true,
+        message: message.to_string(),
+        restorable_until: restorable_until.map(timestamp_to_rfc3339),
+    }))
+}
+
+/// Undo a `DELETE /api/user` within the grace period
+///
+/// Takes the same body as the deletion and puts back the user, their backups
+/// with version history and sync tokens, rate limits and recovery contact.
+///
+/// # Security
+/// - Requires HMAC signature verification (delete scope) over `storageKey`
+/// - Requires timestamp validation
+/// - The storage key must be one of the deleted account's (proves password knowledge)
+pub async fn restore_user(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    Json(payload): Json<DeleteUserRequest>,
+) -> Result<Json<DeleteUserResponse>> {
+    if !User::validate_id(&payload.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if !Backup::validate_storage_key(&payload.storage_key) {
+        return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
+    }
+
+    validate_signed_request(
+        &state,
+        &payload.storage_key,
+        &payload.signature,
+        payload.timestamp,
+        payload.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Delete),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &payload.user_id, ip))?;
+
+    let db = state.db.clone();
+    let user_id = payload.user_id.clone();
+    let storage_key = payload.storage_key;
+
+    state
+        .spawn_db(move || -> Result<()> {
+            let now = Utc::now().timestamp();
+            let write_txn = db.begin_write()?;
+            tombstones::restore(&write_txn, &user_id, &storage_key, now)?;
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+
+            tracing::info!("Deleted user restored");
+
+            Ok(())
+        })
+        .await??;
+
+    state.metrics.incr(metrics::USERS_RESTORED);
+    state
+        .events
+        .publish(ChangeKind::Restore, payload.user_id, None);
 
     Ok(Json(DeleteUserResponse {
         success: true,
-        message: "User and all associated data permanently deleted".to_string(),
+        message: "User and all associated data restored".to_string(),
+        restorable_until: None,
     }))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteBackupsRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "storageKeys")]
+    pub storage_keys: Vec<String>,
+    /// HMAC over [`dailyreps_signing::delete_backups_payload`]
+    pub signature: String,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeletedBackup {
+    #[serde(rename = "storageKey")]
+    pub storage_key: String,
+    /// False when no backup of this user's was stored under the key
+    pub deleted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteBackupsResponse {
+    pub results: Vec<DeletedBackup>,
+}
+
+/// Delete some of a user's backups, keeping the account
+///
+/// For clearing old device slots. All keys are removed in one transaction,
+/// along with their sync tokens, version history and index entries. A key that is unknown or
+/// belongs to another user is reported as not deleted, so the response
+/// doesn't reveal which.
+///
+/// # Security
+/// - Requires HMAC signature (Delete scope) over the keys and timestamp validation
+/// - Each storage key is its own credential, as for `GET /api/backup`
+pub async fn delete_backups(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    Json(payload): Json<DeleteBackupsRequest>,
+) -> Result<Json<DeleteBackupsResponse>> {
+    // 1. Validate formats
+    if !User::validate_id(&payload.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if payload.storage_keys.is_empty() || payload.storage_keys.len() > MAX_BULK_DELETE_KEYS {
+        return Err(AppError::InvalidInput(format!(
+            "storageKeys must list 1 to {} keys",
+            MAX_BULK_DELETE_KEYS
+        )));
+    }
+
+    if !payload
+        .storage_keys
+        .iter()
+        .all(|k| Backup::validate_storage_key(k))
+    {
+        return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
+    }
+
+    // 2. Verify HMAC signature and timestamp
+    validate_signed_request(
+        &state,
+        dailyreps_signing::delete_backups_payload(payload.storage_keys.iter().map(String::as_str)),
+        &payload.signature,
+        payload.timestamp,
+        payload.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Delete),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &payload.user_id, ip))?;
+
+    let _in_flight = state
+        .in_flight
+        .acquire(&payload.user_id)
+        .inspect_err(|_| record_rate_limited(&state, &payload.user_id, ip))?;
+
+    let db = state.db.clone();
+    let user_id = payload.user_id.clone();
+    let storage_keys = payload.storage_keys;
+
+    let results = state
+        .spawn_db(move || -> Result<Vec<DeletedBackup>> {
+            let write_txn = db.begin_write()?;
+            let results = {
+                // 3. Verify user exists
+                let users = write_txn.open_table(tables::USERS)?;
+                if users.get(user_id.as_str())?.is_none() {
+                    tracing::warn!("Bulk delete attempt for non-existent user");
+                    return Err(AppError::UserNotFound);
+                }
+                drop(users);
+
+                // 4. Delete each key the user owns
+                let mut backups = write_txn.open_table(tables::BACKUPS)?;
+                let mut sync_tokens = write_txn.open_table(tables::SYNC_TOKENS)?;
+                let mut results = Vec::with_capacity(storage_keys.len());
+                for storage_key in storage_keys {
+                    let owned = match backups.get(storage_key.as_str())? {
+                        Some(bytes) => {
+                            let (backup, _): (BackupRecord, _) =
+                                bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                            backup.user_id == user_id
+                        }
+                        None => false,
+                    };
+                    if owned {
+                        backups.remove(storage_key.as_str())?;
+                        sync_tokens.remove(storage_key.as_str())?;
+                        versions::remove_all(&write_txn, &storage_key)?;
+                    }
+                    results.push(DeletedBackup {
+                        storage_key,
+                        deleted: owned,
+                    });
+                }
+                drop(backups);
+                drop(sync_tokens);
+
+                // 5. Drop the deleted keys from the user_backups index
+                let mut user_backups = write_txn.open_table(tables::USER_BACKUPS)?;
+                let keys: Vec<String> = user_backups
+                    .get(user_id.as_str())?
+                    .and_then(|b| {
+                        bincode::serde::decode_from_slice::<Vec<String>, _>(
+                            b.value(),
+                            BINCODE_CONFIG,
+                        )
+                        .ok()
+                        .map(|(v, _)| v)
+                    })
+                    .unwrap_or_default();
+                let remaining: Vec<&String> = keys
+                    .iter()
+                    .filter(|k| !results.iter().any(|r| r.deleted && &r.storage_key == *k))
+                    .collect();
+                if remaining.len() != keys.len() {
+                    let keys_bytes = bincode::serde::encode_to_vec(&remaining, BINCODE_CONFIG)?;
+                    user_backups.insert(user_id.as_str(), keys_bytes.as_slice())?;
+                }
+
+                results
+            };
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+
+            Ok(results)
+        })
+        .await??;
+
+    let deleted = results.iter().filter(|r| r.deleted).count();
+    tracing::info!("Bulk delete removed {} backups", deleted);
+    if deleted > 0 {
+        state.metrics.add(metrics::BACKUPS_DELETED, deleted as u64);
+        state
+            .events
+            .publish(ChangeKind::Prune, payload.user_id, None);
+    }
+
+    Ok(Json(DeleteBackupsResponse { results }))
+}
@@ -0,0 +1,186 @@
+use axum::{Json, extract::State};
+use chrono::Utc;
+use redb::ReadableDatabase;
+use serde::Serialize;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use crate::AppState;
+use crate::config::SigningScope;
+use crate::constants::*;
+use crate::db::tables;
+use crate::error::{AppError, Result};
+use crate::models::{Backup, RateLimitRecord, User};
+use crate::routes::backup::{StoreBackupRequest, StoreWarning, parse_sync_token};
+use crate::routes::codec::Negotiated;
+use crate::security::{validate_timestamp, verify_hmac};
+use crate::telemetry::Telemetry;
+
+/// Outcome of one store check
+#[derive(Debug, Serialize)]
+pub struct DryRunCheck {
+    pub check: &'static str,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DryRunCheck {
+    fn new(check: &'static str, outcome: Result<()>) -> Self {
+        DryRunCheck {
+            check,
+            passed: outcome.is_ok(),
+            error: outcome.err().map(|e| e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DryRunResponse {
+    /// True when `POST /api/backup` with this body would be accepted
+    pub valid: bool,
+    pub checks: Vec<DryRunCheck>,
+    /// Warnings the store would return
+    pub warnings: Vec<StoreWarning>,
+}
+
+/// Run the store checks on a `POST /api/backup` body without storing it
+///
+/// Reports each check separately (signature, timestamp, size, identifiers,
+/// sync token, stats, user, rate limit) so client developers can debug
+/// signing against production. Nothing is written, no rate limit is
+/// consumed, and failures are not logged as abuse: a wrong signature here
+/// gains nothing, as the request is never stored.
+pub async fn validate_backup(
+    State(state): State<AppState>,
+    Negotiated(payload): Negotiated<StoreBackupRequest>,
+) -> Result<Json<DryRunResponse>> {
+    let mut checks = Vec::new();
+
+    // The nonce is checked against the signature but not spent
+    let config = state.config();
+    let secrets = config.signing_secrets(SigningScope::Store);
+    checks.push(DryRunCheck::new(
+        "signature",
+        payload.signed_payload().and_then(|signed| {
+            let signed = match &payload.nonce {
+                Some(nonce) => dailyreps_signing::nonce_payload(&signed, nonce),
+                None => signed,
+            };
+            verify_hmac(&signed, &payload.signature, &secrets)
+                .then_some(())
+                .ok_or(AppError::InvalidSignature)
+        }),
+    ));
+    checks.push(DryRunCheck::new(
+        "timestamp",
+        validate_timestamp(payload.timestamp, MAX_TIMESTAMP_AGE_SECS)
+            .then_some(())
+            .ok_or_else(|| AppError::InvalidInput(ERR_INVALID_TIMESTAMP.to_string())),
+    ));
+    checks.push(DryRunCheck::new(
+        "size",
+        (payload.data.len() <= MAX_BACKUP_SIZE_BYTES)
+            .then_some(())
+            .ok_or(AppError::PayloadTooLarge),
+    ));
+
+    let user_id_ok = User::validate_id(&payload.user_id);
+    checks.push(DryRunCheck::new(
+        "userId",
+        user_id_ok
+            .then_some(())
+            .ok_or_else(|| AppError::InvalidInput(ERR_INVALID_USER_ID.to_string())),
+    ));
+    let storage_key_ok = Backup::validate_storage_key(&payload.storage_key);
+    checks.push(DryRunCheck::new(
+        "storageKey",
+        storage_key_ok
+            .then_some(())
+            .ok_or_else(|| AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string())),
+    ));
+
+    let (sync_token, sync_token_format) = match parse_sync_token(payload.sync_token.as_deref()) {
+        Ok(token) => (token, Ok(())),
+        Err(e) => (None, Err(e)),
+    };
+    checks.push(DryRunCheck::new("syncTokenFormat", sync_token_format));
+    checks.push(DryRunCheck::new(
+        "stats",
+        Telemetry::validate(&payload.stats),
+    ));
+
+    let mut warnings = Vec::new();
+
+    // Checks against stored state need well-formed identifiers to look up
+    if user_id_ok && storage_key_ok {
+        let db = state.db.clone();
+        let user_id = payload.user_id;
+        let storage_key = payload.storage_key;
+        let payload_size = payload.data.len();
+        let min_interval = state.config().min_backup_interval_secs;
+
+        let (stored_checks, stored_warnings) = state
+            .spawn_db(move || -> Result<(Vec<DryRunCheck>, Vec<StoreWarning>)> {
+                let now = Utc::now().timestamp();
+                let read_txn = db.begin_read()?;
+                let mut checks = Vec::new();
+
+                let users = read_txn.open_table(tables::USERS)?;
+                let exists = users.get(user_id.as_str())?.is_some();
+                checks.push(DryRunCheck::new(
+                    "user",
+                    exists.then_some(()).ok_or(AppError::UserNotFound),
+                ));
+
+                let backups = read_txn.open_table(tables::BACKUPS)?;
+                let sync_tokens = read_txn.open_table(tables::SYNC_TOKENS)?;
+                let current_version = sync_tokens
+                    .get(storage_key.as_str())?
+                    .map(|v| v.value())
+                    .unwrap_or(0);
+                let has_backup = backups.get(storage_key.as_str())?.is_some();
+                let stale =
+                    matches!(sync_token, Some(token) if has_backup && token != current_version);
+                checks.push(DryRunCheck::new(
+                    "syncToken",
+                    (!stale).then_some(()).ok_or_else(|| {
+                        AppError::InvalidInput(format!(
+                            "Sync token is stale (current is {})",
+                            current_version
+                        ))
+                    }),
+                ));
+
+                // Applied to a copy, so the stored counters are untouched
+                let rate_limits = read_txn.open_table(tables::RATE_LIMITS)?;
+                let mut rate_record = match rate_limits.get(user_id.as_str())? {
+                    Some(bytes) => {
+                        let (record, _): (RateLimitRecord, _) =
+                            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                        record
+                    }
+                    None => RateLimitRecord::new(now),
+                };
+                let rate_limit = rate_record.check_and_increment(now, min_interval);
+                let warnings = if rate_limit.is_ok() {
+                    StoreWarning::collect(payload_size, &rate_record)
+                } else {
+                    Vec::new()
+                };
+                checks.push(DryRunCheck::new("rateLimit", rate_limit));
+
+                Ok((checks, warnings))
+            })
+            .await??;
+
+        checks.extend(stored_checks);
+        warnings = stored_warnings;
+    }
+
+    Ok(Json(DryRunResponse {
+        valid: checks.iter().all(|c| c.passed),
+        checks,
+        warnings,
+    }))
+}
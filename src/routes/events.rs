@@ -0,0 +1,44 @@
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use std::convert::Infallible;
+use tokio_stream::{
+    Stream, StreamExt,
+    wrappers::{BroadcastStream, errors::BroadcastStreamRecvError},
+};
+
+use crate::AppState;
+use crate::routes::AdminAuth;
+
+/// Admin change-data-capture stream
+///
+/// Server-Sent Events feed of every committed register/store/delete, so
+/// external indexing or analytics can mirror state without polling. Each
+/// `change` event carries a JSON [`ChangeEvent`](crate::events::ChangeEvent)
+/// and uses its sequence number as the SSE id. A subscriber that falls too far
+/// behind receives a `lagged` event with the number of dropped changes and
+/// should resync from `/admin/stats/export`.
+///
+/// GET /admin/events/stream
+pub async fn admin_events_stream(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    tracing::info!(admin = %admin.identity, "Change stream subscribed");
+
+    let stream = BroadcastStream::new(state.events.subscribe()).map(|item| {
+        Ok(match item {
+            Ok(change) => Event::default()
+                .event("change")
+                .id(change.seq.to_string())
+                .json_data(&change)
+                .unwrap_or_else(|_| Event::default().comment("unserializable change")),
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                Event::default().event("lagged").data(skipped.to_string())
+            }
+        })
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
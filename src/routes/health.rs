@@ -1,8 +1,11 @@
-use axum::{Json, extract::State};
+use axum::{Json, extract::State, http::StatusCode};
+use chrono::Utc;
 use redb::ReadableDatabase;
 use serde_json::{Value, json};
+use std::time::Instant;
 
 use crate::AppState;
+use crate::health_history::HealthSample;
 
 /// Health check endpoint
 ///
@@ -11,19 +14,97 @@ use crate::AppState;
 pub async fn health_check(State(state): State<AppState>) -> Json<Value> {
     // Check database connectivity by attempting a read transaction
     let db = state.db.clone();
-    let db_status = tokio::task::spawn_blocking(move || match db.begin_read() {
-        Ok(_) => "connected",
+    let started = Instant::now();
+    let db_result = state
+        .spawn_db(move || db.begin_read().map(|_| ()))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r.map_err(|e| e.to_string()));
+
+    let db_status = match &db_result {
+        Ok(()) => "connected",
         Err(e) => {
-            tracing::error!("Database health check failed: {:?}", e);
+            tracing::error!("Database health check failed: {}", e);
             "disconnected"
         }
-    })
-    .await
-    .unwrap_or("error");
+    };
+
+    state.health_history.record(HealthSample {
+        at: Utc::now().timestamp(),
+        healthy: db_result.is_ok(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        error: db_result.err(),
+    });
 
     Json(json!({
         "status": if db_status == "connected" { "healthy" } else { "unhealthy" },
         "database": db_status,
+        "writes": writes_status(&state),
+        "version": env!("CARGO_PKG_VERSION"),
+    }))
+}
+
+/// `read_only` after a disk-full error, `maintenance` while an operator has
+/// turned writes off, otherwise `enabled`
+fn writes_status(state: &AppState) -> &'static str {
+    if state.writes.read_only_since().is_some() {
+        "read_only"
+    } else if state.writes.in_maintenance() {
+        "maintenance"
+    } else {
+        "enabled"
+    }
+}
+
+/// Liveness probe
+///
+/// Answers as long as the process is serving requests, without touching the
+/// database, so an orchestrator only restarts the server if it hangs.
+///
+/// GET /health/live
+pub async fn liveness() -> Json<Value> {
+    Json(json!({
+        "status": "alive",
         "version": env!("CARGO_PKG_VERSION"),
     }))
 }
+
+/// Readiness probe
+///
+/// 503 unless the database accepts a write transaction, writes aren't
+/// disabled by a full disk, and every background job is still running, so an
+/// orchestrator stops routing traffic here while the database recovers.
+/// Maintenance mode leaves the server ready, since retrievals still work.
+///
+/// GET /health/ready
+pub async fn readiness(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let db = state.db.clone();
+    let db_result = state
+        .spawn_db(move || -> crate::Result<()> {
+            db.begin_write()?.abort()?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r.map_err(|e| e.to_string()));
+    if let Err(e) = &db_result {
+        tracing::error!("Database readiness check failed: {}", e);
+    }
+
+    let disk_full = state.writes.read_only_since().is_some();
+    let stopped_jobs = state.jobs.stopped();
+    let ready = db_result.is_ok() && !disk_full && stopped_jobs.is_empty();
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "database": if db_result.is_ok() { "writable" } else { "unavailable" },
+        "writes": writes_status(&state),
+        "stopped_jobs": stopped_jobs,
+    });
+    (status, Json(body))
+}
@@ -0,0 +1,161 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use chrono::Utc;
+use redb::ReadableDatabase;
+use serde::{Deserialize, Serialize};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use crate::config::SigningScope;
+use crate::constants::{ERR_INVALID_USER_ID, MAX_BACKUPS_PER_DAY, MAX_BACKUPS_PER_HOUR};
+use crate::db::tables;
+use crate::error::{AppError, Result};
+use crate::models::{RateLimitRecord, User};
+use crate::routes::{record_signature_failure, timestamp_to_rfc3339, validate_signed_request};
+use crate::{AppState, ClientIp};
+
+#[derive(Debug, Deserialize)]
+pub struct LimitsParams {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    /// HMAC of `userId` (store scope)
+    pub signature: String,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+/// Usage of one backup window
+#[derive(Debug, Serialize)]
+pub struct WindowUsage {
+    pub used: u64,
+    pub limit: u64,
+    pub remaining: u64,
+    /// When `used` drops back to 0; absent while the window hasn't started
+    #[serde(rename = "resetsAt", skip_serializing_if = "Option::is_none")]
+    pub resets_at: Option<String>,
+}
+
+impl WindowUsage {
+    fn new(used: u32, limit: i32, reset_at: i64, now: i64) -> Self {
+        // An expired window restarts with the next backup
+        let (used, resets_at) = if now >= reset_at {
+            (0, None)
+        } else {
+            (u64::from(used), Some(timestamp_to_rfc3339(reset_at)))
+        };
+        let limit = limit as u64;
+        WindowUsage {
+            used,
+            limit,
+            remaining: limit.saturating_sub(used),
+            resets_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LimitsResponse {
+    pub hourly: WindowUsage,
+    pub daily: WindowUsage,
+    /// Earliest next backup under `MIN_BACKUP_INTERVAL_SECS`; absent when
+    /// the interval doesn't hold one back
+    #[serde(rename = "nextBackupAt", skip_serializing_if = "Option::is_none")]
+    pub next_backup_at: Option<String>,
+}
+
+/// GET /api/limits?userId=...&signature=...&timestamp=...
+///
+/// The caller's backup rate-limit counters, so the app can schedule syncs
+/// instead of discovering the limits through 429s. Read-only: consumes no
+/// rate limit.
+///
+/// # Security
+/// - Requires HMAC signature (store scope) over `userId` and timestamp validation
+pub async fn get_limits(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    Query(params): Query<LimitsParams>,
+) -> Result<Json<LimitsResponse>> {
+    if !User::validate_id(&params.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    validate_signed_request(
+        &state,
+        &params.user_id,
+        &params.signature,
+        params.timestamp,
+        params.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Store),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &params.user_id, ip))?;
+
+    let db = state.db.clone();
+    let user_id = params.user_id;
+
+    let record = state
+        .spawn_db(move || -> Result<Option<RateLimitRecord>> {
+            let read_txn = db.begin_read()?;
+
+            let users = read_txn.open_table(tables::USERS)?;
+            if users.get(user_id.as_str())?.is_none() {
+                return Err(AppError::UserNotFound);
+            }
+
+            let rate_limits = read_txn.open_table(tables::RATE_LIMITS)?;
+            rate_limits
+                .get(user_id.as_str())?
+                .map(|b| {
+                    bincode::serde::decode_from_slice(b.value(), BINCODE_CONFIG)
+                        .map(|(r, _)| r)
+                        .map_err(AppError::from)
+                })
+                .transpose()
+        })
+        .await??;
+
+    let now = Utc::now().timestamp();
+    let record = record.unwrap_or_else(|| RateLimitRecord::new(now));
+    let min_interval = state.config().min_backup_interval_secs as i64;
+    let next_backup_at = record
+        .last_backup_at
+        .map(|last| last + min_interval)
+        .filter(|&next| next > now)
+        .map(timestamp_to_rfc3339);
+
+    Ok(Json(LimitsResponse {
+        hourly: WindowUsage::new(
+            record.backups_this_hour,
+            MAX_BACKUPS_PER_HOUR,
+            record.hour_reset_at,
+            now,
+        ),
+        daily: WindowUsage::new(
+            record.backups_today,
+            MAX_BACKUPS_PER_DAY,
+            record.day_reset_at,
+            now,
+        ),
+        next_backup_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_usage() {
+        let active = WindowUsage::new(3, 5, 1_000, 500);
+        assert_eq!((active.used, active.remaining), (3, 2));
+        assert!(active.resets_at.is_some());
+
+        let expired = WindowUsage::new(5, 5, 1_000, 1_000);
+        assert_eq!((expired.used, expired.remaining), (0, 5));
+        assert!(expired.resets_at.is_none());
+    }
+}
@@ -1,13 +1,56 @@
+#[cfg(feature = "admin")]
 pub mod admin;
+pub mod admin_auth;
+#[cfg(feature = "admin")]
+pub mod admin_ui;
+pub mod api_version;
+pub mod archive;
 pub mod backup;
+pub mod bans;
+pub mod check;
+pub mod chunks;
+pub mod codec;
 pub mod delete;
+pub mod dry_run;
+#[cfg(feature = "admin")]
+pub mod events;
 pub mod health;
+pub mod limits;
+pub mod recovery;
 pub mod register;
+pub mod replication;
+pub mod router;
+pub mod sync;
 pub mod validation;
+pub mod versions;
 
-pub use admin::admin_stats;
-pub use backup::{retrieve_backup, store_backup};
-pub use delete::delete_user;
-pub use health::health_check;
+#[cfg(feature = "admin")]
+pub use admin::{
+    admin_abuse_top, admin_compact, admin_health_history, admin_largest_backups, admin_maintenance,
+    admin_metrics, admin_prune_rate_limits, admin_purge, admin_reload_config, admin_resume_writes,
+    admin_runtime, admin_signups, admin_snapshot, admin_stats, admin_stats_export, admin_users,
+};
+pub use admin_auth::AdminAuth;
+#[cfg(feature = "admin")]
+pub use admin_ui::admin_ui;
+pub use archive::export_archive;
+pub use backup::{retrieve_backup, store_backup, store_backup_raw};
+pub use bans::{admin_ban, admin_list_bans, admin_unban, ensure_not_banned};
+pub use check::check_backup;
+pub use chunks::{commit_upload, start_upload, upload_chunk, upload_status};
+pub use delete::{delete_backups, delete_user, restore_user};
+pub use dry_run::validate_backup;
+#[cfg(feature = "admin")]
+pub use events::admin_events_stream;
+pub use health::{health_check, liveness, readiness};
+pub use limits::get_limits;
+pub use recovery::{admin_recovery_authorize, rekey};
 pub use register::register_user;
-pub use validation::{timestamp_to_rfc3339, validate_signed_request};
+pub use replication::replicate;
+pub use router::{RouterOptions, build_router, cors_layer};
+pub use sync::{sync_events, sync_ws};
+pub use validation::{
+    SigVersion, record_duplicate_upload, record_rate_limited, record_signature_failure,
+    timestamp_to_rfc3339, validate_signed_request,
+};
+pub use versions::list_backup_versions;
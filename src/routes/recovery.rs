@@ -0,0 +1,292 @@
+use axum::{Json, extract::State};
+use chrono::Utc;
+use redb::ReadableTable;
+use serde::{Deserialize, Serialize};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use crate::config::SigningScope;
+use crate::constants::{
+    ERR_INVALID_RECOVERY_HASH, ERR_INVALID_STORAGE_KEY, ERR_INVALID_USER_ID, RECOVERY_GRANT_SECS,
+};
+use crate::db::{tables, versions};
+use crate::error::{AppError, Result};
+use crate::events::ChangeKind;
+use crate::models::{Backup, BackupRecord, Recovery, RecoveryGrantRecord, User};
+use crate::routes::{
+    AdminAuth, record_signature_failure, timestamp_to_rfc3339, validate_signed_request,
+};
+use crate::{AppState, ClientIp};
+
+#[derive(Debug, Deserialize)]
+pub struct RecoveryClaimRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    /// Hash of the contact the user gave support, computed by support with
+    /// `dailyreps_signing::recovery_hash`
+    #[serde(rename = "recoveryHash")]
+    pub recovery_hash: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryClaimResponse {
+    /// Whether the claimed contact matches the one bound at registration
+    pub matches: bool,
+    /// Deadline for the user's rekey, when the claim matched
+    #[serde(rename = "rekeyExpiresAt", skip_serializing_if = "Option::is_none")]
+    pub rekey_expires_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RekeyRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "recoveryHash")]
+    pub recovery_hash: String,
+    /// Storage key derived from the user's new password
+    #[serde(rename = "newStorageKey")]
+    pub new_storage_key: String,
+    pub signature: String,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RekeyResponse {
+    pub success: bool,
+    /// Whether a backup was moved to the new storage key
+    pub moved: bool,
+}
+
+/// Verify a recovery claim and, if it matches, authorize a rekey
+///
+/// Support asks the user for their recovery email/phone, hashes it with the
+/// user ID (`dailyreps_signing::recovery_hash`), and submits the hash. A
+/// match lets the user call `POST /api/recovery/rekey` within 24 hours.
+///
+/// POST /admin/recovery/authorize
+pub async fn admin_recovery_authorize(
+    State(state): State<AppState>,
+    admin: AdminAuth,
+    Json(payload): Json<RecoveryClaimRequest>,
+) -> Result<Json<RecoveryClaimResponse>> {
+    if !User::validate_id(&payload.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if !Recovery::validate_hash(&payload.recovery_hash) {
+        return Err(AppError::InvalidInput(
+            ERR_INVALID_RECOVERY_HASH.to_string(),
+        ));
+    }
+
+    let db = state.db.clone();
+    let user_id = payload.user_id.clone();
+    let claimed = payload.recovery_hash;
+    let authorized_by = admin.identity;
+
+    let expires_at = state
+        .spawn_db(move || -> Result<Option<i64>> {
+            let now = Utc::now().timestamp();
+
+            let write_txn = db.begin_write()?;
+            let expires_at = {
+                let users = write_txn.open_table(tables::USERS)?;
+                if users.get(user_id.as_str())?.is_none() {
+                    return Err(AppError::UserNotFound);
+                }
+
+                let contacts = write_txn.open_table(tables::RECOVERY_CONTACTS)?;
+                let bound = contacts
+                    .get(user_id.as_str())?
+                    .map(|h| h.value().to_string())
+                    .ok_or_else(|| {
+                        AppError::InvalidInput(
+                            "No recovery contact bound to this account".to_string(),
+                        )
+                    })?;
+
+                if !bound.eq_ignore_ascii_case(&claimed) {
+                    tracing::warn!(admin = %authorized_by, "Recovery claim did not match");
+                    return Ok(None);
+                }
+
+                let grant = RecoveryGrantRecord {
+                    authorized_at: now,
+                    expires_at: now + RECOVERY_GRANT_SECS,
+                    authorized_by: authorized_by.clone(),
+                };
+                let bytes = bincode::serde::encode_to_vec(&grant, BINCODE_CONFIG)?;
+                let mut grants = write_txn.open_table(tables::RECOVERY_GRANTS)?;
+                grants.insert(user_id.as_str(), bytes.as_slice())?;
+
+                grant.expires_at
+            };
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+
+            tracing::info!(admin = %authorized_by, "Rekey authorized");
+            Ok(Some(expires_at))
+        })
+        .await??;
+
+    Ok(Json(RecoveryClaimResponse {
+        matches: expires_at.is_some(),
+        rekey_expires_at: expires_at.map(timestamp_to_rfc3339),
+    }))
+}
+
+/// Re-bind an account to a new password after an admin-authorized recovery
+///
+/// Moves the user's most recently updated backup to `newStorageKey` and
+/// consumes the grant. The backup is moved as stored: it stays readable only
+/// if the app can decrypt it without the old password (e.g. a data key also
+/// wrapped with a recovery code). Older backups stay under their old keys
+/// until the account is deleted.
+///
+/// # Security
+/// - Requires HMAC signature verification (over the new storage key)
+/// - Requires timestamp validation
+/// - Requires an unexpired grant from `POST /admin/recovery/authorize` and
+///   the same recovery hash the admin verified
+pub async fn rekey(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    Json(payload): Json<RekeyRequest>,
+) -> Result<Json<RekeyResponse>> {
+    if !User::validate_id(&payload.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if !Backup::validate_storage_key(&payload.new_storage_key) {
+        return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
+    }
+
+    if !Recovery::validate_hash(&payload.recovery_hash) {
+        return Err(AppError::InvalidInput(
+            ERR_INVALID_RECOVERY_HASH.to_string(),
+        ));
+    }
+
+    validate_signed_request(
+        &state,
+        &payload.new_storage_key,
+        &payload.signature,
+        payload.timestamp,
+        payload.nonce.as_deref(),
+        &state.config().signing_secrets(SigningScope::Delete),
+    )
+    .await?
+    .inspect_err(|_| record_signature_failure(&state, &payload.user_id, ip))?;
+
+    let db = state.db.clone();
+    let user_id = payload.user_id.clone();
+    let claimed = payload.recovery_hash;
+    let new_key = payload.new_storage_key;
+
+    let moved = state
+        .spawn_db(move || -> Result<bool> {
+            let now = Utc::now().timestamp();
+
+            let write_txn = db.begin_write()?;
+            let moved = {
+                // 1. Require a live grant and the contact support verified
+                let mut grants = write_txn.open_table(tables::RECOVERY_GRANTS)?;
+                let grant: RecoveryGrantRecord = grants
+                    .get(user_id.as_str())?
+                    .map(|g| bincode::serde::decode_from_slice(g.value(), BINCODE_CONFIG))
+                    .transpose()?
+                    .map(|(g, _)| g)
+                    .filter(|g: &RecoveryGrantRecord| g.expires_at > now)
+                    .ok_or(AppError::Unauthorized)?;
+
+                let contacts = write_txn.open_table(tables::RECOVERY_CONTACTS)?;
+                let matches = contacts
+                    .get(user_id.as_str())?
+                    .is_some_and(|h| h.value().eq_ignore_ascii_case(&claimed));
+                if !matches {
+                    tracing::warn!("Rekey attempt with wrong recovery hash");
+                    return Err(AppError::Unauthorized);
+                }
+                drop(contacts);
+
+                // 2. Find the most recently updated backup
+                let mut user_backups = write_txn.open_table(tables::USER_BACKUPS)?;
+                let mut keys: Vec<String> = user_backups
+                    .get(user_id.as_str())?
+                    .map(|b| bincode::serde::decode_from_slice(b.value(), BINCODE_CONFIG))
+                    .transpose()?
+                    .map(|(keys, _)| keys)
+                    .unwrap_or_default();
+
+                let mut backups = write_txn.open_table(tables::BACKUPS)?;
+                if backups.get(new_key.as_str())?.is_some() {
+                    return Err(AppError::InvalidInput(
+                        "New storage key is already in use".to_string(),
+                    ));
+                }
+
+                let mut latest: Option<(String, BackupRecord)> = None;
+                for key in &keys {
+                    if let Some(bytes) = backups.get(key.as_str())? {
+                        let (record, _): (BackupRecord, _) =
+                            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                        if latest
+                            .as_ref()
+                            .is_none_or(|(_, l)| record.updated_at > l.updated_at)
+                        {
+                            latest = Some((key.clone(), record));
+                        }
+                    }
+                }
+
+                // 3. Move it (with its sync token and history) to the new key
+                let moved = if let Some((old_key, record)) = latest {
+                    let bytes = bincode::serde::encode_to_vec(&record, BINCODE_CONFIG)?;
+                    backups.remove(old_key.as_str())?;
+                    backups.insert(new_key.as_str(), bytes.as_slice())?;
+
+                    let mut sync_tokens = write_txn.open_table(tables::SYNC_TOKENS)?;
+                    let version = sync_tokens.remove(old_key.as_str())?.map(|v| v.value());
+                    if let Some(version) = version {
+                        sync_tokens.insert(new_key.as_str(), version)?;
+                    }
+                    versions::rename(&write_txn, &old_key, &new_key)?;
+
+                    for key in keys.iter_mut().filter(|k| **k == old_key) {
+                        *key = new_key.clone();
+                    }
+                    let keys_bytes = bincode::serde::encode_to_vec(&keys, BINCODE_CONFIG)?;
+                    user_backups.insert(user_id.as_str(), keys_bytes.as_slice())?;
+                    true
+                } else {
+                    false
+                };
+
+                // 4. Grants are single-use
+                grants.remove(user_id.as_str())?;
+                tracing::info!(
+                    authorized_by = %grant.authorized_by,
+                    "Account rekeyed after recovery (backup moved: {})",
+                    moved
+                );
+
+                moved
+            };
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+
+            Ok(moved)
+        })
+        .await??;
+
+    state
+        .events
+        .publish(ChangeKind::Rekey, payload.user_id, None);
+
+    Ok(Json(RekeyResponse {
+        success: true,
+        moved,
+    }))
+}
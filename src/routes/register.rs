@@ -5,16 +5,37 @@ use serde::{Deserialize, Serialize};
 
 const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
 
-use crate::AppState;
-use crate::constants::ERR_USER_ID_MUST_BE_SHA256;
-use crate::db::tables;
+use crate::constants::{ERR_INVALID_RECOVERY_HASH, ERR_USER_ID_MUST_BE_SHA256};
+use crate::db::{tables, tombstones};
 use crate::error::{AppError, Result};
-use crate::models::{User, UserRecord};
+use crate::events::ChangeKind;
+use crate::metrics;
+use crate::models::{Recovery, RegistrationAttemptRecord, User, UserRecord};
+use crate::routes::{
+    ensure_not_banned, record_rate_limited, record_signature_failure, validate_signed_request,
+};
+use crate::security::hash_client_ip;
+use crate::{AppState, ClientIp};
+
+/// `REGISTRATION_ATTEMPTS` key shared by requests whose client IP is unknown
+const UNKNOWN_NETWORK: &str = "unknown";
 
 #[derive(Debug, Deserialize)]
 pub struct RegisterRequest {
     #[serde(rename = "userId")]
     pub user_id: String,
+    /// Turnstile/hCaptcha token, required when captcha verification is enabled
+    #[serde(rename = "captchaToken")]
+    pub captcha_token: Option<String>,
+    /// Salted hash of a recovery email/phone (`dailyreps_signing::recovery_hash`),
+    /// letting support authorize a rekey if the password is forgotten
+    #[serde(rename = "recoveryHash")]
+    pub recovery_hash: Option<String>,
+    /// HMAC of `userId`, required only when `REGISTER_SECRET_KEY` is set
+    pub signature: Option<String>,
+    pub timestamp: Option<i64>,
+    #[serde(default)]
+    pub nonce: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -26,8 +47,16 @@ pub struct RegisterResponse {
 ///
 /// Creates a new user record with the provided user ID (SHA-256 hash).
 /// Returns 409 Conflict if the user ID already exists.
+/// When captcha verification is configured, the token is checked with the
+/// provider before anything is written. Clients on a blocklisted network
+/// (Tor exits, datacenters; see `REGISTRATION_BLOCKLIST_URLS`) get 403.
+/// With `REGISTER_SECRET_KEY` set, the request must carry a signature over
+/// `userId` made with that secret. Attempts per client network are limited to
+/// `REGISTER_RATE_LIMIT_REQUESTS` per window, counted in the database so the
+/// limit survives restarts.
 pub async fn register_user(
     State(state): State<AppState>,
+    ip: ClientIp,
     Json(payload): Json<RegisterRequest>,
 ) -> Result<Json<RegisterResponse>> {
     // Validate user ID format (must be 64-char hex string)
@@ -38,33 +67,143 @@ pub async fn register_user(
         ));
     }
 
+    if let Some(secret) = &state.config().register_secret_key {
+        validate_signed_request(
+            &state,
+            &payload.user_id,
+            payload.signature.as_deref().unwrap_or_default(),
+            payload.timestamp.unwrap_or_default(),
+            payload.nonce.as_deref(),
+            &[secret.as_str()],
+        )
+        .await?
+        .inspect_err(|_| record_signature_failure(&state, &payload.user_id, ip))?;
+    }
+
+    if let Some(hash) = &payload.recovery_hash
+        && !Recovery::validate_hash(hash)
+    {
+        return Err(AppError::InvalidInput(
+            ERR_INVALID_RECOVERY_HASH.to_string(),
+        ));
+    }
+
+    ensure_not_banned(&state, Some(&payload.user_id), ip).await?;
+
+    if ip.0.is_some_and(|ip| state.blocklist.contains(ip)) {
+        tracing::info!("Registration refused from blocklisted network");
+        state.metrics.incr(metrics::REGISTRATIONS_BLOCKED);
+        return Err(AppError::NetworkBlocked);
+    }
+
+    // Behind a proxy, a request without the client IP header didn't come
+    // through it; such requests share one budget instead of going unlimited
+    let network = match ip.0 {
+        Some(ip) => Some(hash_client_ip(ip, state.config().app_secret_key())),
+        None if state.config().client_ip_header.is_some() => Some(UNKNOWN_NETWORK.to_string()),
+        None => None,
+    };
+    if let Some(network) = network {
+        throttle_registration(&state, network)
+            .await
+            .inspect_err(|e| {
+                if matches!(e, AppError::RateLimitExceeded(_)) {
+                    record_rate_limited(&state, &payload.user_id, ip);
+                }
+            })?;
+    }
+
+    if let Some(captcha) = &state.captcha {
+        let token = payload.captcha_token.as_deref().unwrap_or_default();
+        if token.is_empty() || !captcha.verify(token).await {
+            return Err(AppError::CaptchaFailed);
+        }
+    }
+
     let db = state.db.clone();
     let user_id = payload.user_id.clone();
+    let recovery_hash = payload.recovery_hash.map(|h| h.to_ascii_lowercase());
 
-    tokio::task::spawn_blocking(move || {
-        let write_txn = db.begin_write()?;
-        {
-            let mut table = write_txn.open_table(tables::USERS)?;
+    state
+        .spawn_db(move || {
+            let write_txn = db.begin_write()?;
+            {
+                let mut table = write_txn.open_table(tables::USERS)?;
 
-            // Check if user already exists
-            if table.get(user_id.as_str())?.is_some() {
-                tracing::info!("User already exists");
-                return Err(AppError::UserAlreadyExists);
+                // Check if user already exists (or was deleted and can still
+                // be restored, which a new registration must not preempt)
+                if table.get(user_id.as_str())?.is_some()
+                    || tombstones::exists(&write_txn, &user_id)?
+                {
+                    tracing::info!("User already exists");
+                    return Err(AppError::UserAlreadyExists);
+                }
+
+                // Insert new user
+                let now = Utc::now().timestamp();
+                let record = UserRecord { created_at: now };
+                let bytes = bincode::serde::encode_to_vec(&record, BINCODE_CONFIG)?;
+                table.insert(user_id.as_str(), bytes.as_slice())?;
+
+                if let Some(hash) = &recovery_hash {
+                    let mut contacts = write_txn.open_table(tables::RECOVERY_CONTACTS)?;
+                    contacts.insert(user_id.as_str(), hash.as_str())?;
+                }
+
+                crate::db::bump_daily_stats(&write_txn, now, |s| s.registrations += 1)?;
             }
+            crate::db::before_commit()?;
+            write_txn.commit()?;
 
-            // Insert new user
-            let record = UserRecord {
-                created_at: Utc::now().timestamp(),
-            };
-            let bytes = bincode::serde::encode_to_vec(&record, BINCODE_CONFIG)?;
-            table.insert(user_id.as_str(), bytes.as_slice())?;
-        }
-        write_txn.commit()?;
+            tracing::info!("New user registered");
+            Ok(())
+        })
+        .await??;
 
-        tracing::info!("New user registered");
-        Ok(())
-    })
-    .await??;
+    state.metrics.incr(metrics::REGISTRATIONS);
+    state
+        .events
+        .publish(ChangeKind::Register, payload.user_id, None);
 
     Ok(Json(RegisterResponse { success: true }))
 }
+
+/// Count a registration attempt from `network` (a keyed hash of the client
+/// network, or [`UNKNOWN_NETWORK`]), refusing it past the limit
+///
+/// Committed on its own, before the captcha check and the user insert, so
+/// failed attempts count too. Expired windows are left to the sweeper (see
+/// [`crate::db::registration_attempts`]), so a flood doesn't scan the table.
+async fn throttle_registration(state: &AppState, network: String) -> Result<()> {
+    let limit = state.config().register_rate_limit_requests;
+    if limit == 0 {
+        return Ok(());
+    }
+
+    let db = state.db.clone();
+    let window_secs = state.config().register_rate_limit_window_secs;
+
+    state
+        .spawn_db(move || -> Result<()> {
+            let now = Utc::now().timestamp();
+            let write_txn = db.begin_write()?;
+            {
+                let mut attempts = write_txn.open_table(tables::REGISTRATION_ATTEMPTS)?;
+                let mut record = match attempts.get(network.as_str())? {
+                    Some(bytes) => {
+                        bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?.0
+                    }
+                    None => RegistrationAttemptRecord::new(now, window_secs),
+                };
+                record.check_and_increment(now, limit, window_secs)?;
+
+                let bytes = bincode::serde::encode_to_vec(&record, BINCODE_CONFIG)?;
+                attempts.insert(network.as_str(), bytes.as_slice())?;
+            }
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+
+            Ok(())
+        })
+        .await?
+}
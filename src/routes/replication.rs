@@ -0,0 +1,55 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+
+use crate::AppState;
+use crate::constants::{ERR_INVALID_TIMESTAMP, ERR_INVALID_USER_ID, MAX_TIMESTAMP_AGE_SECS};
+use crate::error::{AppError, Result};
+use crate::models::User;
+use crate::replication::{self, AccountState, SIGNATURE_HEADER};
+use crate::security::{validate_timestamp, verify_hmac};
+
+/// Apply an account state sent by the primary
+///
+/// The body must be signed with `REPLICATION_SECRET` in
+/// `X-Replication-Signature`. The account's records are replaced by the ones
+/// sent, or removed if none were. A state no newer than the last one applied
+/// for the account is refused with 409.
+///
+/// POST /internal/replicate (mounted only when `REPLICATION_SECRET` is set)
+pub async fn replicate(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode> {
+    let config = state.config();
+    let Some(secret) = config.replication_secret.as_deref() else {
+        return Err(AppError::Unauthorized);
+    };
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !verify_hmac(&body, signature, &[secret]) {
+        tracing::warn!("Replication request with a bad signature");
+        return Err(AppError::Unauthorized);
+    }
+
+    let account: AccountState = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidInput(format!("Invalid account state: {}", e)))?;
+    if !User::validate_id(&account.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+    if !validate_timestamp(account.sent_at / 1000, MAX_TIMESTAMP_AGE_SECS) {
+        return Err(AppError::InvalidInput(ERR_INVALID_TIMESTAMP.to_string()));
+    }
+
+    let db = state.db.clone();
+    state
+        .spawn_db(move || replication::apply(&db, account))
+        .await??;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,325 @@
+use axum::{
+    Router,
+    extract::{DefaultBodyLimit, Request},
+    http::{HeaderName, HeaderValue, Method, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{MethodRouter, delete, get, post, put},
+};
+use std::time::Duration;
+use tower_http::compression::{CompressionLayer, predicate::SizeAbove};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::trace::TraceLayer;
+
+use crate::constants::{
+    COMPRESSION_MIN_BYTES, MAX_BACKUP_SIZE_BYTES, MAX_REPLICATION_BODY_BYTES,
+    MAX_REQUEST_BODY_BYTES, MAX_UPLOAD_CHUNK_BYTES,
+};
+use crate::error::AppError;
+use crate::geoip::enforce_country_policy;
+use crate::network_limit::limit_per_network;
+use crate::read_only::guard_writes;
+use crate::request_id;
+use crate::routes::api_version::{ApiVersion, tag_version};
+use crate::routes::*;
+use crate::{AppState, Config};
+
+/// Transport-level options for [`build_router`]
+#[derive(Debug, Clone, Default)]
+pub struct RouterOptions {
+    /// CORS policy applied to every route (none when `None`)
+    pub cors: Option<CorsLayer>,
+    /// Log every request with `TraceLayer`
+    pub log_requests: bool,
+}
+
+/// `Cache-Control` per path prefix; the first match wins
+///
+/// Backup data, account operations and admin reports must never be cached by
+/// a proxy or browser. `/health` may be cached briefly so that many probes
+/// don't each open a database transaction; the orchestrator probes under
+/// `/health/` must always see the current state.
+const CACHE_POLICY: &[(&str, &str)] = &[
+    ("/health/", "no-store"),
+    ("/health", "public, max-age=5"),
+    ("/api/", "no-store"),
+    ("/admin/", "no-store"),
+    ("/internal/", "no-store"),
+];
+
+/// Apply [`CACHE_POLICY`] unless the handler set its own `Cache-Control`
+async fn cache_control(request: Request, next: Next) -> Response {
+    let policy = CACHE_POLICY
+        .iter()
+        .find(|(prefix, _)| request.uri().path().starts_with(prefix))
+        .map(|(_, value)| HeaderValue::from_static(value));
+
+    let mut response = next.run(request).await;
+    if let Some(value) = policy {
+        response
+            .headers_mut()
+            .entry(header::CACHE_CONTROL)
+            .or_insert(value);
+    }
+    response
+}
+
+/// Give body-limit rejections the usual JSON error body
+///
+/// `RequestBodyLimitLayer` and axum's extractors answer 413 in plain text.
+async fn payload_too_large_as_json(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return AppError::PayloadTooLarge.into_response();
+    }
+    response
+}
+
+/// CORS policy for the configured origins
+///
+/// A long `Access-Control-Max-Age` lets browsers reuse one preflight for many
+/// syncs. Credentials can't be combined with wildcard headers, so request
+/// headers are mirrored instead of `*` when they are enabled.
+pub fn cors_layer(state: &AppState) -> Result<CorsLayer, String> {
+    let config = state.config();
+    for origin in &config.allowed_origins {
+        origin
+            .parse::<HeaderValue>()
+            .map_err(|e| format!("Invalid CORS origin '{}': {}", origin, e))?;
+    }
+
+    // Origins are checked against the current config on each request, so a
+    // reload applies without rebuilding the router
+    let state = state.clone();
+    let allowed_origins = AllowOrigin::predicate(move |origin, _| {
+        state
+            .config()
+            .allowed_origins
+            .iter()
+            .any(|allowed| origin == allowed.as_str())
+    });
+
+    let exposed_headers: Vec<HeaderName> = config
+        .cors_expose_headers
+        .iter()
+        .map(|s| {
+            s.parse()
+                .map_err(|e| format!("Invalid CORS exposed header '{}': {}", s, e))
+        })
+        .collect::<Result<_, _>>()?;
+
+    let allow_headers = if config.cors_allow_credentials {
+        AllowHeaders::mirror_request()
+    } else {
+        AllowHeaders::any()
+    };
+
+    Ok(CorsLayer::new()
+        .allow_origin(allowed_origins)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers(allow_headers)
+        .allow_credentials(config.cors_allow_credentials)
+        .expose_headers(exposed_headers)
+        .max_age(Duration::from_secs(config.cors_max_age_secs)))
+}
+
+/// Build the complete route table
+///
+/// The binary and the integration tests both use this, so a route added here
+/// is served and tested without a second copy to keep in sync.
+pub fn build_router(state: AppState, options: RouterOptions) -> Router {
+    // Client-facing routes: each version under its prefix, plus the legacy
+    // unversioned aliases, all subject to the country access policy and the
+    // per-network request limit
+    let mut api = Router::new().nest("/api", api_routes(&state, ApiVersion::LEGACY));
+    for version in ApiVersion::ALL {
+        api = api.nest(version.prefix(), api_routes(&state, version));
+    }
+    let api = api
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            enforce_country_policy,
+        ))
+        // Counted before anything else runs, so refused requests stay cheap
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_per_network,
+        ))
+        // Oversized bodies are refused from Content-Length, or as soon as a
+        // streamed body passes the limit, before anything is buffered;
+        // routes with a smaller DefaultBodyLimit keep it
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(MAX_REQUEST_BODY_BYTES))
+        .layer(middleware::map_response(payload_too_large_as_json));
+
+    let app = Router::new()
+        .route("/health", get(health_check))
+        .route("/health/live", get(liveness))
+        .route("/health/ready", get(readiness))
+        .merge(api);
+
+    // A standby accepts account states from its primary
+    let app = if state.config().replication_secret.is_some() {
+        app.route(
+            crate::replication::REPLICATE_PATH,
+            post(replicate).layer(DefaultBodyLimit::max(MAX_REPLICATION_BODY_BYTES)),
+        )
+    } else {
+        app
+    };
+
+    // Answer 404 like unknown paths while no admin credential is configured,
+    // so public deployments expose no admin surface; builds without the
+    // `admin` feature leave the handlers out entirely
+    #[cfg(feature = "admin")]
+    let app =
+        app.merge(admin_routes().layer(middleware::from_fn_with_state(state.clone(), admin_only)));
+
+    let mut app = app
+        .with_state(state)
+        .layer(middleware::from_fn(cache_control));
+
+    #[cfg(feature = "chaos")]
+    {
+        app = app.layer(axum::middleware::from_fn(crate::chaos::inject_errors));
+    }
+
+    #[cfg(feature = "netsim")]
+    {
+        app = app.layer(axum::middleware::from_fn(crate::netsim::simulate));
+    }
+
+    app = app.layer(middleware::from_fn(request_id::assign));
+
+    if let Some(cors) = options.cors {
+        app = app.layer(cors);
+    }
+
+    if options.log_requests {
+        app = app.layer(TraceLayer::new_for_http());
+    }
+
+    app
+}
+
+/// Compress `route`'s responses for clients that send `Accept-Encoding`
+///
+/// Only applied to routes returning backup data: it is base64 and shrinks by
+/// about a quarter, while small JSON replies aren't worth the CPU.
+fn compressed(route: MethodRouter<AppState>, config: &Config) -> MethodRouter<AppState> {
+    if !config.response_compression {
+        return route;
+    }
+
+    route.layer(CompressionLayer::new().compress_when(SizeAbove::new(COMPRESSION_MIN_BYTES)))
+}
+
+/// The client route table of `version`, relative to its prefix
+///
+/// Versions share every route except those a later version changed
+/// incompatibly.
+fn api_routes(state: &AppState, version: ApiVersion) -> Router<AppState> {
+    // Mutating handlers are refused while the disk is full
+    let writes = middleware::from_fn_with_state(state.clone(), guard_writes);
+
+    let backup = match version {
+        ApiVersion::V1 => post(store_backup).route_layer(writes.clone()),
+        ApiVersion::V2 => post(store_backup_raw)
+            .route_layer(writes.clone())
+            .layer(DefaultBodyLimit::max(MAX_BACKUP_SIZE_BYTES)),
+    };
+
+    Router::new()
+        .route("/register", post(register_user).route_layer(writes.clone()))
+        .route(
+            "/backup",
+            backup.merge(compressed(get(retrieve_backup), &state.config())),
+        )
+        .route("/backup/check", post(check_backup))
+        .route("/backup/versions", get(list_backup_versions))
+        .route("/limits", get(get_limits))
+        .route("/sync/ws", get(sync_ws))
+        .route("/sync/events", get(sync_events))
+        .route("/backup/validate", post(validate_backup))
+        .route(
+            "/backup/delete",
+            post(delete_backups).route_layer(writes.clone()),
+        )
+        .route(
+            "/backup/archive",
+            compressed(post(export_archive), &state.config()),
+        )
+        .route(
+            "/backup/chunks",
+            post(start_upload).route_layer(writes.clone()),
+        )
+        .route(
+            "/backup/chunks/{upload_id}",
+            put(upload_chunk)
+                .route_layer(writes.clone())
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_CHUNK_BYTES))
+                .get(upload_status),
+        )
+        .route(
+            "/backup/chunks/{upload_id}/commit",
+            post(commit_upload).route_layer(writes.clone()),
+        )
+        .route("/user", delete(delete_user).route_layer(writes.clone()))
+        .route(
+            "/user/restore",
+            post(restore_user).route_layer(writes.clone()),
+        )
+        .route("/recovery/rekey", post(rekey).route_layer(writes))
+        .layer(middleware::from_fn(move |request, next| {
+            tag_version(version, request, next)
+        }))
+}
+
+/// Hide the admin routes unless an admin credential is configured
+///
+/// Checked per request rather than when the router is built, so a reload that
+/// adds or removes `ADMIN_SECRET_KEY` takes effect without a restart.
+#[cfg(feature = "admin")]
+async fn admin_only(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.config().admin_enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    next.run(request).await
+}
+
+/// `/admin/*` routes, each authenticated by [`AdminAuth`]
+#[cfg(feature = "admin")]
+fn admin_routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin/stats", get(admin_stats))
+        .route("/admin/stats/export", get(admin_stats_export))
+        .route("/admin/metrics", get(admin_metrics))
+        .route("/admin/runtime", get(admin_runtime))
+        .route("/admin/health/history", get(admin_health_history))
+        .route("/admin/writes/resume", post(admin_resume_writes))
+        .route("/admin/maintenance", post(admin_maintenance))
+        .route("/admin/config/reload", post(admin_reload_config))
+        .route("/admin/purge", post(admin_purge))
+        .route("/admin/rate-limits/prune", post(admin_prune_rate_limits))
+        .route("/admin/snapshot", post(admin_snapshot))
+        .route("/admin/compact", post(admin_compact))
+        .route("/admin/backups/largest", get(admin_largest_backups))
+        .route("/admin/abuse/top", get(admin_abuse_top))
+        .route("/admin/signups", get(admin_signups))
+        .route("/admin/users", get(admin_users))
+        .route(
+            "/admin/bans",
+            get(admin_list_bans).post(admin_ban).delete(admin_unban),
+        )
+        .route("/admin/recovery/authorize", post(admin_recovery_authorize))
+        .route("/admin/ui", get(admin_ui))
+        .route("/admin/events/stream", get(admin_events_stream))
+}
@@ -0,0 +1,342 @@
+use axum::{
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::HeaderMap,
+    response::{
+        Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use redb::ReadableDatabase;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use crate::constants::{ERR_INVALID_STORAGE_KEY, ERR_INVALID_USER_ID};
+use crate::db::{Db, tables};
+use crate::error::{AppError, Result};
+use crate::events::{ChangeEvent, ChangeKind};
+use crate::in_flight::InFlightGuard;
+use crate::models::{Backup, BackupRecord, User};
+use crate::routes::{ensure_not_banned, timestamp_to_rfc3339};
+use crate::{AppState, ClientIp};
+
+/// Ping interval, so proxies don't close an idle connection
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Header an `EventSource` sends on reconnect, with the last id it received
+const LAST_EVENT_ID: &str = "last-event-id";
+
+#[derive(Debug, Deserialize)]
+pub struct SyncParams {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "storageKey")]
+    pub storage_key: String,
+}
+
+/// A backup's current version
+#[derive(Debug, Serialize)]
+struct BackupVersion {
+    #[serde(rename = "syncToken")]
+    sync_token: String,
+    #[serde(rename = "updatedAt")]
+    updated_at: String,
+}
+
+impl BackupVersion {
+    fn new(sync_token: u64, updated_at: i64) -> Self {
+        Self {
+            sync_token: sync_token.to_string(),
+            updated_at: timestamp_to_rfc3339(updated_at),
+        }
+    }
+}
+
+/// Pushed over the WebSocket when the backup changes
+#[derive(Debug, Serialize)]
+struct BackupStored {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(flatten)]
+    version: BackupVersion,
+}
+
+/// The backup's sync token and last update, if `user_id` owns it
+#[allow(clippy::result_large_err)]
+fn current_version(db: &Db, user_id: &str, storage_key: &str) -> Result<(u64, i64)> {
+    let read_txn = db.begin_read()?;
+    let record: BackupRecord = read_txn
+        .open_table(tables::BACKUPS)?
+        .get(storage_key)?
+        .map(|b| bincode::serde::decode_from_slice(b.value(), BINCODE_CONFIG))
+        .transpose()?
+        .map(|(r, _)| r)
+        .filter(|r: &BackupRecord| r.user_id == user_id)
+        .ok_or(AppError::BackupNotFound)?;
+    let sync_token = read_txn
+        .open_table(tables::SYNC_TOKENS)?
+        .get(storage_key)?
+        .map(|v| v.value())
+        .unwrap_or(0);
+
+    Ok((sync_token, record.updated_at))
+}
+
+async fn lookup(state: &AppState, params: &SyncParams) -> Result<(u64, i64)> {
+    let db = state.db.clone();
+    let (user_id, storage_key) = (params.user_id.clone(), params.storage_key.clone());
+    state
+        .spawn_db(move || current_version(&db, &user_id, &storage_key))
+        .await?
+}
+
+/// A subscription to one backup's changes
+struct Subscription {
+    sync_token: u64,
+    updated_at: i64,
+    events: broadcast::Receiver<ChangeEvent>,
+    /// Held for the connection's life, so a user can't open unbounded
+    /// connections (`MAX_SYNC_SUBSCRIPTIONS_PER_USER`)
+    slot: InFlightGuard,
+}
+
+/// Check the credentials and subscribe to the backup's changes
+///
+/// Returns the current version along with the subscription.
+async fn subscribe(state: &AppState, ip: ClientIp, params: &SyncParams) -> Result<Subscription> {
+    if !User::validate_id(&params.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if !Backup::validate_storage_key(&params.storage_key) {
+        return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
+    }
+
+    ensure_not_banned(state, Some(&params.user_id), ip).await?;
+    let slot = state.subscriptions.acquire(&params.user_id)?;
+
+    // Subscribe before reading the version so no store falls in between
+    let events = state.events.subscribe();
+    let (sync_token, updated_at) = lookup(state, params).await?;
+
+    Ok(Subscription {
+        sync_token,
+        updated_at,
+        events,
+        slot,
+    })
+}
+
+/// Open a push channel for a backup
+///
+/// Like `GET /api/backup`, knowing the storage key is the credential, and the
+/// backup must exist. Whenever a store changes the backup's sync token, the
+/// server sends a text message:
+///
+/// `{"type":"backup_stored","syncToken":"4","updatedAt":"..."}`
+///
+/// A device compares the token with its own to tell another device's upload
+/// from its own. The server closes the socket when the account is deleted or
+/// the backup moves to a new storage key.
+///
+/// GET /api/sync/ws?userId=...&storageKey=...
+pub async fn sync_ws(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    Query(params): Query<SyncParams>,
+    ws: WebSocketUpgrade,
+) -> Result<Response> {
+    let subscription = subscribe(&state, ip, &params).await?;
+
+    Ok(ws.on_upgrade(move |socket| push_changes(state, socket, params, subscription)))
+}
+
+/// Wait for the backup's sync token to move past `sync_token`
+///
+/// `None` once the backup is gone, or the change feed closed.
+async fn next_change(
+    state: &AppState,
+    params: &SyncParams,
+    events: &mut broadcast::Receiver<ChangeEvent>,
+    sync_token: u64,
+) -> Option<(u64, i64)> {
+    loop {
+        match events.recv().await {
+            Ok(event) if event.user_id != params.user_id => continue,
+            Ok(event) if event.kind == ChangeKind::Register => continue,
+            // A store, or anything that may have removed the backup
+            Ok(_) => {}
+            // Missed events may include ours; check
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return None,
+        }
+
+        match lookup(state, params).await {
+            Ok((token, _)) if token == sync_token => continue,
+            Ok(version) => return Some(version),
+            Err(AppError::BackupNotFound) => return None,
+            Err(e) => {
+                tracing::error!("Sync channel lookup failed: {}", e);
+                return None;
+            }
+        }
+    }
+}
+
+/// Run [`next_change`] in its own task, sending each new version
+///
+/// A socket loop that selects on `next_change` directly would drop it, and
+/// the event it had received, whenever a ping or client frame came first.
+/// The task ends once the receiver is dropped.
+fn watch_changes(
+    state: AppState,
+    params: SyncParams,
+    mut events: broadcast::Receiver<ChangeEvent>,
+    mut sync_token: u64,
+) -> mpsc::Receiver<(u64, i64)> {
+    let (tx, rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                change = next_change(&state, &params, &mut events, sync_token) => {
+                    let Some(version) = change else {
+                        break;
+                    };
+                    sync_token = version.0;
+                    if tx.send(version).await.is_err() {
+                        break;
+                    }
+                }
+                _ = tx.closed() => break,
+            }
+        }
+    });
+    rx
+}
+
+async fn push_changes(
+    state: AppState,
+    mut socket: WebSocket,
+    params: SyncParams,
+    subscription: Subscription,
+) {
+    let Subscription {
+        sync_token,
+        events,
+        slot: _slot,
+        ..
+    } = subscription;
+    let mut changes = watch_changes(state, params, events, sync_token);
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    ping.tick().await;
+
+    loop {
+        let (token, updated_at) = tokio::select! {
+            change = changes.recv() => match change {
+                Some(version) => version,
+                None => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Default::default())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        let message = BackupStored {
+            kind: "backup_stored",
+            version: BackupVersion::new(token, updated_at),
+        };
+        let Ok(text) = serde_json::to_string(&message) else {
+            break;
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// `backup-updated` event for a version, with the sync token as its id
+fn backup_updated(sync_token: u64, updated_at: i64) -> Event {
+    Event::default()
+        .event("backup-updated")
+        .id(sync_token.to_string())
+        .json_data(BackupVersion::new(sync_token, updated_at))
+        .unwrap_or_else(|_| Event::default().comment("unserializable version"))
+}
+
+/// Server-Sent Events variant of [`sync_ws`], for clients without WebSockets
+///
+/// Each store that changes the backup's sync token sends a `backup-updated`
+/// event whose id is the new token and whose data is
+/// `{"syncToken":"4","updatedAt":"..."}`. Comment heartbeats keep idle
+/// connections open. When a client reconnects with `Last-Event-ID`, a backup
+/// stored while it was away is sent straight away. The stream ends when the
+/// account is deleted or the backup moves to a new storage key.
+///
+/// GET /api/sync/events?userId=...&storageKey=...
+pub async fn sync_events(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    Query(params): Query<SyncParams>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>> {
+    let Subscription {
+        sync_token,
+        updated_at,
+        mut events,
+        slot,
+    } = subscribe(&state, ip, &params).await?;
+    let last_seen = headers
+        .get(LAST_EVENT_ID)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    let (tx, rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let _slot = slot;
+        if last_seen.is_some_and(|seen| seen != sync_token)
+            && tx
+                .send(Ok(backup_updated(sync_token, updated_at)))
+                .await
+                .is_err()
+        {
+            return;
+        }
+
+        let mut sync_token = sync_token;
+        loop {
+            tokio::select! {
+                change = next_change(&state, &params, &mut events, sync_token) => {
+                    let Some((token, updated_at)) = change else {
+                        break;
+                    };
+                    sync_token = token;
+                    if tx.send(Ok(backup_updated(token, updated_at))).await.is_err() {
+                        break;
+                    }
+                }
+                _ = tx.closed() => break,
+            }
+        }
+    });
+
+    Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
@@ -1,8 +1,17 @@
 use chrono::{DateTime, Utc};
 
-use crate::constants::{ERR_INVALID_TIMESTAMP, MAX_TIMESTAMP_AGE_SECS};
+use crate::Result;
+use crate::constants::{
+    ERR_INVALID_NONCE, ERR_INVALID_TIMESTAMP, MAX_NONCE_LEN, MAX_TIMESTAMP_AGE_SECS, MIN_NONCE_LEN,
+};
+use crate::db::nonces;
 use crate::error::AppError;
+use crate::metrics;
+use crate::models::SecurityEventKind;
+use crate::notifier::AlertKind;
 use crate::security::{validate_timestamp, verify_hmac};
+use crate::security_events;
+use crate::{AppState, ClientIp};
 
 /// Convert Unix timestamp to RFC3339 string, defaulting to now if invalid
 pub fn timestamp_to_rfc3339(timestamp: i64) -> String {
@@ -16,6 +25,8 @@ pub fn timestamp_to_rfc3339(timestamp: i64) -> String {
 pub enum SignedRequestError {
     InvalidSignature,
     InvalidTimestamp,
+    InvalidNonce,
+    ReplayedNonce,
 }
 
 impl From<SignedRequestError> for AppError {
@@ -25,25 +36,134 @@ impl From<SignedRequestError> for AppError {
             SignedRequestError::InvalidTimestamp => {
                 AppError::InvalidInput(ERR_INVALID_TIMESTAMP.to_string())
             }
+            SignedRequestError::InvalidNonce => {
+                AppError::InvalidInput(ERR_INVALID_NONCE.to_string())
+            }
+            SignedRequestError::ReplayedNonce => AppError::ReplayedRequest,
+        }
+    }
+}
+
+/// Signing scheme of a request, from its `sigVersion`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    /// The endpoint's own payload (e.g. just `data`); the default
+    V1,
+    /// [`dailyreps_signing::canonical_payload`] of every field
+    V2,
+}
+
+impl SigVersion {
+    /// Parse `sigVersion`, treating an absent one as v1
+    #[allow(clippy::result_large_err)]
+    pub fn parse(version: Option<u32>) -> Result<Self> {
+        match version {
+            None | Some(1) => Ok(SigVersion::V1),
+            Some(2) => Ok(SigVersion::V2),
+            Some(v) => Err(AppError::InvalidInput(format!(
+                "Unsupported sigVersion {} (expected 1 or 2)",
+                v
+            ))),
         }
     }
 }
 
 /// Verify HMAC signature and timestamp for authenticated requests
-pub fn validate_signed_request(
-    data: &str,
+///
+/// With a `nonce` the signature must cover [`dailyreps_signing::nonce_payload`]
+/// of `data`, and the nonce is spent so the same request can't be replayed
+/// within the timestamp window. Storage errors are returned in the outer
+/// `Result`, so only real rejections reach the caller's failure accounting.
+#[allow(clippy::result_large_err)]
+pub async fn validate_signed_request(
+    state: &AppState,
+    data: impl AsRef<[u8]>,
     signature: &str,
     timestamp: i64,
-    secret: &str,
-) -> Result<(), SignedRequestError> {
-    if !verify_hmac(data, signature, secret) {
+    nonce: Option<&str>,
+    secrets: &[&str],
+) -> Result<std::result::Result<(), SignedRequestError>> {
+    let verified = match nonce {
+        Some(nonce) => verify_hmac(
+            dailyreps_signing::nonce_payload(data.as_ref(), nonce),
+            signature,
+            secrets,
+        ),
+        None => verify_hmac(data, signature, secrets),
+    };
+    if !verified {
         tracing::warn!("Invalid HMAC signature");
-        return Err(SignedRequestError::InvalidSignature);
+        return Ok(Err(SignedRequestError::InvalidSignature));
     }
 
     if !validate_timestamp(timestamp, MAX_TIMESTAMP_AGE_SECS) {
-        return Err(SignedRequestError::InvalidTimestamp);
+        return Ok(Err(SignedRequestError::InvalidTimestamp));
     }
 
-    Ok(())
+    let Some(nonce) = nonce else {
+        return Ok(Ok(()));
+    };
+    if !valid_nonce(nonce) {
+        return Ok(Err(SignedRequestError::InvalidNonce));
+    }
+
+    let db = state.db.clone();
+    let nonce = nonce.to_string();
+    let claimed = state
+        .spawn_db(move || -> Result<bool> {
+            let write_txn = db.begin_write()?;
+            let now = Utc::now().timestamp();
+            let claimed =
+                nonces::claim(&write_txn, &nonce, timestamp + MAX_TIMESTAMP_AGE_SECS, now)?;
+            crate::db::before_commit()?;
+            write_txn.commit()?;
+            Ok(claimed)
+        })
+        .await??;
+    if !claimed {
+        tracing::warn!("Replayed request nonce");
+        return Ok(Err(SignedRequestError::ReplayedNonce));
+    }
+
+    Ok(Ok(()))
+}
+
+/// Whether `nonce` is 16-64 characters of `[A-Za-z0-9_-]`
+fn valid_nonce(nonce: &str) -> bool {
+    (MIN_NONCE_LEN..=MAX_NONCE_LEN).contains(&nonce.len())
+        && nonce
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Count a request rejected by a per-user limit and log it for the abuse report
+pub fn record_rate_limited(state: &AppState, user_id: &str, ip: ClientIp) {
+    state.metrics.incr(metrics::RATE_LIMITED);
+    security_events::record(state, SecurityEventKind::RateLimited, Some(user_id), ip);
+}
+
+/// Count an upload refused as a repeated identical blob and log it for the
+/// abuse report
+pub fn record_duplicate_upload(state: &AppState, user_id: &str, ip: ClientIp) {
+    state.metrics.incr(metrics::DUPLICATE_UPLOADS);
+    security_events::record(state, SecurityEventKind::DuplicateUpload, Some(user_id), ip);
+}
+
+/// Count a failed signed-request check, log it against the claimed user for
+/// the abuse report, and alert operators (throttled)
+pub fn record_signature_failure(state: &AppState, user_id: &str, ip: ClientIp) {
+    state.metrics.incr(metrics::SIGNATURE_FAILURES);
+    security_events::record(
+        state,
+        SecurityEventKind::SignatureFailure,
+        Some(user_id),
+        ip,
+    );
+    state.notifier.alert(
+        AlertKind::SignatureFailures,
+        format!(
+            "{} signed requests rejected since startup",
+            state.metrics.counter(metrics::SIGNATURE_FAILURES)
+        ),
+    );
 }
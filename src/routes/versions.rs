@@ -0,0 +1,85 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use redb::ReadableDatabase;
+use serde::Serialize;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+use crate::AppState;
+use crate::constants::{ERR_INVALID_STORAGE_KEY, ERR_INVALID_USER_ID};
+use crate::db::{tables, versions};
+use crate::error::{AppError, Result};
+use crate::models::{Backup, BackupRecord, User};
+use crate::routes::backup::RetrieveBackupParams;
+use crate::routes::timestamp_to_rfc3339;
+
+/// One kept version of a backup
+#[derive(Debug, Serialize)]
+pub struct BackupVersion {
+    /// Pass as `version` to `GET /api/backup` to fetch it
+    pub version: u64,
+    #[serde(rename = "storedAt")]
+    pub stored_at: String,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupVersionsResponse {
+    /// Newest first; the current backup is not included
+    pub versions: Vec<BackupVersion>,
+}
+
+/// List the earlier versions kept for a backup
+///
+/// Up to `BACKUP_VERSIONS_KEPT` overwritten backups are kept per storage key.
+/// Like `GET /api/backup`, knowing the storage key is the credential.
+pub async fn list_backup_versions(
+    State(state): State<AppState>,
+    Query(params): Query<RetrieveBackupParams>,
+) -> Result<Json<BackupVersionsResponse>> {
+    if !User::validate_id(&params.user_id) {
+        return Err(AppError::InvalidInput(ERR_INVALID_USER_ID.to_string()));
+    }
+
+    if !Backup::validate_storage_key(&params.storage_key) {
+        return Err(AppError::InvalidInput(ERR_INVALID_STORAGE_KEY.to_string()));
+    }
+
+    let db = state.db.clone();
+    let user_id = params.user_id;
+    let storage_key = params.storage_key;
+
+    let kept = state
+        .spawn_db(move || -> Result<Vec<BackupVersion>> {
+            let read_txn = db.begin_read()?;
+            let backups = read_txn.open_table(tables::BACKUPS)?;
+
+            let owned = match backups.get(storage_key.as_str())? {
+                Some(bytes) => {
+                    let (record, _): (BackupRecord, _) =
+                        bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+                    record.user_id == user_id
+                }
+                None => false,
+            };
+            if !owned {
+                return Err(AppError::BackupNotFound);
+            }
+
+            let versions_table = read_txn.open_table(tables::BACKUP_VERSIONS)?;
+            Ok(versions::list(&versions_table, &storage_key)?
+                .into_iter()
+                .map(|(version, record)| BackupVersion {
+                    version,
+                    stored_at: timestamp_to_rfc3339(record.stored_at),
+                    size_bytes: record.encrypted_data.len(),
+                })
+                .collect())
+        })
+        .await??;
+
+    Ok(Json(BackupVersionsResponse { versions: kept }))
+}
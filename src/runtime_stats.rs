@@ -0,0 +1,144 @@
+//! Process resource usage for `/admin/runtime`
+//!
+//! Memory and file-descriptor figures come from `/proc/self` and are `None`
+//! on platforms without it. Allocator stats are only available when built
+//! with the `jemalloc` feature.
+
+use serde::Serialize;
+
+/// Snapshot of process and runtime resource usage
+#[derive(Debug, Serialize)]
+pub struct RuntimeStats {
+    /// Resident set size
+    pub rss_bytes: Option<u64>,
+    /// Peak resident set size since startup
+    pub peak_rss_bytes: Option<u64>,
+    /// OS threads in the process
+    pub threads: Option<u64>,
+    pub open_fds: Option<u64>,
+    /// Soft `RLIMIT_NOFILE`
+    pub fd_limit: Option<u64>,
+    pub tokio: TokioStats,
+    pub allocator: Option<AllocatorStats>,
+}
+
+/// Tokio scheduler figures
+#[derive(Debug, Serialize)]
+pub struct TokioStats {
+    pub workers: usize,
+    pub alive_tasks: usize,
+    /// Tasks waiting in the shared injection queue
+    pub global_queue_depth: usize,
+    /// `spawn_blocking` tasks (all redb work) waiting for a thread; needs
+    /// `RUSTFLAGS="--cfg tokio_unstable"`
+    pub blocking_queue_depth: Option<usize>,
+}
+
+/// Global allocator statistics
+#[derive(Debug, Serialize)]
+pub struct AllocatorStats {
+    pub name: &'static str,
+    /// Bytes allocated by the application
+    pub allocated_bytes: u64,
+    /// Bytes in pages the allocator handed out (>= allocated)
+    pub active_bytes: u64,
+    /// Bytes physically resident in allocator-owned pages
+    pub resident_bytes: u64,
+    /// Bytes kept mapped for reuse rather than returned to the OS
+    pub retained_bytes: u64,
+}
+
+impl RuntimeStats {
+    /// Gather current figures; must be called from within the Tokio runtime
+    pub fn collect() -> Self {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let limits = std::fs::read_to_string("/proc/self/limits").unwrap_or_default();
+
+        Self {
+            rss_bytes: status_kb(&status, "VmRSS").map(|kb| kb * 1024),
+            peak_rss_bytes: status_kb(&status, "VmHWM").map(|kb| kb * 1024),
+            threads: status_kb(&status, "Threads"),
+            open_fds: std::fs::read_dir("/proc/self/fd")
+                .ok()
+                .map(|entries| entries.count() as u64),
+            fd_limit: open_files_limit(&limits),
+            tokio: TokioStats::collect(),
+            allocator: allocator_stats(),
+        }
+    }
+}
+
+impl TokioStats {
+    fn collect() -> Self {
+        let metrics = tokio::runtime::Handle::current().metrics();
+
+        #[cfg(tokio_unstable)]
+        let blocking_queue_depth = Some(metrics.blocking_queue_depth());
+        #[cfg(not(tokio_unstable))]
+        let blocking_queue_depth = None;
+
+        Self {
+            workers: metrics.num_workers(),
+            alive_tasks: metrics.num_alive_tasks(),
+            global_queue_depth: metrics.global_queue_depth(),
+            blocking_queue_depth,
+        }
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+fn allocator_stats() -> Option<AllocatorStats> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // Stats are cached until the epoch advances
+    epoch::advance().ok()?;
+    Some(AllocatorStats {
+        name: "jemalloc",
+        allocated_bytes: stats::allocated::read().ok()? as u64,
+        active_bytes: stats::active::read().ok()? as u64,
+        resident_bytes: stats::resident::read().ok()? as u64,
+        retained_bytes: stats::retained::read().ok()? as u64,
+    })
+}
+
+#[cfg(not(feature = "jemalloc"))]
+fn allocator_stats() -> Option<AllocatorStats> {
+    None
+}
+
+/// Numeric value of a `/proc/self/status` line such as `VmRSS:   1234 kB`
+fn status_kb(status: &str, field: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+}
+
+/// Soft limit from the `Max open files` row of `/proc/self/limits`
+fn open_files_limit(limits: &str) -> Option<u64> {
+    limits
+        .lines()
+        .find_map(|line| line.strip_prefix("Max open files"))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|soft| soft.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_files() {
+        let status = "Name:\tdailyreps\nVmHWM:\t   20480 kB\nVmRSS:\t   10240 kB\nThreads:\t12\n";
+        assert_eq!(status_kb(status, "VmRSS"), Some(10240));
+        assert_eq!(status_kb(status, "VmHWM"), Some(20480));
+        assert_eq!(status_kb(status, "Threads"), Some(12));
+        assert_eq!(status_kb(status, "VmSwap"), None);
+
+        let limits = "Limit                     Soft Limit           Hard Limit           Units\n\
+                      Max open files            1024                 524288               files\n";
+        assert_eq!(open_files_limit(limits), Some(1024));
+        assert_eq!(open_files_limit(""), None);
+    }
+}
@@ -1,7 +1,4 @@
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
-
-type HmacSha256 = Hmac<Sha256>;
+use std::net::IpAddr;
 
 /// Verify HMAC-SHA256 signature
 ///
@@ -11,31 +8,16 @@ type HmacSha256 = Hmac<Sha256>;
 /// # Arguments
 /// * `data` - The data that was signed
 /// * `signature` - The hex-encoded HMAC signature
-/// * `secret` - The shared secret key (from environment)
-pub fn verify_hmac(data: &str, signature: &str, secret: &str) -> bool {
-    // Create HMAC instance with secret key
-    let mut mac = match HmacSha256::new_from_slice(secret.as_bytes()) {
-        Ok(m) => m,
-        Err(_) => {
-            tracing::error!("Failed to create HMAC instance");
-            return false;
-        }
-    };
-
-    // Update with data
-    mac.update(data.as_bytes());
-
-    // Decode hex signature
-    let sig_bytes = match hex::decode(signature) {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            tracing::warn!("Invalid hex signature format");
-            return false;
-        }
-    };
-
-    // Verify signature
-    mac.verify_slice(&sig_bytes).is_ok()
+/// * `secrets` - The accepted shared secrets (from environment); during a key
+///   rotation a signature by any of them is valid
+///
+/// Canonicalization lives in `dailyreps-signing` so clients sign with the
+/// exact same code.
+pub fn verify_hmac(data: impl AsRef<[u8]>, signature: &str, secrets: &[&str]) -> bool {
+    let data = data.as_ref();
+    secrets
+        .iter()
+        .any(|secret| dailyreps_signing::verify(data, signature, secret.as_bytes()))
 }
 
 /// Validate timestamp is within acceptable range
@@ -61,9 +43,100 @@ pub fn validate_timestamp(timestamp: i64, max_age_secs: i64) -> bool {
     true
 }
 
+/// The network a client is treated as: its IPv4 address, or its IPv6 /64,
+/// since one host usually holds a whole prefix
+pub fn client_network(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => {
+            let segments = v6.segments();
+            format!(
+                "{:x}:{:x}:{:x}:{:x}::/64",
+                segments[0], segments[1], segments[2], segments[3]
+            )
+        }
+    }
+}
+
+/// Keyed hash of a client's network, for per-IP records
+///
+/// Keyed with the app secret so stored hashes can't be reversed by hashing
+/// every IPv4 address.
+pub fn hash_client_ip(ip: IpAddr, secret: &str) -> String {
+    dailyreps_signing::sign(client_network(ip).as_bytes(), secret.as_bytes())
+}
+
+/// Keyed hash of a user ID, for admin listings
+///
+/// User IDs are unsalted hashes of usernames, so listing them would let
+/// anyone holding the list confirm a guessed username. Keyed with the app
+/// secret the pseudonym is stable for correlating reports but can't be
+/// reversed that way; it changes when the app secret is rotated.
+pub fn pepper_user_id(user_id: &str, secret: &str) -> String {
+    dailyreps_signing::sign(user_id.as_bytes(), secret.as_bytes())
+}
+
+/// Random hex characters in front of a sealed cursor
+const CURSOR_NONCE_LEN: usize = 32;
+
+/// Hex keystream for a cursor nonce, as long as a user ID
+fn cursor_keystream(nonce: &str, secret: &str) -> String {
+    dailyreps_signing::sign(format!("cursor:{}", nonce).as_bytes(), secret.as_bytes())
+}
+
+/// Add (or with `direction = -1`, subtract) `keystream` to `hex` digit by
+/// digit, mod 16
+fn shift_hex(hex: &str, keystream: &str, direction: i32) -> Option<String> {
+    hex.chars()
+        .zip(keystream.chars())
+        .map(|(c, k)| {
+            let (c, k) = (c.to_digit(16)? as i32, k.to_digit(16)? as i32);
+            char::from_digit((c + direction * k).rem_euclid(16) as u32, 16)
+        })
+        .collect()
+}
+
+/// Encrypt a raw user ID into an opaque pagination cursor
+///
+/// A page cursor has to name a raw ID to seek from, but handing that out
+/// would undo [`pepper_user_id`]. The ID is encrypted with a keystream
+/// derived from the app secret and a random nonce, then authenticated, so
+/// the cursor reveals nothing and can't be forged; [`open_cursor`] reverses
+/// it. Cursors stop working when the app secret is rotated.
+pub fn seal_cursor(user_id: &str, secret: &str) -> Result<String, getrandom::Error> {
+    let mut bytes = [0u8; CURSOR_NONCE_LEN / 2];
+    getrandom::getrandom(&mut bytes)?;
+    let nonce: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    let sealed = shift_hex(user_id, &cursor_keystream(&nonce, secret), 1).unwrap_or_default();
+    let body = format!("{}{}", nonce, sealed);
+    let tag = dailyreps_signing::sign(body.as_bytes(), secret.as_bytes());
+    Ok(format!("{}{}", body, tag))
+}
+
+/// The raw user ID inside a cursor from [`seal_cursor`], if it is genuine
+pub fn open_cursor(cursor: &str, secret: &str) -> Option<String> {
+    let tag_start = cursor.len().checked_sub(64)?;
+    let (body, tag) = (cursor.get(..tag_start)?, cursor.get(tag_start..)?);
+    if !dailyreps_signing::verify(body.as_bytes(), tag, secret.as_bytes()) {
+        return None;
+    }
+
+    let (nonce, sealed) = (body.get(..CURSOR_NONCE_LEN)?, body.get(CURSOR_NONCE_LEN..)?);
+    let keystream = cursor_keystream(nonce, secret);
+    if sealed.len() > keystream.len() {
+        return None;
+    }
+    shift_hex(sealed, &keystream, -1)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    type HmacSha256 = Hmac<Sha256>;
 
     #[test]
     fn test_verify_hmac_valid() {
@@ -77,7 +150,7 @@ mod tests {
         let signature = hex::encode(result.into_bytes());
 
         // Should verify successfully
-        assert!(verify_hmac(data, &signature, secret));
+        assert!(verify_hmac(data, &signature, &[secret]));
     }
 
     #[test]
@@ -86,7 +159,7 @@ mod tests {
         let data = "test data";
         let wrong_signature = "0".repeat(64);
 
-        assert!(!verify_hmac(data, &wrong_signature, secret));
+        assert!(!verify_hmac(data, &wrong_signature, &[secret]));
     }
 
     #[test]
@@ -102,7 +175,49 @@ mod tests {
         let signature = hex::encode(result.into_bytes());
 
         // Verify with wrong secret should fail
-        assert!(!verify_hmac(data, &signature, wrong_secret));
+        assert!(!verify_hmac(data, &signature, &[wrong_secret]));
+    }
+
+    #[test]
+    fn test_verify_hmac_any_accepted_secret() {
+        let data = "test data";
+        let mut mac = HmacSha256::new_from_slice(b"old-secret").unwrap();
+        mac.update(data.as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        assert!(verify_hmac(data, &signature, &["new-secret", "old-secret"]));
+        assert!(!verify_hmac(data, &signature, &["new-secret"]));
+        assert!(!verify_hmac(data, &signature, &[]));
+    }
+
+    #[test]
+    fn test_pepper_user_id() {
+        let user_id = dailyreps_signing::derive_user_id("alice");
+        let peppered = pepper_user_id(&user_id, "secret");
+        assert_eq!(peppered.len(), 64);
+        assert_ne!(peppered, user_id);
+        assert_eq!(peppered, pepper_user_id(&user_id, "secret"));
+        assert_ne!(peppered, pepper_user_id(&user_id, "other-secret"));
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let user_id = dailyreps_signing::derive_user_id("alice");
+        let cursor = seal_cursor(&user_id, "secret").unwrap();
+        assert!(!cursor.contains(&user_id));
+        assert_ne!(cursor, seal_cursor(&user_id, "secret").unwrap());
+        assert_eq!(open_cursor(&cursor, "secret"), Some(user_id));
+
+        // Forged, altered or re-keyed cursors are refused
+        assert_eq!(open_cursor(&cursor, "other-secret"), None);
+        let mut altered = cursor.clone().into_bytes();
+        altered[40] = if altered[40] == b'0' { b'1' } else { b'0' };
+        assert_eq!(
+            open_cursor(&String::from_utf8(altered).unwrap(), "secret"),
+            None
+        );
+        assert_eq!(open_cursor("not-a-cursor", "secret"), None);
+        assert_eq!(open_cursor("", "secret"), None);
     }
 
     #[test]
@@ -124,4 +239,16 @@ mod tests {
         let future = chrono::Utc::now().timestamp() + 400;
         assert!(!validate_timestamp(future, 300));
     }
+
+    #[test]
+    fn test_hash_client_ip_groups_ipv6_by_prefix() {
+        let hash = |ip: &str| hash_client_ip(ip.parse().unwrap(), "secret");
+        assert_eq!(hash("2001:db8:1:2::1"), hash("2001:db8:1:2:ffff::9"));
+        assert_ne!(hash("2001:db8:1:2::1"), hash("2001:db8:1:3::1"));
+        assert_ne!(hash("192.0.2.1"), hash("192.0.2.2"));
+        assert_ne!(
+            hash("192.0.2.1"),
+            hash_client_ip("192.0.2.1".parse().unwrap(), "other")
+        );
+    }
 }
@@ -0,0 +1,412 @@
+//! Rejected-request log behind the admin abuse report
+//!
+//! Rate-limit hits and signature failures are collected in a bounded
+//! in-memory [`SecurityEventBuffer`] and written to the `security_events`
+//! table in batches, keyed by a sequence number, then pruned after
+//! [`SECURITY_EVENT_RETENTION_DAYS`]. A flood of rejected requests therefore
+//! costs one write transaction per flush rather than one per request; events
+//! beyond [`MAX_BUFFERED_SECURITY_EVENTS`] are dropped and counted. Only the
+//! claimed user ID, a keyed hash of the client's network and, with GeoIP
+//! configured, its country are kept; the server does not record IP addresses.
+
+use chrono::Utc;
+use redb::{ReadableDatabase, ReadableTable};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::constants::{MAX_BUFFERED_SECURITY_EVENTS, SECURITY_EVENT_RETENTION_DAYS};
+use crate::db::{Db, tables};
+use crate::error::Result;
+use crate::models::{SecurityEventKind, SecurityEventRecord, User};
+use crate::security::hash_client_ip;
+use crate::{AppState, ClientIp, metrics};
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+/// Rejections attributed to one claimed user ID
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct OffenderSummary {
+    /// `None` groups requests whose user ID was missing or malformed
+    pub user_id: Option<String>,
+    pub rate_limit_hits: u64,
+    pub signature_failures: u64,
+    pub duplicate_uploads: u64,
+    /// Weighted sum used for ordering (see [`SecurityEventKind::severity`])
+    pub severity: u64,
+    pub last_seen_at: i64,
+}
+
+/// Rejections from one country
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CountrySummary {
+    /// ISO country code; `None` groups events without a GeoIP match
+    pub country: Option<String>,
+    pub events: u64,
+    pub severity: u64,
+}
+
+/// Rejections from one client network
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NetworkSummary {
+    /// Keyed hash of the network; `None` groups events without a client IP
+    pub network: Option<String>,
+    pub events: u64,
+    pub severity: u64,
+    pub last_seen_at: i64,
+}
+
+/// Events waiting for the next flush
+#[derive(Debug, Default)]
+pub struct SecurityEventBuffer {
+    events: Mutex<Vec<SecurityEventRecord>>,
+}
+
+impl SecurityEventBuffer {
+    /// Queue `event`, or return `false` if the buffer is full
+    pub fn push(&self, event: SecurityEventRecord) -> bool {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        if events.len() >= MAX_BUFFERED_SECURITY_EVENTS {
+            return false;
+        }
+        events.push(event);
+        true
+    }
+
+    /// Take everything queued so far
+    pub fn take(&self) -> Vec<SecurityEventRecord> {
+        std::mem::take(&mut *self.events.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Log a rejected request for the next flush
+///
+/// Called from error paths, so it never fails or delays the response; when
+/// the buffer is full the event is dropped and counted.
+pub fn record(state: &AppState, kind: SecurityEventKind, user_id: Option<&str>, ip: ClientIp) {
+    let event = SecurityEventRecord {
+        at: Utc::now().timestamp(),
+        kind,
+        user_id: user_id
+            .filter(|id| User::validate_id(id))
+            .map(str::to_string),
+        country: state
+            .geoip
+            .as_ref()
+            .zip(ip.0)
+            .and_then(|(geoip, ip)| geoip.country(ip)),
+        network: ip
+            .0
+            .map(|ip| hash_client_ip(ip, state.config().app_secret_key())),
+    };
+
+    if !state.security_events.push(event) {
+        state.metrics.incr(metrics::SECURITY_EVENTS_DROPPED);
+    }
+}
+
+/// Write the buffered events in one transaction, returning how many
+pub async fn flush(state: &AppState) -> Result<usize> {
+    let events = state.security_events.take();
+    if events.is_empty() {
+        return Ok(0);
+    }
+
+    let db = state.db.clone();
+    let count = events.len();
+    state.spawn_db(move || append(&db, &events)).await??;
+
+    Ok(count)
+}
+
+/// Flush the buffered events every `interval`
+pub fn spawn(state: AppState, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = flush(&state).await {
+                tracing::error!("Failed to record security events: {}", e);
+            }
+        }
+    })
+}
+
+/// Append `batch` and drop events past retention, in one transaction
+#[allow(clippy::result_large_err)]
+pub fn append(db: &Db, batch: &[SecurityEventRecord]) -> Result<()> {
+    let cutoff = Utc::now().timestamp() - SECURITY_EVENT_RETENTION_DAYS * 86400;
+
+    let write_txn = db.begin_write()?;
+    {
+        let mut events = write_txn.open_table(tables::SECURITY_EVENTS)?;
+        let next = events.last()?.map(|(k, _)| k.value() + 1).unwrap_or(0);
+        for (seq, event) in (next..).zip(batch) {
+            let bytes = bincode::serde::encode_to_vec(event, BINCODE_CONFIG)?;
+            events.insert(seq, bytes.as_slice())?;
+        }
+
+        // Keys are in arrival order, so expired events are a prefix
+        let mut expired = Vec::new();
+        for entry in events.iter()? {
+            let (key, bytes) = entry?;
+            let (old, _): (SecurityEventRecord, _) =
+                bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+            if old.at >= cutoff {
+                break;
+            }
+            expired.push(key.value());
+        }
+        for key in expired {
+            events.remove(key)?;
+        }
+    }
+    crate::db::before_commit()?;
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+/// Per-user rejection counts since `since`, most severe first
+#[allow(clippy::result_large_err)]
+pub fn top_offenders(db: &Db, since: i64, limit: usize) -> Result<Vec<OffenderSummary>> {
+    let read_txn = db.begin_read()?;
+    let events = read_txn.open_table(tables::SECURITY_EVENTS)?;
+
+    let mut by_user: HashMap<Option<String>, OffenderSummary> = HashMap::new();
+    for entry in events.iter()? {
+        let (_, bytes) = entry?;
+        let (event, _): (SecurityEventRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+        if event.at < since {
+            continue;
+        }
+
+        let summary = by_user
+            .entry(event.user_id.clone())
+            .or_insert_with(|| OffenderSummary {
+                user_id: event.user_id,
+                ..OffenderSummary::default()
+            });
+        match event.kind {
+            SecurityEventKind::RateLimited => summary.rate_limit_hits += 1,
+            SecurityEventKind::SignatureFailure => summary.signature_failures += 1,
+            SecurityEventKind::DuplicateUpload => summary.duplicate_uploads += 1,
+        }
+        summary.severity += event.kind.severity();
+        summary.last_seen_at = summary.last_seen_at.max(event.at);
+    }
+
+    let mut offenders: Vec<_> = by_user.into_values().collect();
+    offenders.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then(b.last_seen_at.cmp(&a.last_seen_at))
+    });
+    offenders.truncate(limit);
+
+    Ok(offenders)
+}
+
+/// Per-network rejection counts since `since`, most severe first
+#[allow(clippy::result_large_err)]
+pub fn top_networks(db: &Db, since: i64, limit: usize) -> Result<Vec<NetworkSummary>> {
+    let read_txn = db.begin_read()?;
+    let events = read_txn.open_table(tables::SECURITY_EVENTS)?;
+
+    let mut by_network: HashMap<Option<String>, NetworkSummary> = HashMap::new();
+    for entry in events.iter()? {
+        let (_, bytes) = entry?;
+        let (event, _): (SecurityEventRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+        if event.at < since {
+            continue;
+        }
+
+        let summary = by_network
+            .entry(event.network.clone())
+            .or_insert_with(|| NetworkSummary {
+                network: event.network,
+                ..NetworkSummary::default()
+            });
+        summary.events += 1;
+        summary.severity += event.kind.severity();
+        summary.last_seen_at = summary.last_seen_at.max(event.at);
+    }
+
+    let mut networks: Vec<_> = by_network.into_values().collect();
+    networks.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then(b.last_seen_at.cmp(&a.last_seen_at))
+    });
+    networks.truncate(limit);
+
+    Ok(networks)
+}
+
+/// Per-country rejection counts since `since`, most severe first
+#[allow(clippy::result_large_err)]
+pub fn top_countries(db: &Db, since: i64, limit: usize) -> Result<Vec<CountrySummary>> {
+    let read_txn = db.begin_read()?;
+    let events = read_txn.open_table(tables::SECURITY_EVENTS)?;
+
+    let mut by_country: HashMap<Option<String>, CountrySummary> = HashMap::new();
+    for entry in events.iter()? {
+        let (_, bytes) = entry?;
+        let (event, _): (SecurityEventRecord, _) =
+            bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG)?;
+        if event.at < since {
+            continue;
+        }
+
+        let summary = by_country
+            .entry(event.country.clone())
+            .or_insert_with(|| CountrySummary {
+                country: event.country,
+                ..CountrySummary::default()
+            });
+        summary.events += 1;
+        summary.severity += event.kind.severity();
+    }
+
+    let mut countries: Vec<_> = by_country.into_values().collect();
+    countries.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.country.cmp(&b.country)));
+    countries.truncate(limit);
+
+    Ok(countries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+
+    fn event(at: i64, kind: SecurityEventKind, user: &str) -> SecurityEventRecord {
+        SecurityEventRecord {
+            at,
+            kind,
+            user_id: Some(user.repeat(64)),
+            country: None,
+            network: None,
+        }
+    }
+
+    #[test]
+    fn test_top_offenders_ranked_by_severity() {
+        let db = open_in_memory_database().unwrap();
+        let now = Utc::now().timestamp();
+
+        // a: 3 rate-limit hits (severity 3); b: 1 signature failure (severity 5)
+        let rate_limited = event(now, SecurityEventKind::RateLimited, "a");
+        append(&db, &vec![rate_limited; 3]).unwrap();
+        append(
+            &db,
+            &[
+                event(now, SecurityEventKind::SignatureFailure, "b"),
+                // Outside the window
+                event(now - 7200, SecurityEventKind::SignatureFailure, "a"),
+            ],
+        )
+        .unwrap();
+
+        let top = top_offenders(&db, now - 3600, 10).unwrap();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].user_id, Some("b".repeat(64)));
+        assert_eq!(top[0].severity, 5);
+        assert_eq!(top[1].rate_limit_hits, 3);
+        assert_eq!(top[1].signature_failures, 0);
+
+        assert_eq!(top_offenders(&db, now - 3600, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_top_countries() {
+        let db = open_in_memory_database().unwrap();
+        let now = Utc::now().timestamp();
+        let from = |country: Option<&str>, kind| SecurityEventRecord {
+            country: country.map(str::to_string),
+            ..event(now, kind, "a")
+        };
+
+        append(
+            &db,
+            &[
+                from(Some("NL"), SecurityEventKind::RateLimited),
+                from(Some("NL"), SecurityEventKind::RateLimited),
+                from(Some("US"), SecurityEventKind::SignatureFailure),
+                from(None, SecurityEventKind::RateLimited),
+            ],
+        )
+        .unwrap();
+
+        let top = top_countries(&db, now - 3600, 10).unwrap();
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[0].country.as_deref(), Some("US"));
+        assert_eq!(top[0].severity, 5);
+        assert_eq!(top[1].country.as_deref(), Some("NL"));
+        assert_eq!(top[1].events, 2);
+        assert_eq!(top[2].country, None);
+    }
+
+    #[test]
+    fn test_top_networks() {
+        let db = open_in_memory_database().unwrap();
+        let now = Utc::now().timestamp();
+        let from = |network: Option<&str>, kind, user| SecurityEventRecord {
+            network: network.map(str::to_string),
+            ..event(now, kind, user)
+        };
+
+        // One network rotating user IDs still adds up
+        append(
+            &db,
+            &[
+                from(Some("n1"), SecurityEventKind::SignatureFailure, "a"),
+                from(Some("n1"), SecurityEventKind::SignatureFailure, "b"),
+                from(Some("n2"), SecurityEventKind::SignatureFailure, "c"),
+                from(None, SecurityEventKind::RateLimited, "d"),
+            ],
+        )
+        .unwrap();
+
+        let top = top_networks(&db, now - 3600, 10).unwrap();
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[0].network.as_deref(), Some("n1"));
+        assert_eq!(top[0].events, 2);
+        assert_eq!(top[0].severity, 10);
+        assert_eq!(top[1].network.as_deref(), Some("n2"));
+        assert_eq!(top[2].network, None);
+    }
+
+    #[test]
+    fn test_append_prunes_expired_events() {
+        let db = open_in_memory_database().unwrap();
+        let now = Utc::now().timestamp();
+        let expired = now - (SECURITY_EVENT_RETENTION_DAYS + 1) * 86400;
+
+        append(&db, &[event(expired, SecurityEventKind::RateLimited, "a")]).unwrap();
+        append(&db, &[event(now, SecurityEventKind::RateLimited, "b")]).unwrap();
+
+        let all = top_offenders(&db, i64::MIN, 10).unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].user_id, Some("b".repeat(64)));
+    }
+
+    #[test]
+    fn test_buffer_is_bounded() {
+        let buffer = SecurityEventBuffer::default();
+        let now = Utc::now().timestamp();
+        for _ in 0..MAX_BUFFERED_SECURITY_EVENTS {
+            assert!(buffer.push(event(now, SecurityEventKind::RateLimited, "a")));
+        }
+        assert!(!buffer.push(event(now, SecurityEventKind::RateLimited, "a")));
+
+        assert_eq!(buffer.take().len(), MAX_BUFFERED_SECURITY_EVENTS);
+        assert!(buffer.push(event(now, SecurityEventKind::RateLimited, "a")));
+    }
+}
@@ -0,0 +1,226 @@
+//! Synthetic demo data
+//!
+//! Populates a database with users and encrypted-looking backups for demoing
+//! the admin UI and load-testing retrieval. Output is fully determined by the
+//! RNG seed, apart from timestamps, which are spread over the days before
+//! `now`.
+
+use crate::db::{Db, tables};
+use crate::error::Result;
+use crate::models::{BackupRecord, UserRecord};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
+
+const BINCODE_CONFIG: bincode::config::Configuration = bincode::config::standard();
+
+/// Password every seeded user shares (usernames are `demo-<seed>-<n>`)
+pub const DEMO_PASSWORD: &str = "demo-password";
+
+/// Seconds in a day
+const DAY_SECS: i64 = 86_400;
+
+/// What to generate
+#[derive(Debug, Clone)]
+pub struct SeedOptions {
+    /// Number of users to create
+    pub users: u32,
+    /// RNG seed; the same seed always produces the same users and data
+    pub seed: u64,
+    /// Backups are created within this many days before now
+    pub history_days: u32,
+    /// Smallest backup payload in bytes (before base64)
+    pub min_backup_bytes: usize,
+    /// Largest backup payload in bytes (before base64)
+    pub max_backup_bytes: usize,
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        Self {
+            users: 100,
+            seed: 42,
+            history_days: 90,
+            // Real DailyReps backups are ~300KB
+            min_backup_bytes: 20 * 1024,
+            max_backup_bytes: 400 * 1024,
+        }
+    }
+}
+
+impl SeedOptions {
+    /// Username of the `n`th seeded user
+    pub fn username(&self, n: u32) -> String {
+        format!("demo-{}-{}", self.seed, n)
+    }
+
+    /// `(user_id, storage_key)` of the `n`th seeded user
+    pub fn credentials(&self, n: u32) -> (String, String) {
+        let user_id = dailyreps_signing::derive_user_id(&self.username(n));
+        let storage_key = dailyreps_signing::derive_storage_key(&user_id, DEMO_PASSWORD);
+        (user_id, storage_key)
+    }
+}
+
+/// Totals written by [`seed_database`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeedSummary {
+    pub users: u32,
+    pub total_bytes: u64,
+}
+
+/// Write the demo users and backups in a single transaction
+///
+/// Re-running with the same seed overwrites the same records.
+pub fn seed_database(db: &Db, options: &SeedOptions) -> Result<SeedSummary> {
+    let mut rng = SplitMix64(options.seed);
+    let now = Utc::now().timestamp();
+    let mut summary = SeedSummary::default();
+
+    let write_txn = db.begin_write()?;
+    {
+        let mut users = write_txn.open_table(tables::USERS)?;
+        let mut backups = write_txn.open_table(tables::BACKUPS)?;
+        let mut user_backups = write_txn.open_table(tables::USER_BACKUPS)?;
+
+        for n in 0..options.users {
+            let (user_id, storage_key) = options.credentials(n);
+
+            let history_secs = i64::from(options.history_days.max(1)) * DAY_SECS;
+            let created_at = now - rng.below(history_secs as u64) as i64;
+            let updated_at = created_at + rng.below((now - created_at) as u64 + 1) as i64;
+
+            let size = options.min_backup_bytes
+                + rng.below((options.max_backup_bytes - options.min_backup_bytes) as u64 + 1)
+                    as usize;
+            let encrypted_data = BASE64_STANDARD.encode(rng.bytes(size));
+            summary.total_bytes += encrypted_data.len() as u64;
+
+            let user = UserRecord { created_at };
+            let user_bytes = bincode::serde::encode_to_vec(&user, BINCODE_CONFIG)?;
+            users.insert(user_id.as_str(), user_bytes.as_slice())?;
+
+            let backup = BackupRecord::new(user_id.clone(), encrypted_data, created_at, updated_at);
+            let backup_bytes = bincode::serde::encode_to_vec(&backup, BINCODE_CONFIG)?;
+            backups.insert(storage_key.as_str(), backup_bytes.as_slice())?;
+
+            let keys_bytes = bincode::serde::encode_to_vec(vec![storage_key], BINCODE_CONFIG)?;
+            user_backups.insert(user_id.as_str(), keys_bytes.as_slice())?;
+
+            crate::db::bump_daily_stats(&write_txn, created_at, |s| s.registrations += 1)?;
+
+            summary.users += 1;
+        }
+    }
+    write_txn.commit()?;
+
+    tracing::info!(
+        "Seeded {} users ({} bytes of backups) from seed {}",
+        summary.users,
+        summary.total_bytes,
+        options.seed
+    );
+
+    Ok(summary)
+}
+
+/// SplitMix64: small, fast, and stable across releases (unlike `StdRng`),
+/// so a seed means the same data forever
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform-ish value in `0..bound` (`0` when `bound` is 0)
+    fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 { 0 } else { self.next() % bound }
+    }
+
+    /// Random bytes, which look like ciphertext (full entropy)
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(len + 8);
+        while out.len() < len {
+            out.extend_from_slice(&self.next().to_le_bytes());
+        }
+        out.truncate(len);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_in_memory_database;
+    use redb::{ReadableDatabase, ReadableTableMetadata};
+
+    fn small_options(seed: u64) -> SeedOptions {
+        SeedOptions {
+            users: 5,
+            seed,
+            min_backup_bytes: 64,
+            max_backup_bytes: 256,
+            ..SeedOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_seed_database_writes_all_tables() {
+        let db = open_in_memory_database().unwrap();
+        let summary = seed_database(&db, &small_options(7)).unwrap();
+        assert_eq!(summary.users, 5);
+
+        let read_txn = db.begin_read().unwrap();
+        assert_eq!(
+            read_txn.open_table(tables::USERS).unwrap().len().unwrap(),
+            5
+        );
+        assert_eq!(
+            read_txn.open_table(tables::BACKUPS).unwrap().len().unwrap(),
+            5
+        );
+        assert_eq!(
+            read_txn
+                .open_table(tables::USER_BACKUPS)
+                .unwrap()
+                .len()
+                .unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_seed_is_deterministic() {
+        let a = open_in_memory_database().unwrap();
+        let b = open_in_memory_database().unwrap();
+        let options = small_options(7);
+
+        assert_eq!(
+            seed_database(&a, &options).unwrap(),
+            seed_database(&b, &options).unwrap()
+        );
+
+        let (_, storage_key) = options.credentials(3);
+        let data = |db: &Db| {
+            let txn = db.begin_read().unwrap();
+            let table = txn.open_table(tables::BACKUPS).unwrap();
+            let bytes = table.get(storage_key.as_str()).unwrap().unwrap();
+            let (record, _): (BackupRecord, _) =
+                bincode::serde::decode_from_slice(bytes.value(), BINCODE_CONFIG).unwrap();
+            record.encrypted_data
+        };
+        assert_eq!(data(&a), data(&b));
+    }
+
+    #[test]
+    fn test_different_seeds_produce_different_users() {
+        assert_ne!(
+            small_options(1).credentials(0),
+            small_options(2).credentials(0)
+        );
+    }
+}
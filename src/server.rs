@@ -0,0 +1,159 @@
+//! HTTP connection handling
+//!
+//! `axum::serve` has no knobs, so the binary serves connections itself with
+//! hyper, tuned from config:
+//!
+//! - `HTTP_KEEP_ALIVE_SECS`: how long an idle HTTP/1.1 connection is kept
+//!   open for the next request (0 closes it after each response). HTTP/2
+//!   connections are pinged at the same interval and dropped if the ping
+//!   goes unanswered, which clears out phones that vanished mid-connection.
+//! - `MAX_CONNECTIONS`: open connections at once (0 = unlimited). Further
+//!   connections wait in the kernel's accept queue until one closes. With
+//!   native TLS, connections still in their handshake count too.
+//! - `HTTP2`: also speak HTTP/2 (h2c prior knowledge in plain text, ALPN
+//!   `h2` with native TLS), letting a client multiplex requests over one
+//!   connection.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request};
+use axum::serve::Listener;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tower::Service;
+
+use crate::config::Config;
+
+/// Time allowed for a request's headers when keep-alive is off (hyper's
+/// default), so a connection can't be held open by never finishing them
+const HEADER_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connection settings from config
+#[derive(Debug, Clone, Copy)]
+pub struct ServerOptions {
+    /// Idle time before a keep-alive connection is closed (`None` = close
+    /// after each response)
+    pub keep_alive: Option<Duration>,
+    /// Open connections at once (0 = unlimited)
+    pub max_connections: usize,
+    pub http2: bool,
+}
+
+impl ServerOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            keep_alive: (config.http_keep_alive_secs > 0)
+                .then(|| Duration::from_secs(config.http_keep_alive_secs)),
+            max_connections: config.max_connections,
+            http2: config.http2,
+        }
+    }
+
+    /// These options for serving a listener that enforces `max_connections`
+    /// itself (see [`crate::tls::TlsListener`]), so connections aren't
+    /// counted twice
+    pub fn without_connection_limit(self) -> Self {
+        Self {
+            max_connections: 0,
+            ..self
+        }
+    }
+
+    /// Idle keep-alive time doubles as the limit for reading each request's
+    /// headers, since hyper counts both from the same timer
+    fn header_read_timeout(&self) -> Duration {
+        self.keep_alive.unwrap_or(HEADER_READ_TIMEOUT)
+    }
+}
+
+/// The `MAX_CONNECTIONS` budget; each open connection holds one slot
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionSlots(Option<Arc<Semaphore>>);
+
+impl ConnectionSlots {
+    /// `max` slots, or unlimited if 0
+    pub(crate) fn new(max: usize) -> Self {
+        Self((max > 0).then(|| Arc::new(Semaphore::new(max))))
+    }
+
+    /// Wait for a free slot (`None` when unlimited), held until dropped
+    pub(crate) async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.0 {
+            Some(slots) => Some(
+                slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("connection semaphore is never closed"),
+            ),
+            None => None,
+        }
+    }
+}
+
+/// Serve `router` on connections from `listener` until the process exits
+///
+/// Handlers see the peer address as `ConnectInfo<SocketAddr>`, as with
+/// `into_make_service_with_connect_info`.
+pub async fn serve<L>(mut listener: L, router: Router, options: ServerOptions)
+where
+    L: Listener<Addr = SocketAddr>,
+{
+    let slots = ConnectionSlots::new(options.max_connections);
+
+    loop {
+        // Wait for a free slot before accepting, so the backlog queues up
+        // in the kernel rather than as idle tasks here
+        let slot = slots.acquire().await;
+
+        let (io, addr) = listener.accept().await;
+        let router = router.clone();
+
+        tokio::spawn(async move {
+            let service = service_fn(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(addr));
+                router.clone().call(request.map(Body::new))
+            });
+
+            let io = TokioIo::new(io);
+            let result = if options.http2 {
+                let mut builder = auto::Builder::new(TokioExecutor::new());
+                builder
+                    .http1()
+                    .timer(TokioTimer::new())
+                    .keep_alive(options.keep_alive.is_some())
+                    .header_read_timeout(options.header_read_timeout());
+                builder
+                    .http2()
+                    .timer(TokioTimer::new())
+                    .keep_alive_interval(options.keep_alive)
+                    // CONNECT protocol needed for HTTP/2 websockets
+                    .enable_connect_protocol();
+                builder.serve_connection_with_upgrades(io, service).await
+            } else {
+                // auto::Builder ignores http1_only() for upgradable
+                // connections, so HTTP/1-only servers use hyper's own builder
+                hyper::server::conn::http1::Builder::new()
+                    .timer(TokioTimer::new())
+                    .keep_alive(options.keep_alive.is_some())
+                    .header_read_timeout(options.header_read_timeout())
+                    .serve_connection(io, service)
+                    .with_upgrades()
+                    .await
+                    .map_err(Into::into)
+            };
+
+            if let Err(e) = result {
+                tracing::trace!("Connection from {} ended with an error: {}", addr, e);
+            }
+            drop(slot);
+        });
+    }
+}
@@ -0,0 +1,135 @@
+//! Opt-in anonymized usage telemetry
+//!
+//! Clients may attach a small `stats` map to a backup upload, e.g.
+//! `{"workouts": "10-49"}`, with values the client has already bucketed.
+//! Only counts per `(key, value)` pair are kept, in memory, and they are
+//! never tied to the uploading user. They appear in `/admin/metrics` as
+//! `telemetry.<key>.<value>`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::error::{AppError, Result};
+
+/// Most fields a single upload may share
+pub const MAX_STATS_FIELDS: usize = 8;
+
+/// Longest accepted key and value
+const MAX_KEY_LEN: usize = 32;
+const MAX_VALUE_LEN: usize = 16;
+
+/// Distinct `(key, value)` pairs tracked before new ones are dropped, so
+/// made-up buckets can't grow memory without bound
+const MAX_DISTINCT_PAIRS: usize = 1000;
+
+/// Prefix for telemetry entries in the admin metrics snapshot
+pub const METRIC_PREFIX: &str = "telemetry.";
+
+/// Counts-only rollup of shared stats since startup
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    counts: Mutex<HashMap<(String, String), u64>>,
+}
+
+impl Telemetry {
+    /// Check that `stats` is small and uses plain identifiers
+    ///
+    /// Keys are `a-z`, `0-9`, `_`; values additionally allow `A-Z`, `-`,
+    /// `+`, and `.` (enough for buckets like `10-49`, `100+`, or `2.1`).
+    #[allow(clippy::result_large_err)]
+    pub fn validate(stats: &BTreeMap<String, String>) -> Result<()> {
+        let key_ok = |k: &str| {
+            (1..=MAX_KEY_LEN).contains(&k.len())
+                && k.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        };
+        let value_ok = |v: &str| {
+            (1..=MAX_VALUE_LEN).contains(&v.len())
+                && v.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.'))
+        };
+
+        if stats.len() > MAX_STATS_FIELDS || !stats.iter().all(|(k, v)| key_ok(k) && value_ok(v)) {
+            return Err(AppError::InvalidInput(
+                "Invalid stats - at most 8 short key/value buckets".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Count one upload's stats
+    pub fn record(&self, stats: &BTreeMap<String, String>) {
+        let Ok(mut counts) = self.counts.lock() else {
+            return;
+        };
+
+        for (key, value) in stats {
+            let pair = (key.clone(), value.clone());
+            if let Some(count) = counts.get_mut(&pair) {
+                *count += 1;
+            } else if counts.len() < MAX_DISTINCT_PAIRS {
+                counts.insert(pair, 1);
+            }
+        }
+    }
+
+    /// Counts keyed `telemetry.<key>.<value>`
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.counts
+            .lock()
+            .map(|counts| {
+                counts
+                    .iter()
+                    .map(|((k, v), n)| (format!("{}{}.{}", METRIC_PREFIX, k, v), *n))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Telemetry::validate(&stats(&[("workouts", "10-49"), ("app", "2.1")])).is_ok());
+        assert!(Telemetry::validate(&stats(&[])).is_ok());
+        assert!(Telemetry::validate(&stats(&[("Workouts", "1")])).is_err());
+        assert!(Telemetry::validate(&stats(&[("workouts", "")])).is_err());
+        assert!(Telemetry::validate(&stats(&[("w", "a b")])).is_err());
+
+        let too_many: Vec<(String, String)> = (0..=MAX_STATS_FIELDS)
+            .map(|i| (format!("k{}", i), "1".to_string()))
+            .collect();
+        assert!(Telemetry::validate(&too_many.into_iter().collect()).is_err());
+    }
+
+    #[test]
+    fn test_record_counts_pairs() {
+        let telemetry = Telemetry::default();
+        telemetry.record(&stats(&[("workouts", "10-49")]));
+        telemetry.record(&stats(&[("workouts", "10-49"), ("app", "2.1")]));
+
+        let snapshot = telemetry.snapshot();
+        assert_eq!(snapshot["telemetry.workouts.10-49"], 2);
+        assert_eq!(snapshot["telemetry.app.2.1"], 1);
+    }
+
+    #[test]
+    fn test_distinct_pairs_are_capped() {
+        let telemetry = Telemetry::default();
+        for i in 0..MAX_DISTINCT_PAIRS + 10 {
+            telemetry.record(&stats(&[("bucket", &i.to_string())]));
+        }
+        assert_eq!(telemetry.snapshot().len(), MAX_DISTINCT_PAIRS);
+    }
+}
@@ -0,0 +1,312 @@
+//! Test harness for integration tests and examples
+//!
+//! Enabled with the `test-utils` feature. [`TestApp`] owns a temporary
+//! database and the full router, and has helpers for registering users and
+//! building signed requests:
+//!
+//! ```ignore
+//! let app = TestApp::new();
+//! let user = app.register_user().await;
+//! let response = app.send(app.store_backup_request(&user, "ciphertext")).await;
+//! assert_eq!(response.status(), StatusCode::OK);
+//! ```
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+    response::Response,
+};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+use crate::notifier::WebhookKind;
+use crate::routes::{RouterOptions, build_router};
+use crate::{AppState, Config, open_database, open_in_memory_database};
+
+/// App secret used by [`test_config`]
+pub const TEST_APP_SECRET: &str = "test-secret-key";
+
+/// Admin key set by [`TestAppBuilder::with_admin`]
+pub const TEST_ADMIN_SECRET: &str = "test-admin-secret";
+
+/// Configuration for tests: no optional integrations, admin routes disabled
+pub fn test_config() -> Config {
+    Config {
+        server_host: "127.0.0.1".to_string(),
+        server_port: 0,
+        http_keep_alive_secs: 75,
+        max_connections: 0,
+        http2: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        database_path: String::new(),
+        db_restore_snapshot_dir: None,
+        snapshot_dir: None,
+        allowed_origins: vec!["http://localhost:5173".to_string()],
+        cors_max_age_secs: 7200,
+        cors_allow_credentials: false,
+        cors_expose_headers: vec!["retry-after".to_string(), "x-request-id".to_string()],
+        response_compression: true,
+        rate_limit_requests: 100,
+        rate_limit_window_secs: 60,
+        register_rate_limit_requests: 10,
+        register_rate_limit_window_secs: 60,
+        max_in_flight_per_user: 2,
+        min_backup_interval_secs: 0,
+        backup_versions_kept: 5,
+        deletion_grace_days: 7,
+        inactive_purge_days: None,
+        compaction_interval_hours: None,
+        environment: "test".to_string(),
+        app_secret_keys: vec![TEST_APP_SECRET.to_string()],
+        register_secret_key: None,
+        store_secret_key: None,
+        delete_secret_key: None,
+        admin_secret_key: None,
+        log_requests: false,
+        maintenance_mode: false,
+        statsd_addr: None,
+        statsd_prefix: "dailyreps".to_string(),
+        statsd_tags: vec![],
+        captcha_secret_key: None,
+        captcha_verify_url: String::new(),
+        oidc_issuer: None,
+        oidc_audience: None,
+        oidc_allowed_subjects: vec![],
+        alert_webhook_url: None,
+        alert_webhook_kind: WebhookKind::Slack,
+        geoip_db_path: None,
+        client_ip_header: None,
+        trusted_proxies: Default::default(),
+        allowed_countries: vec![],
+        denied_countries: vec![],
+        country_policy_registration_only: true,
+        registration_blocklist_urls: vec![],
+        blocklist_refresh_secs: 3600,
+        replica_url: None,
+        replication_secret: None,
+    }
+}
+
+/// Credentials for a test user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestUser {
+    pub user_id: String,
+    pub storage_key: String,
+}
+
+impl TestUser {
+    /// A user with a unique username
+    pub fn random() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let username = format!(
+            "test-user-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let user_id = dailyreps_signing::derive_user_id(&username);
+        let storage_key = dailyreps_signing::derive_storage_key(&user_id, "test-password");
+
+        Self {
+            user_id,
+            storage_key,
+        }
+    }
+}
+
+/// Builder for [`TestApp`]
+#[derive(Debug, Clone)]
+pub struct TestAppBuilder {
+    config: Config,
+    in_memory: bool,
+}
+
+impl TestAppBuilder {
+    /// Adjust the configuration before the app is built
+    pub fn config(mut self, f: impl FnOnce(&mut Config)) -> Self {
+        f(&mut self.config);
+        self
+    }
+
+    /// Enable admin routes with [`TEST_ADMIN_SECRET`]
+    pub fn with_admin(self) -> Self {
+        self.config(|c| c.admin_secret_key = Some(TEST_ADMIN_SECRET.to_string()))
+    }
+
+    /// Keep the database in memory instead of a temp file (faster; nothing
+    /// is written to `db_path`)
+    pub fn in_memory(mut self) -> Self {
+        self.in_memory = true;
+        self
+    }
+
+    /// Create the temporary database and router
+    pub fn build(mut self) -> TestApp {
+        let dir = TempDir::new().expect("Failed to create temp dir");
+        let db_path = dir.path().join("test.db");
+        self.config.database_path = db_path.to_string_lossy().into_owned();
+
+        let db = if self.in_memory {
+            open_in_memory_database()
+        } else {
+            open_database(&db_path)
+        }
+        .expect("Failed to create test database");
+        let state = AppState::new(db, self.config);
+        let router = build_router(state.clone(), RouterOptions::default());
+
+        TestApp {
+            state,
+            router,
+            db_path,
+            _dir: dir,
+        }
+    }
+}
+
+/// The full application on a temporary database
+///
+/// The database is deleted when the `TestApp` is dropped.
+pub struct TestApp {
+    pub state: AppState,
+    pub router: Router,
+    db_path: PathBuf,
+    _dir: TempDir,
+}
+
+impl Default for TestApp {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl TestApp {
+    /// App with [`test_config`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from [`test_config`] and customize
+    pub fn builder() -> TestAppBuilder {
+        TestAppBuilder {
+            config: test_config(),
+            in_memory: false,
+        }
+    }
+
+    /// Path of the database file
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    /// Send a request through the router
+    pub async fn send(&self, request: Request<Body>) -> Response {
+        self.router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("Router is infallible")
+    }
+
+    /// Send a request and parse the response body as JSON
+    pub async fn send_json(&self, request: Request<Body>) -> (StatusCode, Value) {
+        let response = self.send(request).await;
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("Failed to read body");
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    /// HMAC signature of `data` with the app secret
+    pub fn sign(&self, data: impl AsRef<[u8]>) -> String {
+        dailyreps_signing::sign(
+            data.as_ref(),
+            self.state.config().app_secret_key().as_bytes(),
+        )
+    }
+
+    /// Register a fresh user, panicking on failure
+    pub async fn register_user(&self) -> TestUser {
+        let user = TestUser::random();
+        let (status, body) = self.send_json(self.register_request(&user)).await;
+        assert_eq!(status, StatusCode::OK, "registration failed: {}", body);
+        user
+    }
+
+    /// Register a fresh user and store `data` for them, panicking on failure
+    pub async fn user_with_backup(&self, data: &str) -> TestUser {
+        let user = self.register_user().await;
+        let (status, body) = self.send_json(self.store_backup_request(&user, data)).await;
+        assert_eq!(status, StatusCode::OK, "backup failed: {}", body);
+        user
+    }
+
+    /// `POST /api/register`
+    pub fn register_request(&self, user: &TestUser) -> Request<Body> {
+        json_request("POST", "/api/register", json!({ "userId": user.user_id }))
+    }
+
+    /// Signed `POST /api/backup`
+    pub fn store_backup_request(&self, user: &TestUser, data: &str) -> Request<Body> {
+        json_request(
+            "POST",
+            "/api/backup",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "data": data,
+                "signature": self.sign(data),
+                "timestamp": chrono::Utc::now().timestamp(),
+            }),
+        )
+    }
+
+    /// `GET /api/backup`
+    pub fn retrieve_backup_request(&self, user: &TestUser) -> Request<Body> {
+        Request::builder()
+            .uri(format!(
+                "/api/backup?userId={}&storageKey={}",
+                user.user_id, user.storage_key
+            ))
+            .body(Body::empty())
+            .expect("Valid request")
+    }
+
+    /// Signed `DELETE /api/user`
+    pub fn delete_user_request(&self, user: &TestUser) -> Request<Body> {
+        json_request(
+            "DELETE",
+            "/api/user",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "signature": self.sign(&user.storage_key),
+                "timestamp": chrono::Utc::now().timestamp(),
+            }),
+        )
+    }
+
+    /// `GET` an admin route with [`TEST_ADMIN_SECRET`] as the bearer token
+    pub fn admin_request(&self, path: &str) -> Request<Body> {
+        Request::builder()
+            .uri(path)
+            .header("authorization", format!("Bearer {}", TEST_ADMIN_SECRET))
+            .body(Body::empty())
+            .expect("Valid request")
+    }
+}
+
+fn json_request(method: &str, uri: &str, body: Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("Valid request")
+}
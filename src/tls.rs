@@ -0,0 +1,363 @@
+//! Native TLS
+//!
+//! Small self-hosted deployments can serve HTTPS directly instead of running a
+//! reverse proxy: with `TLS_CERT_PATH` and `TLS_KEY_PATH` set, connections are
+//! terminated in-process with rustls. The files are checked every
+//! `TLS_RELOAD_INTERVAL_SECS` and a renewed certificate (e.g. written by
+//! certbot) is picked up for new connections without a restart. A renewal
+//! that fails to load is logged and the previous certificate stays in use.
+
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use rustls::ServerConfig;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, mpsc};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+use crate::constants::TLS_HANDSHAKE_TIMEOUT_SECS;
+use crate::server::{ConnectionSlots, ServerOptions};
+
+/// Completed handshakes waiting for the server to pick them up
+const ACCEPT_BACKLOG: usize = 128;
+
+/// Modification times of the certificate and key files
+type FileVersions = (Option<SystemTime>, Option<SystemTime>);
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// The certificate served to new connections, reloaded when its files change
+#[derive(Debug)]
+pub struct CertReloader {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    /// File versions the current certificate was loaded from
+    loaded: Mutex<FileVersions>,
+}
+
+impl CertReloader {
+    /// Load the certificate chain and private key (both PEM)
+    pub fn load(
+        cert_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let cert_path = cert_path.into();
+        let key_path = key_path.into();
+        let versions = file_versions(&cert_path, &key_path);
+        let certified = load_certified_key(&cert_path, &key_path)?;
+
+        Ok(Self {
+            cert_path,
+            key_path,
+            current: RwLock::new(Arc::new(certified)),
+            loaded: Mutex::new(versions),
+        })
+    }
+
+    /// Reload the certificate if either file changed since it was loaded
+    ///
+    /// Returns whether a new certificate is now served. If loading fails the
+    /// current one is kept and the next call tries again, which also covers
+    /// renewals that write the certificate and key a moment apart.
+    pub fn reload_if_changed(&self) -> bool {
+        let versions = file_versions(&self.cert_path, &self.key_path);
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if *loaded == versions {
+            return false;
+        }
+
+        match load_certified_key(&self.cert_path, &self.key_path) {
+            Ok(certified) => {
+                *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(certified);
+                *loaded = versions;
+                tracing::info!("Reloaded TLS certificate from {}", self.cert_path.display());
+                true
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to reload TLS certificate, keeping the current one: {:#}",
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Check for a renewed certificate every `interval`
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn_watcher(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let reloader = self.clone();
+                // File reads are blocking
+                let _ = tokio::task::spawn_blocking(move || reloader.reload_if_changed()).await;
+            }
+        })
+    }
+
+    /// The certificate currently served
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl ResolvesServerCert for CertReloader {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+fn file_versions(cert_path: &Path, key_path: &Path) -> FileVersions {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    (modified(cert_path), modified(key_path))
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> anyhow::Result<CertifiedKey> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read certificates from {}", cert_path.display()))?;
+    anyhow::ensure!(
+        !certs.is_empty(),
+        "No certificates in {}",
+        cert_path.display()
+    );
+
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("Failed to read private key from {}", key_path.display()))?;
+
+    CertifiedKey::from_der(certs, key, &provider())
+        .context("TLS private key does not match the certificate")
+}
+
+/// A TCP listener that hands out connections once their TLS handshake is done
+///
+/// Handshakes run in their own tasks, so a slow or silent client can't hold up
+/// everyone else; those that don't finish within `TLS_HANDSHAKE_TIMEOUT_SECS`
+/// are dropped. `MAX_CONNECTIONS` is enforced here, from before the handshake
+/// until the connection closes, so serve it with
+/// [`ServerOptions::without_connection_limit`].
+pub struct TlsListener {
+    connections: mpsc::Receiver<(TlsConnection, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    /// Start accepting TLS connections on `listener`, offering HTTP/2 to
+    /// clients if `options.http2`
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn new(
+        listener: TcpListener,
+        certs: Arc<CertReloader>,
+        options: ServerOptions,
+    ) -> anyhow::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let http2 = options.http2;
+
+        let mut config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(certs);
+        config.alpn_protocols = match http2 {
+            true => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            false => vec![b"http/1.1".to_vec()],
+        };
+
+        let (tx, connections) = mpsc::channel(ACCEPT_BACKLOG);
+        tokio::spawn(accept_loop(
+            listener,
+            TlsAcceptor::from(Arc::new(config)),
+            ConnectionSlots::new(options.max_connections),
+            tx,
+        ));
+
+        Ok(Self {
+            connections,
+            local_addr,
+        })
+    }
+}
+
+/// A TLS connection, holding its `MAX_CONNECTIONS` slot until it is dropped
+pub struct TlsConnection {
+    stream: TlsStream<TcpStream>,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsConnection;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accept loop only stops once this listener is gone
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    slots: ConnectionSlots,
+    tx: mpsc::Sender<(TlsConnection, SocketAddr)>,
+) {
+    let timeout = Duration::from_secs(TLS_HANDSHAKE_TIMEOUT_SECS);
+
+    loop {
+        // Take a slot before accepting, as the plain-HTTP loop does, so
+        // handshakes in progress count against MAX_CONNECTIONS too
+        let slot = tokio::select! {
+            _ = tx.closed() => return,
+            slot = slots.acquire() => slot,
+        };
+
+        let (stream, addr) = tokio::select! {
+            _ = tx.closed() => return,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors; back off instead of spinning
+                    tracing::error!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+        };
+
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(timeout, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let connection = TlsConnection {
+                        stream,
+                        _slot: slot,
+                    };
+                    let _ = tx.send((connection, addr)).await;
+                }
+                Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", addr, e),
+                Err(_) => tracing::debug!("TLS handshake with {} timed out", addr),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_reload_picks_up_renewed_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("cert.pem");
+        let key = dir.path().join("key.pem");
+        fs::copy(fixture("tls_cert_1.pem"), &cert).unwrap();
+        fs::copy(fixture("tls_key_1.pem"), &key).unwrap();
+
+        let reloader = CertReloader::load(&cert, &key).unwrap();
+        let original = reloader.current();
+        assert!(!reloader.reload_if_changed());
+
+        // A renewal that has written the certificate but not yet the key
+        // doesn't load, and the old certificate keeps being served
+        let touch = |path: &Path| {
+            let file = fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(SystemTime::now() + Duration::from_secs(60))
+                .unwrap();
+        };
+        fs::copy(fixture("tls_cert_2.pem"), &cert).unwrap();
+        touch(&cert);
+        assert!(!reloader.reload_if_changed());
+        assert_eq!(reloader.current().cert, original.cert);
+
+        fs::copy(fixture("tls_key_2.pem"), &key).unwrap();
+        touch(&key);
+        assert!(reloader.reload_if_changed());
+        assert_ne!(reloader.current().cert, original.cert);
+        assert!(!reloader.reload_if_changed());
+    }
+
+    #[test]
+    fn test_load_rejects_bad_files() {
+        assert!(CertReloader::load(fixture("tls_cert_1.pem"), fixture("tls_key_2.pem")).is_err());
+        assert!(CertReloader::load(fixture("tls_key_1.pem"), fixture("tls_key_1.pem")).is_err());
+        assert!(CertReloader::load(fixture("missing.pem"), fixture("tls_key_1.pem")).is_err());
+    }
+}
@@ -6,7 +6,7 @@ use axum::{
     Router,
     body::Body,
     http::{Request, StatusCode},
-    routing::{delete, get, post},
+    routing::{get, post},
 };
 use hmac::{Hmac, Mac};
 use http_body_util::BodyExt;
@@ -17,8 +17,13 @@ use std::sync::Arc;
 use tempfile::TempDir;
 use tower::ServiceExt;
 
+use dailyreps_backup_server::AppState;
+use dailyreps_backup_server::constants::MAX_BACKUP_SIZE_BYTES;
+use dailyreps_backup_server::routes::{RouterOptions, build_router, cors_layer};
+use dailyreps_backup_server::test_utils::{self, TestApp};
+
 // Test configuration constants
-const TEST_SECRET: &str = "test-secret-key";
+const TEST_SECRET: &str = test_utils::TEST_APP_SECRET;
 
 // =============================================================================
 // Test Helpers
@@ -26,20 +31,7 @@ const TEST_SECRET: &str = "test-secret-key";
 
 /// Create a test configuration
 fn test_config() -> dailyreps_backup_server::Config {
-    dailyreps_backup_server::Config {
-        server_host: "127.0.0.1".to_string(),
-        server_port: 0,                // Random port
-        database_path: "".to_string(), // Will be set per test
-        allowed_origins: vec!["http://localhost:5173".to_string()],
-        rate_limit_requests: 100,
-        rate_limit_window_secs: 60,
-        register_rate_limit_requests: 10,
-        register_rate_limit_window_secs: 60,
-        environment: "test".to_string(),
-        app_secret_key: TEST_SECRET.to_string(),
-        admin_secret_key: None,
-        log_requests: false,
-    }
+    test_utils::test_config()
 }
 
 /// Create a test database in a temporary directory
@@ -53,8 +45,22 @@ fn create_test_db(temp_dir: &TempDir) -> Arc<Database> {
         use dailyreps_backup_server::db::tables;
         let _ = write_txn.open_table(tables::USERS).unwrap();
         let _ = write_txn.open_table(tables::BACKUPS).unwrap();
+        let _ = write_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+        let _ = write_txn.open_table(tables::TOMBSTONES).unwrap();
+        let _ = write_txn.open_table(tables::BANS).unwrap();
+        let _ = write_txn.open_table(tables::NONCES).unwrap();
+        let _ = write_txn.open_table(tables::UPLOAD_SESSIONS).unwrap();
+        let _ = write_txn.open_table(tables::UPLOAD_CHUNKS).unwrap();
         let _ = write_txn.open_table(tables::RATE_LIMITS).unwrap();
         let _ = write_txn.open_table(tables::USER_BACKUPS).unwrap();
+        let _ = write_txn.open_table(tables::SYNC_TOKENS).unwrap();
+        let _ = write_txn.open_table(tables::SECURITY_EVENTS).unwrap();
+        let _ = write_txn.open_table(tables::DAILY_STATS).unwrap();
+        let _ = write_txn.open_table(tables::RECOVERY_CONTACTS).unwrap();
+        let _ = write_txn.open_table(tables::RECOVERY_GRANTS).unwrap();
+        let _ = write_txn.open_table(tables::REGISTRATION_ATTEMPTS).unwrap();
+        let _ = write_txn.open_table(tables::REPLICATION_QUEUE).unwrap();
+        let _ = write_txn.open_table(tables::META).unwrap();
     }
     write_txn.commit().unwrap();
 
@@ -63,17 +69,8 @@ fn create_test_db(temp_dir: &TempDir) -> Arc<Database> {
 
 /// Create a test app router
 fn create_test_app(db: Arc<Database>) -> Router {
-    use dailyreps_backup_server::routes::*;
-
-    let config = test_config();
-    let state = dailyreps_backup_server::AppState { db, config };
-
-    Router::new()
-        .route("/health", get(health_check))
-        .route("/api/register", post(register_user))
-        .route("/api/backup", post(store_backup).get(retrieve_backup))
-        .route("/api/user", delete(delete_user))
-        .with_state(state)
+    let state = dailyreps_backup_server::AppState::new(db, test_config());
+    build_router(state, RouterOptions::default())
 }
 
 /// Generate a valid SHA-256 hash (64 hex chars)
@@ -137,6 +134,15 @@ fn make_get_request(uri: &str) -> Request<Body> {
     Request::builder().uri(uri).body(Body::empty()).unwrap()
 }
 
+/// Add an `Authorization: Bearer` header, as admin routes expect
+fn with_bearer(mut request: Request<Body>, token: &str) -> Request<Body> {
+    request.headers_mut().insert(
+        "authorization",
+        format!("Bearer {}", token).parse().unwrap(),
+    );
+    request
+}
+
 /// Create a DELETE request with JSON body
 fn make_delete_request(uri: &str, body: String) -> Request<Body> {
     Request::builder()
@@ -717,10 +723,1349 @@ async fn test_delete_user_not_found() {
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[tokio::test]
+async fn test_delete_backups_keeps_account() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("phone").await;
+    let tablet = test_utils::TestUser {
+        user_id: user.user_id.clone(),
+        storage_key: generate_storage_key(&user.user_id, "tablet-slot"),
+    };
+    let (status, _) = app
+        .send_json(app.store_backup_request(&tablet, "tablet"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let other = app.user_with_backup("someone-else").await;
+
+    let keys = [
+        user.storage_key.as_str(),
+        other.storage_key.as_str(),
+        &generate_storage_key(&user.user_id, "never-used"),
+    ];
+    let delete = |keys: &[&str], signature: String| {
+        make_post_request(
+            "/api/backup/delete",
+            json!({
+                "userId": user.user_id,
+                "storageKeys": keys,
+                "signature": signature,
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        )
+    };
+
+    let (status, _) = app
+        .send_json(delete(&keys, app.sign(user.storage_key.as_str())))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let signature = app.sign(dailyreps_signing::delete_backups_payload(keys));
+    let (status, body) = app.send_json(delete(&keys, signature)).await;
+    assert_eq!(status, StatusCode::OK);
+    let deleted: Vec<_> = body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["deleted"].as_bool().unwrap())
+        .collect();
+    assert_eq!(deleted, [true, false, false]);
+
+    let (status, _) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, body) = app.send_json(app.retrieve_backup_request(&tablet)).await;
+    assert_eq!(body["data"], "tablet");
+    let (_, body) = app.send_json(app.retrieve_backup_request(&other)).await;
+    assert_eq!(body["data"], "someone-else");
+}
+
 // =============================================================================
 // Rate Limiting Tests
 // =============================================================================
 
+#[tokio::test]
+async fn test_versioned_api_paths() {
+    let app = TestApp::new();
+    let user = test_utils::TestUser::random();
+
+    // Register and store under /api/v1
+    let body = json!({ "userId": user.user_id }).to_string();
+    let response = app.send(make_post_request("/api/v1/register", body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["api-version"], "1");
+
+    let mut request = app.store_backup_request(&user, "versioned");
+    *request.uri_mut() = "/api/v1/backup".parse().unwrap();
+    let (status, _) = app.send_json(request).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The legacy alias and v2 read the same backup
+    let legacy = app.send(app.retrieve_backup_request(&user)).await;
+    assert_eq!(legacy.status(), StatusCode::OK);
+    assert_eq!(legacy.headers()["api-version"], "1");
+
+    let mut request = app.retrieve_backup_request(&user);
+    *request.uri_mut() = request
+        .uri()
+        .to_string()
+        .replace("/api/", "/api/v2/")
+        .parse()
+        .unwrap();
+    let response = app.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["api-version"], "2");
+
+    // v2 changed the store body; a v1 JSON body is not accepted there
+    let mut request = app.store_backup_request(&user, "json-body");
+    *request.uri_mut() = "/api/v2/backup".parse().unwrap();
+    let (status, _) = app.send_json(request).await;
+    assert!(status.is_client_error());
+
+    let (status, _) = app.send_json(make_get_request("/api/v3/limits")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_backup_download_compression() {
+    use base64::Engine;
+    use std::io::Read;
+
+    // Random bytes, base64-encoded like real ciphertext
+    let ciphertext: Vec<u8> = (0..300_000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    let data = base64::engine::general_purpose::STANDARD.encode(&ciphertext);
+
+    let app = TestApp::new();
+    let user = app.user_with_backup(&data).await;
+    let download = |encoding: Option<&str>| {
+        let mut request = app.retrieve_backup_request(&user);
+        if let Some(encoding) = encoding {
+            request
+                .headers_mut()
+                .insert("accept-encoding", encoding.parse().unwrap());
+        }
+        app.send(request)
+    };
+
+    let response = download(Some("gzip")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+    let compressed = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(compressed.len() < data.len() * 85 / 100);
+    let mut json = String::new();
+    flate2::read::GzDecoder::new(&compressed[..])
+        .read_to_string(&mut json)
+        .unwrap();
+    let body: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(body["data"], data.as_str());
+
+    let response = download(Some("br")).await;
+    assert_eq!(response.headers()["content-encoding"], "br");
+
+    let response = download(None).await;
+    assert!(response.headers().get("content-encoding").is_none());
+
+    // Small JSON replies are never compressed
+    let mut request = app.store_backup_request(&user, "small");
+    request
+        .headers_mut()
+        .insert("accept-encoding", "gzip".parse().unwrap());
+    let response = app.send(request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("content-encoding").is_none());
+
+    let app = TestApp::builder()
+        .config(|c| c.response_compression = false)
+        .build();
+    let user = app.user_with_backup(&data).await;
+    let mut request = app.retrieve_backup_request(&user);
+    request
+        .headers_mut()
+        .insert("accept-encoding", "gzip".parse().unwrap());
+    let response = app.send(request).await;
+    assert!(response.headers().get("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn test_request_body_limit() {
+    use dailyreps_backup_server::constants::MAX_REQUEST_BODY_BYTES;
+
+    let app = TestApp::new();
+    let user = app.register_user().await;
+
+    // JSON stores up to the backup size cap get through the body limit
+    let (status, body) = app
+        .send_json(app.store_backup_request(&user, &"A".repeat(3_000_000)))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // A declared oversized body is refused without being read
+    let oversized = "A".repeat(MAX_REQUEST_BODY_BYTES + 1);
+    let (status, body) = app
+        .send_json(make_post_request("/api/backup", oversized.clone()))
+        .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(body["error"].is_string());
+
+    // So is a streamed one, once it passes the limit
+    let chunks: Vec<Result<_, std::io::Error>> = oversized
+        .into_bytes()
+        .chunks(64 * 1024)
+        .map(|chunk| Ok(axum::body::Bytes::copy_from_slice(chunk)))
+        .collect();
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/v1/backup")
+        .header("content-type", "application/json")
+        .body(Body::from_stream(futures_util::stream::iter(chunks)))
+        .unwrap();
+    let (status, body) = app.send_json(request).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(body["error"].is_string());
+}
+
+#[tokio::test]
+async fn test_sync_token_conflict_returns_current_version() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+
+    let store = |data: &str, sync_token: Option<&str>| {
+        let mut body = json!({
+            "userId": user.user_id,
+            "storageKey": user.storage_key,
+            "data": data,
+            "signature": app.sign(data),
+            "timestamp": chrono::Utc::now().timestamp(),
+        });
+        if let Some(token) = sync_token {
+            body["syncToken"] = json!(token);
+        }
+        make_post_request("/api/backup", body.to_string())
+    };
+
+    // Phone uploads first and gets a token
+    let (status, body) = app.send_json(store("phone-v1", None)).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = body["syncToken"].as_str().unwrap().to_string();
+
+    // Tablet syncs from the same token and wins the race
+    let (status, body) = app.send_json(store("tablet-v2", Some(&token))).await;
+    assert_eq!(status, StatusCode::OK);
+    let tablet_token = body["syncToken"].as_str().unwrap().to_string();
+    assert_ne!(tablet_token, token);
+
+    // Phone's stale token is rejected with the tablet's version to merge
+    let (status, body) = app.send_json(store("phone-v2", Some(&token))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["current"]["data"], "tablet-v2");
+    assert_eq!(body["current"]["syncToken"], tablet_token.as_str());
+    assert_eq!(body["current"]["sizeBytes"], "tablet-v2".len());
+    assert_eq!(
+        body["current"]["checksum"],
+        dailyreps_signing::checksum(b"tablet-v2")
+    );
+    assert!(body["current"]["updatedAt"].is_string());
+    // The phone's base is still in the version history, so its time is known
+    assert_eq!(body["base"]["syncToken"], token.as_str());
+    assert!(body["base"]["updatedAt"].is_string());
+
+    // After merging, the phone stores with the current token
+    let (status, _) = app.send_json(store("merged-v3", Some(&tablet_token))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(body["data"], "merged-v3");
+    assert_eq!(body["syncToken"], "3");
+
+    let (status, _) = app.send_json(store("x", Some("not-a-number"))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_sync_conflict_does_not_reveal_other_users_backup() {
+    let app = TestApp::new();
+    let victim = app.user_with_backup("victim-secret").await;
+    let attacker = app.register_user().await;
+
+    let data = "attacker-v1";
+    let body = json!({
+        "userId": attacker.user_id,
+        "storageKey": victim.storage_key,
+        "data": data,
+        "signature": app.sign(data),
+        "timestamp": chrono::Utc::now().timestamp(),
+        "syncToken": "0",
+    });
+    let (status, body) = app
+        .send_json(make_post_request("/api/backup", body.to_string()))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.get("current").is_none());
+    assert!(!body.to_string().contains("victim-secret"));
+}
+
+#[tokio::test]
+async fn test_limits_report_remaining_budget() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("v1").await;
+
+    let limits = |signature: String| {
+        make_get_request(&format!(
+            "/api/limits?userId={}&signature={}&timestamp={}",
+            user.user_id,
+            signature,
+            chrono::Utc::now().timestamp()
+        ))
+    };
+
+    let (status, body) = app.send_json(limits(app.sign(user.user_id.as_str()))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["hourly"]["used"], 1);
+    assert_eq!(body["hourly"]["remaining"], 4);
+    assert_eq!(body["daily"]["remaining"], 19);
+    assert!(body["daily"]["resetsAt"].is_string());
+    assert!(body.get("nextBackupAt").is_none());
+
+    let (status, _) = app.send_json(limits(app.sign("someone-else"))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_backup_versions_and_rollback() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("v1").await;
+    for data in ["v2", "corrupted"] {
+        let (status, _) = app.send_json(app.store_backup_request(&user, data)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let query = format!("userId={}&storageKey={}", user.user_id, user.storage_key);
+    let (status, body) = app
+        .send_json(make_get_request(&format!("/api/backup/versions?{}", query)))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let versions: Vec<_> = body["versions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["version"].as_u64().unwrap())
+        .collect();
+    assert_eq!(versions, [2, 1]);
+
+    // An old version comes with the current sync token, so it can be restored
+    let (status, body) = app
+        .send_json(make_get_request(&format!(
+            "/api/backup?{}&version=2",
+            query
+        )))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], "v2");
+    assert_eq!(body["syncToken"], "3");
+
+    let (status, _) = app
+        .send_json(make_get_request(&format!(
+            "/api/backup?{}&version=9",
+            query
+        )))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Another user's key reveals nothing
+    let other = app.register_user().await;
+    let (status, _) = app
+        .send_json(make_get_request(&format!(
+            "/api/backup/versions?userId={}&storageKey={}",
+            other.user_id, user.storage_key
+        )))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Deleting the account removes the history too
+    let (status, _) = app.send_json(app.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    use dailyreps_backup_server::db::tables;
+    use redb::{ReadableDatabase, ReadableTableMetadata};
+    let read_txn = app.state.db.begin_read().unwrap();
+    let kept = read_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+    assert!(kept.is_empty().unwrap());
+}
+
+#[tokio::test]
+async fn test_backups_are_compressed_at_rest() {
+    let app = TestApp::new();
+    let data = "QUJDREVGR0g=".repeat(20_000);
+    let user = app.user_with_backup(&data).await;
+
+    use dailyreps_backup_server::db::tables;
+    use redb::ReadableDatabase;
+    let stored = {
+        let read_txn = app.state.db.begin_read().unwrap();
+        let backups = read_txn.open_table(tables::BACKUPS).unwrap();
+        backups
+            .get(user.storage_key.as_str())
+            .unwrap()
+            .unwrap()
+            .value()
+            .len()
+    };
+    assert!(stored < data.len() / 10, "stored {} bytes", stored);
+
+    let (status, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], data.as_str());
+}
+
+#[tokio::test]
+async fn test_chunked_upload() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+
+    let start = || {
+        make_post_request(
+            "/api/backup/chunks",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "signature": app.sign(&user.storage_key),
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        )
+    };
+    let chunk = |upload_id: &str, data: &str| {
+        Request::builder()
+            .method("PUT")
+            .uri(format!("/api/backup/chunks/{}", upload_id))
+            .header("content-type", "application/octet-stream")
+            .body(Body::from(data.to_string()))
+            .unwrap()
+    };
+
+    let (status, body) = app.send_json(start()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let upload_id = body["uploadId"].as_str().unwrap().to_string();
+
+    // The per-user session limit (2) applies while sessions are open
+    assert_eq!(app.send_json(start()).await.0, StatusCode::OK);
+    assert_eq!(
+        app.send_json(start()).await.0,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+
+    let data = "QUJD".repeat(1_000);
+    let (head, tail) = data.split_at(1_500);
+    app.send_json(chunk(&upload_id, head)).await;
+    let (status, body) = app.send_json(chunk(&upload_id, tail)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["receivedBytes"], data.len());
+    assert_eq!(body["chunks"], 2);
+
+    let commit = |signature: String| {
+        make_post_request(
+            &format!("/api/backup/chunks/{}/commit", upload_id),
+            json!({ "signature": signature, "timestamp": chrono::Utc::now().timestamp() })
+                .to_string(),
+        )
+    };
+
+    // A signature over anything but the assembled data is refused
+    let (status, _) = app.send_json(commit(app.sign(head))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = app.send_json(commit(app.sign(&data))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["syncToken"], "1");
+
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(body["data"], data.as_str());
+
+    // With sigVersion 2 the commit also signs the session's identity
+    let (_, body) = app.send_json(start()).await;
+    let second_id = body["uploadId"].as_str().unwrap().to_string();
+    app.send_json(chunk(&second_id, "djI=")).await;
+    let timestamp = chrono::Utc::now().timestamp();
+    let commit_v2 = |user_id: &str| {
+        let signed = dailyreps_signing::canonical_payload([
+            ("userId", user_id),
+            ("storageKey", user.storage_key.as_str()),
+            ("data", "djI="),
+            ("timestamp", &timestamp.to_string()),
+            ("syncToken", "1"),
+        ]);
+        make_post_request(
+            &format!("/api/backup/chunks/{}/commit", second_id),
+            json!({
+                "signature": app.sign(&signed),
+                "timestamp": timestamp,
+                "syncToken": "1",
+                "sigVersion": 2,
+            })
+            .to_string(),
+        )
+    };
+    let other = generate_user_id();
+    let (status, _) = app.send_json(commit_v2(&other)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = app.send_json(commit_v2(&user.user_id)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["syncToken"], "2");
+
+    // The session is closed by the commit
+    let (status, _) = app.send_json(chunk(&upload_id, "more")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(app.send_json(start()).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_too_many_unfinished_uploads_retry_after() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+    let start = || {
+        make_post_request(
+            "/api/backup/chunks",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "signature": app.sign(&user.storage_key),
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        )
+    };
+
+    for _ in 0..2 {
+        let (status, _) = app.send_json(start()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // The next slot frees up when the oldest session expires, an hour out
+    let response = app.send(start()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((3590..=3600).contains(&retry_after));
+    let body = body_to_json(response.into_body()).await;
+    assert_eq!(body["retryAfterSecs"], retry_after);
+}
+
+#[tokio::test]
+async fn test_resume_chunked_upload() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+
+    let (_, body) = app
+        .send_json(make_post_request(
+            "/api/backup/chunks",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "signature": app.sign(&user.storage_key),
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        ))
+        .await;
+    let upload_id = body["uploadId"].as_str().unwrap().to_string();
+    let chunk = |offset: usize, data: &str| {
+        Request::builder()
+            .method("PUT")
+            .uri(format!(
+                "/api/backup/chunks/{}?offset={}",
+                upload_id, offset
+            ))
+            .body(Body::from(data.to_string()))
+            .unwrap()
+    };
+
+    let (status, _) = app.send_json(chunk(0, "first-")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The client lost the response and asks where to resume
+    let (status, body) = app
+        .send_json(make_get_request(&format!(
+            "/api/backup/chunks/{}",
+            upload_id
+        )))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["receivedBytes"], 6);
+    assert!(body["expiresAt"].is_string());
+
+    // Retrying the chunk it already sent is refused, not appended twice
+    let (status, body) = app.send_json(chunk(0, "first-")).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["receivedBytes"], 6);
+
+    let (status, body) = app.send_json(chunk(6, "second")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["receivedBytes"], 12);
+
+    let (status, _) = app
+        .send_json(make_post_request(
+            &format!("/api/backup/chunks/{}/commit", upload_id),
+            json!({
+                "signature": app.sign("first-second"),
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(body["data"], "first-second");
+}
+
+#[tokio::test]
+async fn test_deleted_user_restorable_during_grace_period() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("v1").await;
+    app.send_json(app.store_backup_request(&user, "v2")).await;
+
+    let (status, body) = app.send_json(app.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["restorableUntil"].is_string());
+    let (status, _) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // The user ID stays reserved so a new registration can't preempt the undo
+    let (status, _) = app.send_json(app.register_request(&user)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let restore = |storage_key: &str| {
+        make_post_request(
+            "/api/user/restore",
+            json!({
+                "userId": user.user_id,
+                "storageKey": storage_key,
+                "signature": app.sign(storage_key),
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        )
+    };
+    let wrong_key = generate_storage_key(&user.user_id, "wrong-password");
+    let (status, _) = app.send_json(restore(&wrong_key)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = app.send_json(restore(&user.storage_key)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Data, sync token and version history all come back
+    let (status, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], "v2");
+    assert_eq!(body["syncToken"], "2");
+    let (_, body) = app
+        .send_json(make_get_request(&format!(
+            "/api/backup/versions?userId={}&storageKey={}",
+            user.user_id, user.storage_key
+        )))
+        .await;
+    assert_eq!(body["versions"].as_array().unwrap().len(), 1);
+
+    let (status, _) = app.send_json(restore(&user.storage_key)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_delete_user_without_grace_period_is_immediate() {
+    let app = TestApp::builder()
+        .config(|c| c.deletion_grace_days = 0)
+        .build();
+    let user = app.user_with_backup("ciphertext").await;
+
+    let (status, body) = app.send_json(app.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("restorableUntil").is_none());
+
+    // Nothing is kept, so the user ID is free again
+    let (status, _) = app.send_json(app.register_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_backup_checksum_returned_and_verified() {
+    use dailyreps_backup_server::db::tables;
+    use dailyreps_backup_server::models::BackupRecord;
+    use redb::ReadableTable;
+
+    let app = TestApp::new();
+    let user = app.register_user().await;
+    let expected = dailyreps_signing::checksum(b"ciphertext");
+
+    let (status, body) = app
+        .send_json(app.store_backup_request(&user, "ciphertext"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["checksum"], expected.as_str());
+
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(body["checksum"], expected.as_str());
+
+    // Simulate on-disk corruption of the data behind its checksum
+    let config = bincode::config::standard();
+    let write_txn = app.state.db.begin_write().unwrap();
+    {
+        let mut backups = write_txn.open_table(tables::BACKUPS).unwrap();
+        let bytes = backups
+            .get(user.storage_key.as_str())
+            .unwrap()
+            .unwrap()
+            .value()
+            .to_vec();
+        let (mut record, _): (BackupRecord, _) =
+            bincode::serde::decode_from_slice(&bytes, config).unwrap();
+        record.encrypted_data = "ciphertexT".to_string();
+        let bytes = bincode::serde::encode_to_vec(&record, config).unwrap();
+        backups
+            .insert(user.storage_key.as_str(), bytes.as_slice())
+            .unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let (status, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["error"], "Stored backup failed its integrity check");
+}
+
+#[tokio::test]
+async fn test_backup_version_checksum_verified() {
+    use dailyreps_backup_server::db::tables;
+    use dailyreps_backup_server::models::BackupVersionRecord;
+    use redb::ReadableTable;
+
+    let app = TestApp::new();
+    let user = app.user_with_backup("ciphertext-v1").await;
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "ciphertext-v2"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let old_version = || {
+        make_get_request(&format!(
+            "/api/backup?userId={}&storageKey={}&version=1",
+            user.user_id, user.storage_key
+        ))
+    };
+    let (status, body) = app.send_json(old_version()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], "ciphertext-v1");
+    assert_eq!(
+        body["checksum"],
+        dailyreps_signing::checksum(b"ciphertext-v1")
+    );
+
+    // Corrupt the archived data behind its stored checksum
+    let config = bincode::config::standard();
+    let key = (user.storage_key.as_str(), 1);
+    let write_txn = app.state.db.begin_write().unwrap();
+    {
+        let mut versions = write_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+        let bytes = versions.get(key).unwrap().unwrap().value().to_vec();
+        let (mut record, _): (BackupVersionRecord, _) =
+            bincode::serde::decode_from_slice(&bytes, config).unwrap();
+        record.encrypted_data = "ciphertext-vX".to_string();
+        let bytes = bincode::serde::encode_to_vec(&record, config).unwrap();
+        versions.insert(key, bytes.as_slice()).unwrap();
+    }
+    write_txn.commit().unwrap();
+
+    let (status, body) = app.send_json(old_version()).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body["error"], "Stored backup failed its integrity check");
+}
+
+#[tokio::test]
+async fn test_check_backup_checksum() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("ciphertext-v1").await;
+
+    let check = |checksum: String| {
+        Request::builder()
+            .method("POST")
+            .uri("/api/backup/check")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({
+                    "userId": user.user_id,
+                    "storageKey": user.storage_key,
+                    "checksum": checksum,
+                })
+                .to_string(),
+            ))
+            .unwrap()
+    };
+
+    let same = dailyreps_signing::checksum(b"ciphertext-v1");
+    let (status, body) = app.send_json(check(same.to_uppercase())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["unchanged"], true);
+    assert!(body["updatedAt"].is_string());
+
+    let (status, body) = app
+        .send_json(check(dailyreps_signing::checksum(b"ciphertext-v2")))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["unchanged"], false);
+
+    let (status, _) = app.send_json(check("not-a-checksum".to_string())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Only the matching check counts as a skipped upload
+    assert_eq!(
+        app.state
+            .metrics
+            .counter(dailyreps_backup_server::metrics::UPLOADS_SKIPPED),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_validate_backup_dry_run() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("ciphertext-v1").await;
+
+    let validate = |data: &str, signature: String, sync_token: &str| {
+        make_post_request(
+            "/api/backup/validate",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "data": data,
+                "signature": signature,
+                "timestamp": chrono::Utc::now().timestamp(),
+                "syncToken": sync_token,
+            })
+            .to_string(),
+        )
+    };
+
+    let (status, body) = app
+        .send_json(validate("ciphertext-v2", app.sign("ciphertext-v2"), "1"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["valid"], true, "{}", body);
+    assert!(body["checks"].as_array().unwrap().len() > 5);
+
+    // Each failing check is reported; the others still pass
+    let (status, body) = app
+        .send_json(validate("ciphertext-v2", app.sign("tampered"), "7"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["valid"], false);
+    let failed: Vec<_> = body["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|c| c["passed"] == false)
+        .map(|c| c["check"].as_str().unwrap())
+        .collect();
+    assert_eq!(failed, ["signature", "syncToken"]);
+
+    // Nothing was stored and no rate limit was spent
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(body["data"], "ciphertext-v1");
+    assert_eq!(
+        app.state
+            .metrics
+            .counter(dailyreps_backup_server::metrics::SIGNATURE_FAILURES),
+        0
+    );
+    for i in 0..4 {
+        let (status, _) = app
+            .send_json(app.store_backup_request(&user, &format!("v{}", i)))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn test_country_policy_registration_only() {
+    let app = TestApp::new();
+    let existing = app.user_with_backup("ciphertext").await;
+
+    // Same database, now restricted to NL; test requests have no client IP,
+    // so their country is unknown and falls outside the allowlist
+    let restricted = |registration_only: bool| {
+        let mut config = (*app.state.config()).clone();
+        config.allowed_countries = vec!["NL".to_string()];
+        config.country_policy_registration_only = registration_only;
+        build_router(
+            AppState::new(app.state.db.clone(), config),
+            RouterOptions::default(),
+        )
+    };
+
+    let router = restricted(true);
+    let newcomer = test_utils::TestUser::random();
+    let response = router
+        .clone()
+        .oneshot(app.register_request(&newcomer))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    for request in [
+        app.retrieve_backup_request(&existing),
+        app.store_backup_request(&existing, "ciphertext-2"),
+        make_get_request("/health"),
+    ] {
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Restricting every route refuses existing users too
+    let response = restricted(false)
+        .oneshot(app.retrieve_backup_request(&existing))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_blocklisted_network_cannot_register() {
+    let app = TestApp::builder()
+        .config(|c| c.client_ip_header = Some("x-forwarded-for".to_string()))
+        .build();
+    let existing = app.user_with_backup("ciphertext").await;
+    app.state.blocklist.replace(["185.220.101.0/24"]);
+
+    let from = |mut request: Request<Body>, ip: &str| {
+        request
+            .headers_mut()
+            .insert("x-forwarded-for", ip.parse().unwrap());
+        request
+    };
+
+    let newcomer = test_utils::TestUser::random();
+    let (status, body) = app
+        .send_json(from(
+            app.register_request(&newcomer),
+            "185.220.101.7, 10.0.0.1",
+        ))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        body["error"],
+        "Registration is not available from this network"
+    );
+
+    // Retrieval from the same network is unaffected
+    let (status, _) = app
+        .send_json(from(
+            app.retrieve_backup_request(&existing),
+            "185.220.101.7",
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .send_json(from(app.register_request(&newcomer), "203.0.113.9"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_registration_throttled_per_network() {
+    let app = TestApp::builder()
+        .config(|c| {
+            c.client_ip_header = Some("x-forwarded-for".to_string());
+            c.register_rate_limit_requests = 2;
+        })
+        .build();
+
+    let register_from = |ip: &str| {
+        let mut request = app.register_request(&test_utils::TestUser::random());
+        request
+            .headers_mut()
+            .insert("x-forwarded-for", ip.parse().unwrap());
+        request
+    };
+
+    for _ in 0..2 {
+        let (status, _) = app.send_json(register_from("198.51.100.7")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, body) = app.send_json(register_from("198.51.100.7")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["limitType"], "registration");
+
+    // Other networks are counted separately
+    let (status, _) = app.send_json(register_from("203.0.113.9")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Requests that bypassed the proxy's header share one budget
+    for _ in 0..2 {
+        let request = app.register_request(&test_utils::TestUser::random());
+        let (status, _) = app.send_json(request).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let request = app.register_request(&test_utils::TestUser::random());
+    let (status, body) = app.send_json(request).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["limitType"], "registration");
+
+    // Counted in the database (by keyed hash, never the raw address)
+    use dailyreps_backup_server::db::tables;
+    use redb::{ReadableDatabase, ReadableTableMetadata};
+    let read_txn = app.state.db.begin_read().unwrap();
+    let attempts = read_txn.open_table(tables::REGISTRATION_ATTEMPTS).unwrap();
+    assert_eq!(attempts.len().unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_requests_throttled_per_network() {
+    let app = TestApp::builder()
+        .config(|c| {
+            c.client_ip_header = Some("x-forwarded-for".to_string());
+            c.rate_limit_requests = 3;
+        })
+        .build();
+    let user = app.user_with_backup("ciphertext").await;
+
+    let from = |mut request: Request<Body>, ip: &str| {
+        request
+            .headers_mut()
+            .insert("x-forwarded-for", ip.parse().unwrap());
+        request
+    };
+
+    // Lookups of made-up users count too
+    let stranger = test_utils::TestUser::random();
+    for _ in 0..3 {
+        let (status, _) = app
+            .send_json(from(app.retrieve_backup_request(&stranger), "198.51.100.7"))
+            .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    let (status, body) = app
+        .send_json(from(app.retrieve_backup_request(&user), "198.51.100.7"))
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "RATE_LIMITED");
+    assert_eq!(body["limitType"], "network");
+    assert_eq!(body["limit"], 3);
+
+    // The same /64 shares a budget; other networks don't
+    let (status, _) = app
+        .send_json(from(app.retrieve_backup_request(&user), "203.0.113.9"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send_json(from(app.register_request(&stranger), "2001:db8::1"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    for _ in 0..2 {
+        let (status, _) = app
+            .send_json(from(app.retrieve_backup_request(&user), "2001:db8::2"))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = app
+        .send_json(from(app.retrieve_backup_request(&user), "2001:db8::3"))
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Health checks are never limited
+    let response = app
+        .send(from(make_get_request("/health/live"), "198.51.100.7"))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_forwarded_for_only_believed_from_trusted_proxies() {
+    use axum::extract::ConnectInfo;
+    use dailyreps_backup_server::proxy::TrustedProxies;
+    use std::net::SocketAddr;
+
+    let app = TestApp::builder()
+        .config(|c| {
+            c.trusted_proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+            c.rate_limit_requests = 1;
+        })
+        .build();
+    let user = test_utils::TestUser::random();
+
+    let via = |peer: &str, forwarded_for: &str| {
+        let mut request = app.retrieve_backup_request(&user);
+        let peer: SocketAddr = format!("{}:443", peer).parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(peer));
+        request
+            .headers_mut()
+            .insert("x-forwarded-for", forwarded_for.parse().unwrap());
+        request
+    };
+
+    // Through the proxy, the client can't dodge the limit by making up hops
+    let (status, _) = app.send_json(via("10.0.0.2", "1.1.1.1, 203.0.113.7")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.send_json(via("10.0.0.3", "2.2.2.2, 203.0.113.7")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Direct clients can't claim to be someone else
+    let (status, _) = app.send_json(via("198.51.100.1", "203.0.113.8")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.send_json(via("198.51.100.1", "203.0.113.9")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let (status, _) = app.send_json(via("10.0.0.2", "203.0.113.8")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_per_operation_signing_secrets() {
+    let app = TestApp::builder()
+        .config(|c| {
+            c.register_secret_key = Some("register-secret".to_string());
+            c.delete_secret_key = Some("delete-secret".to_string());
+        })
+        .build();
+    let user = test_utils::TestUser::random();
+    let now = chrono::Utc::now().timestamp();
+
+    // Registration must now be signed, with its own secret
+    let (status, _) = app.send_json(app.register_request(&user)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let register = |secret: &str| {
+        make_post_request(
+            "/api/register",
+            json!({
+                "userId": user.user_id,
+                "signature": dailyreps_signing::sign(user.user_id.as_bytes(), secret.as_bytes()),
+                "timestamp": now,
+            })
+            .to_string(),
+        )
+    };
+    let (status, _) = app.send_json(register(TEST_SECRET)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.send_json(register("register-secret")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Store falls back to APP_SECRET_KEY
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "ciphertext"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The app secret can no longer sign a deletion
+    let (status, _) = app.send_json(app.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let delete = Request::builder()
+        .method("DELETE")
+        .uri("/api/user")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "signature": dailyreps_signing::sign(user.storage_key.as_bytes(), b"delete-secret"),
+                "timestamp": now,
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let (status, _) = app.send_json(delete).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_previous_app_secret_accepted_during_rotation() {
+    let app = TestApp::builder()
+        .config(|c| c.app_secret_keys = vec!["rotated-secret".to_string(), TEST_SECRET.to_string()])
+        .build();
+    let user = app.register_user().await;
+
+    let store = |data: &str, secret: &str| {
+        make_post_request(
+            "/api/backup",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "data": data,
+                "signature": generate_hmac_signature(data, secret),
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        )
+    };
+
+    // The current key and the one it replaced both verify
+    let (status, _) = app.send_json(store("signed-new", "rotated-secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.send_json(store("signed-old", TEST_SECRET)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send_json(store("signed-retired", "retired-secret"))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_nonce_rejects_replayed_request() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+
+    let store = |data: &str, nonce: &str, signed: &[u8]| {
+        make_post_request(
+            "/api/backup",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "data": data,
+                "nonce": nonce,
+                "signature": app.sign(signed),
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        )
+    };
+    let nonce = "replay-test-nonce-0001";
+    let signed = dailyreps_signing::nonce_payload(b"first", nonce);
+
+    let (status, _) = app.send_json(store("first", nonce, &signed)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = app.send_json(store("first", nonce, &signed)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(body["error"].as_str().unwrap().contains("new nonce"));
+
+    // The nonce is covered by the signature, so it can't simply be swapped
+    let (status, _) = app
+        .send_json(store("first", "replay-test-nonce-0002", &signed))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let short = dailyreps_signing::nonce_payload(b"second", "short");
+    let (status, _) = app.send_json(store("second", "short", &short)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_sig_version_2_covers_every_field() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+    let timestamp = chrono::Utc::now().timestamp();
+
+    let signed = dailyreps_signing::canonical_payload([
+        ("userId", user.user_id.as_str()),
+        ("storageKey", user.storage_key.as_str()),
+        ("data", "ciphertext"),
+        ("timestamp", &timestamp.to_string()),
+        ("stats.app", "2.1"),
+    ]);
+    let store = |timestamp: i64, sig_version: u32| {
+        make_post_request(
+            "/api/backup",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "data": "ciphertext",
+                "stats": {"app": "2.1"},
+                "signature": app.sign(&signed),
+                "timestamp": timestamp,
+                "sigVersion": sig_version,
+            })
+            .to_string(),
+        )
+    };
+
+    // v1 signs only data and stats, so the same signature doesn't verify
+    let (status, _) = app.send_json(store(timestamp, 1)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.send_json(store(timestamp - 1, 2)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.send_json(store(timestamp, 3)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app.send_json(store(timestamp, 2)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_store_warns_near_limits() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+
+    let (status, body) = app
+        .send_json(app.store_backup_request(&user, "ciphertext"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["warnings"], json!([]));
+
+    // 4 of 5 hourly uploads is 80%
+    for _ in 0..2 {
+        app.send_json(app.store_backup_request(&user, "ciphertext"))
+            .await;
+    }
+    let (status, body) = app
+        .send_json(app.store_backup_request(&user, "ciphertext"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let warnings = body["warnings"].as_array().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0]["code"], "hourly_limit");
+    assert_eq!(warnings[0]["used"], 4);
+    assert_eq!(warnings[0]["limit"], 5);
+
+    // A backup near the size cap warns about storage too (stored base64,
+    // so 3.3MB raw is ~84% of the cap)
+    let other = app.register_user().await;
+    let large = vec![0u8; 3_300_000];
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/v2/backup")
+        .header("content-type", "application/octet-stream")
+        .header("x-user-id", &other.user_id)
+        .header("x-storage-key", &other.storage_key)
+        .header("x-signature", app.sign(&large))
+        .header("x-timestamp", chrono::Utc::now().timestamp().to_string())
+        .body(Body::from(large))
+        .unwrap();
+    let (status, body) = app.send_json(request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["warnings"][0]["code"], "storage_quota");
+    assert_eq!(body["warnings"][0]["limit"], MAX_BACKUP_SIZE_BYTES);
+}
+
+#[tokio::test]
+async fn test_concurrent_uploads_per_user_limited() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+    let other = app.register_user().await;
+
+    // Simulate two uploads still being written for this user
+    let first = app.state.in_flight.acquire(&user.user_id).unwrap();
+    let _second = app.state.in_flight.acquire(&user.user_id).unwrap();
+
+    let (status, body) = app
+        .send_json(app.store_backup_request(&user, "ciphertext"))
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"], "Too many concurrent requests for this user");
+    assert_eq!(body["retryAfterSecs"], 2);
+
+    // Other users are unaffected
+    let (status, _) = app
+        .send_json(app.store_backup_request(&other, "ciphertext"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Slots are released once requests finish
+    drop(first);
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "ciphertext"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.state.in_flight.in_flight(&user.user_id), 1);
+}
+
 #[tokio::test]
 async fn test_rate_limiting_backup_hourly() {
     let temp_dir = TempDir::new().unwrap();
@@ -867,41 +2212,22 @@ async fn test_backup_update_replaces_data() {
 // Admin Endpoint Tests
 // =============================================================================
 
-const TEST_ADMIN_SECRET: &str = "test-admin-secret";
+const TEST_ADMIN_SECRET: &str = test_utils::TEST_ADMIN_SECRET;
 
 /// Create a test config with admin key enabled
 fn test_config_with_admin() -> dailyreps_backup_server::Config {
-    dailyreps_backup_server::Config {
-        server_host: "127.0.0.1".to_string(),
-        server_port: 0,
-        database_path: "".to_string(),
-        allowed_origins: vec!["http://localhost:5173".to_string()],
-        rate_limit_requests: 100,
-        rate_limit_window_secs: 60,
-        register_rate_limit_requests: 10,
-        register_rate_limit_window_secs: 60,
-        environment: "test".to_string(),
-        app_secret_key: TEST_SECRET.to_string(),
-        admin_secret_key: Some(TEST_ADMIN_SECRET.to_string()),
-        log_requests: false,
-    }
+    let mut config = test_config();
+    config.admin_secret_key = Some(TEST_ADMIN_SECRET.to_string());
+    config
 }
 
 /// Create a test app with admin endpoint enabled
 fn create_test_app_with_admin(db: Arc<Database>, db_path: String) -> Router {
-    use dailyreps_backup_server::routes::*;
-
     let mut config = test_config_with_admin();
     config.database_path = db_path;
-    let state = dailyreps_backup_server::AppState { db, config };
+    let state = dailyreps_backup_server::AppState::new(db, config);
 
-    Router::new()
-        .route("/health", get(health_check))
-        .route("/api/register", post(register_user))
-        .route("/api/backup", post(store_backup).get(retrieve_backup))
-        .route("/api/user", delete(delete_user))
-        .route("/admin/stats", get(admin_stats))
-        .with_state(state)
+    build_router(state, RouterOptions::default())
 }
 
 #[tokio::test]
@@ -916,16 +2242,30 @@ async fn test_admin_stats_success() {
         use dailyreps_backup_server::db::tables;
         let _ = write_txn.open_table(tables::USERS).unwrap();
         let _ = write_txn.open_table(tables::BACKUPS).unwrap();
+        let _ = write_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+        let _ = write_txn.open_table(tables::TOMBSTONES).unwrap();
+        let _ = write_txn.open_table(tables::BANS).unwrap();
+        let _ = write_txn.open_table(tables::NONCES).unwrap();
+        let _ = write_txn.open_table(tables::UPLOAD_SESSIONS).unwrap();
+        let _ = write_txn.open_table(tables::UPLOAD_CHUNKS).unwrap();
         let _ = write_txn.open_table(tables::RATE_LIMITS).unwrap();
         let _ = write_txn.open_table(tables::USER_BACKUPS).unwrap();
+        let _ = write_txn.open_table(tables::SYNC_TOKENS).unwrap();
+        let _ = write_txn.open_table(tables::SECURITY_EVENTS).unwrap();
+        let _ = write_txn.open_table(tables::DAILY_STATS).unwrap();
+        let _ = write_txn.open_table(tables::RECOVERY_CONTACTS).unwrap();
+        let _ = write_txn.open_table(tables::RECOVERY_GRANTS).unwrap();
+        let _ = write_txn.open_table(tables::REGISTRATION_ATTEMPTS).unwrap();
+        let _ = write_txn.open_table(tables::REPLICATION_QUEUE).unwrap();
+        let _ = write_txn.open_table(tables::META).unwrap();
     }
     write_txn.commit().unwrap();
 
     let db = Arc::new(db);
     let app = create_test_app_with_admin(db, db_path.to_string_lossy().to_string());
 
-    let uri = format!("/admin/stats?key={}", TEST_ADMIN_SECRET);
-    let response = app.oneshot(make_get_request(&uri)).await.unwrap();
+    let request = with_bearer(make_get_request("/admin/stats"), TEST_ADMIN_SECRET);
+    let response = app.oneshot(request).await.unwrap();
 
     assert_eq!(response.status(), StatusCode::OK);
 
@@ -947,8 +2287,22 @@ async fn test_admin_stats_invalid_key() {
         use dailyreps_backup_server::db::tables;
         let _ = write_txn.open_table(tables::USERS).unwrap();
         let _ = write_txn.open_table(tables::BACKUPS).unwrap();
+        let _ = write_txn.open_table(tables::BACKUP_VERSIONS).unwrap();
+        let _ = write_txn.open_table(tables::TOMBSTONES).unwrap();
+        let _ = write_txn.open_table(tables::BANS).unwrap();
+        let _ = write_txn.open_table(tables::NONCES).unwrap();
+        let _ = write_txn.open_table(tables::UPLOAD_SESSIONS).unwrap();
+        let _ = write_txn.open_table(tables::UPLOAD_CHUNKS).unwrap();
         let _ = write_txn.open_table(tables::RATE_LIMITS).unwrap();
         let _ = write_txn.open_table(tables::USER_BACKUPS).unwrap();
+        let _ = write_txn.open_table(tables::SYNC_TOKENS).unwrap();
+        let _ = write_txn.open_table(tables::SECURITY_EVENTS).unwrap();
+        let _ = write_txn.open_table(tables::DAILY_STATS).unwrap();
+        let _ = write_txn.open_table(tables::RECOVERY_CONTACTS).unwrap();
+        let _ = write_txn.open_table(tables::RECOVERY_GRANTS).unwrap();
+        let _ = write_txn.open_table(tables::REGISTRATION_ATTEMPTS).unwrap();
+        let _ = write_txn.open_table(tables::REPLICATION_QUEUE).unwrap();
+        let _ = write_txn.open_table(tables::META).unwrap();
     }
     write_txn.commit().unwrap();
 
@@ -956,7 +2310,7 @@ async fn test_admin_stats_invalid_key() {
     let app = create_test_app_with_admin(db, db_path.to_string_lossy().to_string());
 
     let response = app
-        .oneshot(make_get_request("/admin/stats?key=wrong-key"))
+        .oneshot(with_bearer(make_get_request("/admin/stats"), "wrong-key"))
         .await
         .unwrap();
 
@@ -972,17 +2326,2496 @@ async fn test_admin_stats_disabled_without_key() {
     use dailyreps_backup_server::routes::*;
 
     let config = test_config();
-    let state = dailyreps_backup_server::AppState { db, config };
+    let state = dailyreps_backup_server::AppState::new(db, config);
 
     let app = Router::new()
         .route("/admin/stats", get(admin_stats))
         .with_state(state);
 
     let response = app
-        .oneshot(make_get_request("/admin/stats?key=any-key"))
+        .oneshot(with_bearer(make_get_request("/admin/stats"), "any-key"))
         .await
         .unwrap();
 
     // Should return unauthorized because admin_secret_key is None
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 }
+
+#[tokio::test]
+async fn test_admin_stats_export_csv() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (user_id, _storage_key, _data, _app) = setup_user_with_backup(db.clone()).await;
+
+    let app = create_test_app_with_admin(db, String::new());
+    let request = with_bearer(
+        make_get_request("/admin/stats/export?format=csv&range=7d"),
+        TEST_ADMIN_SECRET,
+    );
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/csv; charset=utf-8"
+    );
+
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let csv = String::from_utf8(bytes.to_vec()).unwrap();
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    assert!(csv.contains(&format!("{},1,1", today)));
+    assert!(csv.contains(&format!("{},", user_id)));
+}
+
+#[tokio::test]
+async fn test_admin_stats_export_invalid_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let app = create_test_app_with_admin(db, String::new());
+
+    let response = app
+        .oneshot(with_bearer(
+            make_get_request("/admin/stats/export"),
+            "wrong-key",
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_admin_metrics_success() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let app = create_test_app_with_admin(db, String::new());
+
+    let request = with_bearer(make_get_request("/admin/metrics"), TEST_ADMIN_SECRET);
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_to_json(response.into_body()).await.is_object());
+}
+
+#[tokio::test]
+async fn test_admin_largest_backups() {
+    use sha2::{Digest, Sha256};
+
+    let app = TestApp::builder().with_admin().build();
+    let small = app.user_with_backup("small").await;
+    // Biggest decoded, but compresses to almost nothing
+    let compressible = app.user_with_backup(&"x".repeat(4096)).await;
+    // Smaller decoded, but barely compresses
+    let noise: String = (0..64u32)
+        .map(|i| hex::encode(Sha256::digest(i.to_be_bytes())))
+        .collect();
+    let incompressible = app.user_with_backup(&noise[..2048]).await;
+
+    let (status, body) = app
+        .send_json(app.admin_request("/admin/backups/largest?limit=2"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Ranked and reported by stored size
+    let backups = body["backups"].as_array().unwrap();
+    assert_eq!(backups.len(), 2);
+    assert_eq!(backups[0]["owner"], &incompressible.user_id[..8]);
+    assert_eq!(backups[1]["owner"], &compressible.user_id[..8]);
+    let sizes: Vec<u64> = backups
+        .iter()
+        .map(|b| b["size_bytes"].as_u64().unwrap())
+        .collect();
+    assert!(sizes[0] > sizes[1]);
+    assert!(sizes[0] < 4096 && sizes[1] < 2048);
+    assert!(backups.iter().all(|b| b["owner"] != small.user_id[..8]));
+
+    let response = app
+        .send(with_bearer(
+            make_get_request("/admin/backups/largest"),
+            "wrong",
+        ))
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_admin_users_paginates() {
+    use dailyreps_backup_server::db::tables;
+    use redb::{ReadableDatabase, ReadableTable};
+
+    let app = TestApp::builder().with_admin().build();
+    let with_backup = app.user_with_backup(&"x".repeat(100)).await;
+    app.register_user().await;
+    app.register_user().await;
+
+    let (status, first) = app
+        .send_json(app.admin_request("/admin/users?limit=2"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["users"].as_array().unwrap().len(), 2);
+    let cursor = first["next_cursor"].as_str().unwrap();
+
+    let (status, second) = app
+        .send_json(app.admin_request(&format!("/admin/users?limit=2&cursor={}", cursor)))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["users"].as_array().unwrap().len(), 1);
+    assert!(second.get("next_cursor").is_none());
+
+    // IDs are peppered, never the raw user ID
+    let users: Vec<_> = first["users"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .chain(second["users"].as_array().unwrap())
+        .collect();
+    let peppered = dailyreps_backup_server::security::pepper_user_id(
+        &with_backup.user_id,
+        test_utils::TEST_APP_SECRET,
+    );
+    let owner = users.iter().find(|u| u["id"] == peppered.as_str()).unwrap();
+    assert_eq!(owner["backup_count"], 1);
+    assert_eq!(owner["total_bytes"], 100);
+    assert!(
+        users
+            .iter()
+            .all(|u| u["id"] != with_backup.user_id.as_str())
+    );
+
+    // The cursor is opaque too: it isn't any registered user's raw ID
+    let user_ids: Vec<String> = {
+        let read_txn = app.state.db.begin_read().unwrap();
+        let table = read_txn.open_table(tables::USERS).unwrap();
+        table
+            .iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().0.value().to_string())
+            .collect()
+    };
+    assert_eq!(user_ids.len(), 3);
+    assert!(user_ids.iter().all(|id| !cursor.contains(id.as_str())));
+
+    // A raw user ID is refused as a cursor
+    for cursor in ["not-a-user-id", user_ids[0].as_str()] {
+        let (status, _) = app
+            .send_json(app.admin_request(&format!("/admin/users?cursor={}", cursor)))
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+async fn test_admin_purge_inactive() {
+    let app = TestApp::builder().with_admin().build();
+    let user = app.user_with_backup("ciphertext").await;
+
+    let purge = |days: u64| {
+        with_bearer(
+            make_post_request(
+                &format!("/admin/purge?inactive_days={}", days),
+                String::new(),
+            ),
+            TEST_ADMIN_SECRET,
+        )
+    };
+
+    // Too short a threshold is refused outright
+    let (status, _) = app.send_json(purge(1)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A user who just stored a backup is active
+    let (status, body) = app.send_json(purge(30)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["purged_users"], 0);
+    assert!(body.get("restorable_until").is_none());
+    let (status, _) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_admin_ban_refuses_user_and_network() {
+    let app = TestApp::builder()
+        .with_admin()
+        .config(|c| c.client_ip_header = Some("x-forwarded-for".to_string()))
+        .build();
+    let banned = app.user_with_backup("ciphertext").await;
+    let bystander = app.user_with_backup("ciphertext").await;
+    let peppered = dailyreps_backup_server::security::pepper_user_id(
+        &banned.user_id,
+        test_utils::TEST_APP_SECRET,
+    );
+
+    let ban = |body: Value| {
+        with_bearer(
+            make_post_request("/admin/bans", body.to_string()),
+            TEST_ADMIN_SECRET,
+        )
+    };
+    let from = |mut request: Request<Body>, ip: &str| {
+        request
+            .headers_mut()
+            .insert("x-forwarded-for", ip.parse().unwrap());
+        request
+    };
+
+    let (status, _) = app
+        .send_json(ban(json!({ "user": peppered, "reason": "abuse" })))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app.send_json(app.retrieve_backup_request(&banned)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "Access denied");
+    let (status, _) = app
+        .send_json(app.store_backup_request(&banned, "more"))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app.send_json(app.retrieve_backup_request(&bystander)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // An IPv6 ban covers the whole /64
+    let (status, _) = app.send_json(ban(json!({ "ip": "2001:db8:1:2::5" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send_json(from(
+            app.retrieve_backup_request(&bystander),
+            "2001:db8:1:2::77",
+        ))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let newcomer = test_utils::TestUser::random();
+    let (status, _) = app
+        .send_json(from(app.register_request(&newcomer), "2001:db8:1:2::77"))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // The list shows peppered IDs, never raw ones
+    let (status, list) = app.send_json(app.admin_request("/admin/bans")).await;
+    assert_eq!(status, StatusCode::OK);
+    let bans = list["bans"].as_array().unwrap();
+    assert_eq!(bans.len(), 2);
+    assert!(bans.iter().any(|b| b["user"] == peppered.as_str()));
+    assert!(bans.iter().any(|b| b["network"] == "2001:db8:1:2::/64"));
+    assert!(!list.to_string().contains(&banned.user_id));
+
+    let (status, body) = app
+        .send_json(with_bearer(
+            make_delete_request("/admin/bans", json!({ "user": peppered }).to_string()),
+            TEST_ADMIN_SECRET,
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["removed"], true);
+    let (status, _) = app.send_json(app.retrieve_backup_request(&banned)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .send_json(ban(json!({ "user": peppered, "ip": "203.0.113.9" })))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_admin_abuse_top_ranks_signature_failures() {
+    let app = TestApp::builder()
+        .with_admin()
+        .config(|c| c.client_ip_header = Some("x-forwarded-for".to_string()))
+        .build();
+    let forger = app.register_user().await;
+
+    let mut forged = app.store_backup_request(&forger, "data");
+    *forged.body_mut() = Body::from(
+        json!({
+            "userId": forger.user_id,
+            "storageKey": forger.storage_key,
+            "data": "data",
+            "signature": "0".repeat(64),
+            "timestamp": chrono::Utc::now().timestamp(),
+        })
+        .to_string(),
+    );
+    forged
+        .headers_mut()
+        .insert("x-forwarded-for", "203.0.113.9".parse().unwrap());
+    assert_eq!(app.send(forged).await.status(), StatusCode::UNAUTHORIZED);
+
+    // Buffered events are flushed before the report is read
+    let (status, report) = app
+        .send_json(app.admin_request("/admin/abuse/top?range=7d"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let offenders = report["offenders"].as_array().unwrap();
+    assert_eq!(offenders.len(), 1);
+    assert_eq!(offenders[0]["user_id"], forger.user_id.as_str());
+    assert_eq!(offenders[0]["signature_failures"], 1);
+    assert_eq!(offenders[0]["severity"], 5);
+
+    // Without a GeoIP database every event lands in the unknown-country bucket
+    assert_eq!(
+        report["countries"],
+        json!([{"country": null, "events": 1, "severity": 5}])
+    );
+
+    // The network is kept only as a keyed hash
+    let networks = report["networks"].as_array().unwrap();
+    assert_eq!(networks.len(), 1);
+    assert_eq!(
+        networks[0]["network"],
+        dailyreps_backup_server::security::hash_client_ip(
+            "203.0.113.9".parse().unwrap(),
+            app.state.config().app_secret_key()
+        )
+        .as_str()
+    );
+    assert_eq!(networks[0]["events"], 1);
+}
+
+#[tokio::test]
+async fn test_recovery_rekey_flow() {
+    let app = TestApp::builder().with_admin().build();
+    let user = test_utils::TestUser::random();
+    let contact_hash = dailyreps_signing::recovery_hash(&user.user_id, "Me@Example.com");
+
+    let register = json!({ "userId": user.user_id, "recoveryHash": contact_hash });
+    let (status, _) = app
+        .send_json(make_post_request("/api/register", register.to_string()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "ciphertext"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let new_key = dailyreps_signing::derive_storage_key(&user.user_id, "new-password");
+    let rekey = |hash: &str| {
+        make_post_request(
+            "/api/recovery/rekey",
+            json!({
+                "userId": user.user_id,
+                "recoveryHash": hash,
+                "newStorageKey": new_key,
+                "signature": app.sign(&new_key),
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        )
+    };
+    let authorize = |hash: &str| {
+        with_bearer(
+            make_post_request(
+                "/admin/recovery/authorize",
+                json!({ "userId": user.user_id, "recoveryHash": hash }).to_string(),
+            ),
+            test_utils::TEST_ADMIN_SECRET,
+        )
+    };
+
+    // No grant yet
+    let (status, _) = app.send_json(rekey(&contact_hash)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Support checks a wrong contact, then the right one
+    let wrong = dailyreps_signing::recovery_hash(&user.user_id, "other@example.com");
+    let (status, body) = app.send_json(authorize(&wrong)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["matches"], false);
+    assert!(body.get("rekeyExpiresAt").is_none());
+
+    let (status, body) = app.send_json(authorize(&contact_hash)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["matches"], true);
+    assert!(body["rekeyExpiresAt"].is_string());
+
+    let (status, body) = app.send_json(rekey(&contact_hash)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["moved"], true);
+
+    // The backup now lives under the new key
+    let rekeyed = test_utils::TestUser {
+        user_id: user.user_id.clone(),
+        storage_key: new_key.clone(),
+    };
+    let (status, body) = app.send_json(app.retrieve_backup_request(&rekeyed)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], "ciphertext");
+    let (status, _) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Grants are single-use
+    let (status, _) = app.send_json(rekey(&contact_hash)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_admin_signups_time_series() {
+    let app = TestApp::builder().with_admin().build();
+    let _kept = app.register_user().await;
+    let leaving = app.user_with_backup("ciphertext").await;
+    let (status, _) = app.send_json(app.delete_user_request(&leaving)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app
+        .send_json(app.admin_request("/admin/signups?days=7"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let days = body["days"].as_array().unwrap();
+    assert_eq!(days.len(), 7);
+    assert!(days[..6].iter().all(|d| d["registrations"] == 0));
+
+    let today = &days[6];
+    assert_eq!(
+        today["date"],
+        chrono::Utc::now().date_naive().to_string().as_str()
+    );
+    assert_eq!(today["registrations"], 2);
+    assert_eq!(today["deletions"], 1);
+
+    let (status, _) = app
+        .send_json(app.admin_request("/admin/signups?days=0"))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_opt_in_stats_are_signed_and_aggregated() {
+    let app = TestApp::builder().with_admin().build();
+    let user = app.register_user().await;
+
+    let store = |signature: String| {
+        make_post_request(
+            "/api/backup",
+            json!({
+                "userId": user.user_id,
+                "storageKey": user.storage_key,
+                "data": "ciphertext",
+                "stats": { "workouts": "10-49", "app": "2.1" },
+                "signature": signature,
+                "timestamp": chrono::Utc::now().timestamp(),
+            })
+            .to_string(),
+        )
+    };
+
+    // Signing only `data` doesn't cover the stats
+    let (status, _) = app.send_json(store(app.sign("ciphertext"))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let payload =
+        dailyreps_signing::backup_payload("ciphertext", [("workouts", "10-49"), ("app", "2.1")]);
+    let (status, _) = app.send_json(store(app.sign(payload))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, metrics) = app.send_json(app.admin_request("/admin/metrics")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(metrics["telemetry.workouts.10-49"], 1);
+    assert_eq!(metrics["telemetry.app.2.1"], 1);
+    assert_eq!(metrics["backups.stored"], 1);
+}
+
+#[tokio::test]
+async fn test_admin_ui_served_without_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let app = create_test_app_with_admin(db, String::new());
+
+    let response = app.oneshot(make_get_request("/admin/ui")).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/html; charset=utf-8"
+    );
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8_lossy(&bytes).contains("DailyReps Backup Admin"));
+}
+
+#[tokio::test]
+async fn test_admin_key_in_query_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let app = create_test_app_with_admin(db, String::new());
+
+    // Only the header is accepted, so the key never lands in access logs
+    let uri = format!("/admin/stats?key={}", TEST_ADMIN_SECRET);
+    let response = app.oneshot(make_get_request(&uri)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_admin_events_stream_emits_mutations() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let app = create_test_app_with_admin(db, String::new());
+
+    let request = with_bearer(make_get_request("/admin/events/stream"), TEST_ADMIN_SECRET);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    let user_id = generate_user_id();
+    let body = json!({ "userId": user_id });
+    let register = app
+        .oneshot(make_post_request("/api/register", body.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(register.status(), StatusCode::OK);
+
+    let mut stream = response.into_body();
+    let frame = tokio::time::timeout(std::time::Duration::from_secs(5), stream.frame())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let text = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+
+    assert!(text.contains("event: change"));
+    assert!(text.contains("id: 1"));
+    assert!(text.contains(r#""type":"register""#));
+    assert!(text.contains(&user_id));
+}
+
+#[tokio::test]
+async fn test_admin_events_stream_requires_auth() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let app = create_test_app_with_admin(db, String::new());
+
+    let response = app
+        .oneshot(with_bearer(
+            make_get_request("/admin/events/stream"),
+            "wrong-key",
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+// =============================================================================
+// Captcha Tests
+// =============================================================================
+
+/// Start a mock siteverify provider that accepts only "valid-token"
+async fn start_mock_captcha_provider() -> String {
+    use axum::Form;
+    use std::collections::HashMap;
+
+    let provider = Router::new().route(
+        "/siteverify",
+        post(|Form(form): Form<HashMap<String, String>>| async move {
+            let success = form.get("secret").map(String::as_str) == Some("captcha-secret")
+                && form.get("response").map(String::as_str) == Some("valid-token");
+            axum::Json(json!({ "success": success, "error-codes": [] }))
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, provider).await.unwrap() });
+
+    format!("http://{}/siteverify", addr)
+}
+
+/// Create a test app with captcha verification enabled
+fn create_test_app_with_captcha(db: Arc<Database>, verify_url: String) -> Router {
+    let mut config = test_config();
+    config.captcha_secret_key = Some("captcha-secret".to_string());
+    config.captcha_verify_url = verify_url;
+    let state = dailyreps_backup_server::AppState::new(db, config);
+
+    build_router(state, RouterOptions::default())
+}
+
+#[tokio::test]
+async fn test_register_with_valid_captcha() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let app = create_test_app_with_captcha(db, start_mock_captcha_provider().await);
+
+    let body = json!({ "userId": generate_user_id(), "captchaToken": "valid-token" });
+    let response = app
+        .oneshot(make_post_request("/api/register", body.to_string()))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_register_with_invalid_or_missing_captcha() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let verify_url = start_mock_captcha_provider().await;
+
+    let app = create_test_app_with_captcha(db.clone(), verify_url.clone());
+    let body = json!({ "userId": generate_user_id(), "captchaToken": "forged-token" });
+    let response = app
+        .oneshot(make_post_request("/api/register", body.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let app = create_test_app_with_captcha(db, verify_url);
+    let body = json!({ "userId": generate_user_id() });
+    let response = app
+        .oneshot(make_post_request("/api/register", body.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+// =============================================================================
+// OIDC Admin Auth Tests
+// =============================================================================
+
+/// RSA modulus (base64url) of tests/fixtures/oidc_test_key.pem
+const OIDC_TEST_KEY_N: &str = "wreGqVeKmoBp-kdJ7Loi-x1XLcnD7nW_LRdJJYQGRvJfXvd4Z25-gqOxySj1hC9yJOg5xxtELUwKShxylPbamMew3WgjxyFlhIyc9aZMu7za1paOm3tEZJckRjIW5VZOmyaZy4xQ5FZCyF6j4Y1MeLZt7C8BVznCEq78wgxJ5Q6G7nUsoxFApn5wviwSp11--jbgkA9bXhh2s9yItxyBsmd360kwqMO6WlV4rnpTHfukkog072a4yzyHoNbH_McCa2kaopF53BwyGUpQTxWVTV8Ui5KPAuoaDrpwSlWrq9H4J2Nfv-lFp8q7zz9MxmNIEFYPAc4jX7VaQyxXzSR8Mw";
+
+/// Start a mock OIDC provider serving discovery metadata and a JWKS
+async fn start_mock_oidc_provider() -> String {
+    start_counting_oidc_provider().await.0
+}
+
+/// [`start_mock_oidc_provider`], also counting JWKS fetches
+async fn start_counting_oidc_provider() -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let fetches = Arc::new(AtomicUsize::new(0));
+
+    let jwks_uri = format!("{}/jwks", issuer);
+    let counter = fetches.clone();
+    let provider = Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            get(move || async move { axum::Json(json!({ "jwks_uri": jwks_uri })) }),
+        )
+        .route(
+            "/jwks",
+            get(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                axum::Json(json!({
+                    "keys": [{
+                        "kty": "RSA",
+                        "kid": "test-key",
+                        "alg": "RS256",
+                        "use": "sig",
+                        "n": OIDC_TEST_KEY_N,
+                        "e": "AQAB"
+                    }]
+                }))
+            }),
+        );
+    tokio::spawn(async move { axum::serve(listener, provider).await.unwrap() });
+
+    (issuer, fetches)
+}
+
+/// Sign an admin token with the test key
+fn sign_admin_token(issuer: &str, audience: &str, subject: &str) -> String {
+    use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+
+    let key = EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_test_key.pem")).unwrap();
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("test-key".to_string());
+
+    let claims = json!({
+        "iss": issuer,
+        "aud": audience,
+        "sub": subject,
+        "exp": chrono::Utc::now().timestamp() + 300,
+    });
+    encode(&header, &claims, &key).unwrap()
+}
+
+/// Create a test app whose admin routes are protected by the mock provider
+fn create_test_app_with_oidc(db: Arc<Database>, issuer: String) -> Router {
+    let mut config = test_config();
+    config.oidc_issuer = Some(issuer);
+    config.oidc_audience = Some("dailyreps-admin".to_string());
+    config.oidc_allowed_subjects = vec!["alice".to_string()];
+    let state = dailyreps_backup_server::AppState::new(db, config);
+
+    build_router(state, RouterOptions::default())
+}
+
+fn make_bearer_request(uri: &str, token: &str) -> Request<Body> {
+    Request::builder()
+        .uri(uri)
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_admin_stats_with_oidc_token() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let issuer = start_mock_oidc_provider().await;
+    let app = create_test_app_with_oidc(db, issuer.clone());
+
+    let token = sign_admin_token(&issuer, "dailyreps-admin", "alice");
+    let response = app
+        .oneshot(make_bearer_request("/admin/stats", &token))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_admin_stats_rejects_bad_oidc_tokens() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let issuer = start_mock_oidc_provider().await;
+
+    let cases = [
+        // Subject not on the allow list
+        sign_admin_token(&issuer, "dailyreps-admin", "mallory"),
+        // Wrong audience
+        sign_admin_token(&issuer, "some-other-app", "alice"),
+        // Wrong issuer
+        sign_admin_token("https://evil.example", "dailyreps-admin", "alice"),
+        // Garbage
+        "not-a-jwt".to_string(),
+    ];
+
+    for token in cases {
+        let app = create_test_app_with_oidc(db.clone(), issuer.clone());
+        let response = app
+            .oneshot(make_bearer_request("/admin/stats", &token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_oidc_key_does_not_refetch_every_request() {
+    use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+    use std::sync::atomic::Ordering;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (issuer, fetches) = start_counting_oidc_provider().await;
+    let app = create_test_app_with_oidc(db, issuer.clone());
+
+    let key = EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_test_key.pem")).unwrap();
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("rotated-away".to_string());
+    let claims = json!({
+        "iss": issuer,
+        "aud": "dailyreps-admin",
+        "sub": "alice",
+        "exp": chrono::Utc::now().timestamp() + 300,
+    });
+    let unknown = encode(&header, &claims, &key).unwrap();
+
+    for _ in 0..5 {
+        let response = app
+            .clone()
+            .oneshot(make_bearer_request("/admin/stats", &unknown))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+    // Known keys keep working from the cache
+    let token = sign_admin_token(&issuer, "dailyreps-admin", "alice");
+    let response = app
+        .oneshot(make_bearer_request("/admin/stats", &token))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(fetches.load(Ordering::SeqCst), 1);
+}
+
+// =============================================================================
+// Alerting Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_signature_failure_sends_chat_alert() {
+    use dailyreps_backup_server::notifier::{Notifier, WebhookKind};
+    use tokio::sync::mpsc;
+
+    // Mock Slack webhook that forwards received bodies to the test
+    let (tx, mut rx) = mpsc::channel::<Value>(4);
+    let webhook = Router::new().route(
+        "/hook",
+        post(move |axum::Json(body): axum::Json<Value>| {
+            let tx = tx.clone();
+            async move {
+                tx.send(body).await.unwrap();
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, webhook).await.unwrap() });
+
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (user_id, storage_key, _app) = setup_registered_user(db.clone()).await;
+
+    let mut state = dailyreps_backup_server::AppState::new(db, test_config());
+    state.notifier = Arc::new(Notifier::spawn(url, WebhookKind::Slack, "test".to_string()));
+    let app = build_router(state, RouterOptions::default());
+
+    let backup_body = json!({
+        "userId": user_id,
+        "storageKey": storage_key,
+        "data": "data",
+        "signature": "0".repeat(64),
+        "timestamp": chrono::Utc::now().timestamp()
+    });
+    let response = app
+        .oneshot(make_post_request("/api/backup", backup_body.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let alert = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    let text = alert["text"].as_str().unwrap();
+    assert!(text.contains("Signature verification failures"));
+    assert!(text.contains("1 signed requests rejected"));
+}
+
+// =============================================================================
+// Archive Export Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_export_archive_success() {
+    use std::io::Read;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (user_id, storage_key, data, app) = setup_user_with_backup(db).await;
+
+    let body = json!({
+        "userId": user_id,
+        "storageKey": storage_key,
+        "signature": generate_hmac_signature(&storage_key, TEST_SECRET),
+        "timestamp": chrono::Utc::now().timestamp()
+    });
+    let response = app
+        .oneshot(make_post_request("/api/backup/archive", body.to_string()))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-tar");
+
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let mut archive = tar::Archive::new(bytes.as_ref());
+    let mut files = std::collections::HashMap::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().to_string_lossy().to_string();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        files.insert(path, contents);
+    }
+
+    assert_eq!(files[&format!("backups/{}.b64", storage_key)], data);
+
+    let manifest: Value = serde_json::from_str(&files["manifest.json"]).unwrap();
+    assert_eq!(manifest["userId"], user_id);
+    assert_eq!(manifest["backups"][0]["storageKey"], storage_key);
+    assert_eq!(manifest["backups"][0]["sizeBytes"], data.len());
+}
+
+#[tokio::test]
+async fn test_export_archive_wrong_storage_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (user_id, _storage_key, _data, app) = setup_user_with_backup(db).await;
+
+    let wrong_key = generate_storage_key(&user_id, "wrong-password");
+    let body = json!({
+        "userId": user_id,
+        "storageKey": wrong_key,
+        "signature": generate_hmac_signature(&wrong_key, TEST_SECRET),
+        "timestamp": chrono::Utc::now().timestamp()
+    });
+    let response = app
+        .oneshot(make_post_request("/api/backup/archive", body.to_string()))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+// =============================================================================
+// Content Negotiation Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_store_and_retrieve_backup_msgpack() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (user_id, storage_key, app) = setup_registered_user(db.clone()).await;
+
+    let data = generate_valid_backup_data();
+    let backup_body = json!({
+        "userId": user_id,
+        "storageKey": storage_key,
+        "data": data,
+        "signature": generate_hmac_signature(&data, TEST_SECRET),
+        "timestamp": chrono::Utc::now().timestamp()
+    });
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/backup")
+        .header("content-type", "application/msgpack")
+        .header("accept", "application/cbor")
+        .body(Body::from(rmp_serde::to_vec_named(&backup_body).unwrap()))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/cbor");
+
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = ciborium::from_reader(bytes.as_ref()).unwrap();
+    assert_eq!(body["success"], true);
+
+    let app = create_test_app(db);
+    let request = Request::builder()
+        .uri(format!(
+            "/api/backup?userId={}&storageKey={}",
+            user_id, storage_key
+        ))
+        .header("accept", "application/msgpack")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/msgpack");
+
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = rmp_serde::from_slice(&bytes).unwrap();
+    assert_eq!(body["data"], data);
+}
+
+#[tokio::test]
+async fn test_store_and_retrieve_backup_protobuf() {
+    use dailyreps_backup_server::proto;
+    use prost::Message;
+
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (user_id, storage_key, app) = setup_registered_user(db.clone()).await;
+
+    let data = generate_valid_backup_data();
+    let backup = proto::StoreBackupRequest {
+        user_id: user_id.clone(),
+        storage_key: storage_key.clone(),
+        signature: generate_hmac_signature(&data, TEST_SECRET),
+        data: data.clone(),
+        timestamp: chrono::Utc::now().timestamp(),
+        sync_token: String::new(),
+        stats: Default::default(),
+        nonce: String::new(),
+        sig_version: 0,
+    };
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/backup")
+        .header("content-type", "application/x-protobuf")
+        .header("accept", "application/x-protobuf")
+        .body(Body::from(backup.encode_to_vec()))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-protobuf");
+
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body = proto::StoreBackupResponse::decode(bytes).unwrap();
+    assert!(body.success);
+
+    let app = create_test_app(db);
+    let request = Request::builder()
+        .uri(format!(
+            "/api/backup?userId={}&storageKey={}",
+            user_id, storage_key
+        ))
+        .header("accept", "application/x-protobuf")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body = proto::RetrieveBackupResponse::decode(bytes).unwrap();
+    assert_eq!(body.data, data);
+}
+
+#[tokio::test]
+async fn test_store_backup_unsupported_content_type() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let app = create_test_app(db);
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/backup")
+        .header("content-type", "text/plain")
+        .body(Body::from("hello"))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+// =============================================================================
+// Raw Upload Tests
+// =============================================================================
+
+/// Create a raw octet-stream upload request signed over the body bytes
+fn make_raw_upload_request(
+    user_id: &str,
+    storage_key: &str,
+    body: &[u8],
+    secret: &str,
+) -> Request<Body> {
+    type HmacSha256 = Hmac<Sha256>;
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    let signature = hex::encode(mac.finalize().into_bytes());
+
+    Request::builder()
+        .method("POST")
+        .uri("/api/v2/backup")
+        .header("content-type", "application/octet-stream")
+        .header("x-user-id", user_id)
+        .header("x-storage-key", storage_key)
+        .header("x-signature", signature)
+        .header("x-timestamp", chrono::Utc::now().timestamp().to_string())
+        .body(Body::from(body.to_vec()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_store_backup_raw_success() {
+    use base64::{Engine, prelude::BASE64_STANDARD};
+
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (user_id, storage_key, app) = setup_registered_user(db.clone()).await;
+
+    let blob: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+    let response = app
+        .oneshot(make_raw_upload_request(
+            &user_id,
+            &storage_key,
+            &blob,
+            TEST_SECRET,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Retrievable through the regular endpoint, base64-encoded
+    let app = create_test_app(db);
+    let uri = format!("/api/backup?userId={}&storageKey={}", user_id, storage_key);
+    let response = app.oneshot(make_get_request(&uri)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = body_to_json(response.into_body()).await;
+    assert_eq!(body["data"], BASE64_STANDARD.encode(&blob));
+}
+
+#[tokio::test]
+async fn test_store_backup_raw_sig_version_2() {
+    use base64::{Engine, prelude::BASE64_STANDARD};
+
+    let app = TestApp::new();
+    let user = app.register_user().await;
+    let blob = b"raw-ciphertext";
+    let timestamp = chrono::Utc::now().timestamp();
+    let signed = dailyreps_signing::canonical_payload([
+        ("userId", user.user_id.as_str()),
+        ("storageKey", user.storage_key.as_str()),
+        ("data", BASE64_STANDARD.encode(blob).as_str()),
+        ("timestamp", &timestamp.to_string()),
+    ]);
+    let upload = |user_id: &str, sig_version: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/api/v2/backup")
+            .header("content-type", "application/octet-stream")
+            .header("x-user-id", user_id)
+            .header("x-storage-key", user.storage_key.as_str())
+            .header("x-signature", app.sign(&signed))
+            .header("x-timestamp", timestamp.to_string())
+            .header("x-sig-version", sig_version)
+            .body(Body::from(blob.to_vec()))
+            .unwrap()
+    };
+
+    // The identity headers are signed, so they can't be swapped
+    let other = app.register_user().await;
+    let (status, _) = app.send_json(upload(&other.user_id, "2")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.send_json(upload(&user.user_id, "1")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app.send_json(upload(&user.user_id, "two")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = app.send_json(upload(&user.user_id, "2")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(body["data"], BASE64_STANDARD.encode(blob));
+}
+
+#[tokio::test]
+async fn test_store_backup_raw_invalid_signature_and_missing_headers() {
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let (user_id, storage_key, app) = setup_registered_user(db.clone()).await;
+
+    let response = app
+        .oneshot(make_raw_upload_request(
+            &user_id,
+            &storage_key,
+            b"blob",
+            "wrong-secret",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let app = create_test_app(db);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/v2/backup")
+        .header("x-user-id", &user_id)
+        .body(Body::from("blob"))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+// =============================================================================
+// Client SDK Tests
+// =============================================================================
+
+/// Serve the full app on a random local port and return its base URL
+async fn start_test_server(db: Arc<Database>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = create_test_app(db);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn test_client_sdk_full_lifecycle() {
+    use dailyreps_backup_client::{BackupClient, ClientError, Credentials};
+
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let client = BackupClient::new(start_test_server(db).await, TEST_SECRET);
+    let creds = Credentials::derive("Alice", "correct horse battery staple");
+
+    client.register(&creds, None).await.unwrap();
+    assert!(matches!(
+        client.register(&creds, None).await,
+        Err(ClientError::UserAlreadyExists)
+    ));
+
+    assert!(matches!(
+        client.retrieve_backup(&creds).await,
+        Err(ClientError::BackupNotFound)
+    ));
+
+    let data = generate_valid_backup_data();
+    assert!(!client.backup_unchanged(&creds, &data).await.unwrap());
+    client.store_backup(&creds, &data).await.unwrap();
+    assert!(client.backup_unchanged(&creds, &data).await.unwrap());
+    assert_eq!(client.retrieve_backup(&creds).await.unwrap().data, data);
+
+    assert!(client.delete_user(&creds).await.unwrap().is_some());
+    assert!(matches!(
+        client.retrieve_backup(&creds).await,
+        Err(ClientError::BackupNotFound)
+    ));
+
+    client.restore_user(&creds).await.unwrap();
+    assert_eq!(client.retrieve_backup(&creds).await.unwrap().data, data);
+}
+
+#[tokio::test]
+async fn test_client_sdk_wrong_secret_is_unauthorized() {
+    use dailyreps_backup_client::{BackupClient, ClientError, Credentials};
+
+    let temp_dir = TempDir::new().unwrap();
+    let db = create_test_db(&temp_dir);
+    let client = BackupClient::new(start_test_server(db).await, "not-the-app-secret");
+    let creds = Credentials::derive("bob", "hunter2");
+
+    client.register(&creds, None).await.unwrap();
+    assert!(matches!(
+        client.store_backup(&creds, "ciphertext").await,
+        Err(ClientError::Unauthorized(_))
+    ));
+}
+
+// =============================================================================
+// Test Harness Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_harness_backup_round_trip() {
+    let app = TestApp::new();
+    let data = generate_valid_backup_data();
+    let user = app.user_with_backup(&data).await;
+
+    let (status, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], data);
+
+    let (status, _) = app.send_json(app.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_harness_admin_builder() {
+    let app = TestApp::builder().with_admin().build();
+    app.register_user().await;
+
+    let (status, body) = app.send_json(app.admin_request("/admin/stats")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["user_count"], 1);
+    assert!(app.db_path().exists());
+}
+
+#[tokio::test]
+async fn test_harness_in_memory_database() {
+    let app = TestApp::builder().in_memory().build();
+    let data = generate_valid_backup_data();
+    let user = app.user_with_backup(&data).await;
+
+    let (status, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], data);
+    assert!(!app.db_path().exists());
+}
+
+#[tokio::test]
+async fn test_read_only_mode_refuses_writes_but_serves_reads() {
+    let app = TestApp::builder().with_admin().build();
+    let user = app.user_with_backup("ciphertext").await;
+    assert!(app.state.writes.trip(chrono::Utc::now().timestamp()));
+
+    let (status, body) = app
+        .send_json(app.store_backup_request(&user, "newer"))
+        .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        body["error"],
+        "Server is temporarily read-only - backups can still be retrieved"
+    );
+
+    let newcomer = test_utils::TestUser::random();
+    let (status, _) = app.send_json(app.register_request(&newcomer)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    let (status, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], "ciphertext");
+
+    let (_, health) = app.send_json(make_get_request("/health")).await;
+    assert_eq!(health["writes"], "read_only");
+
+    let resume = || {
+        with_bearer(
+            make_post_request("/admin/writes/resume", String::new()),
+            TEST_ADMIN_SECRET,
+        )
+    };
+    let (status, body) = app.send_json(resume()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["resumed"], true);
+    let (_, body) = app.send_json(resume()).await;
+    assert_eq!(body["resumed"], false);
+
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "newer"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_maintenance_mode_refuses_writes_but_serves_reads() {
+    let app = TestApp::builder()
+        .with_admin()
+        .config(|c| c.maintenance_mode = true)
+        .build();
+    let user = test_utils::TestUser::random();
+
+    let (status, body) = app.send_json(app.register_request(&user)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        body["error"],
+        "Server is under maintenance - backups can still be retrieved, try saving again later"
+    );
+    let (_, health) = app.send_json(make_get_request("/health")).await;
+    assert_eq!(health["writes"], "maintenance");
+    let (status, _) = app.send_json(make_get_request("/health/ready")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let toggle = |enabled: bool| {
+        with_bearer(
+            make_post_request(
+                "/admin/maintenance",
+                json!({ "enabled": enabled }).to_string(),
+            ),
+            TEST_ADMIN_SECRET,
+        )
+    };
+    let (status, body) = app.send_json(toggle(false)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["maintenance"], false);
+    let user = app.user_with_backup("ciphertext").await;
+
+    app.send_json(toggle(true)).await;
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "newer"))
+        .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let (status, _) = app.send_json(app.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+    let (status, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], "ciphertext");
+}
+
+#[tokio::test]
+async fn test_admin_prune_rate_limits() {
+    let app = TestApp::builder().with_admin().build();
+    let active = app.user_with_backup("ciphertext").await;
+
+    // A record from a user who stopped syncing long ago
+    {
+        use dailyreps_backup_server::{db::tables, models::RateLimitRecord};
+        let stale =
+            bincode::serde::encode_to_vec(RateLimitRecord::new(0), bincode::config::standard())
+                .unwrap();
+        let write_txn = app.state.db.begin_write().unwrap();
+        write_txn
+            .open_table(tables::RATE_LIMITS)
+            .unwrap()
+            .insert("f".repeat(64).as_str(), stale.as_slice())
+            .unwrap();
+        write_txn.commit().unwrap();
+    }
+
+    let prune = || {
+        with_bearer(
+            make_post_request("/admin/rate-limits/prune", String::new()),
+            TEST_ADMIN_SECRET,
+        )
+    };
+    let (status, body) = app.send_json(prune()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["removed"], 1);
+    assert_eq!(body["remaining"], 1);
+
+    // The active user's limits are untouched
+    let (status, _) = app
+        .send_json(app.store_backup_request(&active, "newer"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = app.send_json(prune()).await;
+    assert_eq!(body["removed"], 0);
+}
+
+#[tokio::test]
+async fn test_admin_snapshot() {
+    let snapshots = tempfile::TempDir::new().unwrap();
+    let dir = snapshots.path().to_string_lossy().into_owned();
+    let app = TestApp::builder()
+        .with_admin()
+        .config(|c| c.snapshot_dir = Some(dir))
+        .build();
+    let user = app.user_with_backup("ciphertext").await;
+
+    let snapshot = || {
+        with_bearer(
+            make_post_request("/admin/snapshot", String::new()),
+            TEST_ADMIN_SECRET,
+        )
+    };
+    let (status, body) = app.send_json(snapshot()).await;
+    assert_eq!(status, StatusCode::OK);
+    let path = std::path::PathBuf::from(body["path"].as_str().unwrap());
+    assert!(path.starts_with(snapshots.path()));
+    assert_eq!(
+        body["size_bytes"].as_u64().unwrap(),
+        std::fs::metadata(&path).unwrap().len()
+    );
+
+    // The copy is a working database holding the backup
+    use dailyreps_backup_server::db::tables;
+    use redb::ReadableDatabase;
+    let copy = dailyreps_backup_server::open_database(&path).unwrap();
+    let read_txn = copy.begin_read().unwrap();
+    let backups = read_txn.open_table(tables::BACKUPS).unwrap();
+    assert!(backups.get(user.storage_key.as_str()).unwrap().is_some());
+
+    let app = TestApp::builder().with_admin().build();
+    let (status, _) = app.send_json(snapshot()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_admin_compact() {
+    let app = TestApp::builder().with_admin().build();
+    let payload = "x".repeat(200_000);
+    for _ in 0..5 {
+        let user = app.user_with_backup(&payload).await;
+        let (status, _) = app.send_json(app.delete_user_request(&user)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, body) = app
+        .send_json(with_bearer(
+            make_post_request("/admin/compact", String::new()),
+            TEST_ADMIN_SECRET,
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let before = body["before_bytes"].as_u64().unwrap();
+    let after = body["after_bytes"].as_u64().unwrap();
+    assert!(after < before);
+    assert_eq!(body["reclaimed_bytes"].as_u64().unwrap(), before - after);
+    assert_eq!(after, std::fs::metadata(app.db_path()).unwrap().len());
+
+    // The database is still usable
+    let user = app.user_with_backup("ciphertext").await;
+    let (status, _) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_replication_to_standby() {
+    use dailyreps_backup_server::replication::{self, REPLICATE_PATH, SIGNATURE_HEADER};
+
+    const REPLICATION_SECRET: &str = "replication-test-secret";
+    let standby = TestApp::builder()
+        .config(|c| c.replication_secret = Some(REPLICATION_SECRET.to_string()))
+        .build();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = standby.router.clone();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    let primary = TestApp::new();
+    replication::spawn(
+        primary.state.clone(),
+        url.clone(),
+        REPLICATION_SECRET.to_string(),
+    );
+    tokio::task::yield_now().await;
+
+    let user = primary.user_with_backup("replicated-ciphertext").await;
+    let mut retrieved = None;
+    for _ in 0..100 {
+        let (status, body) = standby
+            .send_json(standby.retrieve_backup_request(&user))
+            .await;
+        if status == StatusCode::OK {
+            retrieved = Some(body);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(
+        retrieved.expect("backup never reached the standby")["data"],
+        "replicated-ciphertext"
+    );
+
+    // Deleting on the primary deletes on the standby
+    let (status, _) = primary.send_json(primary.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    let mut deleted = false;
+    for _ in 0..100 {
+        let (status, _) = standby
+            .send_json(standby.retrieve_backup_request(&user))
+            .await;
+        if status != StatusCode::OK {
+            deleted = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(deleted, "deletion never reached the standby");
+
+    // Unsigned or badly signed states are refused
+    let forged = json!({ "user_id": user.user_id, "sent_at": 0, "entries": [] }).to_string();
+    let request = Request::builder()
+        .method("POST")
+        .uri(REPLICATE_PATH)
+        .header(
+            SIGNATURE_HEADER,
+            replication::sign(forged.as_bytes(), "wrong"),
+        )
+        .body(Body::from(forged))
+        .unwrap();
+    let (status, _) = standby.send_json(request).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // A signed but older state (a replay) can't roll the standby back
+    let sent_at = chrono::Utc::now().timestamp_millis() - 60_000;
+    let stale = json!({ "user_id": user.user_id, "sent_at": sent_at, "entries": [] }).to_string();
+    let request = Request::builder()
+        .method("POST")
+        .uri(REPLICATE_PATH)
+        .header(
+            SIGNATURE_HEADER,
+            replication::sign(stale.as_bytes(), REPLICATION_SECRET),
+        )
+        .body(Body::from(stale))
+        .unwrap();
+    let (status, _) = standby.send_json(request).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Servers without the secret don't serve the endpoint at all
+    let request = Request::builder()
+        .method("POST")
+        .uri(REPLICATE_PATH)
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = primary.send_json(request).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_sync_ws_pushes_new_backup() {
+    use futures_util::StreamExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let app = TestApp::new();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = app.router.clone();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    let user = app.user_with_backup("first-device").await;
+    let (status, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    let first_token = body["syncToken"].as_str().unwrap().to_string();
+
+    let url = format!(
+        "ws://{}/api/sync/ws?userId={}&storageKey={}",
+        addr, user.user_id, user.storage_key
+    );
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .unwrap();
+
+    // Another device uploads
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "second-device"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let message = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                Message::Text(text) => return serde_json::from_str::<Value>(&text).unwrap(),
+                _ => continue,
+            }
+        }
+    })
+    .await
+    .expect("no push for the new backup");
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(message["type"], "backup_stored");
+    assert_eq!(message["syncToken"], body["syncToken"]);
+    assert_ne!(message["syncToken"], first_token.as_str());
+    assert_eq!(message["updatedAt"], body["updatedAt"]);
+
+    // Deleting the account closes the channel
+    let (status, _) = app.send_json(app.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    let closed = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            match socket.next().await {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => return,
+                Some(Ok(_)) => continue,
+            }
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "channel stayed open after deletion");
+
+    // The storage key is the credential; a wrong one is refused before upgrading
+    let other = app.user_with_backup("other").await;
+    let url = format!(
+        "ws://{}/api/sync/ws?userId={}&storageKey={}",
+        addr, user.user_id, other.storage_key
+    );
+    match tokio_tungstenite::connect_async(url.as_str()).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::NOT_FOUND)
+        }
+        other => panic!("expected 404, got {:?}", other.map(|(_, r)| r.status())),
+    }
+}
+
+#[tokio::test]
+async fn test_sync_ws_push_survives_client_frames() {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    let app = TestApp::builder()
+        .config(|c| c.register_rate_limit_requests = 100)
+        .build();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = app.router.clone();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    // The push races the client's frames; repeat to give it a chance to lose
+    for _ in 0..20 {
+        let user = app.user_with_backup("first-device").await;
+        let url = format!(
+            "ws://{}/api/sync/ws?userId={}&storageKey={}",
+            addr, user.user_id, user.storage_key
+        );
+        let (socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        let (mut sink, mut stream) = socket.split();
+
+        // The client keeps sending frames while another device uploads
+        let chatter = tokio::spawn(async move {
+            while sink.send(Message::Text("hello".into())).await.is_ok() {
+                tokio::task::yield_now().await;
+            }
+        });
+        let (status, _) = app
+            .send_json(app.store_backup_request(&user, "second-device"))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                match stream.next().await.unwrap().unwrap() {
+                    Message::Text(text) => {
+                        return serde_json::from_str::<Value>(&text).unwrap();
+                    }
+                    _ => continue,
+                }
+            }
+        })
+        .await
+        .expect("push lost to client frames");
+        chatter.abort();
+        assert_eq!(message["syncToken"], body["syncToken"]);
+    }
+}
+
+#[tokio::test]
+async fn test_sync_events_stream() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("first-device").await;
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    let first_token = body["syncToken"].as_str().unwrap().to_string();
+
+    let events_request = |last_event_id: Option<&str>| {
+        let mut request = Request::builder().uri(format!(
+            "/api/sync/events?userId={}&storageKey={}",
+            user.user_id, user.storage_key
+        ));
+        if let Some(id) = last_event_id {
+            request = request.header("last-event-id", id);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+    async fn next_text(body: &mut Body) -> Option<String> {
+        tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+            .await
+            .expect("no event")
+            .map(|frame| String::from_utf8(frame.unwrap().into_data().unwrap().to_vec()).unwrap())
+    }
+
+    let response = app.send(events_request(None)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut stream = response.into_body();
+
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "second-device"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    let token = body["syncToken"].as_str().unwrap();
+
+    let text = next_text(&mut stream).await.unwrap();
+    assert!(text.contains("event: backup-updated"));
+    assert!(text.contains(&format!("id: {}", token)));
+    assert!(text.contains(&format!(r#""syncToken":"{}""#, token)));
+
+    // Reconnecting with an old id delivers the backup stored in between
+    let mut resumed = app
+        .send(events_request(Some(&first_token)))
+        .await
+        .into_body();
+    let text = next_text(&mut resumed).await.unwrap();
+    assert!(text.contains(&format!("id: {}", token)));
+
+    // Deleting the account ends the stream
+    let (status, _) = app.send_json(app.delete_user_request(&user)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(next_text(&mut stream).await.is_none());
+
+    let (status, _) = app.send_json(events_request(None)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_sync_subscriptions_capped_per_user() {
+    use dailyreps_backup_server::constants::MAX_SYNC_SUBSCRIPTIONS_PER_USER;
+
+    let app = TestApp::new();
+    let user = app.user_with_backup("data").await;
+    let events_request = || {
+        make_get_request(&format!(
+            "/api/sync/events?userId={}&storageKey={}",
+            user.user_id, user.storage_key
+        ))
+    };
+
+    let mut streams = Vec::new();
+    for _ in 0..MAX_SYNC_SUBSCRIPTIONS_PER_USER {
+        let response = app.send(events_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        streams.push(response.into_body());
+    }
+    let (status, body) = app.send_json(events_request()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "TOO_MANY_IN_FLIGHT");
+
+    // Uploads don't share the cap
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "other-device"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Closing a stream frees its slot
+    streams.pop();
+    let mut status = StatusCode::TOO_MANY_REQUESTS;
+    for _ in 0..50 {
+        status = app.send(events_request()).await.status();
+        if status == StatusCode::OK {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_admin_runtime_stats() {
+    let app = TestApp::builder().with_admin().build();
+
+    let (status, body) = app.send_json(app.admin_request("/admin/runtime")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["tokio"]["workers"].as_u64().unwrap() >= 1);
+    if cfg!(target_os = "linux") {
+        assert!(body["rss_bytes"].as_u64().unwrap() > 0);
+        assert!(body["open_fds"].as_u64().unwrap() > 0);
+    }
+
+    let (status, _) = app
+        .send_json(with_bearer(make_get_request("/admin/runtime"), "wrong"))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_error_codes() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("v1").await;
+
+    let (status, body) = app.send_json(app.register_request(&user)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "USER_EXISTS");
+
+    let mut forged = app.store_backup_request(&user, "v2");
+    *forged.body_mut() = Body::from(
+        json!({
+            "userId": user.user_id,
+            "storageKey": user.storage_key,
+            "data": "v2",
+            "signature": "0".repeat(64),
+            "timestamp": chrono::Utc::now().timestamp(),
+        })
+        .to_string(),
+    );
+    let (status, body) = app.send_json(forged).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "INVALID_SIGNATURE");
+    assert!(body["error"].is_string());
+
+    let (status, body) = app
+        .send_json(app.store_backup_request(&user, &"A".repeat(MAX_BACKUP_SIZE_BYTES + 1)))
+        .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["code"], "QUOTA_EXCEEDED");
+
+    let stranger = test_utils::TestUser::random();
+    let (status, body) = app.send_json(app.retrieve_backup_request(&stranger)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "BACKUP_NOT_FOUND");
+
+    let (status, body) = app
+        .send_json(make_post_request("/api/backup", "not json".to_string()))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "INVALID_INPUT");
+}
+
+#[tokio::test]
+async fn test_min_backup_interval() {
+    let app = TestApp::builder()
+        .config(|c| c.min_backup_interval_secs = 60)
+        .build();
+    let user = app.user_with_backup("first").await;
+
+    let response = app.send(app.store_backup_request(&user, "second")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((59..=60).contains(&retry_after));
+
+    let body = body_to_json(response.into_body()).await;
+    assert_eq!(body["code"], "RATE_LIMITED");
+    assert_eq!(body["limitType"], "interval");
+    assert_eq!(body["limit"], 1);
+    assert_eq!(body["window"], 60);
+    assert_eq!(body["retryAfterSecs"], retry_after);
+
+    // The first backup is untouched
+    let (_, body) = app.send_json(app.retrieve_backup_request(&user)).await;
+    assert_eq!(body["data"], "first");
+}
+
+#[tokio::test]
+async fn test_admin_routes_not_mounted_without_credentials() {
+    let app = TestApp::new();
+
+    for path in ["/admin/stats", "/admin/ui", "/admin/metrics"] {
+        let response = app
+            .send(with_bearer(make_get_request(path), "anything"))
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+
+    let response = app.send(make_get_request("/health")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_liveness_and_readiness_probes() {
+    let app = TestApp::new();
+
+    let (status, body) = app.send_json(make_get_request("/health/live")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "alive");
+
+    let response = app.send(make_get_request("/health/ready")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    let body = body_to_json(response.into_body()).await;
+    assert_eq!(body["database"], "writable");
+
+    // A full disk takes the server out of rotation but it stays alive
+    app.state.writes.trip(1);
+    let (status, body) = app.send_json(make_get_request("/health/ready")).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["writes"], "read_only");
+    let (status, _) = app.send_json(make_get_request("/health/live")).await;
+    assert_eq!(status, StatusCode::OK);
+    app.state.writes.resume();
+
+    // So does a background job that died
+    app.state.jobs.track("test job", tokio::spawn(async {}));
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    let (status, body) = app.send_json(make_get_request("/health/ready")).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["stopped_jobs"], json!(["test job"]));
+}
+
+#[tokio::test]
+async fn test_admin_health_history() {
+    let app = TestApp::builder().with_admin().build();
+    for _ in 0..3 {
+        app.send(make_get_request("/health")).await;
+    }
+
+    let (status, body) = app
+        .send_json(app.admin_request("/admin/health/history"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["checks"], 3);
+    assert_eq!(body["failures"], 0);
+    let samples = body["samples"].as_array().unwrap();
+    assert!(samples[0]["at"].as_i64().unwrap() >= samples[2]["at"].as_i64().unwrap());
+    assert_eq!(samples[0]["healthy"], true);
+    assert!(samples[0].get("error").is_none());
+}
+
+#[tokio::test]
+async fn test_cors_preflight_is_cacheable() {
+    let preflight = || {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/api/backup")
+            .header("origin", "http://localhost:5173")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let app = TestApp::new();
+    let router = build_router(
+        app.state.clone(),
+        RouterOptions {
+            cors: Some(cors_layer(&app.state).unwrap()),
+            log_requests: false,
+        },
+    );
+    let response = router.oneshot(preflight()).await.unwrap();
+    let headers = response.headers();
+    assert_eq!(headers["access-control-max-age"], "7200");
+    assert_eq!(headers["access-control-allow-headers"], "*");
+    assert!(!headers.contains_key("access-control-allow-credentials"));
+
+    let app = TestApp::builder()
+        .config(|c| {
+            c.cors_allow_credentials = true;
+            c.cors_max_age_secs = 600;
+        })
+        .build();
+    let router = build_router(
+        app.state.clone(),
+        RouterOptions {
+            cors: Some(cors_layer(&app.state).unwrap()),
+            log_requests: false,
+        },
+    );
+    let response = router.clone().oneshot(preflight()).await.unwrap();
+    let headers = response.headers();
+    assert_eq!(headers["access-control-max-age"], "600");
+    assert_eq!(headers["access-control-allow-credentials"], "true");
+    assert_eq!(headers["access-control-allow-headers"], "content-type");
+
+    // Exposed headers are sent on actual responses
+    let request = Request::builder()
+        .uri("/health")
+        .header("origin", "http://localhost:5173")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(
+        response.headers()["access-control-expose-headers"],
+        "retry-after,x-request-id"
+    );
+
+    let app = TestApp::builder()
+        .config(|c| c.allowed_origins = vec!["not a url\n".to_string()])
+        .build();
+    assert!(cors_layer(&app.state).is_err());
+}
+
+#[tokio::test]
+async fn test_config_reload_applies_without_rebuilding_router() {
+    let app = TestApp::builder().with_admin().build();
+    let router = build_router(
+        app.state.clone(),
+        RouterOptions {
+            cors: Some(cors_layer(&app.state).unwrap()),
+            log_requests: false,
+        },
+    );
+    let allows_origin = |origin: &str| {
+        let request = Request::builder()
+            .uri("/health")
+            .header("origin", origin)
+            .body(Body::empty())
+            .unwrap();
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            response
+                .headers()
+                .contains_key("access-control-allow-origin")
+        }
+    };
+    assert!(allows_origin("http://localhost:5173").await);
+    assert!(!allows_origin("https://app.example.com").await);
+
+    let mut new = (*app.state.config()).clone();
+    new.allowed_origins = vec!["https://app.example.com".to_string()];
+    new.admin_secret_key = Some("rotated-admin-secret".to_string());
+    new.max_in_flight_per_user += 1;
+    let changed = app.state.apply_config(&new);
+    assert_eq!(changed, vec!["ALLOWED_ORIGINS", "ADMIN_SECRET_KEY"]);
+
+    // Only reloadable settings change
+    assert_eq!(
+        app.state.config().max_in_flight_per_user + 1,
+        new.max_in_flight_per_user
+    );
+    assert!(app.state.apply_config(&new).is_empty());
+
+    assert!(!allows_origin("http://localhost:5173").await);
+    assert!(allows_origin("https://app.example.com").await);
+
+    let response = app.send(app.admin_request("/admin/stats")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app
+        .send(with_bearer(
+            make_get_request("/admin/stats"),
+            "rotated-admin-secret",
+        ))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The reload endpoint is an admin route like the others
+    let request = Request::builder()
+        .method("POST")
+        .uri("/admin/config/reload")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.send(request).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_config_reload_adds_and_removes_admin_key() {
+    let app = TestApp::new();
+    let stats = || with_bearer(make_get_request("/admin/stats"), "reloaded-admin-secret");
+    assert_eq!(app.send(stats()).await.status(), StatusCode::NOT_FOUND);
+
+    let mut new = (*app.state.config()).clone();
+    new.admin_secret_key = Some("reloaded-admin-secret".to_string());
+    assert_eq!(app.state.apply_config(&new), vec!["ADMIN_SECRET_KEY"]);
+    assert_eq!(app.send(stats()).await.status(), StatusCode::OK);
+
+    new.admin_secret_key = None;
+    assert_eq!(app.state.apply_config(&new), vec!["ADMIN_SECRET_KEY"]);
+    assert_eq!(app.send(stats()).await.status(), StatusCode::NOT_FOUND);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/admin/config/reload")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.send(request).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_request_id_in_header_and_error_body() {
+    let app = TestApp::new();
+
+    let response = app.send(make_get_request("/health")).await;
+    let first = response.headers()["x-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(first.len(), 36);
+
+    let response = app
+        .send(make_get_request(&format!(
+            "/api/backup?userId={}&storageKey={}",
+            "a".repeat(64),
+            "b".repeat(64)
+        )))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let id = response.headers()["x-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_ne!(id, first);
+    let body = body_to_json(response.into_body()).await;
+    assert_eq!(body["requestId"], id.as_str());
+}
+
+#[tokio::test]
+async fn test_cache_control_policy() {
+    let app = TestApp::new();
+    let user = app.user_with_backup("ciphertext").await;
+
+    let response = app.send(app.retrieve_backup_request(&user)).await;
+    assert_eq!(response.headers()["cache-control"], "no-store");
+
+    let other = test_utils::TestUser::random();
+    let response = app.send(app.register_request(&other)).await;
+    assert_eq!(response.headers()["cache-control"], "no-store");
+
+    // Errors from backup routes aren't cacheable either
+    let response = app.send(app.retrieve_backup_request(&other)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["cache-control"], "no-store");
+
+    let response = app.send(make_get_request("/health")).await;
+    assert_eq!(response.headers()["cache-control"], "public, max-age=5");
+}
+
+#[tokio::test]
+async fn test_repeated_identical_uploads_are_refused() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+    let limit = dailyreps_backup_server::constants::DUPLICATE_UPLOADS_PER_DAY;
+
+    // Fill the duplicate allowance without touching the hourly/daily limits
+    for _ in 0..=limit {
+        app.state.duplicates.record(
+            &user.user_id,
+            &dailyreps_signing::checksum(b"same"),
+            chrono::Utc::now().timestamp(),
+        );
+    }
+
+    let (status, body) = app.send_json(app.store_backup_request(&user, "same")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["limitType"], "duplicate");
+    assert_eq!(body["limit"], limit);
+
+    // Changed data is still accepted
+    let (status, _) = app
+        .send_json(app.store_backup_request(&user, "changed"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_refused_uploads_do_not_use_duplicate_allowance() {
+    let app = TestApp::new();
+    let user = app.register_user().await;
+    let limit = dailyreps_backup_server::constants::DUPLICATE_UPLOADS_PER_DAY;
+    let store = |sync_token: &str| {
+        let body = json!({
+            "userId": user.user_id,
+            "storageKey": user.storage_key,
+            "data": "same",
+            "signature": app.sign("same"),
+            "timestamp": chrono::Utc::now().timestamp(),
+            "syncToken": sync_token,
+        });
+        make_post_request("/api/backup", body.to_string())
+    };
+
+    let (status, body) = app.send_json(app.store_backup_request(&user, "same")).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = body["syncToken"].as_str().unwrap().to_string();
+
+    // A client retrying the same blob after conflicts is never stored
+    for _ in 0..=limit + 1 {
+        let (status, _) = app.send_json(store("999")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    let (status, _) = app.send_json(store(&token)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+// =============================================================================
+// Native TLS Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_serves_https_with_configured_certificate() {
+    use dailyreps_backup_server::server::{self, ServerOptions};
+    use dailyreps_backup_server::tls::{CertReloader, TlsListener};
+    use tokio::io::AsyncWriteExt;
+
+    let app = TestApp::new();
+    let user = app.user_with_backup("over-tls").await;
+
+    let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+    let certs = CertReloader::load(
+        format!("{}/tls_cert_1.pem", fixtures),
+        format!("{}/tls_key_1.pem", fixtures),
+    )
+    .unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let options = ServerOptions::from_config(&test_config());
+    let listener = TlsListener::new(listener, Arc::new(certs), options).unwrap();
+    tokio::spawn(server::serve(
+        listener,
+        app.router.clone(),
+        options.without_connection_limit(),
+    ));
+
+    // A client speaking plain HTTP fails its handshake without affecting others
+    let mut plain = tokio::net::TcpStream::connect(addr).await.unwrap();
+    plain
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    let root = reqwest::Certificate::from_pem(include_bytes!("fixtures/tls_cert_1.pem")).unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(root)
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!(
+            "https://localhost:{}/api/backup?userId={}&storageKey={}",
+            addr.port(),
+            user.user_id,
+            user.storage_key
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"], "over-tls");
+}
+
+#[tokio::test]
+async fn test_tls_handshakes_count_against_max_connections() {
+    use dailyreps_backup_server::server::{self, ServerOptions};
+    use dailyreps_backup_server::tls::{CertReloader, TlsListener};
+
+    let app = TestApp::new();
+    let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+    let certs = CertReloader::load(
+        format!("{}/tls_cert_1.pem", fixtures),
+        format!("{}/tls_key_1.pem", fixtures),
+    )
+    .unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let options = ServerOptions {
+        keep_alive: Some(std::time::Duration::from_secs(60)),
+        max_connections: 1,
+        http2: false,
+    };
+    let listener = TlsListener::new(listener, Arc::new(certs), options).unwrap();
+    tokio::spawn(server::serve(
+        listener,
+        app.router.clone(),
+        options.without_connection_limit(),
+    ));
+
+    // A client that never starts its handshake holds the only slot...
+    let silent = tokio::net::TcpStream::connect(addr).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let root = reqwest::Certificate::from_pem(include_bytes!("fixtures/tls_cert_1.pem")).unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(root)
+        .build()
+        .unwrap();
+    let health = || {
+        client
+            .get(format!("https://localhost:{}/health/live", addr.port()))
+            .send()
+    };
+    let waiting = tokio::time::timeout(std::time::Duration::from_millis(300), health()).await;
+    assert!(waiting.is_err());
+
+    // ...until it goes away
+    drop(silent);
+    let response = tokio::time::timeout(std::time::Duration::from_secs(5), health())
+        .await
+        .expect("queued connection should be served")
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
+
+// =============================================================================
+// Server Tuning Tests
+// =============================================================================
+
+/// Serve `app` over TCP with the given connection settings
+async fn spawn_tuned_server(
+    app: &TestApp,
+    options: dailyreps_backup_server::server::ServerOptions,
+) -> std::net::SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(dailyreps_backup_server::server::serve(
+        listener,
+        app.router.clone(),
+        options,
+    ));
+    addr
+}
+
+/// Send a bare HTTP/1.1 health check
+async fn send_http1_health_check(stream: &mut tokio::net::TcpStream) {
+    use tokio::io::AsyncWriteExt;
+
+    stream
+        .write_all(b"GET /health/live HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+}
+
+/// Read until the end of a response's headers
+async fn read_http1_response(stream: &mut tokio::net::TcpStream) -> String {
+    use tokio::io::AsyncReadExt;
+
+    let mut response = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !String::from_utf8_lossy(&response).contains("\r\n\r\n") {
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(n > 0, "connection closed before a response");
+        response.extend_from_slice(&buf[..n]);
+    }
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+async fn http1_health_check(stream: &mut tokio::net::TcpStream) -> String {
+    send_http1_health_check(stream).await;
+    read_http1_response(stream).await
+}
+
+#[tokio::test]
+async fn test_server_keep_alive_timeout() {
+    use dailyreps_backup_server::server::ServerOptions;
+    use tokio::io::AsyncReadExt;
+
+    let app = TestApp::new();
+    let addr = spawn_tuned_server(
+        &app,
+        ServerOptions {
+            keep_alive: Some(std::time::Duration::from_millis(300)),
+            max_connections: 0,
+            http2: false,
+        },
+    )
+    .await;
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    assert!(
+        http1_health_check(&mut stream)
+            .await
+            .starts_with("HTTP/1.1 200")
+    );
+    // The connection is reused for the next request...
+    assert!(
+        http1_health_check(&mut stream)
+            .await
+            .starts_with("HTTP/1.1 200")
+    );
+
+    // ...and closed once idle for longer than the keep-alive timeout
+    let mut buf = [0u8; 64];
+    let n = tokio::time::timeout(std::time::Duration::from_secs(5), stream.read(&mut buf))
+        .await
+        .expect("idle connection should be closed")
+        .unwrap_or(0);
+    assert_eq!(n, 0);
+}
+
+#[tokio::test]
+async fn test_server_max_connections() {
+    use dailyreps_backup_server::server::ServerOptions;
+
+    let app = TestApp::new();
+    let addr = spawn_tuned_server(
+        &app,
+        ServerOptions {
+            keep_alive: Some(std::time::Duration::from_secs(60)),
+            max_connections: 1,
+            http2: false,
+        },
+    )
+    .await;
+
+    let mut first = tokio::net::TcpStream::connect(addr).await.unwrap();
+    assert!(
+        http1_health_check(&mut first)
+            .await
+            .starts_with("HTTP/1.1 200")
+    );
+
+    // A second connection waits while the first is open...
+    let mut second = tokio::net::TcpStream::connect(addr).await.unwrap();
+    send_http1_health_check(&mut second).await;
+    let waiting = tokio::time::timeout(
+        std::time::Duration::from_millis(300),
+        read_http1_response(&mut second),
+    )
+    .await;
+    assert!(waiting.is_err());
+
+    // ...and is served once it closes
+    drop(first);
+    let response = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        read_http1_response(&mut second),
+    )
+    .await
+    .expect("queued connection should be served");
+    assert!(response.starts_with("HTTP/1.1 200"));
+}
+
+#[tokio::test]
+async fn test_server_http2() {
+    use dailyreps_backup_server::server::ServerOptions;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// HTTP/2 connection preface followed by an empty SETTINGS frame
+    const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\x00\x00\x00\x04\x00\x00\x00\x00\x00";
+
+    let app = TestApp::new();
+    for http2 in [true, false] {
+        let addr = spawn_tuned_server(
+            &app,
+            ServerOptions {
+                keep_alive: Some(std::time::Duration::from_secs(60)),
+                max_connections: 0,
+                http2,
+            },
+        )
+        .await;
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(PREFACE).await.unwrap();
+        if http2 {
+            // The server's own SETTINGS frame comes first
+            let mut header = [0u8; 9];
+            stream.read_exact(&mut header).await.unwrap();
+            assert_eq!(header[3], 0x04);
+        } else {
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await.ok();
+            // An HTTP/1-only server hangs up on the preface
+            assert!(response.is_empty() || response.starts_with(b"HTTP/1.1 "));
+        }
+    }
+}